@@ -0,0 +1,43 @@
+use std::{env, fs, path::Path};
+
+/// Embeds the name and the `down.sql` of every migration of the backend, so
+/// the binary can list and revert them without the sources.
+/// `embed_migrations!` only embeds the `up.sql`.
+fn main() {
+  let backend = if env::var_os("CARGO_FEATURE_POSTGRES").is_some() {
+    "postgres"
+  } else {
+    "sqlite"
+  };
+  let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").expect("cargo sets it"))
+    .join("migrations")
+    .join(backend);
+  println!("cargo:rerun-if-changed={}", dir.display());
+
+  let mut names = fs::read_dir(&dir)
+    .expect("the migrations directory must exist")
+    .map(|entry| {
+      entry
+        .expect("the migrations directory must be readable")
+        .file_name()
+        .into_string()
+        .expect("the migration names must be UTF-8")
+    })
+    .collect::<Vec<String>>();
+  names.sort();
+  let migrations = names
+    .iter()
+    .map(|name| {
+      format!(
+        "  ({:?}, include_str!({:?})),\n",
+        name,
+        dir.join(name).join("down.sql")
+      )
+    })
+    .collect::<String>();
+
+  let out = Path::new(&env::var("OUT_DIR").expect("cargo sets it"))
+    .join("migrations.rs");
+  fs::write(out, format!("&[\n{}]\n", migrations))
+    .expect("the embedded migrations must be written");
+}
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "users" (
+"id"	SERIAL PRIMARY KEY,
+"username"	TEXT NOT NULL UNIQUE,
+"hashed_password"	TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE "logins" (
+"id"	SERIAL PRIMARY KEY,
+"username"	TEXT NOT NULL UNIQUE,
+"token"	TEXT NOT NULL
+);
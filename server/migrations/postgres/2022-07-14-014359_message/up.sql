@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE "messages" (
+"id"	SERIAL PRIMARY KEY,
+"from"	INTEGER NOT NULL REFERENCES "users"("id"),
+"to"	INTEGER NOT NULL REFERENCES "users"("id"),
+"message"	TEXT NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE "refresh_tokens" (
+"id"	SERIAL PRIMARY KEY,
+"user_id"	INTEGER NOT NULL REFERENCES "users"("id"),
+"token_hash"	TEXT NOT NULL UNIQUE,
+"family"	TEXT NOT NULL,
+"expires_at"	TIMESTAMP NOT NULL,
+"used_at"	TIMESTAMP,
+"revoked"	BOOLEAN NOT NULL DEFAULT FALSE
+);
+CREATE INDEX "refresh_tokens_family" ON "refresh_tokens" ("family");
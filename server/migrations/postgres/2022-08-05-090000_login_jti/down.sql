@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "logins_jti";
+ALTER TABLE "logins" DROP COLUMN "jti";
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "logins" ADD COLUMN "jti" TEXT NOT NULL DEFAULT '';
+CREATE INDEX "logins_jti" ON "logins" ("jti");
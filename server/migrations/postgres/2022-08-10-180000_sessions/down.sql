@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM "logins"
+WHERE "id" NOT IN (SELECT MAX("id") FROM "logins" GROUP BY "username");
+DROP INDEX "logins_family";
+DROP INDEX "logins_user_id";
+ALTER TABLE "logins" DROP COLUMN "last_seen_at";
+ALTER TABLE "logins" DROP COLUMN "created_at";
+ALTER TABLE "logins" DROP COLUMN "ip";
+ALTER TABLE "logins" DROP COLUMN "user_agent";
+ALTER TABLE "logins" DROP COLUMN "device";
+ALTER TABLE "logins" DROP COLUMN "family";
+ALTER TABLE "logins" DROP COLUMN "user_id";
+ALTER TABLE "logins" ADD CONSTRAINT "logins_username_key" UNIQUE ("username");
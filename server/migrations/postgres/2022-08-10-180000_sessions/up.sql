@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE "logins" DROP CONSTRAINT "logins_username_key";
+ALTER TABLE "logins" ADD COLUMN "user_id" INTEGER REFERENCES "users"("id");
+UPDATE "logins" SET "user_id" = "users"."id"
+FROM "users" WHERE "users"."username" = "logins"."username";
+DELETE FROM "logins" WHERE "user_id" IS NULL;
+ALTER TABLE "logins" ALTER COLUMN "user_id" SET NOT NULL;
+ALTER TABLE "logins" ADD COLUMN "family" TEXT NOT NULL DEFAULT '';
+ALTER TABLE "logins" ADD COLUMN "device" TEXT;
+ALTER TABLE "logins" ADD COLUMN "user_agent" TEXT;
+ALTER TABLE "logins" ADD COLUMN "ip" TEXT;
+ALTER TABLE "logins" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+ALTER TABLE "logins" ADD COLUMN "last_seen_at" TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+CREATE INDEX "logins_user_id" ON "logins" ("user_id");
+CREATE INDEX "logins_family" ON "logins" ("family");
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "message_revisions";
+ALTER TABLE "messages" DROP COLUMN "deleted_at";
+ALTER TABLE "messages" DROP COLUMN "updated_at";
+ALTER TABLE "messages" DROP COLUMN "created_at";
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+ALTER TABLE "messages" ADD COLUMN "created_at" TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+ALTER TABLE "messages" ADD COLUMN "updated_at" TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc');
+ALTER TABLE "messages" ADD COLUMN "deleted_at" TIMESTAMP;
+CREATE TABLE "message_revisions" (
+"id"	SERIAL PRIMARY KEY,
+"message_id"	INTEGER NOT NULL REFERENCES "messages"("id"),
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX "message_revisions_message_id" ON "message_revisions" ("message_id");
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE "message_receipts" (
+"id"	SERIAL PRIMARY KEY,
+"message_id"	INTEGER NOT NULL REFERENCES "messages"("id"),
+"user_id"	INTEGER NOT NULL,
+"delivered_at"	TIMESTAMP,
+"read_at"	TIMESTAMP,
+UNIQUE("message_id", "user_id")
+);
+INSERT INTO "message_receipts" ("message_id", "user_id")
+SELECT "id", "to" FROM "messages";
+CREATE INDEX "message_receipts_unread" ON "message_receipts" ("user_id", "read_at");
@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM "message_receipts" WHERE "message_id" IN
+(SELECT "id" FROM "messages" WHERE "conversation_id" IS NOT NULL);
+DELETE FROM "message_revisions" WHERE "message_id" IN
+(SELECT "id" FROM "messages" WHERE "conversation_id" IS NOT NULL);
+DELETE FROM "messages" WHERE "conversation_id" IS NOT NULL;
+DROP INDEX "messages_conversation_id";
+ALTER TABLE "messages" DROP COLUMN "conversation_id";
+ALTER TABLE "messages" ALTER COLUMN "to" SET NOT NULL;
+DROP TABLE "conversation_members";
+DROP TABLE "conversations";
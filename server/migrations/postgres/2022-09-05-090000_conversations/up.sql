@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE "conversations" (
+"id"	SERIAL PRIMARY KEY,
+"name"	TEXT NOT NULL,
+"created_by"	INTEGER NOT NULL REFERENCES "users"("id"),
+"created_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE TABLE "conversation_members" (
+"id"	SERIAL PRIMARY KEY,
+"conversation_id"	INTEGER NOT NULL REFERENCES "conversations"("id"),
+"user_id"	INTEGER NOT NULL REFERENCES "users"("id"),
+"role"	TEXT NOT NULL DEFAULT 'member',
+"joined_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+UNIQUE("conversation_id", "user_id")
+);
+CREATE INDEX "conversation_members_user_id" ON "conversation_members" ("user_id");
+ALTER TABLE "messages" ALTER COLUMN "to" DROP NOT NULL;
+ALTER TABLE "messages" ADD COLUMN "conversation_id" INTEGER REFERENCES "conversations"("id");
+CREATE INDEX "messages_conversation_id" ON "messages" ("conversation_id", "id");
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TABLE "webhooks" (
+"id"	SERIAL PRIMARY KEY,
+"user_id"	INTEGER NOT NULL REFERENCES "users"("id"),
+"url"	TEXT NOT NULL,
+"secret"	TEXT NOT NULL,
+"events"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX "webhooks_user_id" ON "webhooks" ("user_id");
+CREATE TABLE "webhook_outbox" (
+"id"	SERIAL PRIMARY KEY,
+"webhook_id"	INTEGER NOT NULL REFERENCES "webhooks"("id"),
+"event"	TEXT NOT NULL,
+"payload"	TEXT NOT NULL,
+"attempts"	INTEGER NOT NULL DEFAULT 0,
+"next_attempt_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc'),
+"last_error"	TEXT,
+"delivered_at"	TIMESTAMP,
+"dead_at"	TIMESTAMP,
+"created_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX "webhook_outbox_pending" ON "webhook_outbox" ("delivered_at", "dead_at", "next_attempt_at");
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "message_receipts" DROP CONSTRAINT "message_receipts_user_id_fkey";
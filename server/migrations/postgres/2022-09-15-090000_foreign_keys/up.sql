@@ -0,0 +1,4 @@
+-- Your SQL goes here
+DELETE FROM "message_receipts" WHERE "user_id" NOT IN (SELECT "id" FROM "users");
+ALTER TABLE "message_receipts" ADD CONSTRAINT "message_receipts_user_id_fkey"
+FOREIGN KEY ("user_id") REFERENCES "users"("id");
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+CREATE INDEX "messages_search" ON "messages"
+USING GIN (to_tsvector('simple', "message")) WHERE "deleted_at" IS NULL;
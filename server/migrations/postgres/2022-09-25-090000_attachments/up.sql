@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "attachments" (
+"id"	SERIAL PRIMARY KEY,
+"user_id"	INTEGER NOT NULL REFERENCES "users"("id"),
+"message_id"	INTEGER REFERENCES "messages"("id"),
+"hash"	TEXT NOT NULL,
+"filename"	TEXT NOT NULL,
+"mime_type"	TEXT NOT NULL,
+"size"	BIGINT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+CREATE INDEX "attachments_user_id" ON "attachments" ("user_id");
+CREATE INDEX "attachments_message_id" ON "attachments" ("message_id");
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+ALTER TABLE "attachments" ADD COLUMN "width" INTEGER;
+ALTER TABLE "attachments" ADD COLUMN "height" INTEGER;
+ALTER TABLE "attachments" ADD COLUMN "thumbnail_state" TEXT;
+CREATE INDEX "attachments_thumbnail_state" ON "attachments" ("thumbnail_state");
+CREATE TABLE "thumbnails" (
+"id"	SERIAL PRIMARY KEY,
+"attachment_id"	INTEGER NOT NULL REFERENCES "attachments"("id"),
+"size"	TEXT NOT NULL,
+"hash"	TEXT NOT NULL,
+"mime_type"	TEXT NOT NULL,
+"width"	INTEGER NOT NULL,
+"height"	INTEGER NOT NULL,
+UNIQUE("attachment_id", "size")
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+CREATE INDEX "attachments_hash" ON "attachments" ("hash");
+CREATE INDEX "thumbnails_hash" ON "thumbnails" ("hash");
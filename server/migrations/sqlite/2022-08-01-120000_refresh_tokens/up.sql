@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE "refresh_tokens" (
+"id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"token_hash"	TEXT NOT NULL UNIQUE,
+"family"	TEXT NOT NULL,
+"expires_at"	TIMESTAMP NOT NULL,
+"used_at"	TIMESTAMP,
+"revoked"	BOOLEAN NOT NULL DEFAULT 0,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("user_id") REFERENCES "users"("id")
+);
+CREATE INDEX "refresh_tokens_family" ON "refresh_tokens" ("family");
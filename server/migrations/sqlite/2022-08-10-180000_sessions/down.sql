@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE "single_logins" (
+"id"	INTEGER NOT NULL,
+"username"	TEXT NOT NULL UNIQUE,
+"token"	TEXT NOT NULL,
+"jti"	TEXT NOT NULL DEFAULT '',
+PRIMARY KEY("id" AUTOINCREMENT)
+);
+INSERT INTO "single_logins" ("id", "username", "token", "jti")
+SELECT "id", "username", "token", "jti" FROM "logins"
+WHERE "id" IN (SELECT MAX("id") FROM "logins" GROUP BY "username");
+DROP TABLE "logins";
+ALTER TABLE "single_logins" RENAME TO "logins";
+CREATE INDEX "logins_jti" ON "logins" ("jti");
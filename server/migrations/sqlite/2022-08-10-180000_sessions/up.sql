@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TABLE "sessions" (
+"id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"username"	TEXT NOT NULL,
+"token"	TEXT NOT NULL,
+"jti"	TEXT NOT NULL,
+"family"	TEXT NOT NULL DEFAULT '',
+"device"	TEXT,
+"user_agent"	TEXT,
+"ip"	TEXT,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"last_seen_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("user_id") REFERENCES "users"("id")
+);
+INSERT INTO "sessions" ("id", "user_id", "username", "token", "jti")
+SELECT "logins"."id", "users"."id", "logins"."username", "logins"."token", "logins"."jti"
+FROM "logins" JOIN "users" ON "users"."username" = "logins"."username";
+DROP TABLE "logins";
+ALTER TABLE "sessions" RENAME TO "logins";
+CREATE INDEX "logins_user_id" ON "logins" ("user_id");
+CREATE INDEX "logins_jti" ON "logins" ("jti");
+CREATE INDEX "logins_family" ON "logins" ("family");
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "users" ADD COLUMN "role" TEXT NOT NULL DEFAULT 'user';
+ALTER TABLE "users" ADD COLUMN "suspended" BOOLEAN NOT NULL DEFAULT 0;
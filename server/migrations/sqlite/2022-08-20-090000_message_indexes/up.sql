@@ -0,0 +1,3 @@
+-- Your SQL goes here
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "message_revisions";
+CREATE TABLE "untimed_messages" (
+"id"	INTEGER NOT NULL,
+"from"	INTEGER NOT NULL,
+"to"	INTEGER NOT NULL,
+"message"	TEXT NOT NULL,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("from") REFERENCES "user"("id"),
+FOREIGN KEY("to") REFERENCES "user"("id")
+);
+INSERT INTO "untimed_messages" ("id", "from", "to", "message")
+SELECT "id", "from", "to", "message" FROM "messages";
+DROP TABLE "messages";
+ALTER TABLE "untimed_messages" RENAME TO "messages";
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
@@ -0,0 +1,28 @@
+-- Your SQL goes here
+CREATE TABLE "timed_messages" (
+"id"	INTEGER NOT NULL,
+"from"	INTEGER NOT NULL,
+"to"	INTEGER NOT NULL,
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"updated_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"deleted_at"	TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("from") REFERENCES "user"("id"),
+FOREIGN KEY("to") REFERENCES "user"("id")
+);
+INSERT INTO "timed_messages" ("id", "from", "to", "message")
+SELECT "id", "from", "to", "message" FROM "messages";
+DROP TABLE "messages";
+ALTER TABLE "timed_messages" RENAME TO "messages";
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
+CREATE TABLE "message_revisions" (
+"id"	INTEGER NOT NULL,
+"message_id"	INTEGER NOT NULL,
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("message_id") REFERENCES "messages"("id")
+);
+CREATE INDEX "message_revisions_message_id" ON "message_revisions" ("message_id");
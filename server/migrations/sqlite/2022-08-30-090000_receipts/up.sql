@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE "message_receipts" (
+"id"	INTEGER NOT NULL,
+"message_id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"delivered_at"	TIMESTAMP,
+"read_at"	TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+UNIQUE("message_id", "user_id"),
+FOREIGN KEY("message_id") REFERENCES "messages"("id")
+);
+INSERT INTO "message_receipts" ("message_id", "user_id")
+SELECT "id", "to" FROM "messages";
+CREATE INDEX "message_receipts_unread" ON "message_receipts" ("user_id", "read_at");
@@ -0,0 +1,26 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM "message_receipts" WHERE "message_id" IN
+(SELECT "id" FROM "messages" WHERE "conversation_id" IS NOT NULL);
+DELETE FROM "message_revisions" WHERE "message_id" IN
+(SELECT "id" FROM "messages" WHERE "conversation_id" IS NOT NULL);
+CREATE TABLE "direct_messages" (
+"id"	INTEGER NOT NULL,
+"from"	INTEGER NOT NULL,
+"to"	INTEGER NOT NULL,
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"updated_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"deleted_at"	TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("from") REFERENCES "user"("id"),
+FOREIGN KEY("to") REFERENCES "user"("id")
+);
+INSERT INTO "direct_messages" ("id", "from", "to", "message", "created_at", "updated_at", "deleted_at")
+SELECT "id", "from", "to", "message", "created_at", "updated_at", "deleted_at" FROM "messages"
+WHERE "conversation_id" IS NULL;
+DROP TABLE "messages";
+ALTER TABLE "direct_messages" RENAME TO "messages";
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
+DROP TABLE "conversation_members";
+DROP TABLE "conversations";
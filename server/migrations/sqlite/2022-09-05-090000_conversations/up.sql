@@ -0,0 +1,42 @@
+-- Your SQL goes here
+CREATE TABLE "conversations" (
+"id"	INTEGER NOT NULL,
+"name"	TEXT NOT NULL,
+"created_by"	INTEGER NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("created_by") REFERENCES "users"("id")
+);
+CREATE TABLE "conversation_members" (
+"id"	INTEGER NOT NULL,
+"conversation_id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"role"	TEXT NOT NULL DEFAULT 'member',
+"joined_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+UNIQUE("conversation_id", "user_id"),
+FOREIGN KEY("conversation_id") REFERENCES "conversations"("id"),
+FOREIGN KEY("user_id") REFERENCES "users"("id")
+);
+CREATE INDEX "conversation_members_user_id" ON "conversation_members" ("user_id");
+CREATE TABLE "group_messages" (
+"id"	INTEGER NOT NULL,
+"from"	INTEGER NOT NULL,
+"to"	INTEGER,
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"updated_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"deleted_at"	TIMESTAMP,
+"conversation_id"	INTEGER,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("from") REFERENCES "user"("id"),
+FOREIGN KEY("to") REFERENCES "user"("id"),
+FOREIGN KEY("conversation_id") REFERENCES "conversations"("id")
+);
+INSERT INTO "group_messages" ("id", "from", "to", "message", "created_at", "updated_at", "deleted_at")
+SELECT "id", "from", "to", "message", "created_at", "updated_at", "deleted_at" FROM "messages";
+DROP TABLE "messages";
+ALTER TABLE "group_messages" RENAME TO "messages";
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
+CREATE INDEX "messages_conversation_id" ON "messages" ("conversation_id", "id");
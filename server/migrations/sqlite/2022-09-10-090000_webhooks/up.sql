@@ -0,0 +1,27 @@
+-- Your SQL goes here
+CREATE TABLE "webhooks" (
+"id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"url"	TEXT NOT NULL,
+"secret"	TEXT NOT NULL,
+"events"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("user_id") REFERENCES "users"("id")
+);
+CREATE INDEX "webhooks_user_id" ON "webhooks" ("user_id");
+CREATE TABLE "webhook_outbox" (
+"id"	INTEGER NOT NULL,
+"webhook_id"	INTEGER NOT NULL,
+"event"	TEXT NOT NULL,
+"payload"	TEXT NOT NULL,
+"attempts"	INTEGER NOT NULL DEFAULT 0,
+"next_attempt_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"last_error"	TEXT,
+"delivered_at"	TIMESTAMP,
+"dead_at"	TIMESTAMP,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("webhook_id") REFERENCES "webhooks"("id")
+);
+CREATE INDEX "webhook_outbox_pending" ON "webhook_outbox" ("delivered_at", "dead_at", "next_attempt_at");
@@ -0,0 +1,37 @@
+-- This file should undo anything in `up.sql`
+CREATE TABLE "unchecked_receipts" (
+"id"	INTEGER NOT NULL,
+"message_id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"delivered_at"	TIMESTAMP,
+"read_at"	TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+UNIQUE("message_id", "user_id"),
+FOREIGN KEY("message_id") REFERENCES "messages"("id")
+);
+INSERT INTO "unchecked_receipts" ("id", "message_id", "user_id", "delivered_at", "read_at")
+SELECT "id", "message_id", "user_id", "delivered_at", "read_at" FROM "message_receipts";
+DROP TABLE "message_receipts";
+ALTER TABLE "unchecked_receipts" RENAME TO "message_receipts";
+CREATE INDEX "message_receipts_unread" ON "message_receipts" ("user_id", "read_at");
+CREATE TABLE "unchecked_messages" (
+"id"	INTEGER NOT NULL,
+"from"	INTEGER NOT NULL,
+"to"	INTEGER,
+"message"	TEXT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"updated_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+"deleted_at"	TIMESTAMP,
+"conversation_id"	INTEGER,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("from") REFERENCES "user"("id"),
+FOREIGN KEY("to") REFERENCES "user"("id"),
+FOREIGN KEY("conversation_id") REFERENCES "conversations"("id")
+);
+INSERT INTO "unchecked_messages" ("id", "from", "to", "message", "created_at", "updated_at", "deleted_at", "conversation_id")
+SELECT "id", "from", "to", "message", "created_at", "updated_at", "deleted_at", "conversation_id" FROM "messages";
+DROP TABLE "messages";
+ALTER TABLE "unchecked_messages" RENAME TO "messages";
+CREATE INDEX "messages_from" ON "messages" ("from", "id");
+CREATE INDEX "messages_to" ON "messages" ("to", "id");
+CREATE INDEX "messages_conversation_id" ON "messages" ("conversation_id", "id");
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER "messages_fts_update";
+DROP TRIGGER "messages_fts_delete";
+DROP TRIGGER "messages_fts_insert";
+DROP TABLE "messages_fts";
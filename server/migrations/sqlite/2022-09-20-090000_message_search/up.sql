@@ -0,0 +1,21 @@
+-- Your SQL goes here
+CREATE VIRTUAL TABLE "messages_fts" USING fts5("message", content='messages', content_rowid='id');
+INSERT INTO "messages_fts" ("rowid", "message")
+SELECT "id", "message" FROM "messages" WHERE "deleted_at" IS NULL;
+CREATE TRIGGER "messages_fts_insert" AFTER INSERT ON "messages"
+WHEN new."deleted_at" IS NULL
+BEGIN
+INSERT INTO "messages_fts" ("rowid", "message") VALUES (new."id", new."message");
+END;
+CREATE TRIGGER "messages_fts_delete" AFTER DELETE ON "messages"
+WHEN old."deleted_at" IS NULL
+BEGIN
+INSERT INTO "messages_fts" ("messages_fts", "rowid", "message") VALUES ('delete', old."id", old."message");
+END;
+CREATE TRIGGER "messages_fts_update" AFTER UPDATE OF "message", "deleted_at" ON "messages"
+BEGIN
+INSERT INTO "messages_fts" ("messages_fts", "rowid", "message")
+SELECT 'delete', old."id", old."message" WHERE old."deleted_at" IS NULL;
+INSERT INTO "messages_fts" ("rowid", "message")
+SELECT new."id", new."message" WHERE new."deleted_at" IS NULL;
+END;
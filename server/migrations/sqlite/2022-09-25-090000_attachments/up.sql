@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE "attachments" (
+"id"	INTEGER NOT NULL,
+"user_id"	INTEGER NOT NULL,
+"message_id"	INTEGER,
+"hash"	TEXT NOT NULL,
+"filename"	TEXT NOT NULL,
+"mime_type"	TEXT NOT NULL,
+"size"	BIGINT NOT NULL,
+"created_at"	TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("user_id") REFERENCES "users"("id"),
+FOREIGN KEY("message_id") REFERENCES "messages"("id")
+);
+CREATE INDEX "attachments_user_id" ON "attachments" ("user_id");
+CREATE INDEX "attachments_message_id" ON "attachments" ("message_id");
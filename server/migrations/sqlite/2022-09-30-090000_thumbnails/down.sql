@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE "thumbnails";
+DROP INDEX "attachments_thumbnail_state";
+ALTER TABLE "attachments" DROP COLUMN "thumbnail_state";
+ALTER TABLE "attachments" DROP COLUMN "height";
+ALTER TABLE "attachments" DROP COLUMN "width";
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+ALTER TABLE "attachments" ADD COLUMN "width" INTEGER;
+ALTER TABLE "attachments" ADD COLUMN "height" INTEGER;
+ALTER TABLE "attachments" ADD COLUMN "thumbnail_state" TEXT;
+CREATE INDEX "attachments_thumbnail_state" ON "attachments" ("thumbnail_state");
+CREATE TABLE "thumbnails" (
+"id"	INTEGER NOT NULL,
+"attachment_id"	INTEGER NOT NULL,
+"size"	TEXT NOT NULL,
+"hash"	TEXT NOT NULL,
+"mime_type"	TEXT NOT NULL,
+"width"	INTEGER NOT NULL,
+"height"	INTEGER NOT NULL,
+PRIMARY KEY("id" AUTOINCREMENT),
+FOREIGN KEY("attachment_id") REFERENCES "attachments"("id"),
+UNIQUE("attachment_id", "size")
+);
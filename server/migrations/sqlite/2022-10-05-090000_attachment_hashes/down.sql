@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX "thumbnails_hash";
+DROP INDEX "attachments_hash";
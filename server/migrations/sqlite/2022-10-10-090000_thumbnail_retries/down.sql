@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE "attachments" DROP COLUMN "thumbnail_retry_at";
+ALTER TABLE "attachments" DROP COLUMN "thumbnail_attempts";
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE "attachments" ADD COLUMN "thumbnail_attempts" INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE "attachments" ADD COLUMN "thumbnail_retry_at" TIMESTAMP;
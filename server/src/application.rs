@@ -1,4 +1,13 @@
+pub mod admin_handler;
+pub mod attachment_handler;
+pub mod conversation_handler;
 pub mod error;
+pub mod group_handler;
 pub mod health_handler;
+pub mod jwks_handler;
 pub mod message_handler;
+pub mod pagination;
+pub mod polling;
+pub mod session_handler;
 pub mod user_handler;
+pub mod webhook_handler;
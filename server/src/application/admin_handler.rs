@@ -0,0 +1,415 @@
+use crate::{
+  application::{
+    error::{ApplicationResult, ErrorResponse},
+    pagination::{Cursors, PageLinks, Paginated},
+  },
+  auth::middleware::AdminUser,
+  model::{message::Message, user::User},
+  Authenticator, MessageService, UserService,
+};
+
+use rocket::{response::status::Accepted, State};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use utoipa::Component;
+
+/// The name of the paged list of users, its cursors are only valid for it.
+const USERS_LIST: &str = "users";
+
+/// List a page of the registered users, ordered by id. Only for admins.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `admin` - The admin that makes the request.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of users.
+///
+/// # Return
+/// * 202 Accepted and the page of users, with the `Link` header of the next and
+///   previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't an admin.
+/// * 500 Internal error for any other error.
+#[utoipa::path(
+context_path = "/admin",
+params(
+  ("cursor" = Option<String>, query, description = "The cursor of the page"),
+  ("limit" = Option<i64>, query, description = "The max quantity of users"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = AdminUserPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user isn't an admin"),
+(status = 500, description = "Internal error")
+),
+)]
+#[get("/users?<cursor>&<limit>")]
+pub fn list_users(
+  us_state: State<Box<dyn UserService>>,
+  cursors: State<Cursors>,
+  admin: AdminUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<AdminUserPageDto>>>> {
+  let user_service = us_state.inner();
+  let request = cursors.page_request(USERS_LIST, cursor, limit)?;
+
+  let page = user_service.users(request).map_err(|err| {
+    log::error!("error: {}", err.to_string());
+    let err_msg = format!("Cannot retrieve the users because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  log::info!("admin {} listed the users", admin.get_id());
+
+  let users_dto = page
+    .get_items()
+    .iter()
+    .map(AdminUserDto::from)
+    .collect::<Vec<AdminUserDto>>();
+  let links = cursors.links(USERS_LIST, request, &page);
+  let page_dto = AdminUserPageDto::new(users_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Suspend a user, closing all of its sessions. Only for admins.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `auth_state` - The authenticator that caches the tokens of the user.
+/// * `admin` - The admin that makes the request.
+/// * `id` - The id of the user to suspend.
+///
+/// # Return
+/// * 202 Accepted and the suspended user.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't an admin.
+/// * 404 Not found if the user doesn't exist.
+#[utoipa::path(
+context_path = "/admin",
+params(
+  ("id" = i32, description = "The id of the user"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 202, description = "The user was suspended", body = AdminUserDto),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user isn't an admin"),
+(status = 404, description = "User not found")
+),
+)]
+#[put("/users/<id>/suspend")]
+pub fn suspend_user(
+  us_state: State<Box<dyn UserService>>,
+  auth_state: State<Box<dyn Authenticator>>,
+  admin: AdminUser,
+  id: i32,
+) -> ApplicationResult<Accepted<Json<AdminUserDto>>> {
+  let user_service = us_state.inner();
+  let authenticator = auth_state.inner();
+
+  let user = user_service.suspend_user(id, true).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot suspend the user because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  authenticator.forget_user(id);
+  log::info!("admin {} suspended the user {}", admin.get_id(), id);
+  Ok(Accepted(Option::from(Json(AdminUserDto::from(&user)))))
+}
+
+/// Reinstate a suspended user, so it can login again. Only for admins.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `admin` - The admin that makes the request.
+/// * `id` - The id of the user to reinstate.
+///
+/// # Return
+/// * 202 Accepted and the reinstated user.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't an admin.
+/// * 404 Not found if the user doesn't exist.
+#[utoipa::path(
+context_path = "/admin",
+params(
+  ("id" = i32, description = "The id of the user"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 202, description = "The user was reinstated", body = AdminUserDto),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user isn't an admin"),
+(status = 404, description = "User not found")
+),
+)]
+#[delete("/users/<id>/suspend")]
+pub fn reinstate_user(
+  us_state: State<Box<dyn UserService>>,
+  admin: AdminUser,
+  id: i32,
+) -> ApplicationResult<Accepted<Json<AdminUserDto>>> {
+  let user_service = us_state.inner();
+
+  let user = user_service.suspend_user(id, false).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot reinstate the user because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  log::info!("admin {} reinstated the user {}", admin.get_id(), id);
+  Ok(Accepted(Option::from(Json(AdminUserDto::from(&user)))))
+}
+
+/// Get any message from its id, regardless of its sender and recipient. Only
+/// for admins.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `admin` - The admin that makes the request.
+/// * `id` - The message id to retrieve.
+///
+/// # Return
+/// * 202 Accepted and the message.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't an admin.
+/// * 404 Not found if the message doesn't exist.
+#[utoipa::path(
+context_path = "/admin",
+params(
+  ("id" = i32, description = "The id of the message"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = AdminMessageDto),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user isn't an admin"),
+(status = 404, description = "Message not found")
+),
+)]
+#[get("/messages/<id>")]
+pub fn get_any_message(
+  msg_state: State<Box<dyn MessageService>>,
+  admin: AdminUser,
+  id: i32,
+) -> ApplicationResult<Accepted<Json<AdminMessageDto>>> {
+  let message_service = msg_state.inner();
+
+  let msg = message_service.get(id).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot retrieve the message because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  log::info!("admin {} read the message {}", admin.get_id(), id);
+  Ok(Accepted(Option::from(Json(AdminMessageDto::from(&msg)))))
+}
+
+#[derive(Serialize, Component)]
+#[component(
+  example = json!({"id": 1, "username": "juan", "role": "user", "suspended": false})
+)]
+pub struct AdminUserDto {
+  id: i32,
+  username: String,
+  role: String,
+  suspended: bool,
+}
+
+/// A page of users, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{"id": 1, "username": "juan", "role": "user", "suspended": false}],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct AdminUserPageDto {
+  items: Vec<AdminUserDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl AdminUserPageDto {
+  pub fn new(the_items: Vec<AdminUserDto>, links: &PageLinks) -> Self {
+    AdminUserPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&User> for AdminUserDto {
+  fn from(user: &User) -> Self {
+    AdminUserDto {
+      id: user.get_id(),
+      username: user.get_username(),
+      role: user.get_role().to_string(),
+      suspended: user.is_suspended(),
+    }
+  }
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({"id": 1, "from": 1, "to": 2, "message": "something"}))]
+pub struct AdminMessageDto {
+  id: i32,
+  from: i32,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  to: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  conversation_id: Option<i32>,
+  message: String,
+}
+
+impl From<&Message> for AdminMessageDto {
+  fn from(msg: &Message) -> Self {
+    AdminMessageDto {
+      id: msg.get_id(),
+      from: msg.get_from(),
+      to: msg.get_to(),
+      conversation_id: msg.get_conversation_id(),
+      message: msg.get_message(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::{error::Error, token::MockAuthenticator},
+    model::{
+      page::{Page, PageRequest},
+      role::Role,
+      user::Builder,
+      user_service::MockUserService,
+    },
+  };
+  use mockall::predicate::{always, eq};
+  use rocket::{
+    http::{Header, Status},
+    local::Client,
+  };
+
+  #[test]
+  fn list_users_ok() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_users()
+      .with(eq(PageRequest::first(None)))
+      .times(1)
+      .returning(|page| {
+        let admin = Builder::new()
+          .with_id(1)
+          .with_username("juan")
+          .with_hashed_password("password")
+          .with_role(Role::Admin)
+          .build();
+        Ok(Page::from_rows(vec![admin], page, User::get_id))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_require_role()
+      .with(always(), eq(Role::Admin))
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/admin", routes![list_users,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/admin/users")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"items\":[{\"id\":1,\"username\":\"juan\",\"role\":\"admin\",\"\
+         suspended\":false}]}"
+      ))
+    )
+  }
+
+  #[test]
+  fn list_users_forbidden() {
+    let mut mock_us = MockUserService::new();
+    mock_us.expect_users().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_require_role()
+      .times(1)
+      .returning(|_, _| Err(Error::NoPermissionError));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/admin", routes![list_users,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get("/admin/users")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+
+  #[test]
+  fn suspend_user_forgets_the_tokens() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_suspend_user()
+      .with(eq(2), eq(true))
+      .times(1)
+      .returning(|_, _| {
+        Ok(
+          Builder::new()
+            .with_id(2)
+            .with_username("pedro")
+            .with_hashed_password("password")
+            .with_suspended(true)
+            .build(),
+        )
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_require_role()
+      .times(1)
+      .returning(|_, _| Ok(1));
+    mock_auth
+      .expect_forget_user()
+      .with(eq(2))
+      .times(1)
+      .returning(|_| ());
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/admin", routes![suspend_user,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .put("/admin/users/2/suspend")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"id\":2,\"username\":\"pedro\",\"role\":\"user\",\"suspended\":\
+         true}"
+      ))
+    )
+  }
+}
@@ -0,0 +1,744 @@
+use crate::{
+  application::error::{ApplicationResult, ErrorResponse},
+  auth::middleware::AuthenticatedUser,
+  model::{
+    attachment::Attachment,
+    thumbnail::{ThumbnailSize, ThumbnailState},
+  },
+  AttachmentService, MessageService,
+};
+
+use multipart::server::Multipart;
+use rocket::{
+  http::{hyper::StatusCode, ContentType, Status},
+  request::{FromRequest, Outcome},
+  response::{self, status::Created, Responder, Response},
+  Data, Request, State,
+};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use std::io::{self, Cursor, Read};
+use utoipa::Component;
+
+/// The name of the part of the multipart body with the file.
+const FILE_FIELD: &str = "file";
+/// The bytes read around the file in a multipart body, for the boundaries
+/// and the headers of the parts.
+const MULTIPART_OVERHEAD: u64 = 64 * 1024;
+/// How long the browsers keep a thumbnail, only for the user who got it.
+/// Its content never changes, but the user could lose the access to it.
+const THUMBNAIL_CACHE_CONTROL: &str = "private, max-age=86400";
+
+/// Upload a file of the owner of the access token, to attach it to one of
+/// its next messages with the `attachments` of the message. The body is a
+/// `multipart/form-data` with the file in the `file` part.
+///
+/// # Arguments
+/// * `attachment_state` - The attachment service.
+/// * `user` - The authenticated user who uploads the file.
+/// * `content_type` - The content type of the body, with its boundary.
+/// * `data` - The multipart body.
+///
+/// # Return
+/// * 201 Created and the attachment.
+/// * 400 Bad request if the body isn't valid or the file is empty.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 413 Payload too large if the file is too big or the quota of the user is
+///   full.
+/// * 415 Unsupported media type if the body isn't multipart.
+#[utoipa::path(
+context_path = "/attachments",
+params(
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 201, description = "The file was uploaded", body = AttachmentDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 413, description = "The file is too big", body = ErrorResponse),
+(status = 415, description = "The body isn't multipart/form-data")
+),
+)]
+#[post("/", data = "<data>")]
+pub fn upload_attachment(
+  attachment_state: State<Box<dyn AttachmentService>>,
+  user: AuthenticatedUser,
+  content_type: &ContentType,
+  data: Data,
+) -> ApplicationResult<Created<Json<AttachmentDto>>> {
+  let attachment_service = attachment_state.inner();
+  let (filename, mime_type, content) =
+    read_file(content_type, data, attachment_service.max_size())?;
+
+  let attachment = attachment_service
+    .upload(user.get_id(), filename, mime_type, content)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot upload the file because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let dto = AttachmentDto::from(&attachment);
+  Ok(Created(dto.url.to_string(), Option::from(Json(dto))))
+}
+
+/// Download an attachment. Only its uploader and the readers of its message
+/// can get it, and not after the message is deleted.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `attachment_state` - The attachment service.
+/// * `user` - The authenticated user who downloads the file.
+/// * `id` - The id of the attachment.
+///
+/// # Return
+/// * 200 Ok and the content of the file, with its MIME type.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the message belongs to other users.
+/// * 404 Not found if the attachment doesn't exist.
+#[utoipa::path(
+context_path = "/attachments",
+params(
+("id" = i32, description = "The id of the attachment"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 200, description = "The content of the file"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The message belongs to other users"),
+(status = 404, description = "Attachment not found", body = ErrorResponse)
+),
+)]
+#[get("/<id>")]
+pub fn download_attachment(
+  msg_state: State<Box<dyn MessageService>>,
+  attachment_state: State<Box<dyn AttachmentService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<AttachmentFile> {
+  let uid = user.get_id();
+
+  let attachment = msg_state.attachment(id, uid).map_err(|err| {
+    log::warn!("user {} cannot get the attachment {}: {}", uid, id, err);
+    let err_msg = format!("Cannot retrieve the attachment because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  let content = attachment_state.content(&attachment).map_err(|err| {
+    log::error!("error: {}", err.to_string());
+    let err_msg = format!("Cannot retrieve the file because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  Ok(AttachmentFile {
+    filename: attachment.get_filename(),
+    mime_type: attachment.get_mime_type(),
+    content,
+  })
+}
+
+/// Download a thumbnail of an image attachment, with the same access as the
+/// attachment. A thumbnail never changes, its `ETag` is the hash of its
+/// content and the browsers can keep it for a day.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `attachment_state` - The attachment service.
+/// * `user` - The authenticated user who downloads the thumbnail.
+/// * `if_none_match` - The entity tags the client already has.
+/// * `id` - The id of the attachment.
+/// * `size` - The size of the thumbnail: small, medium or large.
+///
+/// # Return
+/// * 200 Ok and the thumbnail, a JPEG or a PNG.
+/// * 304 Not modified if the client has it.
+/// * 400 Bad request if the size doesn't exist.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the message belongs to other users.
+/// * 404 Not found if the attachment doesn't exist, isn't an image or its
+///   thumbnails aren't ready yet.
+#[utoipa::path(
+context_path = "/attachments",
+params(
+("id" = i32, description = "The id of the attachment"),
+("size" = String, description = "The size: small, medium or large"),
+("Authorization", header, description = "The token access"),
+("If-None-Match", header, description = "The ETag of a previous download"),
+),
+responses(
+(status = 200, description = "The content of the thumbnail"),
+(status = 304, description = "The thumbnail didn't change"),
+(status = 400, description = "Unknown size", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The message belongs to other users"),
+(status = 404, description = "Thumbnail not found", body = ErrorResponse)
+),
+)]
+#[get("/<id>/thumb/<size>")]
+pub fn download_thumbnail(
+  msg_state: State<Box<dyn MessageService>>,
+  attachment_state: State<Box<dyn AttachmentService>>,
+  user: AuthenticatedUser,
+  if_none_match: IfNoneMatch,
+  id: i32,
+  size: String,
+) -> ApplicationResult<ThumbnailFile> {
+  let uid = user.get_id();
+
+  let attachment = msg_state.attachment(id, uid).map_err(|err| {
+    log::warn!("user {} cannot get the attachment {}: {}", uid, id, err);
+    let err_msg = format!("Cannot retrieve the attachment because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  let thumbnail =
+    attachment_state
+      .thumbnail(&attachment, size)
+      .map_err(|err| {
+        log::debug!("{}", err.to_string());
+        let err_msg = format!("Cannot retrieve the thumbnail because {}", err);
+        ErrorResponse::from_service_error(&err_msg, &err)
+      })?;
+  let etag = format!("\"{}\"", thumbnail.get_hash());
+  if if_none_match.matches(&etag) {
+    return Ok(ThumbnailFile {
+      etag,
+      content: None,
+    });
+  }
+  let content =
+    attachment_state
+      .thumbnail_content(&thumbnail)
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        let err_msg = format!("Cannot retrieve the thumbnail because {}", err);
+        ErrorResponse::from_service_error(&err_msg, &err)
+      })?;
+  Ok(ThumbnailFile {
+    etag,
+    content: Some((thumbnail.get_mime_type(), content)),
+  })
+}
+
+/// Reads the file of a multipart body. The body is read up to the biggest
+/// file, a bigger one is cut to be rejected by its size.
+///
+/// # Arguments
+/// * `content_type` - The content type of the body.
+/// * `data` - The body.
+/// * `max_size` - The size of the biggest file.
+///
+/// # Return
+/// * The name, the MIME type and the content of the file.
+/// * 400 Bad request if the body isn't valid or doesn't have a file.
+/// * 415 Unsupported media type if the body isn't multipart.
+fn read_file(
+  content_type: &ContentType,
+  data: Data,
+  max_size: i64,
+) -> ApplicationResult<(String, String, Vec<u8>)> {
+  let boundary = content_type
+    .params()
+    .find(|(name, _)| *name == "boundary")
+    .map(|(_, boundary)| boundary)
+    .filter(|_| content_type.is_form_data())
+    .ok_or_else(|| {
+      ErrorResponse::create_error(
+        "The body must be multipart/form-data",
+        StatusCode::UnsupportedMediaType,
+      )
+    })?;
+  let malformed = |err: io::Error| {
+    log::debug!("{}", err.to_string());
+    ErrorResponse::create_error(
+      "The multipart body isn't valid",
+      StatusCode::BadRequest,
+    )
+  };
+
+  let limit = max_size as u64 + 1;
+  let body = data.open().take(limit + MULTIPART_OVERHEAD);
+  let mut multipart = Multipart::with_body(body, boundary);
+  while let Some(mut field) = multipart.read_entry().map_err(malformed)? {
+    if &*field.headers.name != FILE_FIELD {
+      continue;
+    }
+    let mut content = Vec::new();
+    (&mut field.data)
+      .take(limit)
+      .read_to_end(&mut content)
+      .map_err(malformed)?;
+    let filename = field.headers.filename.clone().unwrap_or_default();
+    let mime_type = field
+      .headers
+      .content_type
+      .as_ref()
+      .map_or("application/octet-stream", |mime| mime.essence_str())
+      .to_string();
+    return Ok((filename, mime_type, content));
+  }
+  Err(ErrorResponse::create_error(
+    "The body doesn't have a file part",
+    StatusCode::BadRequest,
+  ))
+}
+
+/// The content of an attachment, downloaded as a file. The browsers don't
+/// guess another type, so an uploaded page isn't shown as a page.
+pub struct AttachmentFile {
+  filename: String,
+  mime_type: String,
+  content: Vec<u8>,
+}
+
+impl<'r> Responder<'r> for AttachmentFile {
+  fn respond_to(self, _: &Request) -> response::Result<'r> {
+    let content_type = ContentType::parse_flexible(&self.mime_type)
+      .unwrap_or(ContentType::Binary);
+    Response::build()
+      .header(content_type)
+      .raw_header("Content-Disposition", content_disposition(&self.filename))
+      .raw_header("X-Content-Type-Options", "nosniff")
+      .sized_body(Cursor::new(self.content))
+      .ok()
+  }
+}
+
+/// The `If-None-Match` header of a request, with the entity tags of the
+/// responses the client keeps.
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch {
+  /// Checks if the client has a response.
+  ///
+  /// # Arguments
+  /// * `etag` - The entity tag of the response, quoted.
+  ///
+  /// # Return
+  /// * True if the header has the tag, weak or not, or is `*`.
+  pub fn matches(&self, etag: &str) -> bool {
+    self.0.as_deref().map_or(false, |tags| {
+      tags
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+    })
+  }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+  type Error = ();
+
+  fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+    let tags = request.headers().get_one("If-None-Match").map(String::from);
+    Outcome::Success(IfNoneMatch(tags))
+  }
+}
+
+/// A thumbnail, the browsers keep it and ask again with its entity tag.
+pub struct ThumbnailFile {
+  etag: String,
+  /// The MIME type and the content, none when the client already has them.
+  content: Option<(String, Vec<u8>)>,
+}
+
+impl<'r> Responder<'r> for ThumbnailFile {
+  fn respond_to(self, _: &Request) -> response::Result<'r> {
+    let mut response = Response::build();
+    response
+      .raw_header("ETag", self.etag)
+      .raw_header("Cache-Control", THUMBNAIL_CACHE_CONTROL);
+    match self.content {
+      Some((mime_type, content)) => {
+        response
+          .header(
+            ContentType::parse_flexible(&mime_type)
+              .unwrap_or(ContentType::Binary),
+          )
+          .raw_header("X-Content-Type-Options", "nosniff")
+          .sized_body(Cursor::new(content));
+      },
+      None => {
+        response.status(Status::NotModified);
+      },
+    }
+    response.ok()
+  }
+}
+
+/// Builds the `Content-Disposition` of a download, as defined by the RFC
+/// 6266: an ASCII name for the old clients and the UTF-8 one.
+///
+/// # Arguments
+/// * `filename` - The name of the file.
+///
+/// # Return
+/// * The value of the header.
+fn content_disposition(filename: &str) -> String {
+  let ascii = filename
+    .chars()
+    .map(|c| match c {
+      ' '..='~' if c != '"' && c != '\\' => c,
+      _ => '_',
+    })
+    .collect::<String>();
+  let encoded = filename
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' => {
+        (byte as char).to_string()
+      },
+      _ => format!("%{:02X}", byte),
+    })
+    .collect::<String>();
+  format!(
+    "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+    ascii, encoded
+  )
+}
+
+/// A file attached to a message, downloaded from its URL. The images also
+/// have their dimensions, and the URLs of their thumbnails once they are
+/// ready.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "id": 1,
+  "filename": "photo.png",
+  "mime_type": "image/png",
+  "size": 52340,
+  "url": "/attachments/1",
+  "width": 1600,
+  "height": 1200,
+  "thumbnails": {
+    "small": "/attachments/1/thumb/small",
+    "medium": "/attachments/1/thumb/medium",
+    "large": "/attachments/1/thumb/large"
+  }
+}))]
+pub struct AttachmentDto {
+  id: i32,
+  filename: String,
+  mime_type: String,
+  size: i64,
+  url: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  width: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  height: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  thumbnails: Option<ThumbnailsDto>,
+}
+
+impl From<&Attachment> for AttachmentDto {
+  fn from(attachment: &Attachment) -> Self {
+    let thumbnails = match attachment.get_thumbnail_state() {
+      Some(ThumbnailState::Ready) => Some(ThumbnailsDto::new(attachment)),
+      _ => None,
+    };
+    AttachmentDto {
+      id: attachment.get_id(),
+      filename: attachment.get_filename(),
+      mime_type: attachment.get_mime_type(),
+      size: attachment.get_size(),
+      url: format!("/attachments/{}", attachment.get_id()),
+      width: attachment.get_width(),
+      height: attachment.get_height(),
+      thumbnails,
+    }
+  }
+}
+
+/// The URLs of the thumbnails of an image.
+#[derive(Serialize, Component)]
+pub struct ThumbnailsDto {
+  small: String,
+  medium: String,
+  large: String,
+}
+
+impl ThumbnailsDto {
+  fn new(attachment: &Attachment) -> Self {
+    let url = |size: ThumbnailSize| {
+      format!("/attachments/{}/thumb/{}", attachment.get_id(), size)
+    };
+    ThumbnailsDto {
+      small: url(ThumbnailSize::Small),
+      medium: url(ThumbnailSize::Medium),
+      large: url(ThumbnailSize::Large),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::token::MockAuthenticator,
+    model::{
+      attachment::Builder, attachment_service::MockAttachmentService,
+      error::Error as ServiceError, message_service::MockMessageService,
+      thumbnail::Builder as ThumbnailBuilder,
+    },
+    Authenticator,
+  };
+  use mockall::predicate::eq;
+  use rocket::{
+    http::{Header, Status},
+    local::Client,
+  };
+
+  fn client_with(
+    mock_ms: MockMessageService,
+    mock_as: MockAttachmentService,
+  ) -> Client {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_as) as Box<dyn AttachmentService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount(
+        "/attachments",
+        routes![upload_attachment, download_attachment, download_thumbnail],
+      );
+    Client::new(rocket).expect("valid rocket instance")
+  }
+
+  fn form_data() -> ContentType {
+    ContentType::with_params("multipart", "form-data", ("boundary", "XYZ"))
+  }
+
+  fn multipart_body(part: &str, content: &str) -> String {
+    format!(
+      "--XYZ\r\nContent-Disposition: form-data; name=\"{}\"; \
+       filename=\"notes.txt\"\r\nContent-Type: \
+       text/plain\r\n\r\n{}\r\n--XYZ--\r\n",
+      part, content
+    )
+  }
+
+  #[test]
+  fn upload_a_file() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as.expect_max_size().returning(|| 1024);
+    mock_as
+      .expect_upload()
+      .with(
+        eq(1),
+        eq(String::from("notes.txt")),
+        eq(String::from("text/plain")),
+        eq(b"some notes".to_vec()),
+      )
+      .times(1)
+      .returning(|uid, _, _, _| {
+        Ok(
+          Builder::new()
+            .with_id(3)
+            .with_user_id(uid)
+            .with_file("notes.txt", "text/plain")
+            .build(),
+        )
+      });
+
+    let client = client_with(MockMessageService::new(), mock_as);
+    let mut response = client
+      .post("/attachments")
+      .header(form_data())
+      .header(Header::new("Authorization", "Bearer 1"))
+      .body(multipart_body("file", "some notes"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"id\":3,\"filename\":\"notes.txt\",\"mime_type\":\"text/plain\",\"\
+         size\":7,\"url\":\"/attachments/3\"}"
+      ))
+    );
+  }
+
+  #[test]
+  fn upload_without_a_file_part() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as.expect_max_size().returning(|| 1024);
+    mock_as.expect_upload().times(0);
+
+    let client = client_with(MockMessageService::new(), mock_as);
+    let response = client
+      .post("/attachments")
+      .header(form_data())
+      .header(Header::new("Authorization", "Bearer 1"))
+      .body(multipart_body("photo", "some notes"))
+      .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+  }
+
+  #[test]
+  fn upload_a_file_over_the_quota() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as.expect_max_size().returning(|| 1024);
+    mock_as.expect_upload().times(1).returning(|_, _, _, _| {
+      Err(ServiceError::LimitExceeded(String::from(
+        "the quota is full",
+      )))
+    });
+
+    let client = client_with(MockMessageService::new(), mock_as);
+    let response = client
+      .post("/attachments")
+      .header(form_data())
+      .header(Header::new("Authorization", "Bearer 1"))
+      .body(multipart_body("file", "some notes"))
+      .dispatch();
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+  }
+
+  #[test]
+  fn download_a_file() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_attachment()
+      .with(eq(3), eq(1))
+      .times(1)
+      .returning(|id, _| {
+        Ok(
+          Builder::new()
+            .with_id(id)
+            .with_user_id(2)
+            .with_message_id(10)
+            .with_file("résumé.html", "text/html")
+            .build(),
+        )
+      });
+    let mut mock_as = MockAttachmentService::new();
+    mock_as
+      .expect_content()
+      .times(1)
+      .returning(|_| Ok(b"<p>hi</p>".to_vec()));
+
+    let client = client_with(mock_ms, mock_as);
+    let mut response = client
+      .get("/attachments/3")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+    assert_eq!(
+      response.headers().get_one("Content-Disposition"),
+      Some(
+        "attachment; filename=\"r_sum_.html\"; \
+         filename*=UTF-8''r%C3%A9sum%C3%A9.html"
+      )
+    );
+    assert_eq!(
+      response.headers().get_one("X-Content-Type-Options"),
+      Some("nosniff")
+    );
+    assert_eq!(response.body_string(), Some(String::from("<p>hi</p>")));
+  }
+
+  #[test]
+  fn download_a_file_of_other_users() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_attachment().times(1).returning(|_, _| {
+      Err(ServiceError::Forbidden(String::from(
+        "The message belongs to other users",
+      )))
+    });
+    let mut mock_as = MockAttachmentService::new();
+    mock_as.expect_content().times(0);
+
+    let client = client_with(mock_ms, mock_as);
+    let response = client
+      .get("/attachments/3")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+
+  fn mock_image_attachment() -> MockMessageService {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_attachment()
+      .with(eq(3), eq(1))
+      .times(1)
+      .returning(|id, _| {
+        Ok(
+          Builder::new()
+            .with_id(id)
+            .with_image(1600, 1200, ThumbnailState::Ready)
+            .build(),
+        )
+      });
+    mock_ms
+  }
+
+  #[test]
+  fn download_a_thumbnail() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as
+      .expect_thumbnail()
+      .withf(|attachment, size| attachment.get_id() == 3 && size == "small")
+      .times(1)
+      .returning(|_, _| {
+        Ok(ThumbnailBuilder::new().with_attachment_id(3).build())
+      });
+    mock_as
+      .expect_thumbnail_content()
+      .times(1)
+      .returning(|_| Ok(b"thumbnail".to_vec()));
+
+    let etag = format!("\"{}\"", ThumbnailBuilder::new().build().get_hash());
+
+    let client = client_with(mock_image_attachment(), mock_as);
+    let mut response = client
+      .get("/attachments/3/thumb/small")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JPEG));
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert_eq!(
+      response.headers().get_one("Cache-Control"),
+      Some(THUMBNAIL_CACHE_CONTROL)
+    );
+    assert_eq!(response.body_string(), Some(String::from("thumbnail")));
+  }
+
+  #[test]
+  fn download_a_thumbnail_not_modified() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as
+      .expect_thumbnail()
+      .times(1)
+      .returning(|_, _| Ok(ThumbnailBuilder::new().build()));
+    mock_as.expect_thumbnail_content().times(0);
+
+    let client = client_with(mock_image_attachment(), mock_as);
+    let etag = format!("W/\"{}\"", ThumbnailBuilder::new().build().get_hash());
+    let mut response = client
+      .get("/attachments/3/thumb/small")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .header(Header::new("If-None-Match", etag))
+      .dispatch();
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.body_string(), None);
+  }
+
+  #[test]
+  fn download_a_thumbnail_not_ready() {
+    let mut mock_as = MockAttachmentService::new();
+    mock_as
+      .expect_thumbnail()
+      .times(1)
+      .returning(|_, _| Err(ServiceError::NotFound("thumbnail")));
+    mock_as.expect_thumbnail_content().times(0);
+
+    let client = client_with(mock_image_attachment(), mock_as);
+    let response = client
+      .get("/attachments/3/thumb/large")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+  }
+}
@@ -0,0 +1,303 @@
+use crate::{
+  application::{
+    error::{ApplicationResult, ErrorResponse},
+    message_handler::{
+      message_attachments, sent_receipts, MessagePageDto, ResponseMessageDto,
+    },
+    pagination::{Cursors, Paginated},
+  },
+  auth::middleware::AuthenticatedUser,
+  MessageService,
+};
+
+use rocket::{
+  response::status::{Accepted, NoContent},
+  State,
+};
+use rocket_contrib::json::Json;
+use serde::Deserialize;
+use utoipa::Component;
+
+/// The name of the paged list of a conversation, its cursors are only valid
+/// for it.
+const CONVERSATION_LIST: &str = "conversation";
+
+/// Get a page of the messages exchanged between the owner of the access
+/// token and another user, in both directions. The received messages are
+/// marked as delivered, and the sent ones have their delivery and read dates.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user who reads the conversation.
+/// * `user_id` - The id of the other user of the conversation.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of messages.
+///
+/// # Return
+/// * 202 Accepted and the page of messages order by id in desc mode, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/conversations",
+params(
+("user_id" = i32, description = "The id of the other user"),
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = MessagePageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/<user_id>?<cursor>&<limit>")]
+pub fn get_conversation(
+  msg_state: State<Box<dyn MessageService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  user_id: i32,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<MessagePageDto>>>> {
+  let message_service = msg_state.inner();
+  let request = cursors.page_request(CONVERSATION_LIST, cursor, limit)?;
+  let page = message_service
+    .conversation(user.get_id(), user_id, request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the conversation because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let receipts =
+    sent_receipts(message_service.as_ref(), user.get_id(), page.get_items())?;
+  let attachments =
+    message_attachments(message_service.as_ref(), page.get_items())?;
+  let messages_dto = page
+    .get_items()
+    .iter()
+    .map(|a_msg| {
+      ResponseMessageDto::from(a_msg)
+        .with_receipt(receipts.get(&a_msg.get_id()))
+        .with_attachments(attachments.get(&a_msg.get_id()))
+    })
+    .collect::<Vec<ResponseMessageDto>>();
+  let links = cursors.links(CONVERSATION_LIST, request, &page);
+  let page_dto = MessagePageDto::new(messages_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Mark as read the messages the other user of the conversation sent to the
+/// owner of the access token, up to the message indicated.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `user` - The authenticated user who read the messages.
+/// * `user_id` - The id of the other user of the conversation.
+/// * `read_dto` - The id of the last message read.
+///
+/// # Return
+/// * 204 No content if the messages were marked as read.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/conversations",
+request_body = ReadConversationDto,
+params(
+("user_id" = i32, description = "The id of the other user"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The messages were marked as read"),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[post("/<user_id>/read", format = "application/json", data = "<read_dto>")]
+pub fn read_conversation(
+  msg_state: State<Box<dyn MessageService>>,
+  user: AuthenticatedUser,
+  user_id: i32,
+  read_dto: Json<ReadConversationDto>,
+) -> ApplicationResult<NoContent> {
+  let message_service = msg_state.inner();
+
+  let read = message_service
+    .mark_conversation_read(user.get_id(), user_id, read_dto.until)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg =
+        format!("Cannot mark the conversation as read because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  log::debug!("user {} read {} messages", user.get_id(), read);
+  Ok(NoContent)
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"until": 4}))]
+pub struct ReadConversationDto {
+  until: i32,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    application::error::unauthorized,
+    auth::{error::Error::RevokedTokenError, token::MockAuthenticator},
+    model::{
+      message::{Builder, Message},
+      message_service::MockMessageService,
+      page::{Direction, Page, PageRequest},
+    },
+    Authenticator,
+  };
+  use mockall::predicate::eq;
+  use rocket::{
+    http::{ContentType, Header, Status},
+    local::Client,
+  };
+
+  #[test]
+  fn get_conversation_ok() {
+    let received = Builder::new()
+      .with_id(4)
+      .with_from(2)
+      .with_to(1)
+      .with_message("Hello")
+      .build();
+    let sent = Builder::new()
+      .with_id(3)
+      .with_from(1)
+      .with_to(2)
+      .with_message("Hi")
+      .build();
+
+    let cursors = Cursors::new(b"secret");
+    let cursor = cursors.encode(CONVERSATION_LIST, Direction::After, 5);
+    let request = PageRequest::next_to(Direction::After, 5, Some(2));
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_conversation()
+      .with(eq(1), eq(2), eq(request))
+      .times(1)
+      .returning(move |_, _, page| {
+        let rows = vec![received.clone(), sent.clone()];
+        Ok(Page::from_rows(rows, page, Message::get_id))
+      });
+    mock_ms
+      .expect_receipts()
+      .with(eq(vec![3]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![4, 3]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/conversations", routes![get_conversation,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get(format!("/conversations/2?cursor={}&limit=2", cursor))
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    let prev = cursors.encode(CONVERSATION_LIST, Direction::Before, 4);
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.headers().get_one("Link"),
+      Some(
+        format!("</conversations/2?cursor={}&limit=2>; rel=\"prev\"", prev)
+          .as_str()
+      )
+    );
+    assert_eq!(
+      response.body_string(),
+      Some(format!(
+        "{{\"items\":[{{\"id\":4,\"from\":2,\"to\":1,\"message\":\"Hello\",\"\
+         created_at\":\"1970-01-01T00:00:00+00:00\",\"updated_at\":\"\
+         1970-01-01T00:00:00+00:00\"}},{{\"id\":3,\"from\":1,\"to\":2,\"\
+         message\":\"Hi\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"\
+         updated_at\":\"1970-01-01T00:00:00+00:00\"}}],\"prev_cursor\":\"{}\"\
+         }}",
+        prev
+      ))
+    )
+  }
+
+  #[test]
+  fn get_conversation_unauthorized() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_conversation().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/conversations", routes![get_conversation,])
+      .register(catchers![unauthorized]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get("/conversations/2")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+  }
+
+  #[test]
+  fn read_conversation_ok() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_mark_conversation_read()
+      .with(eq(1), eq(2), eq(4))
+      .times(1)
+      .returning(|_, _, _| Ok(2));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/conversations", routes![read_conversation,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/conversations/2/read")
+      .body(r#"{ "until": 4}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+  }
+}
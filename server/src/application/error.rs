@@ -1,42 +1,312 @@
-use rocket::{http::hyper::StatusCode, Responder};
+use crate::model::error::Error as ServiceError;
+use rocket::{
+  http::{hyper::StatusCode, ContentType, Status},
+  response::{self, Responder, Response},
+  Request,
+};
 use rocket_contrib::json::Json;
 use serde::Serialize;
 use std::collections::HashMap;
+use utoipa::Component;
 
 pub type GenericResponse = HashMap<String, String>;
 pub type ApplicationResult<T> = Result<T, Error>;
 
-#[derive(Debug, Serialize, Responder)]
-pub struct ErrorResponse {
+/// The prefix of the problem types, a consumer can branch on the full type.
+pub const PROBLEM_TYPE_PREFIX: &str = "/problems/";
+
+/// The type of the problems that doesn't have a more specific one.
+const ABOUT_BLANK: &str = "about:blank";
+
+/// A field of the request body that didn't pass the validation.
+#[derive(Debug, Clone, PartialEq, Serialize, Component)]
+#[component(example = json!({"field": "username", "message": "is empty"}))]
+pub struct FieldError {
+  pub field: String,
   pub message: String,
 }
 
+impl FieldError {
+  pub fn new(field: &str, message: &str) -> Self {
+    FieldError {
+      field: field.to_string(),
+      message: message.to_string(),
+    }
+  }
+}
+
+/// The body of every error response, a problem detail as defined by the RFC
+/// 7807. It's sent with the `application/problem+json` content type.
+#[derive(Debug, Serialize, Component)]
+#[component(
+  example = json!({
+    "type": "/problems/not-found",
+    "title": "Not Found",
+    "status": 404,
+    "detail": "Cannot retrieve the message because message not found",
+    "instance": "/message/1"
+  })
+)]
+pub struct ErrorResponse {
+  #[serde(rename = "type")]
+  pub problem_type: String,
+  pub title: String,
+  pub status: u16,
+  pub detail: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub instance: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub errors: Vec<FieldError>,
+}
+
 impl ErrorResponse {
+  /// Creates an error response for any HTTP status. The problem type is
+  /// derived from the status.
+  ///
+  /// # Arguments
+  /// * `message` - The detail of the problem.
+  /// * `http_status_code` - The HTTP status of the response.
+  ///
+  /// # Return
+  /// * The error response.
   pub fn create_error(message: &str, http_status_code: StatusCode) -> Error {
-    match http_status_code {
-      StatusCode::BadRequest => Error::BadRequestError(Json(ErrorResponse {
-        message: message.to_string(),
-      })),
-
-      StatusCode::Unauthorized => {
-        Error::UnauthorizedError(Json(ErrorResponse {
-          message: message.to_string(),
-        }))
-      },
-
-      _ => Error::StandardError(Json(ErrorResponse {
-        message: message.to_string(),
-      })),
+    let problem_type = match http_status_code.canonical_reason() {
+      Some(reason) => format!("{}{}", PROBLEM_TYPE_PREFIX, slug(reason)),
+      None => String::from(ABOUT_BLANK),
+    };
+    Error(ErrorResponse::new(problem_type, message, http_status_code))
+  }
+
+  /// Creates the error response of a failed service call, with the status
+  /// and the problem type that match the kind of failure.
+  ///
+  /// # Arguments
+  /// * `message` - The detail of the problem.
+  /// * `err` - The error of the service.
+  ///
+  /// # Return
+  /// * The error response.
+  pub fn from_service_error(message: &str, err: &ServiceError) -> Error {
+    let problem_type = format!("{}{}", PROBLEM_TYPE_PREFIX, problem_of(err));
+    Error(ErrorResponse::new(problem_type, message, status_of(err)))
+  }
+
+  /// Creates the error response of a request body that didn't pass the
+  /// validation, listing every invalid field.
+  ///
+  /// # Arguments
+  /// * `message` - The detail of the problem.
+  /// * `errors` - The invalid fields.
+  ///
+  /// # Return
+  /// * The error response.
+  pub fn validation_error(message: &str, errors: Vec<FieldError>) -> Error {
+    let problem_type = format!("{}validation", PROBLEM_TYPE_PREFIX);
+    let mut response = ErrorResponse::new(
+      problem_type,
+      message,
+      StatusCode::UnprocessableEntity,
+    );
+    response.errors = errors;
+    Error(response)
+  }
+
+  fn new(
+    problem_type: String,
+    message: &str,
+    http_status_code: StatusCode,
+  ) -> Self {
+    ErrorResponse {
+      problem_type,
+      title: http_status_code
+        .canonical_reason()
+        .unwrap_or("Unknown Error")
+        .to_string(),
+      status: http_status_code.to_u16(),
+      detail: message.to_string(),
+      instance: None,
+      errors: vec![],
     }
   }
 }
 
-#[derive(Debug, Responder)]
-pub enum Error {
-  #[response(status = 400, content_type = "application/json")]
-  BadRequestError(Json<ErrorResponse>),
-  #[response(status = 401, content_type = "application/json")]
-  UnauthorizedError(Json<ErrorResponse>),
-  #[response(status = 500, content_type = "application/json")]
-  StandardError(Json<ErrorResponse>),
+/// Converts a reason phrase in the last segment of a problem type, like
+/// `Not Found` to `not-found`.
+fn slug(reason: &str) -> String {
+  reason
+    .split_whitespace()
+    .map(|word| {
+      word
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+    })
+    .collect::<Vec<String>>()
+    .join("-")
+}
+
+/// Maps every kind of service error to its HTTP status.
+///
+/// # Arguments
+/// * `err` - The error of the service.
+///
+/// # Return
+/// * The HTTP status.
+pub fn status_of(err: &ServiceError) -> StatusCode {
+  match err {
+    ServiceError::NotFound(_) => StatusCode::NotFound,
+    ServiceError::AlreadyExists(_) => StatusCode::Conflict,
+    ServiceError::InvalidInput(_) => StatusCode::BadRequest,
+    ServiceError::UnknownReference(_) => StatusCode::UnprocessableEntity,
+    ServiceError::InvalidCredentials | ServiceError::Unauthorized(_) => {
+      StatusCode::Unauthorized
+    },
+    ServiceError::Forbidden(_) => StatusCode::Forbidden,
+    ServiceError::LimitExceeded(_) => StatusCode::PayloadTooLarge,
+    ServiceError::Unavailable => StatusCode::ServiceUnavailable,
+    ServiceError::Internal(_) => StatusCode::InternalServerError,
+  }
+}
+
+/// Maps every kind of service error to the last segment of its problem type.
+fn problem_of(err: &ServiceError) -> &'static str {
+  match err {
+    ServiceError::NotFound(_) => "not-found",
+    ServiceError::AlreadyExists(_) => "already-exists",
+    ServiceError::InvalidInput(_) => "invalid-input",
+    ServiceError::UnknownReference(_) => "unknown-reference",
+    ServiceError::InvalidCredentials => "invalid-credentials",
+    ServiceError::Unauthorized(_) => "unauthorized",
+    ServiceError::Forbidden(_) => "forbidden",
+    ServiceError::LimitExceeded(_) => "limit-exceeded",
+    ServiceError::Unavailable => "unavailable",
+    ServiceError::Internal(_) => "internal",
+  }
+}
+
+/// The error of a handler. It responds with the status of the problem and
+/// its `application/problem+json` body, the instance is the requested path
+/// when it isn't set.
+#[derive(Debug)]
+pub struct Error(ErrorResponse);
+
+impl Error {
+  pub fn get_response(&self) -> &ErrorResponse {
+    return &self.0;
+  }
+}
+
+impl<'r> Responder<'r> for Error {
+  fn respond_to(self, request: &Request) -> response::Result<'r> {
+    let mut problem = self.0;
+    if problem.instance.is_none() {
+      problem.instance = Some(request.uri().path().to_string());
+    }
+    let status = Status::from_code(problem.status)
+      .unwrap_or_else(|| Status::new(problem.status, ""));
+
+    Response::build_from(Json(problem).respond_to(request)?)
+      .status(status)
+      .header(ContentType::new("application", "problem+json"))
+      .ok()
+  }
+}
+
+/// Replaces the default body of a 400 response, used when the request can't
+/// be parsed.
+#[catch(400)]
+pub fn bad_request() -> Error {
+  ErrorResponse::create_error("Malformed request", StatusCode::BadRequest)
+}
+
+/// Replaces the default body of a 401 response, used when a request guard
+/// rejects the access token before reaching the handler.
+#[catch(401)]
+pub fn unauthorized() -> Error {
+  ErrorResponse::create_error("Access denied", StatusCode::Unauthorized)
+}
+
+/// Replaces the default body of a 403 response, used when a request guard
+/// rejects the role of the access token before reaching the handler.
+#[catch(403)]
+pub fn forbidden() -> Error {
+  ErrorResponse::create_error("Forbidden", StatusCode::Forbidden)
+}
+
+/// Replaces the default body of a 404 response, used when no route matches.
+#[catch(404)]
+pub fn not_found() -> Error {
+  ErrorResponse::create_error("Resource not found", StatusCode::NotFound)
+}
+
+/// Replaces the default body of a 422 response, used when the body doesn't
+/// match the expected json.
+#[catch(422)]
+pub fn unprocessable_entity() -> Error {
+  ErrorResponse::create_error(
+    "The request body is not valid",
+    StatusCode::UnprocessableEntity,
+  )
+}
+
+/// Replaces the default body of a 500 response.
+#[catch(500)]
+pub fn internal_error() -> Error {
+  ErrorResponse::create_error("Internal error", StatusCode::InternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use rocket::local::Client;
+
+  #[get("/teapot")]
+  fn teapot() -> ApplicationResult<String> {
+    Err(ErrorResponse::create_error(
+      "No coffee",
+      StatusCode::ImATeapot,
+    ))
+  }
+
+  #[test]
+  fn any_status_is_a_problem() {
+    let rocket = rocket::ignite().mount("/", routes![teapot,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client.get("/teapot").dispatch();
+    assert_eq!(response.status().code, 418);
+    assert_eq!(
+      response.content_type(),
+      Some(ContentType::new("application", "problem+json"))
+    );
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"type\":\"/problems/im-a-teapot\",\"title\":\"I'm a \
+         teapot\",\"status\":418,\"detail\":\"No \
+         coffee\",\"instance\":\"/teapot\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn service_errors_have_their_own_type() {
+    let err = ErrorResponse::from_service_error(
+      "Cannot login",
+      &ServiceError::InvalidCredentials,
+    );
+    let problem = err.get_response();
+    assert_eq!(problem.problem_type, "/problems/invalid-credentials");
+    assert_eq!(problem.status, 401);
+    assert_eq!(problem.title, "Unauthorized");
+
+    let err = ErrorResponse::from_service_error(
+      "Cannot upload the file",
+      &ServiceError::LimitExceeded(String::from("the quota is full")),
+    );
+    let problem = err.get_response();
+    assert_eq!(problem.problem_type, "/problems/limit-exceeded");
+    assert_eq!(problem.status, 413);
+  }
 }
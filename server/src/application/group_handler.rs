@@ -0,0 +1,870 @@
+use crate::{
+  application::{
+    error::{ApplicationResult, ErrorResponse, FieldError, GenericResponse},
+    message_handler::{
+      message_attachments, MessagePageDto, ResponseMessageDto,
+    },
+    pagination::{Cursors, PageLinks, Paginated},
+    session_handler::to_rfc3339,
+  },
+  auth::middleware::AuthenticatedUser,
+  model::conversation::{Conversation, Member, MemberRole},
+  ConversationService, MessageService,
+};
+
+use rocket::{
+  response::status::{Accepted, Created, NoContent},
+  State,
+};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::Component;
+
+/// The names of the paged lists, their cursors are only valid for them.
+const GROUPS_LIST: &str = "groups";
+const MEMBERS_LIST: &str = "group-members";
+const GROUP_MESSAGES_LIST: &str = "group-messages";
+
+/// Get a page of the group conversations of the owner of the access token.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of groups.
+///
+/// # Return
+/// * 202 Accepted and the page of groups ordered by id, with the `Link` header
+///   of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/groups",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of groups"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = GroupPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/?<cursor>&<limit>")]
+pub fn list_groups(
+  conv_state: State<Box<dyn ConversationService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<GroupPageDto>>>> {
+  let conversation_service = conv_state.inner();
+  let request = cursors.page_request(GROUPS_LIST, cursor, limit)?;
+
+  let page =
+    conversation_service
+      .list(user.get_id(), request)
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        let err_msg = format!("Cannot retrieve the groups because {}", err);
+        ErrorResponse::from_service_error(&err_msg, &err)
+      })?;
+  let groups_dto = page
+    .get_items()
+    .iter()
+    .map(GroupDto::from)
+    .collect::<Vec<GroupDto>>();
+  let links = cursors.links(GROUPS_LIST, request, &page);
+  let page_dto = GroupPageDto::new(groups_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Create a group conversation owned by the owner of the access token, with
+/// its first members.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `user` - The authenticated user who creates the group.
+/// * `group_dto` - The name and the first members of the group.
+///
+/// # Return
+/// * 201 Created and the id of the group.
+/// * 400 Bad request if the name is empty.
+/// * 401 Unauthorized if the token isn't valid.
+#[utoipa::path(
+context_path = "/groups",
+request_body = CreateGroupDto,
+params(
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 201, description = "The group was created"),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user")
+),
+)]
+#[post("/", format = "application/json", data = "<group_dto>")]
+pub fn create_group(
+  conv_state: State<Box<dyn ConversationService>>,
+  user: AuthenticatedUser,
+  group_dto: Json<CreateGroupDto>,
+) -> ApplicationResult<Created<Json<GenericResponse>>> {
+  let conversation_service = conv_state.inner();
+
+  let group_dto = group_dto.into_inner();
+  let id = conversation_service
+    .create(user.get_id(), group_dto.name, group_dto.members)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot create the group because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let mut response = GenericResponse::new();
+  response.insert(String::from("id"), id.to_string());
+  Ok(Created(
+    format!("/groups/{}", id),
+    Option::from(Json(response)),
+  ))
+}
+
+/// Rename a group conversation. Only its owner and its admins can rename it.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `user` - The authenticated user who renames the group.
+/// * `id` - The id of the group.
+/// * `rename_dto` - The new name.
+///
+/// # Return
+/// * 202 Accepted and the renamed group.
+/// * 400 Bad request if the name is empty.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user is a plain member.
+/// * 404 Not found if the user isn't a member of the group.
+#[utoipa::path(
+context_path = "/groups",
+request_body = RenameGroupDto,
+params(
+("id" = i32, description = "The id of the group"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "The group was renamed", body = GroupDto),
+(status = 400, description = "Bad request"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user cannot manage the group"),
+(status = 404, description = "Group not found")
+),
+)]
+#[put("/<id>", format = "application/json", data = "<rename_dto>")]
+pub fn rename_group(
+  conv_state: State<Box<dyn ConversationService>>,
+  user: AuthenticatedUser,
+  id: i32,
+  rename_dto: Json<RenameGroupDto>,
+) -> ApplicationResult<Accepted<Json<GroupDto>>> {
+  let conversation_service = conv_state.inner();
+
+  let group = conversation_service
+    .rename(id, user.get_id(), rename_dto.name.to_string())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot rename the group because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  Ok(Accepted(Option::from(Json(GroupDto::from(&group)))))
+}
+
+/// Get a page of the members of a group conversation. Only its members can
+/// see them.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated member.
+/// * `id` - The id of the group.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of members.
+///
+/// # Return
+/// * 202 Accepted and the page of members ordered by their join, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 404 Not found if the user isn't a member of the group.
+#[utoipa::path(
+context_path = "/groups",
+params(
+("id" = i32, description = "The id of the group"),
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of members"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = MemberPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 404, description = "Group not found")
+),
+)]
+#[get("/<id>/members?<cursor>&<limit>")]
+pub fn list_members(
+  conv_state: State<Box<dyn ConversationService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  id: i32,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<MemberPageDto>>>> {
+  let conversation_service = conv_state.inner();
+  let request = cursors.page_request(MEMBERS_LIST, cursor, limit)?;
+
+  let page = conversation_service
+    .members(id, user.get_id(), request)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot retrieve the members because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let members_dto = page
+    .get_items()
+    .iter()
+    .map(MemberDto::from)
+    .collect::<Vec<MemberDto>>();
+  let links = cursors.links(MEMBERS_LIST, request, &page);
+  let page_dto = MemberPageDto::new(members_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Invite a user to a group conversation, as a plain member by default. The
+/// owner can invite admins and members, and the admins only members.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `user` - The authenticated user who invites.
+/// * `id` - The id of the group.
+/// * `invite_dto` - The invited user and its role.
+///
+/// # Return
+/// * 204 No content if the user was added to the group.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user cannot grant the role.
+/// * 404 Not found if the user isn't a member of the group.
+/// * 409 Conflict if the invited user is already a member.
+/// * 422 Unprocessable entity if the role is unknown.
+#[utoipa::path(
+context_path = "/groups",
+request_body = InviteDto,
+params(
+("id" = i32, description = "The id of the group"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The user was added to the group"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user cannot grant the role"),
+(status = 404, description = "Group not found"),
+(status = 409, description = "The user is already a member"),
+(status = 422, description = "Invalid fields", body = ErrorResponse)
+),
+)]
+#[post("/<id>/members", format = "application/json", data = "<invite_dto>")]
+pub fn invite_member(
+  conv_state: State<Box<dyn ConversationService>>,
+  user: AuthenticatedUser,
+  id: i32,
+  invite_dto: Json<InviteDto>,
+) -> ApplicationResult<NoContent> {
+  let conversation_service = conv_state.inner();
+
+  let role = match invite_dto.role.as_deref() {
+    Some(role) => role.parse::<MemberRole>().map_err(|err| {
+      log::debug!("{}", err);
+      ErrorResponse::validation_error(
+        "The invitation is not valid",
+        vec![FieldError::new("role", "must be admin or member")],
+      )
+    })?,
+    None => MemberRole::default(),
+  };
+  conversation_service
+    .invite(id, user.get_id(), invite_dto.user_id, role)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot invite the user because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  Ok(NoContent)
+}
+
+/// Remove a member from a group conversation. The owner can remove anyone
+/// and the admins only the plain members.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `user` - The authenticated user who removes.
+/// * `id` - The id of the group.
+/// * `user_id` - The id of the removed member.
+///
+/// # Return
+/// * 204 No content if the member was removed.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user cannot remove the member.
+/// * 404 Not found if the group or the member doesn't exist.
+#[utoipa::path(
+context_path = "/groups",
+params(
+("id" = i32, description = "The id of the group"),
+("user_id" = i32, description = "The id of the member"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The member was removed"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user cannot remove the member"),
+(status = 404, description = "Group or member not found")
+),
+)]
+#[delete("/<id>/members/<user_id>")]
+pub fn remove_member(
+  conv_state: State<Box<dyn ConversationService>>,
+  user: AuthenticatedUser,
+  id: i32,
+  user_id: i32,
+) -> ApplicationResult<NoContent> {
+  let conversation_service = conv_state.inner();
+
+  conversation_service
+    .remove(id, user.get_id(), user_id)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot remove the member because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  log::info!(
+    "user {} removed {} from the group {}",
+    user.get_id(),
+    user_id,
+    id
+  );
+  Ok(NoContent)
+}
+
+/// Leave a group conversation. Its owner can't leave it.
+///
+/// # Arguments
+/// * `conv_state` - The conversation service.
+/// * `user` - The authenticated member who leaves.
+/// * `id` - The id of the group.
+///
+/// # Return
+/// * 204 No content if the user left the group.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user is the owner.
+/// * 404 Not found if the user isn't a member of the group.
+#[utoipa::path(
+context_path = "/groups",
+params(
+("id" = i32, description = "The id of the group"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The user left the group"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The owner cannot leave the group"),
+(status = 404, description = "Group not found")
+),
+)]
+#[post("/<id>/leave")]
+pub fn leave_group(
+  conv_state: State<Box<dyn ConversationService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let conversation_service = conv_state.inner();
+
+  conversation_service
+    .leave(id, user.get_id())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot leave the group because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  Ok(NoContent)
+}
+
+/// Get a page of the messages of a group conversation. Only its members can
+/// read them, and the received messages are marked as delivered.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated member who reads the messages.
+/// * `id` - The id of the group.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of messages.
+///
+/// # Return
+/// * 202 Accepted and the page of messages order by id in desc mode, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't a member of the group.
+#[utoipa::path(
+context_path = "/groups",
+params(
+("id" = i32, description = "The id of the group"),
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = MessagePageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The user isn't a member of the group")
+),
+)]
+#[get("/<id>/messages?<cursor>&<limit>")]
+pub fn get_group_messages(
+  msg_state: State<Box<dyn MessageService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  id: i32,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<MessagePageDto>>>> {
+  let message_service = msg_state.inner();
+  let request = cursors.page_request(GROUP_MESSAGES_LIST, cursor, limit)?;
+  let page = message_service
+    .conversation_messages(id, user.get_id(), request)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot retrieve the messages because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let attachments =
+    message_attachments(message_service.as_ref(), page.get_items())?;
+  let messages_dto = page
+    .get_items()
+    .iter()
+    .map(|a_msg| {
+      ResponseMessageDto::from(a_msg)
+        .with_attachments(attachments.get(&a_msg.get_id()))
+    })
+    .collect::<Vec<ResponseMessageDto>>();
+  let links = cursors.links(GROUP_MESSAGES_LIST, request, &page);
+  let page_dto = MessagePageDto::new(messages_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"name": "friends", "members": [2, 3]}))]
+pub struct CreateGroupDto {
+  name: String,
+  #[serde(default)]
+  members: Vec<i32>,
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"name": "old friends"}))]
+pub struct RenameGroupDto {
+  name: String,
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"user_id": 4, "role": "admin"}))]
+pub struct InviteDto {
+  user_id: i32,
+  role: Option<String>,
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "id": 1,
+  "name": "friends",
+  "created_by": 1,
+  "created_at": "2022-09-05T09:00:00+00:00"
+}))]
+pub struct GroupDto {
+  id: i32,
+  name: String,
+  created_by: i32,
+  created_at: String,
+}
+
+/// A page of groups, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "id": 1,
+    "name": "friends",
+    "created_by": 1,
+    "created_at": "2022-09-05T09:00:00+00:00"
+  }],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct GroupPageDto {
+  items: Vec<GroupDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl GroupPageDto {
+  pub fn new(the_items: Vec<GroupDto>, links: &PageLinks) -> Self {
+    GroupPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&Conversation> for GroupDto {
+  fn from(conversation: &Conversation) -> Self {
+    GroupDto {
+      id: conversation.get_id(),
+      name: conversation.get_name(),
+      created_by: conversation.get_created_by(),
+      created_at: to_rfc3339(conversation.get_created_at()),
+    }
+  }
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "user_id": 2,
+  "role": "member",
+  "joined_at": "2022-09-05T09:00:00+00:00"
+}))]
+pub struct MemberDto {
+  user_id: i32,
+  role: String,
+  joined_at: String,
+}
+
+/// A page of members, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "user_id": 2,
+    "role": "member",
+    "joined_at": "2022-09-05T09:00:00+00:00"
+  }],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct MemberPageDto {
+  items: Vec<MemberDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl MemberPageDto {
+  pub fn new(the_items: Vec<MemberDto>, links: &PageLinks) -> Self {
+    MemberPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&Member> for MemberDto {
+  fn from(member: &Member) -> Self {
+    MemberDto {
+      user_id: member.get_user_id(),
+      role: member.get_role().to_string(),
+      joined_at: to_rfc3339(member.get_joined_at()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    application::error::unauthorized,
+    auth::{error::Error::RevokedTokenError, token::MockAuthenticator},
+    model::{
+      conversation::{Builder, Conversation},
+      conversation_service::MockConversationService,
+      error::Error as ServiceError,
+      message_service::MockMessageService,
+      page::{Direction, Page, PageRequest},
+    },
+    Authenticator,
+  };
+  use mockall::predicate::eq;
+  use rocket::{
+    http::{ContentType, Header, Status},
+    local::Client,
+  };
+
+  #[test]
+  fn create_group_ok() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs
+      .expect_create()
+      .with(eq(1), eq(String::from("friends")), eq(vec![2, 3]))
+      .times(1)
+      .returning(|_, _, _| Ok(7));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/groups", routes![create_group,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/groups")
+      .body(r#"{ "name": "friends", "members": [2, 3]}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(response.body_string(), Some(String::from("{\"id\":\"7\"}")))
+  }
+
+  #[test]
+  fn create_group_unauthorized() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs.expect_create().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/groups", routes![create_group,])
+      .register(catchers![unauthorized]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/groups")
+      .body(r#"{ "name": "friends"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+  }
+
+  #[test]
+  fn list_groups_with_a_next_page() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs
+      .expect_list()
+      .with(eq(1), eq(PageRequest::first(Some(1))))
+      .times(1)
+      .returning(|_, page| {
+        let group = |group_id| {
+          Builder::new()
+            .with_conversation_id(group_id)
+            .with_user_id(1)
+            .build_conversation("friends")
+        };
+        let rows = vec![group(3), group(4)];
+        Ok(Page::from_rows(rows, page, Conversation::get_id))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/groups", routes![list_groups,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/groups?limit=1")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+    let next = Cursors::new(b"secret").encode(GROUPS_LIST, Direction::After, 3);
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(format!(
+        concat!(
+          "{{\"items\":[{{\"id\":3,\"name\":\"friends\",\"created_by\":1,",
+          "\"created_at\":\"1970-01-01T00:00:00+00:00\"}}],",
+          "\"next_cursor\":\"{}\"}}",
+        ),
+        next
+      ))
+    )
+  }
+
+  #[test]
+  fn list_members_ok() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs
+      .expect_members()
+      .with(eq(7), eq(2), eq(PageRequest::first(None)))
+      .times(1)
+      .returning(|_, _, page| {
+        let rows = vec![
+          Builder::new()
+            .with_conversation_id(7)
+            .with_user_id(1)
+            .with_role(MemberRole::Owner)
+            .build(),
+          Builder::new()
+            .with_conversation_id(7)
+            .with_user_id(2)
+            .build(),
+        ];
+        Ok(Page::from_rows(rows, page, Member::get_id))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(2));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/groups", routes![list_members,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/groups/7/members")
+      .header(Header::new("Authorization", "Bearer 2"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(concat!(
+        "{\"items\":[{\"user_id\":1,\"role\":\"owner\",",
+        "\"joined_at\":\"1970-01-01T00:00:00+00:00\"},",
+        "{\"user_id\":2,\"role\":\"member\",",
+        "\"joined_at\":\"1970-01-01T00:00:00+00:00\"}]}",
+      )))
+    )
+  }
+
+  #[test]
+  fn invite_member_with_unknown_role() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs.expect_invite().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/groups", routes![invite_member,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/groups/7/members")
+      .body(r#"{ "user_id": 4, "role": "guest"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+  }
+
+  #[test]
+  fn remove_member_forbidden() {
+    let mut mock_cs = MockConversationService::new();
+    mock_cs
+      .expect_remove()
+      .with(eq(7), eq(2), eq(1))
+      .times(1)
+      .returning(|_, _, _| {
+        Err(ServiceError::Forbidden(String::from(
+          "Cannot remove a member with the role owner",
+        )))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(2));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_cs) as Box<dyn ConversationService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/groups", routes![remove_member,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .delete("/groups/7/members/1")
+      .header(Header::new("Authorization", "Bearer 2"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+
+  #[test]
+  fn get_group_messages_of_another_member() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_conversation_messages()
+      .with(eq(7), eq(4), eq(PageRequest::first(None)))
+      .times(1)
+      .returning(|_, _, _| {
+        Err(ServiceError::Forbidden(String::from(
+          "Only the members can access the conversation",
+        )))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(4));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/groups", routes![get_group_messages,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get("/groups/7/messages")
+      .header(Header::new("Authorization", "Bearer 4"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+}
@@ -3,7 +3,7 @@ use crate::{
   UserService,
 };
 
-use rocket::{http::hyper::StatusCode, response::status::Accepted, State};
+use rocket::{response::status::Accepted, State};
 
 /// Implements a pong end point.
 ///
@@ -13,10 +13,12 @@ use rocket::{http::hyper::StatusCode, response::status::Accepted, State};
 /// # Return
 /// * 202 and pong message if we can make a simple sql query.
 /// * 500 and the error message.
+/// * 503 if the database can't be reached.
 #[utoipa::path(
 responses(
   (status = 202, description = "The server is ok"),
-  (status = 500, description = "The server is malfunction")
+  (status = 500, description = "The server is malfunction"),
+  (status = 503, description = "The database is unavailable")
 ),
 )]
 #[get("/ping")]
@@ -28,17 +30,14 @@ pub fn ping(
 
   match result {
     Ok(_) => Ok(Accepted(Option::from(String::from("pong")))),
-    Err(err) => Err(ErrorResponse::create_error(
-      &err.to_string(),
-      StatusCode::InternalServerError,
-    )),
+    Err(err) => Err(ErrorResponse::from_service_error(&err.to_string(), &err)),
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::model::user_service::MockUserService;
+  use crate::model::{error::Error, user_service::MockUserService};
   use rocket::{http::Status, local::Client};
 
   #[test]
@@ -62,7 +61,7 @@ mod tests {
     mock_us
       .expect_total()
       .times(1)
-      .returning(|| Err(String::from("some error")));
+      .returning(|| Err(Error::Internal(String::from("some error"))));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_us) as Box<dyn UserService>)
@@ -73,7 +72,28 @@ mod tests {
     assert_eq!(response.status(), Status::InternalServerError);
     assert_eq!(
       response.body_string(),
-      Some(String::from("{\"message\":\"some error\"}"))
+      Some(String::from(
+        "{\"type\":\"/problems/internal\",\"title\":\"Internal Server \
+         Error\",\"status\":500,\"detail\":\"some \
+         error\",\"instance\":\"/ping\"}"
+      ))
     )
   }
+
+  #[test]
+  fn ping_database_unavailable() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_total()
+      .times(1)
+      .returning(|| Err(Error::Unavailable));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .mount("/", routes![ping,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client.get("/ping").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+  }
 }
@@ -0,0 +1,45 @@
+use crate::{auth::keys::JwkSet, Authenticator};
+
+use rocket::State;
+use rocket_contrib::json::Json;
+
+/// Publishes the public keys that verify the access tokens, so other services
+/// can validate them without sharing a secret.
+///
+/// # Arguments
+/// * `auth_state` - The authenticator that owns the keys.
+///
+/// # Return
+/// * 200 and the JSON Web Key Set, empty if the tokens use a shared secret.
+#[utoipa::path(
+context_path = "/.well-known",
+responses(
+  (status = 200, description = "The JSON Web Key Set")
+),
+)]
+#[get("/jwks.json")]
+pub fn jwks(auth_state: State<Box<dyn Authenticator>>) -> Json<JwkSet> {
+  Json(auth_state.inner().jwks())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::auth::token::MockAuthenticator;
+  use rocket::{http::Status, local::Client};
+
+  #[test]
+  fn jwks_ok() {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth.expect_jwks().times(1).returning(JwkSet::default);
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/.well-known", routes![jwks,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client.get("/.well-known/jwks.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.body_string(), Some(String::from("{\"keys\":[]}")))
+  }
+}
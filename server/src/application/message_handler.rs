@@ -1,222 +1,931 @@
 use crate::{
-  application::error::{ApplicationResult, ErrorResponse, GenericResponse},
-  auth::middleware::AccessToken,
-  Authenticator, MessageService,
+  application::{
+    attachment_handler::AttachmentDto,
+    error::{ApplicationResult, ErrorResponse, FieldError, GenericResponse},
+    pagination::{Cursors, PageLinks, Paginated},
+    polling::Polls,
+    session_handler::to_rfc3339,
+  },
+  auth::middleware::AuthenticatedUser,
+  model::{attachment::Attachment, message::Message, receipt::Receipt},
+  MessageService,
 };
 
 use rocket::{
   http::hyper::StatusCode,
-  response::status::{Accepted, Created},
+  response::status::{Accepted, Created, NoContent},
   State,
 };
 use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
-use std::borrow::Borrow;
+use std::{collections::HashMap, slice, time::Duration};
 use utoipa::Component;
 
-/// Send a message from one user to another one.
+/// How long a long-poll waits when the timeout isn't specified, in seconds.
+const DEFAULT_POLL_TIMEOUT: u64 = 20;
+/// The longest wait of a long-poll, in seconds. Every waiting poll holds a
+/// worker of the server, so it's kept short.
+const MAX_POLL_TIMEOUT: u64 = 30;
+/// The names of the paged lists, their cursors are only valid for them.
+const SENT_LIST: &str = "messages";
+const INBOX_LIST: &str = "inbox";
+const UNREAD_LIST: &str = "unread";
+
+/// Send a message from the owner of the access token to another user, or to
+/// the members of a group conversation. The message has either a recipient
+/// or a conversation, and the files the user uploaded for it.
 ///
 /// # Arguments
 /// * `msg_state` - The message service.
-/// * `auth_state` - The authenticator used to validate the access token.
-/// * `token` - The access token used to validate if the user who sent the
-///   messages is a valid one.
+/// * `user` - The authenticated user who sends the message.
 /// * `msg_dto` - The message dto to persist.
 ///
 /// # Return
 /// * 201 Created and the id of the message inserted.
 /// * 400 Bad request and the error message.
 /// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't a member of the conversation.
+/// * 422 Unprocessable entity if there isn't exactly one recipient or
+///   conversation, if it doesn't exist, or if an attachment isn't a pending one
+///   of the user.
 #[utoipa::path(
 context_path = "/message",
 request_body = MessageDto,
 params(
-  ("x-access-token", header, description = "The jwt token access"),
+  ("Authorization", header, description = "The jwt token access"),
 ),
 responses(
   (status = 201, description = "The message was created"),
   (status = 400, description = "Bad request"),
-  (status = 401, description = "Unauthorized user")
+  (status = 401, description = "Unauthorized user"),
+  (status = 403, description = "The user isn't a member of the conversation"),
+  (status = 422, description = "Invalid fields or unknown recipient", body = ErrorResponse)
 ),
 )]
 #[post("/send", format = "application/json", data = "<msg_dto>")]
 pub fn send_message(
   msg_state: State<Box<dyn MessageService>>,
-  auth_state: State<Box<dyn Authenticator>>,
-  token: AccessToken,
+  user: AuthenticatedUser,
   msg_dto: Json<MessageDto>,
 ) -> ApplicationResult<Created<Json<GenericResponse>>> {
   let message_service = msg_state.inner();
-  let authenticator = auth_state.inner();
-  match authenticator.authorize(token.borrow(), msg_dto.from) {
-    Ok(_) => {
-      let msg_id = message_service
-        .create(msg_dto.from, msg_dto.to, msg_dto.message.to_string())
-        .map_err(|err| {
-          log::error!("error: {}", err.to_string());
-          let err_msg = format!("Cannot insert the message because {}", err);
-          ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
-        })?;
-
-      let mut response = GenericResponse::new();
-      response.insert(String::from("id"), msg_id.to_string());
-      Ok(Created(
-        format!("/message/{}", msg_id),
-        Option::from(Json(response)),
-      ))
+  let message = msg_dto.message.to_string();
+  let attachments = msg_dto.attachments.clone();
+  let created = match (msg_dto.to, msg_dto.conversation_id) {
+    (Some(to), None) => {
+      message_service.create(user.get_id(), to, message, attachments)
     },
-    Err(_) => Err(ErrorResponse::create_error(
-      "Access denied",
-      StatusCode::Unauthorized,
-    )),
-  }
+    (None, Some(conversation_id)) => message_service.create_in_conversation(
+      user.get_id(),
+      conversation_id,
+      message,
+      attachments,
+    ),
+    _ => {
+      return Err(ErrorResponse::validation_error(
+        "The message is not valid",
+        vec![FieldError::new(
+          "to",
+          "exactly one of to and conversation_id is required",
+        )],
+      ));
+    },
+  };
+  let msg_id = created.map_err(|err| {
+    log::error!("error: {}", err.to_string());
+    let err_msg = format!("Cannot insert the message because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+
+  let mut response = GenericResponse::new();
+  response.insert(String::from("id"), msg_id.to_string());
+  Ok(Created(
+    format!("/message/{}", msg_id),
+    Option::from(Json(response)),
+  ))
 }
 
-/// Get a message from its id.
+/// Get a message from its id. Only its sender and its recipient can read it,
+/// or the members of its conversation for a group message. A deleted message
+/// is returned as a tombstone, without its content. The message is marked as
+/// delivered when one of its recipients gets it, and the sender of a direct
+/// message gets the delivery and read dates.
 ///
 /// # Arguments
 /// * `msg_state` - The message service.
-/// * `auth_state` - The authenticator used to validate the access token.
-/// * `token` - The access token used to validate if the user who sent the
-///   messages is a valid one.
+/// * `user` - The authenticated user who reads the message.
 /// * `id` - The message id to retrieve.
 ///
 /// # Return
 /// * 202 Accepted and the message.
 /// * 400 Bad request and the error message.
-/// * 401 Unauthorized if the token isn't valid (Not implemented yet).
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't the sender nor a recipient.
 #[utoipa::path(
 context_path = "/message",
 params(
 ("id" = i32, description = "The id of the message"),
-("x-access-token", header, description = "The token access"),
+("Authorization", header, description = "The token access"),
 ),
 responses(
 (status = 202, description = "Accepted", body = ResponseMessageDto),
 (status = 400, description = "Bad request"),
-(status = 401, description = "Unauthorized user (Not implemented yet)")
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The message belongs to other users")
 ),
 )]
 #[get("/<id>", format = "application/json")]
 pub fn get_message(
   msg_state: State<Box<dyn MessageService>>,
-  _auth_state: State<Box<dyn Authenticator>>,
-  _token: AccessToken,
+  user: AuthenticatedUser,
   id: i32,
 ) -> ApplicationResult<Accepted<Json<ResponseMessageDto>>> {
   let message_service = msg_state.inner();
-  let msg = message_service.get(id).map_err(|err| {
-    log::error!("error: {}", err.to_string());
+  let uid = user.get_id();
+
+  let msg = message_service.open(id, uid).map_err(|err| {
+    log::warn!("user {} cannot read the message {}: {}", uid, id, err);
     let err_msg = format!("Cannot retrieve the message because {}", err);
-    ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
+    ErrorResponse::from_service_error(&err_msg, &err)
   })?;
+  let receipts =
+    sent_receipts(message_service.as_ref(), uid, slice::from_ref(&msg))?;
+  let attachments =
+    message_attachments(message_service.as_ref(), slice::from_ref(&msg))?;
 
   let dto = ResponseMessageDto {
     id: None,
+    from: None,
     to: None,
-    message: msg.get_message(),
+    ..ResponseMessageDto::from(&msg)
+      .with_receipt(receipts.get(&id))
+      .with_attachments(attachments.get(&id))
   };
   Ok(Accepted(Option::from(Json(dto))))
 }
 
-/// Get a message from the user specified, since the id indicated and with a
-/// limit.
+/// Mark a message as read by its recipient.
 ///
 /// # Arguments
 /// * `msg_state` - The message service.
-/// * `auth_state` - The authenticator used to validate the access token.
-/// * `token` - The access token used to validate if the user who sent the
-///   messages is a valid one.
-/// * `msg_dto` - The message params to retrieve.
+/// * `user` - The authenticated user who read the message.
+/// * `id` - The message id to mark.
 ///
 /// # Return
-/// * 202 Accepted and the a list of messages order by id in desc mode.
-/// * 400 Bad request and the error message.
+/// * 204 No content if the message was marked as read.
 /// * 401 Unauthorized if the token isn't valid.
+/// * 404 Not found if the user isn't the recipient of the message.
 #[utoipa::path(
 context_path = "/message",
-request_body = SearchMessageDto,
 params(
-("x-access-token", header, description = "The token access"),
+("id" = i32, description = "The id of the message"),
+("Authorization", header, description = "The token access"),
 ),
 responses(
-(status = 202, description = "Accepted", body = ResponseMessageDto),
+(status = 204, description = "The message was marked as read"),
+(status = 401, description = "Unauthorized user"),
+(status = 404, description = "Message not found")
+),
+)]
+#[post("/<id>/read")]
+pub fn read_message(
+  msg_state: State<Box<dyn MessageService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let message_service = msg_state.inner();
+
+  message_service
+    .mark_read(id, user.get_id())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot mark the message as read because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  Ok(NoContent)
+}
+
+/// Count the unread messages of the owner of the access token, grouped by
+/// their sender, a page of senders at a time.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user who received the messages.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of senders.
+///
+/// # Return
+/// * 202 Accepted and the page of unread counts ordered by sender, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/message",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of senders"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = UnreadPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/unread?<cursor>&<limit>")]
+pub fn get_unread(
+  msg_state: State<Box<dyn MessageService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<UnreadPageDto>>>> {
+  let message_service = msg_state.inner();
+  let request = cursors.page_request(UNREAD_LIST, cursor, limit)?;
+  let page = message_service
+    .unread(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot count the unread messages because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let counts_dto = page
+    .get_items()
+    .iter()
+    .map(|count| UnreadDto {
+      from: count.get_from(),
+      count: count.get_count(),
+    })
+    .collect::<Vec<UnreadDto>>();
+  let links = cursors.links(UNREAD_LIST, request, &page);
+  let page_dto = UnreadPageDto::new(counts_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Search the messages sent or received by the owner of the access token
+/// whose content has all the words of the query. The deleted messages and
+/// the ones of the groups the user left aren't searched.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `user` - The authenticated user who searches.
+/// * `q` - The words to look for.
+/// * `limit` - The max quantity of messages, 5 by default and 100 at most.
+///
+/// # Return
+/// * 202 Accepted and the messages with a snippet of their content, the best
+///   match first. The snippet is escaped for HTML and its matched words are
+///   between `<mark>` tags.
+/// * 400 Bad request if the query is empty.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/message",
+params(
+("q" = String, query, description = "The words to look for"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = [SearchResultDto]),
+(status = 400, description = "Empty query", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/search?<q>&<limit>")]
+pub fn search_messages(
+  msg_state: State<Box<dyn MessageService>>,
+  user: AuthenticatedUser,
+  q: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Accepted<Json<Vec<SearchResultDto>>>> {
+  let message_service = msg_state.inner();
+  let hits = message_service
+    .search(user.get_id(), q.unwrap_or_default(), limit)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot search the messages because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let messages = hits
+    .iter()
+    .map(|hit| hit.get_message().clone())
+    .collect::<Vec<Message>>();
+  let attachments = message_attachments(message_service.as_ref(), &messages)?;
+  let hits_dto = hits
+    .iter()
+    .map(|hit| SearchResultDto {
+      message: ResponseMessageDto::from(hit.get_message())
+        .with_attachments(attachments.get(&hit.get_message().get_id())),
+      snippet: hit.get_snippet(),
+    })
+    .collect::<Vec<SearchResultDto>>();
+  Ok(Accepted(Option::from(Json(hits_dto))))
+}
+
+/// Edit the content of a message, the previous content is kept as a
+/// revision. Only its sender can edit it.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `user` - The authenticated user who edits the message.
+/// * `id` - The message id to edit.
+/// * `edit_dto` - The new content of the message.
+///
+/// # Return
+/// * 202 Accepted and the edited message.
+/// * 400 Bad request if the new content is empty.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't the sender.
+/// * 404 Not found if the message doesn't exist or was deleted.
+#[utoipa::path(
+context_path = "/message",
+request_body = EditMessageDto,
+params(
+("id" = i32, description = "The id of the message"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "The message was edited", body = ResponseMessageDto),
 (status = 400, description = "Bad request"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The message belongs to other sender"),
+(status = 404, description = "Message not found")
+),
+)]
+#[patch("/<id>", format = "application/json", data = "<edit_dto>")]
+pub fn edit_message(
+  msg_state: State<Box<dyn MessageService>>,
+  user: AuthenticatedUser,
+  id: i32,
+  edit_dto: Json<EditMessageDto>,
+) -> ApplicationResult<Accepted<Json<ResponseMessageDto>>> {
+  let message_service = msg_state.inner();
+
+  let msg = message_service
+    .edit(id, user.get_id(), edit_dto.message.to_string())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot edit the message because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let attachments =
+    message_attachments(message_service.as_ref(), slice::from_ref(&msg))?;
+
+  let dto = ResponseMessageDto::from(&msg)
+    .with_attachments(attachments.get(&msg.get_id()));
+  Ok(Accepted(Option::from(Json(dto))))
+}
+
+/// Delete a message, readers get a tombstone instead of its content. Only its
+/// sender can delete it.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `user` - The authenticated user who deletes the message.
+/// * `id` - The message id to delete.
+///
+/// # Return
+/// * 204 No content if the message was deleted.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if the user isn't the sender.
+/// * 404 Not found if the message doesn't exist or was already deleted.
+#[utoipa::path(
+context_path = "/message",
+params(
+("id" = i32, description = "The id of the message"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The message was deleted"),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "The message belongs to other sender"),
+(status = 404, description = "Message not found")
+),
+)]
+#[delete("/<id>")]
+pub fn delete_message(
+  msg_state: State<Box<dyn MessageService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let message_service = msg_state.inner();
+
+  message_service.delete(id, user.get_id()).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot delete the message because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  log::info!("user {} deleted the message {}", user.get_id(), id);
+  Ok(NoContent)
+}
+
+/// Get a page of the messages sent by the owner of the access token, with
+/// their delivery and read dates.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user who sent the messages.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of messages.
+///
+/// # Return
+/// * 202 Accepted and the page of messages order by id in desc mode, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+#[utoipa::path(
+context_path = "/message",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = MessagePageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
 (status = 401, description = "Unauthorized user")
 ),
 )]
-#[post("/", format = "application/json", data = "<search_dto>")]
+#[get("/?<cursor>&<limit>")]
 pub fn get_message_from(
   msg_state: State<Box<dyn MessageService>>,
-  auth_state: State<Box<dyn Authenticator>>,
-  token: AccessToken,
-  search_dto: Json<SearchMessageDto>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<MessagePageDto>>>> {
+  let message_service = msg_state.inner();
+  let request = cursors.page_request(SENT_LIST, cursor, limit)?;
+  let page = message_service
+    .find(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the messages because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let receipts =
+    sent_receipts(message_service.as_ref(), user.get_id(), page.get_items())?;
+  let attachments =
+    message_attachments(message_service.as_ref(), page.get_items())?;
+  let messages_dto = page
+    .get_items()
+    .iter()
+    .map(|a_msg| ResponseMessageDto {
+      from: None,
+      ..ResponseMessageDto::from(a_msg)
+        .with_receipt(receipts.get(&a_msg.get_id()))
+        .with_attachments(attachments.get(&a_msg.get_id()))
+    })
+    .collect::<Vec<ResponseMessageDto>>();
+  let links = cursors.links(SENT_LIST, request, &page);
+  let page_dto = MessagePageDto::new(messages_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Get a page of the messages sent to the owner of the access token. The
+/// messages are marked as delivered.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user who received the messages.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of messages.
+///
+/// # Return
+/// * 202 Accepted and the page of messages order by id in desc mode, with the
+///   `Link` header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/message",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = MessagePageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/inbox?<cursor>&<limit>")]
+pub fn get_inbox(
+  msg_state: State<Box<dyn MessageService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<MessagePageDto>>>> {
+  let message_service = msg_state.inner();
+  let request = cursors.page_request(INBOX_LIST, cursor, limit)?;
+  let page = message_service
+    .inbox(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the inbox because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let attachments =
+    message_attachments(message_service.as_ref(), page.get_items())?;
+  let messages_dto = page
+    .get_items()
+    .iter()
+    .map(|a_msg| {
+      ResponseMessageDto::from(a_msg)
+        .with_attachments(attachments.get(&a_msg.get_id()))
+    })
+    .collect::<Vec<ResponseMessageDto>>();
+  let links = cursors.links(INBOX_LIST, request, &page);
+  let page_dto = MessagePageDto::new(messages_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Waits for the messages received by the owner of the access token after
+/// the given one, the fallback of the clients that can't keep a stream open.
+/// It answers as soon as there is a new message, or with an empty list when
+/// the timeout expires. The messages are marked as delivered.
+///
+/// # Arguments
+/// * `msg_state` - The message service.
+/// * `polls` - The limit of the waiting polls.
+/// * `user` - The authenticated user who receives the messages.
+/// * `after` - The id of the last message received, 0 by default.
+/// * `timeout` - How long to wait in seconds, 20 by default and 30 at most.
+/// * `limit` - The max quantity of messages, 5 by default and 100 at most.
+///
+/// # Return
+/// * 202 Accepted and the list of messages order by id in asc mode.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if too many polls are waiting or the database
+///   can't be reached.
+#[utoipa::path(
+context_path = "/message",
+params(
+("after" = Option<i32>, query, description = "The last message id received"),
+("timeout" = Option<u64>, query, description = "The seconds to wait"),
+("limit" = Option<i64>, query, description = "The max quantity of messages"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = [ResponseMessageDto]),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Too many polls or database unavailable")
+),
+)]
+#[get("/poll?<after>&<timeout>&<limit>")]
+pub fn poll_messages(
+  msg_state: State<Box<dyn MessageService>>,
+  polls: State<Polls>,
+  user: AuthenticatedUser,
+  after: Option<i32>,
+  timeout: Option<u64>,
+  limit: Option<i64>,
 ) -> ApplicationResult<Accepted<Json<Vec<ResponseMessageDto>>>> {
   let message_service = msg_state.inner();
-  let authenticator = auth_state.inner();
-  match authenticator.authorize(token.borrow(), search_dto.from) {
-    Ok(_) => {
-      let messages = message_service
-        .find(search_dto.since, search_dto.from, search_dto.limit)
-        .map_err(|err| {
-          log::error!("error: {}", err.to_string());
-          let err_msg = format!("Cannot retrieve the messages because {}", err);
-          ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
-        })?;
-
-      let messages_dto = messages
-        .iter()
-        .map(|a_msg| ResponseMessageDto {
-          id: Option::from(a_msg.get_id()),
-          to: Option::from(a_msg.get_to()),
-          message: a_msg.get_message(),
-        })
-        .collect::<Vec<ResponseMessageDto>>();
-      Ok(Accepted(Option::from(Json(messages_dto))))
-    },
-    Err(_) => {
-      log::error!("error: Access denied");
-      Err(ErrorResponse::create_error(
-        "Access denied",
-        StatusCode::Unauthorized,
-      ))
-    },
-  }
+  let _permit = polls.acquire().ok_or_else(|| {
+    ErrorResponse::create_error(
+      "Too many polls are waiting, retry later or use the event stream",
+      StatusCode::ServiceUnavailable,
+    )
+  })?;
+  let timeout = timeout
+    .unwrap_or(DEFAULT_POLL_TIMEOUT)
+    .min(MAX_POLL_TIMEOUT);
+  let messages = message_service
+    .poll(
+      user.get_id(),
+      after.unwrap_or(0),
+      limit,
+      Duration::from_secs(timeout),
+    )
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot poll the messages because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let attachments = message_attachments(message_service.as_ref(), &messages)?;
+  let messages_dto = messages
+    .iter()
+    .map(|a_msg| {
+      ResponseMessageDto::from(a_msg)
+        .with_attachments(attachments.get(&a_msg.get_id()))
+    })
+    .collect::<Vec<ResponseMessageDto>>();
+  Ok(Accepted(Option::from(Json(messages_dto))))
 }
 
 #[derive(Deserialize, Component)]
-#[component(example = json!({"from": 1, "to": 2, "message": "something"}))]
+#[component(example = json!({
+  "to": 2,
+  "message": "something",
+  "attachments": [3]
+}))]
 pub struct MessageDto {
-  from: i32,
-  to: i32,
+  to: Option<i32>,
+  conversation_id: Option<i32>,
   message: String,
+  #[serde(default)]
+  attachments: Vec<i32>,
+}
+
+/// Get the receipts of the messages sent by a user, for their recipients.
+///
+/// # Arguments
+/// * `message_service` - The message service.
+/// * `uid` - The user who sent the messages.
+/// * `messages` - The messages, the ones received by the user and the group
+///   messages are ignored.
+///
+/// # Return
+/// * The receipts by message id.
+/// * The error response if the receipts can't be retrieved.
+pub fn sent_receipts(
+  message_service: &dyn MessageService,
+  uid: i32,
+  messages: &[Message],
+) -> ApplicationResult<HashMap<i32, Receipt>> {
+  let recipients = messages
+    .iter()
+    .filter(|msg| msg.get_from() == uid)
+    .filter_map(|msg| msg.get_to().map(|to| (msg.get_id(), to)))
+    .collect::<HashMap<i32, i32>>();
+  if recipients.is_empty() {
+    return Ok(HashMap::new());
+  }
+  let receipts = message_service
+    .receipts(recipients.keys().copied().collect())
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the receipts because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  Ok(
+    receipts
+      .into_iter()
+      .filter(|receipt| {
+        let recipient = recipients.get(&receipt.get_message_id());
+        recipient == Some(&receipt.get_user_id())
+      })
+      .map(|receipt| (receipt.get_message_id(), receipt))
+      .collect(),
+  )
+}
+
+/// Get the attachments of some messages, the deleted messages don't have
+/// any.
+///
+/// # Arguments
+/// * `message_service` - The message service.
+/// * `messages` - The messages.
+///
+/// # Return
+/// * The attachments by message id.
+/// * The error response if the attachments can't be retrieved.
+pub fn message_attachments(
+  message_service: &dyn MessageService,
+  messages: &[Message],
+) -> ApplicationResult<HashMap<i32, Vec<Attachment>>> {
+  let ids = messages
+    .iter()
+    .filter(|msg| !msg.is_deleted())
+    .map(Message::get_id)
+    .collect::<Vec<i32>>();
+  if ids.is_empty() {
+    return Ok(HashMap::new());
+  }
+  let attachments = message_service.attachments(ids).map_err(|err| {
+    log::error!("error: {}", err.to_string());
+    let err_msg = format!("Cannot retrieve the attachments because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+
+  let mut by_message = HashMap::<i32, Vec<Attachment>>::new();
+  for attachment in attachments {
+    if let Some(message_id) = attachment.get_message_id() {
+      by_message.entry(message_id).or_default().push(attachment);
+    }
+  }
+  Ok(by_message)
 }
 
 #[derive(Deserialize, Component)]
-#[component(example = json!({"from": 1, "since": 1, "limit": 4}))]
-pub struct SearchMessageDto {
-  from: i32,
-  since: i32,
-  limit: Option<i64>,
+#[component(example = json!({"message": "something else"}))]
+pub struct EditMessageDto {
+  message: String,
 }
 
+/// A message for its readers, with its attachments. A deleted message is a
+/// tombstone, with an empty content and the date of its deletion. Only the
+/// sender of a direct message gets the delivery and read dates.
 #[derive(Serialize, Component)]
-#[component(example = json!({"id": 1, "to": 2, "message": "something"}))]
+#[component(example = json!({
+  "id": 1,
+  "to": 2,
+  "message": "something",
+  "created_at": "2022-08-25T09:00:00+00:00",
+  "updated_at": "2022-08-25T09:05:00+00:00",
+  "attachments": [{
+    "id": 3,
+    "filename": "photo.png",
+    "mime_type": "image/png",
+    "size": 52340,
+    "url": "/attachments/3"
+  }]
+}))]
 pub struct ResponseMessageDto {
   #[serde(skip_serializing_if = "Option::is_none")]
   id: Option<i32>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  from: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   to: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  conversation_id: Option<i32>,
   message: String,
+  created_at: String,
+  updated_at: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  deleted_at: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  delivered_at: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  read_at: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  attachments: Vec<AttachmentDto>,
+}
+
+impl ResponseMessageDto {
+  /// Adds the delivery and read dates of the receipt, if any.
+  ///
+  /// # Arguments
+  /// * `receipt` - The receipt of the message for its recipient.
+  pub fn with_receipt(mut self, receipt: Option<&Receipt>) -> Self {
+    if let Some(receipt) = receipt {
+      self.delivered_at = receipt.get_delivered_at().map(to_rfc3339);
+      self.read_at = receipt.get_read_at().map(to_rfc3339);
+    }
+    self
+  }
+
+  /// Adds the attachments of the message, if any.
+  ///
+  /// # Arguments
+  /// * `attachments` - The attachments of the message.
+  pub fn with_attachments(
+    mut self,
+    attachments: Option<&Vec<Attachment>>,
+  ) -> Self {
+    if let Some(attachments) = attachments {
+      self.attachments = attachments.iter().map(AttachmentDto::from).collect();
+    }
+    self
+  }
+}
+
+impl From<&Message> for ResponseMessageDto {
+  fn from(msg: &Message) -> Self {
+    let message = if msg.is_deleted() {
+      String::new()
+    } else {
+      msg.get_message()
+    };
+    ResponseMessageDto {
+      id: Option::from(msg.get_id()),
+      from: Option::from(msg.get_from()),
+      to: msg.get_to(),
+      conversation_id: msg.get_conversation_id(),
+      message,
+      created_at: to_rfc3339(msg.get_created_at()),
+      updated_at: to_rfc3339(msg.get_updated_at()),
+      deleted_at: msg.get_deleted_at().map(to_rfc3339),
+      delivered_at: None,
+      read_at: None,
+      attachments: vec![],
+    }
+  }
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({"from": 2, "count": 3}))]
+pub struct UnreadDto {
+  from: i32,
+  count: i64,
+}
+
+/// A page of unread counts, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{"from": 2, "count": 3}],
+  "next_cursor": "YToy.dHbgSg9ZEqGQ7Jz1Mx0pXy3V8m9bK3qF0n2cW5sLr4A"
+}))]
+pub struct UnreadPageDto {
+  items: Vec<UnreadDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl UnreadPageDto {
+  pub fn new(the_items: Vec<UnreadDto>, links: &PageLinks) -> Self {
+    UnreadPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+/// A page of messages, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "id": 1,
+    "to": 2,
+    "message": "something",
+    "created_at": "2022-08-25T09:00:00+00:00",
+    "updated_at": "2022-08-25T09:05:00+00:00"
+  }],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct MessagePageDto {
+  items: Vec<ResponseMessageDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl MessagePageDto {
+  pub fn new(the_items: Vec<ResponseMessageDto>, links: &PageLinks) -> Self {
+    MessagePageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+/// A message found by a search, with the fragment of its content that
+/// matched.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "message": {
+    "id": 1,
+    "from": 1,
+    "to": 2,
+    "message": "See you at lunch",
+    "created_at": "2022-09-20T09:00:00+00:00",
+    "updated_at": "2022-09-20T09:00:00+00:00"
+  },
+  "snippet": "See you at <mark>lunch</mark>"
+}))]
+pub struct SearchResultDto {
+  message: ResponseMessageDto,
+  snippet: String,
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
   use crate::{
-    auth::{error::Error::NoPermissionError, token::MockAuthenticator},
-    model::{message::Builder, message_service::MockMessageService},
+    application::error::unauthorized,
+    auth::{error::Error::RevokedTokenError, token::MockAuthenticator},
+    model::{
+      attachment::Builder as AttachmentBuilder,
+      error::Error as ServiceError,
+      message::{Builder, SearchHit},
+      message_service::MockMessageService,
+      page::{Direction, Page, PageRequest},
+      receipt::{Builder as ReceiptBuilder, UnreadCount},
+    },
+    Authenticator,
   };
+  use chrono::NaiveDateTime;
   use mockall::predicate::{always, eq};
   use rocket::{
     http::{ContentType, Header, Status},
@@ -228,15 +937,19 @@ mod tests {
     let mut mock_ms = MockMessageService::new();
     mock_ms
       .expect_create()
-      .with(eq(1), eq(2), eq(String::from("test message")))
+      .with(
+        eq(1),
+        eq(2),
+        eq(String::from("test message")),
+        eq(Vec::<i32>::new()),
+      )
       .times(1)
-      .returning(|_, _, _| Ok(1));
+      .returning(|_, _, _, _| Ok(1));
     let mut mock_auth = MockAuthenticator::new();
     mock_auth
-      .expect_authorize()
-      .with(always(), eq(1))
+      .expect_authenticate()
       .times(1)
-      .returning(|_, _| Ok(()));
+      .returning(|_| Ok(1));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_ms) as Box<dyn MessageService>)
@@ -244,10 +957,10 @@ mod tests {
       .mount("/message", routes![send_message,]);
     let client = Client::new(rocket).expect("valid rocket instance");
 
-    let access_token_header = Header::new("x-access-token", "Bearer 1");
+    let access_token_header = Header::new("Authorization", "Bearer 1");
     let mut request = client
       .post("/message/send")
-      .body(r#"{ "from": 1, "to": 2, "message": "test message"}"#);
+      .body(r#"{ "to": 2, "message": "test message"}"#);
     request.add_header(ContentType::JSON);
     request.add_header(access_token_header);
 
@@ -257,31 +970,65 @@ mod tests {
     assert_eq!(response.body_string(), Some(String::from("{\"id\":\"1\"}")))
   }
 
+  #[test]
+  fn send_message_to_an_unknown_recipient() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_create()
+      .with(eq(1), eq(99), always(), always())
+      .times(1)
+      .returning(|_, _, _, _| Err(ServiceError::UnknownReference("recipient")));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![send_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut request = client
+      .post("/message/send")
+      .body(r#"{ "to": 99, "message": "test message"}"#);
+    request.add_header(ContentType::JSON);
+    request.add_header(Header::new("Authorization", "Bearer 1"));
+
+    let mut response = request.dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body = response.body_string().unwrap();
+    assert!(body.contains("\"type\":\"/problems/unknown-reference\""));
+    assert!(body.contains("unknown recipient"));
+  }
+
   #[test]
   fn send_message_unauthorized() {
     let mut mock_ms = MockMessageService::new();
     mock_ms
       .expect_create()
-      .with(always(), always(), always())
+      .with(always(), always(), always(), always())
       .times(0)
-      .returning(|_, _, _| Ok(1));
+      .returning(|_, _, _, _| Ok(1));
     let mut mock_auth = MockAuthenticator::new();
     mock_auth
-      .expect_authorize()
-      .with(always(), eq(1))
+      .expect_authenticate()
       .times(1)
-      .returning(|_, _| Err(NoPermissionError));
+      .returning(|_| Err(RevokedTokenError));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_ms) as Box<dyn MessageService>)
       .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
-      .mount("/message", routes![send_message,]);
+      .mount("/message", routes![send_message,])
+      .register(catchers![unauthorized]);
     let client = Client::new(rocket).expect("valid rocket instance");
 
     let access_token_header = Header::new("x-access-token", "Bearer 1");
     let mut request = client
       .post("/message/send")
-      .body(r#"{ "from": 1, "to": 2, "message": "test message"}"#);
+      .body(r#"{ "to": 2, "message": "test message"}"#);
     request.add_header(ContentType::JSON);
     request.add_header(access_token_header);
 
@@ -290,7 +1037,11 @@ mod tests {
     assert_eq!(response.status(), Status::Unauthorized);
     assert_eq!(
       response.body_string(),
-      Some(String::from("{\"message\":\"Access denied\"}"))
+      Some(String::from(
+        "{\"type\":\"/problems/unauthorized\",\"title\":\"Unauthorized\",\"\
+         status\":401,\"detail\":\"Access \
+         denied\",\"instance\":\"/message/send\"}"
+      ))
     )
   }
 
@@ -299,11 +1050,11 @@ mod tests {
     let mut mock_ms = MockMessageService::new();
     mock_ms
       .expect_create()
-      .with(always(), always(), always())
+      .with(always(), always(), always(), always())
       .times(0)
-      .returning(|_, _, _| Ok(1));
+      .returning(|_, _, _, _| Ok(1));
     let mut mock_auth = MockAuthenticator::new();
-    mock_auth.expect_authorize().with(always(), eq(1)).times(0);
+    mock_auth.expect_authenticate().times(0);
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_ms) as Box<dyn MessageService>)
@@ -313,34 +1064,114 @@ mod tests {
 
     let response = client
       .post("/message/send")
-      .body(r#"{ "from": 1, "to": 2, "message": "test message"}"#)
+      .body(r#"{ "to": 2, "message": "test message"}"#)
       .header(ContentType::JSON)
       .dispatch();
 
-    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.status(), Status::Unauthorized);
   }
 
   #[test]
-  fn get_message_ok() {
-    let message = Builder::new()
-      .with_id(1)
-      .with_from(1)
+  fn send_message_to_conversation_ok() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_create().times(0);
+    mock_ms
+      .expect_create_in_conversation()
+      .with(
+        eq(1),
+        eq(7),
+        eq(String::from("test message")),
+        eq(vec![3, 4]),
+      )
+      .times(1)
+      .returning(|_, _, _, _| Ok(10));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![send_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/message/send")
+      .body(
+        r#"{ "conversation_id": 7, "message": "test message",
+        "attachments": [3, 4]}"#,
+      )
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from("{\"id\":\"10\"}"))
+    )
+  }
+
+  #[test]
+  fn send_message_to_user_and_conversation() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_create().times(0);
+    mock_ms.expect_create_in_conversation().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![send_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/message/send")
+      .body(r#"{ "to": 2, "conversation_id": 7, "message": "test message"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+  }
+
+  #[test]
+  fn get_message_ok() {
+    let message = Builder::new()
+      .with_id(1)
+      .with_from(1)
       .with_to(2)
       .with_message("Some message")
       .build();
 
     let mut mock_ms = MockMessageService::new();
     mock_ms
-      .expect_get()
-      .with(eq(1))
+      .expect_open()
+      .with(eq(1), eq(2))
       .times(1)
-      .returning(move |_| Ok(message.clone()));
+      .returning(move |_, _| Ok(message.clone()));
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![1]))
+      .times(1)
+      .returning(|_| {
+        Ok(vec![AttachmentBuilder::new()
+          .with_id(3)
+          .with_user_id(1)
+          .with_message_id(1)
+          .build()])
+      });
     let mut mock_auth = MockAuthenticator::new();
     mock_auth
-      .expect_authorize()
-      .with(always(), eq(1))
-      .times(0)
-      .returning(|_, _| Ok(()));
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(2));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_ms) as Box<dyn MessageService>)
@@ -358,24 +1189,98 @@ mod tests {
     assert_eq!(response.status(), Status::Accepted);
     assert_eq!(
       response.body_string(),
-      Some(String::from("{\"message\":\"Some message\"}"))
+      Some(String::from(concat!(
+        "{\"message\":\"Some message\",",
+        "\"created_at\":\"1970-01-01T00:00:00+00:00\",",
+        "\"updated_at\":\"1970-01-01T00:00:00+00:00\",",
+        "\"attachments\":[{\"id\":3,\"filename\":\"file.txt\",",
+        "\"mime_type\":\"text/plain\",\"size\":7,",
+        "\"url\":\"/attachments/3\"}]}",
+      )))
     )
   }
 
+  #[test]
+  fn get_message_of_other_users() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_open()
+      .with(eq(1), eq(3))
+      .times(1)
+      .returning(|_, _| {
+        Err(ServiceError::Forbidden(String::from(
+          "The message belongs to other users",
+        )))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(3));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![get_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let access_token_header = Header::new("x-access-token", "Bearer 3");
+    let mut request = client.get("/message/1");
+    request.add_header(ContentType::JSON);
+    request.add_header(access_token_header);
+
+    let mut response = request.dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"type\":\"/problems/forbidden\",\"title\":\"Forbidden\",\"status\":\
+         403,\"detail\":\"Cannot retrieve the message because The message \
+         belongs to other users\",\"instance\":\"/message/1\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn get_message_unauthorized() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_open().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![get_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let access_token_header = Header::new("x-access-token", "Bearer 1");
+    let mut request = client.get("/message/1");
+    request.add_header(ContentType::JSON);
+    request.add_header(access_token_header);
+
+    let response = request.dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+  }
+
   #[test]
   fn get_message_non_existing() {
     let mut mock_ms = MockMessageService::new();
     mock_ms
-      .expect_get()
-      .with(eq(1))
+      .expect_open()
+      .with(eq(1), eq(1))
       .times(1)
-      .returning(|_| Err(String::from("some error")));
+      .returning(|_, _| Err(ServiceError::NotFound("message")));
     let mut mock_auth = MockAuthenticator::new();
     mock_auth
-      .expect_authorize()
-      .with(always(), eq(0))
-      .times(0)
-      .returning(|_, _| Ok(()));
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_ms) as Box<dyn MessageService>)
@@ -390,18 +1295,516 @@ mod tests {
 
     let mut response = request.dispatch();
 
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"type\":\"/problems/not-found\",\"title\":\"Not \
+         Found\",\"status\":404,\"detail\":\"Cannot retrieve the message \
+         because message not found\",\"instance\":\"/message/1\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn get_message_from_ok() {
+    let message = Builder::new()
+      .with_id(4)
+      .with_from(1)
+      .with_to(2)
+      .with_message("Some message")
+      .build();
+
+    let cursors = Cursors::new(b"secret");
+    let cursor = cursors.encode(SENT_LIST, Direction::After, 5);
+    let request = PageRequest::next_to(Direction::After, 5, Some(1));
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_find()
+      .with(eq(1), eq(request))
+      .times(1)
+      .returning(move |_, page| {
+        let older = Builder::new().with_id(3).with_message("Older").build();
+        let rows = vec![message.clone(), older];
+        Ok(Page::from_rows(rows, page, Message::get_id))
+      });
+    mock_ms
+      .expect_receipts()
+      .with(eq(vec![4]))
+      .times(1)
+      .returning(|_| {
+        Ok(vec![ReceiptBuilder::new()
+          .with_message_id(4)
+          .with_user_id(2)
+          .with_delivered_at(NaiveDateTime::from_timestamp(60, 0))
+          .build()])
+      });
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![4]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/message", routes![get_message_from,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get(format!("/message?cursor={}&limit=1", cursor))
+      .header(Header::new("Cookie", "session=1"))
+      .dispatch();
+
+    let next = cursors.encode(SENT_LIST, Direction::After, 4);
+    let prev = cursors.encode(SENT_LIST, Direction::Before, 4);
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.headers().get_one("Link"),
+      Some(
+        format!(
+          "</message?cursor={}&limit=1>; rel=\"next\", \
+           </message?cursor={}&limit=1>; rel=\"prev\"",
+          next, prev
+        )
+        .as_str()
+      )
+    );
+    assert_eq!(
+      response.body_string(),
+      Some(format!(
+        "{{\"items\":[{{\"id\":4,\"to\":2,\"message\":\"Some \
+         message\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"updated_at\"\
+         :\"1970-01-01T00:00:00+00:00\",\"delivered_at\":\"1970-01-01T00:01:\
+         00+00:00\"}}],\"next_cursor\":\"{}\",\"prev_cursor\":\"{}\"}}",
+        next, prev
+      ))
+    )
+  }
+
+  #[test]
+  fn get_message_from_with_a_forged_cursor() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_find().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let cursor = Cursors::new(b"other").encode(SENT_LIST, Direction::After, 5);
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/message", routes![get_message_from,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get(format!("/message?cursor={}", cursor))
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
     assert_eq!(response.status(), Status::BadRequest);
+  }
+
+  #[test]
+  fn get_inbox_ok() {
+    let message = Builder::new()
+      .with_id(7)
+      .with_from(2)
+      .with_to(1)
+      .with_message("Some message")
+      .build();
+
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_inbox()
+      .with(eq(1), eq(PageRequest::first(Some(10))))
+      .times(1)
+      .returning(move |_, page| {
+        Ok(Page::from_rows(
+          vec![message.clone()],
+          page,
+          Message::get_id,
+        ))
+      });
+    mock_ms.expect_find().times(0);
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![7]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/message", routes![get_inbox, get_message]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/message/inbox?limit=10")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(response.headers().get_one("Link"), None);
     assert_eq!(
       response.body_string(),
       Some(String::from(
-        "{\"message\":\"Cannot retrieve the message because some error\"}"
+        "{\"items\":[{\"id\":7,\"from\":2,\"to\":1,\"message\":\"Some \
+         message\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"updated_at\"\
+         :\"1970-01-01T00:00:00+00:00\"}]}"
       ))
     )
   }
 
   #[test]
-  fn get_message_from_ok() {}
+  fn poll_messages_caps_the_timeout() {
+    let message = Builder::new()
+      .with_id(8)
+      .with_from(2)
+      .with_to(1)
+      .with_message("Some message")
+      .build();
+
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_poll()
+      .with(
+        eq(1),
+        eq(7),
+        eq(None),
+        eq(Duration::from_secs(MAX_POLL_TIMEOUT)),
+      )
+      .times(1)
+      .returning(move |_, _, _, _| Ok(vec![message.clone()]));
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![8]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Polls::new(1))
+      .mount("/message", routes![poll_messages, get_message]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/message/poll?after=7&timeout=600")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "[{\"id\":8,\"from\":2,\"to\":1,\"message\":\"Some \
+         message\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"updated_at\"\
+         :\"1970-01-01T00:00:00+00:00\"}]"
+      ))
+    )
+  }
+
+  #[test]
+  fn poll_messages_when_too_many_polls_wait() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms.expect_poll().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Polls::new(0))
+      .mount("/message", routes![poll_messages]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get("/message/poll?after=7")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+  }
 
   #[test]
-  fn get_message_from_ok_empty_messages() {}
+  fn get_deleted_message() {
+    let message = Builder::new()
+      .with_id(1)
+      .with_from(1)
+      .with_to(2)
+      .with_message("Some message")
+      .with_deleted_at(NaiveDateTime::from_timestamp(60, 0))
+      .build();
+
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_open()
+      .with(eq(1), eq(2))
+      .times(1)
+      .returning(move |_, _| Ok(message.clone()));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(2));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![get_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/message/1")
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 2"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"message\":\"\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"\
+         updated_at\":\"1970-01-01T00:00:00+00:00\",\"deleted_at\":\"\
+         1970-01-01T00:01:00+00:00\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn edit_message_of_another_sender() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_edit()
+      .with(eq(1), eq(2), eq(String::from("Edited")))
+      .times(1)
+      .returning(|_, _, _| {
+        Err(ServiceError::Forbidden(String::from(
+          "Only the sender can change the message",
+        )))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(2));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![edit_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .patch("/message/1")
+      .body(r#"{ "message": "Edited"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("Authorization", "Bearer 2"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Forbidden);
+  }
+
+  #[test]
+  fn delete_message_ok() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_delete()
+      .with(eq(1), eq(1))
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![delete_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .delete("/message/1")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+  }
+
+  #[test]
+  fn read_message_of_another_recipient() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_mark_read()
+      .with(eq(1), eq(3))
+      .times(1)
+      .returning(|_, _| Err(ServiceError::NotFound("message")));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(3));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![read_message,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/message/1/read")
+      .header(Header::new("Authorization", "Bearer 3"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+  }
+
+  #[test]
+  fn get_unread_ok() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_unread()
+      .with(eq(1), eq(PageRequest::first(None)))
+      .times(1)
+      .returning(|_, page| {
+        let rows = vec![UnreadCount::new(2, 3), UnreadCount::new(5, 1)];
+        Ok(Page::from_rows(rows, page, UnreadCount::get_from))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/message", routes![get_unread, get_message]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/message/unread")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"items\":[{\"from\":2,\"count\":3},{\"from\":5,\"count\":1}]}"
+      ))
+    )
+  }
+  #[test]
+  fn search_messages_ok() {
+    let message = Builder::new()
+      .with_id(7)
+      .with_from(2)
+      .with_to(1)
+      .with_message("Lunch at noon")
+      .build();
+
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_search()
+      .with(eq(1), eq(String::from("lunch")), eq(None))
+      .times(1)
+      .returning(move |_, _, _| {
+        Ok(vec![SearchHit::new(
+          message.clone(),
+          String::from("<mark>Lunch</mark> at noon"),
+        )])
+      });
+    mock_ms
+      .expect_attachments()
+      .with(eq(vec![7]))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![search_messages, get_message]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/message/search?q=lunch")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "[{\"message\":{\"id\":7,\"from\":2,\"to\":1,\"message\":\"Lunch at \
+         noon\",\"created_at\":\"1970-01-01T00:00:00+00:00\",\"updated_at\":\"\
+         1970-01-01T00:00:00+00:00\"},\"snippet\":\"<mark>Lunch</mark> at \
+         noon\"}]"
+      ))
+    )
+  }
+
+  #[test]
+  fn search_messages_without_query() {
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_search()
+      .with(eq(1), eq(String::new()), eq(None))
+      .times(1)
+      .returning(|_, _, _| {
+        Err(ServiceError::InvalidInput(String::from(
+          "the query is empty",
+        )))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ms) as Box<dyn MessageService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/message", routes![search_messages, get_message]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .get("/message/search")
+      .header(Header::new("Authorization", "Bearer 1"))
+      .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+  }
 }
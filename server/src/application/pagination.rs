@@ -0,0 +1,309 @@
+use crate::{
+  application::error::{ApplicationResult, ErrorResponse},
+  crypto::{hmac_sha256, verify_hmac_sha256},
+  model::page::{Direction, Page, PageRequest},
+};
+
+use dotenv::dotenv;
+use rand::{rngs::OsRng, RngCore};
+use rocket::{
+  http::hyper::StatusCode,
+  response::{self, Responder},
+  Request,
+};
+use std::env;
+
+/// Signs and reads the cursors of the paged lists. A cursor is the position
+/// of a page, opaque for the clients, and it's signed with the name of its
+/// list so it can't be forged nor used in another list.
+pub struct Cursors {
+  secret: Vec<u8>,
+}
+
+impl Cursors {
+  pub fn new(the_secret: &[u8]) -> Self {
+    Cursors {
+      secret: the_secret.to_vec(),
+    }
+  }
+
+  /// Initialize the cursors with the `cursor_secret` variable. When it's not
+  /// set a random secret is used, and the cursors stop working when the app
+  /// restarts.
+  ///
+  /// # Return
+  /// * The cursors.
+  pub fn from_env() -> Self {
+    dotenv().ok();
+
+    match env::var("cursor_secret") {
+      Ok(secret) => Cursors::new(secret.as_bytes()),
+      Err(_) => {
+        log::warn!("cursor_secret is not set, the cursors expire on restart");
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        Cursors::new(&secret)
+      },
+    }
+  }
+
+  /// Get what the signature of a cursor covers: the name of its list and
+  /// its payload.
+  fn signed(list: &str, payload: &str) -> String {
+    format!("{}.{}", list, payload)
+  }
+
+  /// Creates the cursor of the page on a side of a row.
+  ///
+  /// # Arguments
+  /// * `list` - The name of the list.
+  /// * `direction` - The side of the row.
+  /// * `key` - The id of the row.
+  ///
+  /// # Return
+  /// * The cursor.
+  pub fn encode(&self, list: &str, direction: Direction, key: i32) -> String {
+    let side = match direction {
+      Direction::Before => "b",
+      Direction::After => "a",
+    };
+    let payload = base64::encode_config(
+      format!("{}:{}", side, key),
+      base64::URL_SAFE_NO_PAD,
+    );
+    let signature = base64::encode_config(
+      hmac_sha256(&self.secret, Cursors::signed(list, &payload).as_bytes()),
+      base64::URL_SAFE_NO_PAD,
+    );
+    format!("{}.{}", payload, signature)
+  }
+
+  /// Reads a cursor created by `encode`.
+  ///
+  /// # Arguments
+  /// * `list` - The name of the list.
+  /// * `cursor` - The cursor.
+  ///
+  /// # Return
+  /// * The side and the id of the row, none if the cursor isn't valid for the
+  ///   list.
+  pub fn decode(&self, list: &str, cursor: &str) -> Option<(Direction, i32)> {
+    let (payload, signature) = cursor.split_once('.')?;
+    let signature =
+      base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+    let signed = Cursors::signed(list, payload);
+    if !verify_hmac_sha256(&self.secret, signed.as_bytes(), &signature) {
+      return None;
+    }
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+      .ok()
+      .and_then(|bytes| String::from_utf8(bytes).ok())?;
+    let (side, key) = payload.split_once(':')?;
+    let direction = match side {
+      "b" => Direction::Before,
+      "a" => Direction::After,
+      _ => return None,
+    };
+    Some((direction, key.parse().ok()?))
+  }
+
+  /// Get the page requested with the query params of a list.
+  ///
+  /// # Arguments
+  /// * `list` - The name of the list.
+  /// * `cursor` - The cursor of the page, none for the first page.
+  /// * `limit` - The size of the page.
+  ///
+  /// # Return
+  /// * The request of the page.
+  /// * 400 Bad request if the cursor isn't valid.
+  pub fn page_request(
+    &self,
+    list: &str,
+    cursor: Option<String>,
+    limit: Option<i64>,
+  ) -> ApplicationResult<PageRequest> {
+    let cursor = match cursor {
+      Some(cursor) => cursor,
+      None => return Ok(PageRequest::first(limit)),
+    };
+    let (direction, key) = self.decode(list, &cursor).ok_or_else(|| {
+      ErrorResponse::create_error(
+        "The cursor isn't valid",
+        StatusCode::BadRequest,
+      )
+    })?;
+    Ok(PageRequest::next_to(direction, key, limit))
+  }
+
+  /// Creates the cursors of the pages next to a page.
+  ///
+  /// # Arguments
+  /// * `list` - The name of the list.
+  /// * `request` - The request of the page.
+  /// * `page` - The page.
+  ///
+  /// # Return
+  /// * The cursors of the next and the previous pages, if there are.
+  pub fn links<T>(
+    &self,
+    list: &str,
+    request: PageRequest,
+    page: &Page<T>,
+  ) -> PageLinks {
+    PageLinks {
+      next_cursor: page
+        .get_next_key()
+        .map(|key| self.encode(list, Direction::After, key)),
+      prev_cursor: page
+        .get_prev_key()
+        .map(|key| self.encode(list, Direction::Before, key)),
+      size: request.get_size(),
+    }
+  }
+}
+
+/// The cursors of the pages next to a page, and its size to keep it in the
+/// next requests.
+#[derive(Clone)]
+pub struct PageLinks {
+  next_cursor: Option<String>,
+  prev_cursor: Option<String>,
+  size: i64,
+}
+
+impl PageLinks {
+  pub fn get_next_cursor(&self) -> Option<String> {
+    return self.next_cursor.clone();
+  }
+
+  pub fn get_prev_cursor(&self) -> Option<String> {
+    return self.prev_cursor.clone();
+  }
+}
+
+/// A response with a page of a list. The URLs of the next and the previous
+/// pages go in a `Link` header, as defined by the RFC 8288.
+pub struct Paginated<R> {
+  inner: R,
+  links: PageLinks,
+}
+
+impl<R> Paginated<R> {
+  pub fn new(the_inner: R, the_links: PageLinks) -> Self {
+    Paginated {
+      inner: the_inner,
+      links: the_links,
+    }
+  }
+}
+
+/// Creates the URL of a page of the list of a request, keeping the other
+/// params of its query, like the filters of the list.
+///
+/// # Arguments
+/// * `path` - The path of the request.
+/// * `query` - The query of the request, if it has one.
+/// * `cursor` - The cursor of the page.
+/// * `size` - The size of the page.
+///
+/// # Return
+/// * The URL of the page.
+fn page_url(
+  path: &str,
+  query: Option<&str>,
+  cursor: &str,
+  size: i64,
+) -> String {
+  let kept = query
+    .unwrap_or_default()
+    .split('&')
+    .filter(|param| {
+      let name = param.split('=').next().unwrap_or_default();
+      !name.is_empty() && name != "cursor" && name != "limit"
+    })
+    .map(|param| format!("{}&", param))
+    .collect::<String>();
+  format!("{}?{}cursor={}&limit={}", path, kept, cursor, size)
+}
+
+impl<'r, R: Responder<'r>> Responder<'r> for Paginated<R> {
+  fn respond_to(self, request: &Request) -> response::Result<'r> {
+    let mut response = self.inner.respond_to(request)?;
+    let path = request.uri().path();
+    let query = request.uri().query();
+    let size = self.links.size;
+    let links = [
+      (self.links.next_cursor, "next"),
+      (self.links.prev_cursor, "prev"),
+    ]
+    .iter()
+    .filter_map(|(cursor, rel)| {
+      cursor.as_ref().map(|cursor| {
+        let url = page_url(path, query, cursor, size);
+        format!("<{}>; rel=\"{}\"", url, rel)
+      })
+    })
+    .collect::<Vec<String>>();
+    if !links.is_empty() {
+      response.set_raw_header("Link", links.join(", "));
+    }
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decode_an_encoded_cursor() {
+    let cursors = Cursors::new(b"secret");
+    let cursor = cursors.encode("users", Direction::Before, 42);
+
+    assert_eq!(
+      cursors.decode("users", &cursor),
+      Some((Direction::Before, 42))
+    );
+  }
+
+  #[test]
+  fn cursor_of_another_list_or_secret() {
+    let cursor = Cursors::new(b"secret").encode("users", Direction::After, 42);
+
+    assert_eq!(Cursors::new(b"secret").decode("sessions", &cursor), None);
+    assert_eq!(Cursors::new(b"other").decode("users", &cursor), None);
+  }
+
+  #[test]
+  fn forged_cursor() {
+    let cursors = Cursors::new(b"secret");
+    let cursor = cursors.encode("users", Direction::After, 42);
+    let (_, signature) = cursor.split_once('.').unwrap();
+    let forged = format!(
+      "{}.{}",
+      base64::encode_config("a:1", base64::URL_SAFE_NO_PAD),
+      signature
+    );
+
+    assert_eq!(cursors.decode("users", &forged), None);
+    assert_eq!(cursors.decode("users", "not a cursor"), None);
+  }
+
+  #[test]
+  fn page_url_keeps_the_other_params() {
+    assert_eq!(
+      page_url("/users", None, "abc", 10),
+      "/users?cursor=abc&limit=10"
+    );
+    assert_eq!(
+      page_url(
+        "/search",
+        Some("q=lunch%20time&cursor=old&limit=5"),
+        "abc",
+        5
+      ),
+      "/search?q=lunch%20time&cursor=abc&limit=5"
+    );
+  }
+}
@@ -0,0 +1,81 @@
+use dotenv::dotenv;
+use std::{
+  env,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Limits the quantity of long-polls waiting at once. Every waiting poll
+/// holds a worker of Rocket, so without a limit the polls could take all of
+/// them and the API would stop answering. The real-time server limits its
+/// connections the same way.
+pub struct Polls {
+  waiting: AtomicUsize,
+  max_waiting: usize,
+}
+
+/// A long-poll allowed to wait, released when it's dropped.
+pub struct Permit<'a>(&'a AtomicUsize);
+
+impl Drop for Permit<'_> {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl Polls {
+  pub fn new(the_max_waiting: usize) -> Self {
+    Polls {
+      waiting: AtomicUsize::new(0),
+      max_waiting: the_max_waiting,
+    }
+  }
+
+  /// Initialize the limit with the `poll_max_waiting` variable, half of the
+  /// workers when it's not set so the other requests always get some.
+  ///
+  /// # Arguments
+  /// * `workers` - The quantity of workers of Rocket.
+  ///
+  /// # Return
+  /// * The limit of the long-polls.
+  pub fn from_env(workers: usize) -> Self {
+    dotenv().ok();
+
+    Polls::new(
+      env::var("poll_max_waiting")
+        .ok()
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(workers / 2),
+    )
+  }
+
+  /// Lets a long-poll wait if the limit isn't reached.
+  ///
+  /// # Return
+  /// * The permit of the poll, None if too many polls are waiting.
+  pub fn acquire(&self) -> Option<Permit<'_>> {
+    let permit = Permit(&self.waiting);
+    if self.waiting.fetch_add(1, Ordering::SeqCst) < self.max_waiting {
+      Some(permit)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn acquire_up_to_the_limit() {
+    let polls = Polls::new(1);
+
+    let permit = polls.acquire();
+    assert!(permit.is_some());
+    assert!(polls.acquire().is_none());
+
+    drop(permit);
+    assert!(polls.acquire().is_some());
+  }
+}
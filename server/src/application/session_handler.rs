@@ -0,0 +1,331 @@
+use crate::{
+  application::{
+    error::{ApplicationResult, ErrorResponse},
+    pagination::{Cursors, PageLinks, Paginated},
+  },
+  auth::middleware::AuthenticatedUser,
+  model::login::Login,
+  Authenticator, UserService,
+};
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use rocket::{
+  response::status::{Accepted, NoContent},
+  State,
+};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+use utoipa::Component;
+
+/// The name of the paged list of sessions, its cursors are only valid for it.
+const SESSIONS_LIST: &str = "sessions";
+
+/// List a page of the open sessions of the owner of the access token, the
+/// most recently opened first.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of sessions.
+///
+/// # Return
+/// * 202 Accepted and the page of sessions, with the `Link` header of the next
+///   and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 500 Internal error for any other error.
+#[utoipa::path(
+context_path = "/sessions",
+params(
+  ("cursor" = Option<String>, query, description = "The cursor of the page"),
+  ("limit" = Option<i64>, query, description = "The max quantity of sessions"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = SessionPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 500, description = "Internal error")
+),
+)]
+#[get("/?<cursor>&<limit>")]
+pub fn list_sessions(
+  us_state: State<Box<dyn UserService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<SessionPageDto>>>> {
+  let user_service = us_state.inner();
+  let request = cursors.page_request(SESSIONS_LIST, cursor, limit)?;
+
+  let page = user_service
+    .sessions(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the sessions because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let sessions_dto = page
+    .get_items()
+    .iter()
+    .map(SessionDto::from)
+    .collect::<Vec<SessionDto>>();
+  let links = cursors.links(SESSIONS_LIST, request, &page);
+  let page_dto = SessionPageDto::new(sessions_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Close one of the sessions of the owner of the access token. The access and
+/// refresh tokens of the session stop working.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `auth_state` - The authenticator that caches the tokens of the session.
+/// * `user` - The authenticated user.
+/// * `id` - The id of the session to close.
+///
+/// # Return
+/// * 204 No content.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 404 Not found if the session doesn't belong to the user.
+#[utoipa::path(
+context_path = "/sessions",
+params(
+  ("id" = i32, description = "The id of the session"),
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 204, description = "The session was closed"),
+(status = 401, description = "Unauthorized user"),
+(status = 404, description = "Session not found")
+),
+)]
+#[delete("/<id>")]
+pub fn revoke_session(
+  us_state: State<Box<dyn UserService>>,
+  auth_state: State<Box<dyn Authenticator>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let user_service = us_state.inner();
+  let authenticator = auth_state.inner();
+
+  let session =
+    user_service
+      .revoke_session(user.get_id(), id)
+      .map_err(|err| {
+        log::debug!("{}", err.to_string());
+        let err_msg = format!("Cannot close the session because {}", err);
+        ErrorResponse::from_service_error(&err_msg, &err)
+      })?;
+  authenticator.forget(session.get_jti());
+  Ok(NoContent)
+}
+
+/// Formats a timestamp stored in UTC as RFC 3339.
+pub fn to_rfc3339(date: NaiveDateTime) -> String {
+  Utc.from_utc_datetime(&date).to_rfc3339()
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "id": 1,
+  "device": "laptop",
+  "user_agent": "Mozilla/5.0",
+  "ip": "127.0.0.1",
+  "created_at": "2022-08-10T18:00:00+00:00",
+  "last_seen_at": "2022-08-10T18:30:00+00:00"
+}))]
+pub struct SessionDto {
+  id: i32,
+  device: Option<String>,
+  user_agent: Option<String>,
+  ip: Option<String>,
+  created_at: String,
+  last_seen_at: String,
+}
+
+/// A page of sessions, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "id": 1,
+    "device": "laptop",
+    "user_agent": "Mozilla/5.0",
+    "ip": "127.0.0.1",
+    "created_at": "2022-08-10T18:00:00+00:00",
+    "last_seen_at": "2022-08-10T18:30:00+00:00"
+  }],
+  "prev_cursor": "Yjox.s9xVNzBbqMB3ZbKhDeFZj6lWmVxK0xt9W1lVZnk4Kic"
+}))]
+pub struct SessionPageDto {
+  items: Vec<SessionDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl SessionPageDto {
+  pub fn new(the_items: Vec<SessionDto>, links: &PageLinks) -> Self {
+    SessionPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&Login> for SessionDto {
+  fn from(login: &Login) -> Self {
+    SessionDto {
+      id: login.get_id(),
+      device: login.get_device(),
+      user_agent: login.get_user_agent(),
+      ip: login.get_ip(),
+      created_at: to_rfc3339(login.get_created_at()),
+      last_seen_at: to_rfc3339(login.get_last_seen_at()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::token::MockAuthenticator,
+    model::{
+      error::Error as ServiceError,
+      login::Builder,
+      page::{Direction, Page, PageRequest},
+      user_service::MockUserService,
+    },
+  };
+  use mockall::predicate::eq;
+  use rocket::{
+    http::{Header, Status},
+    local::Client,
+  };
+
+  #[test]
+  fn list_sessions_ok() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_sessions()
+      .with(eq(1), eq(PageRequest::first(Some(1))))
+      .times(1)
+      .returning(|_, page| {
+        let session = |the_id| {
+          Builder::new()
+            .with_id(the_id)
+            .with_user_id(1)
+            .with_username("juan")
+            .with_token("token")
+            .with_device("laptop")
+            .build()
+        };
+        let rows = vec![session(3), session(2)];
+        Ok(Page::from_rows(rows, page, Login::get_id))
+      });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount("/sessions", routes![list_sessions,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .get("/sessions?limit=1")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    let next =
+      Cursors::new(b"secret").encode(SESSIONS_LIST, Direction::After, 3);
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.headers().get_one("Link"),
+      Some(
+        format!("</sessions?cursor={}&limit=1>; rel=\"next\"", next).as_str()
+      )
+    );
+    assert_eq!(
+      response.body_string(),
+      Some(format!(
+        concat!(
+          "{{\"items\":[{{\"id\":3,\"device\":\"laptop\",",
+          "\"user_agent\":null,\"ip\":null,",
+          "\"created_at\":\"1970-01-01T00:00:00+00:00\",",
+          "\"last_seen_at\":\"1970-01-01T00:00:00+00:00\"}}],",
+          "\"next_cursor\":\"{}\"}}",
+        ),
+        next
+      ))
+    )
+  }
+
+  #[test]
+  fn revoke_session_of_another_user() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_revoke_session()
+      .with(eq(1), eq(3))
+      .times(1)
+      .returning(|_, _| Err(ServiceError::NotFound("session")));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+    mock_auth.expect_forget().times(0);
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/sessions", routes![revoke_session,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .delete("/sessions/3")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+  }
+
+  #[test]
+  fn revoke_session_with_a_store_failure() {
+    let mut mock_us = MockUserService::new();
+    mock_us.expect_revoke_session().times(1).returning(|_, _| {
+      Err(ServiceError::Internal(String::from("disk I/O error")))
+    });
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+    mock_auth.expect_forget().times(0);
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/sessions", routes![revoke_session,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .delete("/sessions/3")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::InternalServerError);
+  }
+}
@@ -1,11 +1,16 @@
 use crate::{
-  application::error::{ApplicationResult, ErrorResponse},
+  application::error::{ApplicationResult, ErrorResponse, FieldError},
+  auth::{
+    middleware::{session_cookie, AccessToken, ClientInfo},
+    token::ACCESS_TOKEN_MINUTES,
+  },
+  model::{error::Error as ServiceError, login::Device},
   Authenticator, UserService,
 };
 
 use rocket::{
-  http::hyper::StatusCode,
-  response::status::{Accepted, Created},
+  http::{hyper::StatusCode, Cookies},
+  response::status::{Accepted, Created, NoContent},
   State,
 };
 use rocket_contrib::json::Json;
@@ -25,14 +30,21 @@ use utoipa::Component;
 /// * 400 Bad request for any exception in the creation of the user, with
 ///   specific
 /// description.
+/// * 409 Conflict if the username is taken.
+/// * 422 Unprocessable entity and the invalid fields if the username or the
+///   password are empty.
 /// * 500 Internal error for any other error.
+/// * 503 Service unavailable if the database can't be reached.
 #[utoipa::path(
 context_path = "/users",
 request_body = UserDto,
 responses(
 (status = 201, description = "The user was created", body = ResponseUserDto),
-(status = 400, description = "Bad request"),
-(status = 500, description = "Internal error")
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 409, description = "The username is taken", body = ErrorResponse),
+(status = 422, description = "Invalid fields", body = ErrorResponse),
+(status = 500, description = "Internal error"),
+(status = 503, description = "Database unavailable")
 ),
 )]
 #[post("/", format = "application/json", data = "<new_user_dto>")]
@@ -42,6 +54,15 @@ pub fn create_user(
 ) -> ApplicationResult<Created<Json<ResponseUserDto>>> {
   let user_service = us_state.inner();
 
+  let errors = new_user_dto.validate();
+  if !errors.is_empty() {
+    log::debug!("invalid user {:?}", errors);
+    return Err(ErrorResponse::validation_error(
+      "The user is not valid",
+      errors,
+    ));
+  }
+
   let id_user = user_service
     .create_user(
       new_user_dto.username.to_string(),
@@ -54,7 +75,7 @@ pub fn create_user(
         err
       );
       log::error!("{}", err_msg);
-      ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
+      ErrorResponse::from_service_error(&err_msg, &err)
     })?;
 
   log::info!("new username {}", new_user_dto.username);
@@ -69,60 +90,267 @@ pub fn create_user(
 }
 
 /// Login a user. Checks if the username exist and if the password is the same.
-/// This login generates a Jason Web Token with a expiration of 1 day.
-/// If already exists another session for the user the a new token is generated
-/// and replace the old one.
+/// This login generates a short lived Jason Web Token and a refresh token used
+/// to get new ones. If already exists another session for the user the a new
+/// token is generated and replace the old one. The token is also set in an
+/// HttpOnly session cookie for the browser clients.
 ///
 /// # Arguments
 /// * `us_state` - The user service.
 /// * `auth_state` - The authenticator used to validate the access token.
-/// * `user_dto` - The user data to make the login.
+/// * `client` - The user agent and ip of the client.
+/// * `cookies` - The cookies of the response.
+/// * `user_dto` - The user data to make the login and an optional device label.
 ///
 /// # Return
-/// * 202 Accepted and the Jason Web Token (JWT).
+/// * 202 Accepted, the Jason Web Token (JWT) and the refresh token.
 /// * 400 Bad request and the error message.
+/// * 503 Service unavailable if the database can't be reached.
 #[utoipa::path(
 context_path = "/login",
-request_body = UserDto,
+request_body = LoginRequestDto,
 responses(
 (status = 202, description = "Login correct", body = LoginDto),
-(status = 400, description = "Bad request")
+(status = 400, description = "Bad request"),
+(status = 503, description = "Database unavailable")
 ),
 )]
 #[post("/", format = "application/json", data = "<user_dto>")]
 pub fn login(
   us_state: State<Box<dyn UserService>>,
   auth_state: State<Box<dyn Authenticator>>,
-  user_dto: Json<UserDto>,
+  client: ClientInfo,
+  mut cookies: Cookies,
+  user_dto: Json<LoginRequestDto>,
 ) -> ApplicationResult<Accepted<Json<LoginDto>>> {
   let user_service = us_state.inner();
   let authenticator = auth_state.inner();
 
   let user = user_service
     .find_user(user_dto.username.to_string(), user_dto.password.to_string())
+    .map_err(|err| match err {
+      ServiceError::Unavailable | ServiceError::Internal(_) => {
+        log::error!("error: {}", err.to_string());
+        ErrorResponse::from_service_error("Cannot make the login", &err)
+      },
+      _ => {
+        log::debug!("{}", err.to_string());
+        let err_msg = String::from("Invalid credentials");
+        ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
+      },
+    })?;
+  let token = authenticator
+    .create_token(user.get_id(), user.get_role())
     .map_err(|err| {
       log::debug!("{}", err.to_string());
-      let err_msg = String::from("Invalid credentials");
-      ErrorResponse::create_error(&err_msg, StatusCode::BadRequest)
+      let err_msg = String::from("Cannot create the token");
+      ErrorResponse::create_error(&err_msg, StatusCode::InternalServerError)
     })?;
-  let token = authenticator.create_token(user.get_id()).map_err(|err| {
-    log::debug!("{}", err.to_string());
-    let err_msg = String::from("Cannot create the token");
-    ErrorResponse::create_error(&err_msg, StatusCode::InternalServerError)
-  })?;
-  let login = user_service.login(user.borrow(), token).map_err(|err| {
-    log::debug!("{}", err.to_string());
-    let err_msg = String::from("Cannot make the login");
-    ErrorResponse::create_error(&err_msg, StatusCode::InternalServerError)
-  })?;
+  let device = Device::new(
+    user_dto.device.clone(),
+    client.get_user_agent(),
+    client.get_ip(),
+  );
+  let login = user_service
+    .login(user.borrow(), token.get_token(), token.get_jti(), device)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = String::from("Cannot make the login");
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let refresh_token = user_service
+    .issue_refresh_token(login.borrow())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = String::from("Cannot create the refresh token");
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  cookies.add(session_cookie(login.get_token()));
 
   let dto = LoginDto {
     token: login.get_token(),
     id: login.get_id(),
+    refresh_token,
+    expires_in: ACCESS_TOKEN_MINUTES * 60,
   };
   Ok(Accepted(Option::from(Json(dto))))
 }
 
+/// Exchanges a refresh token for a new access token and a new refresh token.
+/// The refresh token presented can't be used again, presenting it twice
+/// revokes every token derived from the same login. The session keeps its id
+/// and its new access token replaces the old one, carrying the current role of
+/// the user. The session cookie is updated with the new access token.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `auth_state` - The authenticator used to create the access token.
+/// * `cookies` - The cookies of the response.
+/// * `refresh_dto` - The refresh token.
+///
+/// # Return
+/// * 202 Accepted, the Jason Web Token (JWT) and the new refresh token.
+/// * 401 Unauthorized if the refresh token is invalid, expired or reused, or
+///   the user is suspended.
+#[utoipa::path(
+context_path = "/login",
+request_body = RefreshDto,
+responses(
+(status = 202, description = "Tokens rotated", body = LoginDto),
+(status = 401, description = "Invalid refresh token")
+),
+)]
+#[post("/refresh", format = "application/json", data = "<refresh_dto>")]
+pub fn refresh(
+  us_state: State<Box<dyn UserService>>,
+  auth_state: State<Box<dyn Authenticator>>,
+  mut cookies: Cookies,
+  refresh_dto: Json<RefreshDto>,
+) -> ApplicationResult<Accepted<Json<LoginDto>>> {
+  let user_service = us_state.inner();
+  let authenticator = auth_state.inner();
+
+  let (session, refresh_token) = user_service
+    .rotate_refresh_token(refresh_dto.refresh_token.to_string())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = String::from("Invalid refresh token");
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let user = user_service
+    .get_user(session.get_user_id())
+    .ok()
+    .filter(|user| !user.is_suspended())
+    .ok_or_else(|| {
+      let err_msg = String::from("Invalid refresh token");
+      ErrorResponse::create_error(&err_msg, StatusCode::Unauthorized)
+    })?;
+  let token = authenticator
+    .create_token(user.get_id(), user.get_role())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = String::from("Cannot create the token");
+      ErrorResponse::create_error(&err_msg, StatusCode::InternalServerError)
+    })?;
+  let login = user_service
+    .renew_session(session.borrow(), token.get_token(), token.get_jti())
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = String::from("Cannot make the login");
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  authenticator.forget(session.get_jti());
+  cookies.add(session_cookie(login.get_token()));
+
+  let dto = LoginDto {
+    token: login.get_token(),
+    id: login.get_id(),
+    refresh_token,
+    expires_in: ACCESS_TOKEN_MINUTES * 60,
+  };
+  Ok(Accepted(Option::from(Json(dto))))
+}
+
+/// Logout the session of the given access token. If a refresh token of the
+/// same user is sent, its family is revoked too. The session cookie is
+/// removed.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `auth_state` - The authenticator used to revoke the access token.
+/// * `token` - The access token to revoke.
+/// * `cookies` - The cookies of the response.
+/// * `logout_dto` - The optional refresh token of the session.
+///
+/// # Return
+/// * 204 No content.
+/// * 401 Unauthorized if the token isn't valid.
+#[utoipa::path(
+context_path = "/logout",
+request_body = LogoutDto,
+params(
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 204, description = "Logout correct"),
+(status = 401, description = "Unauthorized user")
+),
+)]
+#[post("/", data = "<logout_dto>")]
+pub fn logout(
+  us_state: State<Box<dyn UserService>>,
+  auth_state: State<Box<dyn Authenticator>>,
+  token: AccessToken,
+  mut cookies: Cookies,
+  logout_dto: Option<Json<LogoutDto>>,
+) -> ApplicationResult<NoContent> {
+  let user_service = us_state.inner();
+  let authenticator = auth_state.inner();
+
+  let uid = authenticator.revoke(token.borrow()).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    ErrorResponse::create_error("Access denied", StatusCode::Unauthorized)
+  })?;
+  cookies.remove(session_cookie(String::new()));
+  if let Some(refresh_token) =
+    logout_dto.and_then(|dto| dto.into_inner().refresh_token)
+  {
+    user_service
+      .revoke_refresh_token(uid, refresh_token)
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        let err_msg = String::from("Cannot revoke the refresh token");
+        ErrorResponse::from_service_error(&err_msg, &err)
+      })?;
+  }
+  Ok(NoContent)
+}
+
+/// Logout every session of the owner of the access token, revoking all of its
+/// access and refresh tokens. The session cookie is removed.
+///
+/// # Arguments
+/// * `us_state` - The user service.
+/// * `auth_state` - The authenticator used to revoke the access tokens.
+/// * `token` - The access token that identifies the user.
+/// * `cookies` - The cookies of the response.
+///
+/// # Return
+/// * 204 No content.
+/// * 401 Unauthorized if the token isn't valid.
+#[utoipa::path(
+context_path = "/logout",
+params(
+  ("Authorization", header, description = "The jwt token access"),
+),
+responses(
+(status = 204, description = "Logout correct"),
+(status = 401, description = "Unauthorized user")
+),
+)]
+#[post("/all")]
+pub fn logout_all(
+  us_state: State<Box<dyn UserService>>,
+  auth_state: State<Box<dyn Authenticator>>,
+  token: AccessToken,
+  mut cookies: Cookies,
+) -> ApplicationResult<NoContent> {
+  let user_service = us_state.inner();
+  let authenticator = auth_state.inner();
+
+  let uid = authenticator.revoke_all(token.borrow()).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    ErrorResponse::create_error("Access denied", StatusCode::Unauthorized)
+  })?;
+  cookies.remove(session_cookie(String::new()));
+  user_service.revoke_refresh_tokens(uid).map_err(|err| {
+    log::error!("error: {}", err.to_string());
+    let err_msg = String::from("Cannot revoke the refresh tokens");
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  Ok(NoContent)
+}
+
 #[derive(Deserialize, Component)]
 #[component(example = json!({"username": "juan", "password": "password"}))]
 pub struct UserDto {
@@ -130,6 +358,33 @@ pub struct UserDto {
   password: String,
 }
 
+impl UserDto {
+  /// Checks the fields of the new user.
+  ///
+  /// # Return
+  /// * The invalid fields, empty if the user is valid.
+  fn validate(&self) -> Vec<FieldError> {
+    let mut errors = vec![];
+    if self.username.trim().is_empty() {
+      errors.push(FieldError::new("username", "must not be empty"));
+    }
+    if self.password.is_empty() {
+      errors.push(FieldError::new("password", "must not be empty"));
+    }
+    errors
+  }
+}
+
+#[derive(Deserialize, Component)]
+#[component(
+  example = json!({"username": "juan", "password": "password", "device": "laptop"})
+)]
+pub struct LoginRequestDto {
+  username: String,
+  password: String,
+  device: Option<String>,
+}
+
 #[derive(Serialize, Component)]
 #[component(example = json!({"id": 1, "username": "juan"}))]
 pub struct ResponseUserDto {
@@ -138,25 +393,44 @@ pub struct ResponseUserDto {
 }
 
 #[derive(Serialize, Component)]
-#[component(example = json!({"id": 1, "token": "xxx"}))]
+#[component(
+  example = json!({"id": 1, "token": "xxx", "refresh_token": "yyy", "expires_in": 900})
+)]
 pub struct LoginDto {
   id: i32,
   token: String,
+  refresh_token: String,
+  expires_in: i64,
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"refresh_token": "yyy"}))]
+pub struct RefreshDto {
+  refresh_token: String,
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({"refresh_token": "yyy"}))]
+pub struct LogoutDto {
+  refresh_token: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
   use crate::{
-    auth::token::MockAuthenticator,
+    auth::{
+      error::Error::RevokedTokenError,
+      token::{IssuedToken, MockAuthenticator},
+    },
     model::{
-      login::Builder, user::Builder as UserBuilder,
+      login::Builder, role::Role, user::Builder as UserBuilder,
       user_service::MockUserService,
     },
   };
   use mockall::predicate::{always, eq};
   use rocket::{
-    http::{ContentType, Status},
+    http::{ContentType, Header, Status},
     local::Client,
   };
 
@@ -193,7 +467,11 @@ mod tests {
       .expect_create_user()
       .with(eq(String::from("juan")), eq(String::from("password")))
       .times(1)
-      .returning(|_, _| Err(String::from("cannot create user")));
+      .returning(|_, _| {
+        Err(ServiceError::AlreadyExists(String::from(
+          "the username juan",
+        )))
+      });
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_us) as Box<dyn UserService>)
@@ -205,16 +483,46 @@ mod tests {
       .body(r#"{ "username": "juan", "password": "password"}"#)
       .header(ContentType::JSON)
       .dispatch();
-    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(response.status(), Status::Conflict);
     assert_eq!(
       response.body_string(),
       Some(String::from(
-        "{\"message\":\"Cannot insert the username juan because cannot create \
-         user\"}"
+        "{\"type\":\"/problems/already-exists\",\"title\":\"Conflict\",\"\
+         status\":409,\"detail\":\"Cannot insert the username juan because \
+         the username juan already exists\",\"instance\":\"/users\"}"
       ))
     )
   }
 
+  #[test]
+  fn create_user_invalid_fields() {
+    let mut mock_us = MockUserService::new();
+    mock_us.expect_create_user().times(0);
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .mount("/users", routes![create_user,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/users")
+      .body(r#"{ "username": " ", "password": ""}"#)
+      .header(ContentType::JSON)
+      .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(concat!(
+        "{\"type\":\"/problems/validation\",",
+        "\"title\":\"Unprocessable Entity\",\"status\":422,",
+        "\"detail\":\"The user is not valid\",\"instance\":\"/users\",",
+        "\"errors\":[{\"field\":\"username\",",
+        "\"message\":\"must not be empty\"},{\"field\":\"password\",",
+        "\"message\":\"must not be empty\"}]}",
+      )))
+    )
+  }
+
   #[test]
   fn login_ok() {
     let mut mock_us = MockUserService::new();
@@ -237,16 +545,25 @@ mod tests {
       .build();
     mock_us
       .expect_login()
-      .with(always(), eq(String::from("my_token")))
+      .with(
+        always(),
+        eq(String::from("my_token")),
+        eq(String::from("my_jti")),
+        eq(Device::new(Some(String::from("laptop")), None, None)),
+      )
+      .times(1)
+      .returning(move |_, _, _, _| Ok(login.clone()));
+    mock_us
+      .expect_issue_refresh_token()
       .times(1)
-      .returning(move |_, _| Ok(login.clone()));
+      .returning(|_| Ok(String::from("my_refresh")));
 
     let mut mock_auth = MockAuthenticator::new();
     mock_auth
       .expect_create_token()
-      .with(eq(1))
+      .with(eq(1), eq(Role::User))
       .times(1)
-      .returning(|_| Ok("my_token".to_string()));
+      .returning(|_, _| Ok(IssuedToken::new("my_token", "my_jti")));
 
     let rocket = rocket::ignite()
       .manage(Box::new(mock_us) as Box<dyn UserService>)
@@ -256,13 +573,23 @@ mod tests {
 
     let mut response = client
       .post("/login")
-      .body(r#"{ "username": "juan", "password": "password"}"#)
+      .body(
+        r#"{ "username": "juan", "password": "password", "device": "laptop"}"#,
+      )
       .header(ContentType::JSON)
       .dispatch();
     assert_eq!(response.status(), Status::Accepted);
+    let cookie = response.headers().get_one("Set-Cookie").unwrap();
+    assert!(cookie.starts_with("session=my_token"));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("Secure"));
+    assert!(cookie.contains("SameSite=Strict"));
     assert_eq!(
       response.body_string(),
-      Some(String::from("{\"id\":1,\"token\":\"my_token\"}"))
+      Some(String::from(
+        "{\"id\":1,\"token\":\"my_token\",\"refresh_token\":\"my_refresh\",\"\
+         expires_in\":900}"
+      ))
     )
   }
 
@@ -273,7 +600,7 @@ mod tests {
       .expect_find_user()
       .with(eq(String::from("juan")), eq(String::from("password")))
       .times(1)
-      .returning(move |_, _| Err(String::from("invalid password")));
+      .returning(move |_, _| Err(ServiceError::InvalidCredentials));
     mock_us.expect_login().times(0);
 
     let mut mock_auth = MockAuthenticator::new();
@@ -293,7 +620,209 @@ mod tests {
     assert_eq!(response.status(), Status::BadRequest);
     assert_eq!(
       response.body_string(),
-      Some(String::from("{\"message\":\"Invalid credentials\"}"))
+      Some(String::from(
+        "{\"type\":\"/problems/bad-request\",\"title\":\"Bad \
+         Request\",\"status\":400,\"detail\":\"Invalid \
+         credentials\",\"instance\":\"/login\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn refresh_ok() {
+    let mut mock_us = MockUserService::new();
+    let session = Builder::new()
+      .with_id(1)
+      .with_user_id(1)
+      .with_username("juan")
+      .with_token("old_token")
+      .with_jti("old_jti")
+      .build();
+    mock_us
+      .expect_rotate_refresh_token()
+      .with(eq(String::from("old_refresh")))
+      .times(1)
+      .returning(move |_| Ok((session.clone(), String::from("new_refresh"))));
+    mock_us
+      .expect_get_user()
+      .with(eq(1))
+      .times(1)
+      .returning(|_| {
+        Ok(
+          UserBuilder::new()
+            .with_id(1)
+            .with_username("juan")
+            .with_hashed_password("password")
+            .build(),
+        )
+      });
+
+    let login = Builder::new()
+      .with_id(1)
+      .with_user_id(1)
+      .with_username("juan")
+      .with_token("my_token")
+      .build();
+    mock_us
+      .expect_renew_session()
+      .with(
+        always(),
+        eq(String::from("my_token")),
+        eq(String::from("my_jti")),
+      )
+      .times(1)
+      .returning(move |_, _, _| Ok(login.clone()));
+
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_create_token()
+      .with(eq(1), eq(Role::User))
+      .times(1)
+      .returning(|_, _| Ok(IssuedToken::new("my_token", "my_jti")));
+    mock_auth
+      .expect_forget()
+      .with(eq(String::from("old_jti")))
+      .times(1)
+      .returning(|_| ());
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/login", routes![refresh,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/login/refresh")
+      .body(r#"{ "refresh_token": "old_refresh"}"#)
+      .header(ContentType::JSON)
+      .dispatch();
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"id\":1,\"token\":\"my_token\",\"refresh_token\":\"new_refresh\",\"\
+         expires_in\":900}"
+      ))
+    )
+  }
+
+  #[test]
+  fn refresh_reused_token() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_rotate_refresh_token()
+      .times(1)
+      .returning(|_| {
+        Err(ServiceError::Unauthorized(String::from(
+          "Refresh token already used",
+        )))
+      });
+    mock_us.expect_renew_session().times(0);
+
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth.expect_create_token().times(0);
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/login", routes![refresh,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/login/refresh")
+      .body(r#"{ "refresh_token": "old_refresh"}"#)
+      .header(ContentType::JSON)
+      .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"type\":\"/problems/unauthorized\",\"title\":\"Unauthorized\",\"\
+         status\":401,\"detail\":\"Invalid refresh \
+         token\",\"instance\":\"/login/refresh\"}"
+      ))
+    )
+  }
+
+  #[test]
+  fn logout_with_refresh_token() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_revoke_refresh_token()
+      .with(eq(1), eq(String::from("my_refresh")))
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth.expect_revoke().times(1).returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/logout", routes![logout,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/logout")
+      .body(r#"{ "refresh_token": "my_refresh"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+  }
+
+  #[test]
+  fn logout_all_ok() {
+    let mut mock_us = MockUserService::new();
+    mock_us
+      .expect_revoke_refresh_tokens()
+      .with(eq(1))
+      .times(1)
+      .returning(|_| Ok(()));
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth.expect_revoke_all().times(1).returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/logout", routes![logout_all,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let response = client
+      .post("/logout/all")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+  }
+
+  #[test]
+  fn logout_revoked_token() {
+    let mut mock_us = MockUserService::new();
+    mock_us.expect_revoke_refresh_token().times(0);
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_revoke()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_us) as Box<dyn UserService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .mount("/logout", routes![logout,]);
+    let client = Client::new(rocket).expect("valid rocket instance");
+
+    let mut response = client
+      .post("/logout")
+      .body(r#"{ "refresh_token": "my_refresh"}"#)
+      .header(ContentType::JSON)
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+      response.body_string(),
+      Some(String::from(
+        "{\"type\":\"/problems/unauthorized\",\"title\":\"Unauthorized\",\"\
+         status\":401,\"detail\":\"Access denied\",\"instance\":\"/logout\"}"
+      ))
     )
   }
 }
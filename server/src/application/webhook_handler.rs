@@ -0,0 +1,562 @@
+use crate::{
+  application::{
+    error::{ApplicationResult, ErrorResponse, FieldError, GenericResponse},
+    pagination::{Cursors, PageLinks, Paginated},
+    session_handler::to_rfc3339,
+  },
+  auth::middleware::AuthenticatedUser,
+  model::webhook::{Delivery, Webhook, WebhookEvent},
+  WebhookService,
+};
+
+use rocket::{
+  response::status::{Accepted, Created, NoContent},
+  State,
+};
+use rocket_contrib::json::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::Component;
+
+/// The names of the paged lists, their cursors are only valid for them.
+const WEBHOOKS_LIST: &str = "webhooks";
+const DEAD_LIST: &str = "dead-deliveries";
+
+/// Get a page of the webhooks of the owner of the access token, without their
+/// secrets.
+///
+/// # Arguments
+/// * `wh_state` - The webhook service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of webhooks.
+///
+/// # Return
+/// * 202 Accepted and the page of webhooks ordered by id, with the `Link`
+///   header of the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/webhooks",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of webhooks"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = WebhookPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/?<cursor>&<limit>")]
+pub fn list_webhooks(
+  wh_state: State<Box<dyn WebhookService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<WebhookPageDto>>>> {
+  let webhook_service = wh_state.inner();
+  let request = cursors.page_request(WEBHOOKS_LIST, cursor, limit)?;
+
+  let page = webhook_service
+    .list(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the webhooks because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let webhooks_dto = page
+    .get_items()
+    .iter()
+    .map(WebhookDto::from)
+    .collect::<Vec<WebhookDto>>();
+  let links = cursors.links(WEBHOOKS_LIST, request, &page);
+  let page_dto = WebhookPageDto::new(webhooks_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Register a webhook for the owner of the access token. The secret that
+/// signs its requests is only returned here.
+///
+/// # Arguments
+/// * `wh_state` - The webhook service.
+/// * `user` - The authenticated user who owns the webhook.
+/// * `webhook_dto` - The URL and the events of the webhook.
+///
+/// # Return
+/// * 201 Created and the id and the secret of the webhook.
+/// * 400 Bad request if the URL isn't http or https or there are no events.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 403 Forbidden if a user subscribes to `user.created`.
+/// * 422 Unprocessable entity if an event is unknown.
+#[utoipa::path(
+context_path = "/webhooks",
+request_body = CreateWebhookDto,
+params(
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 201, description = "The webhook was registered"),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 403, description = "Only the admins can subscribe to the event"),
+(status = 422, description = "Unknown event", body = ErrorResponse)
+),
+)]
+#[post("/", format = "application/json", data = "<webhook_dto>")]
+pub fn create_webhook(
+  wh_state: State<Box<dyn WebhookService>>,
+  user: AuthenticatedUser,
+  webhook_dto: Json<CreateWebhookDto>,
+) -> ApplicationResult<Created<Json<GenericResponse>>> {
+  let webhook_service = wh_state.inner();
+
+  let webhook_dto = webhook_dto.into_inner();
+  let events = webhook_dto
+    .events
+    .iter()
+    .map(|event| event.parse())
+    .collect::<Result<Vec<WebhookEvent>, String>>()
+    .map_err(|err| {
+      log::debug!("{}", err);
+      ErrorResponse::validation_error(
+        "The webhook is not valid",
+        vec![FieldError::new(
+          "events",
+          "must be message.created, message.read or user.created",
+        )],
+      )
+    })?;
+  let (id, secret) = webhook_service
+    .register(user.get_id(), webhook_dto.url, events)
+    .map_err(|err| {
+      log::debug!("{}", err.to_string());
+      let err_msg = format!("Cannot register the webhook because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+
+  let mut response = GenericResponse::new();
+  response.insert(String::from("id"), id.to_string());
+  response.insert(String::from("secret"), secret);
+  Ok(Created(
+    format!("/webhooks/{}", id),
+    Option::from(Json(response)),
+  ))
+}
+
+/// Delete a webhook of the owner of the access token, with the deliveries
+/// still in its outbox.
+///
+/// # Arguments
+/// * `wh_state` - The webhook service.
+/// * `user` - The authenticated user.
+/// * `id` - The id of the webhook.
+///
+/// # Return
+/// * 204 No content.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 404 Not found if the user doesn't own the webhook.
+#[utoipa::path(
+context_path = "/webhooks",
+params(
+("id" = i32, description = "The id of the webhook"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The webhook was deleted"),
+(status = 401, description = "Unauthorized user"),
+(status = 404, description = "Webhook not found")
+),
+)]
+#[delete("/<id>")]
+pub fn delete_webhook(
+  wh_state: State<Box<dyn WebhookService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let webhook_service = wh_state.inner();
+
+  webhook_service.remove(id, user.get_id()).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot delete the webhook because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  Ok(NoContent)
+}
+
+/// Get a page of the deliveries to the webhooks of the owner of the access
+/// token that failed every attempt, the newest first.
+///
+/// # Arguments
+/// * `wh_state` - The webhook service.
+/// * `cursors` - The cursors of the paged lists.
+/// * `user` - The authenticated user.
+/// * `cursor` - The cursor of the page, the first page by default.
+/// * `limit` - The max quantity of deliveries.
+///
+/// # Return
+/// * 202 Accepted and the page of dead deliveries, with the `Link` header of
+///   the next and previous pages.
+/// * 400 Bad request if the cursor isn't valid.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 503 Service unavailable if the database can't be reached.
+#[utoipa::path(
+context_path = "/webhooks",
+params(
+("cursor" = Option<String>, query, description = "The cursor of the page"),
+("limit" = Option<i64>, query, description = "The max quantity of deliveries"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 202, description = "Accepted", body = DeliveryPageDto),
+(status = 400, description = "Bad request", body = ErrorResponse),
+(status = 401, description = "Unauthorized user"),
+(status = 503, description = "Database unavailable")
+),
+)]
+#[get("/dead?<cursor>&<limit>")]
+pub fn get_dead_letters(
+  wh_state: State<Box<dyn WebhookService>>,
+  cursors: State<Cursors>,
+  user: AuthenticatedUser,
+  cursor: Option<String>,
+  limit: Option<i64>,
+) -> ApplicationResult<Paginated<Accepted<Json<DeliveryPageDto>>>> {
+  let webhook_service = wh_state.inner();
+  let request = cursors.page_request(DEAD_LIST, cursor, limit)?;
+
+  let page = webhook_service
+    .dead_letters(user.get_id(), request)
+    .map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      let err_msg = format!("Cannot retrieve the deliveries because {}", err);
+      ErrorResponse::from_service_error(&err_msg, &err)
+    })?;
+  let deliveries_dto = page
+    .get_items()
+    .iter()
+    .map(DeliveryDto::from)
+    .collect::<Vec<DeliveryDto>>();
+  let links = cursors.links(DEAD_LIST, request, &page);
+  let page_dto = DeliveryPageDto::new(deliveries_dto, &links);
+  Ok(Paginated::new(
+    Accepted(Option::from(Json(page_dto))),
+    links,
+  ))
+}
+
+/// Send again a dead delivery of a webhook of the owner of the access token,
+/// with a new round of attempts.
+///
+/// # Arguments
+/// * `wh_state` - The webhook service.
+/// * `user` - The authenticated user.
+/// * `id` - The id of the delivery.
+///
+/// # Return
+/// * 204 No content.
+/// * 401 Unauthorized if the token isn't valid.
+/// * 404 Not found if it isn't a dead delivery of the user.
+#[utoipa::path(
+context_path = "/webhooks",
+params(
+("id" = i32, description = "The id of the delivery"),
+("Authorization", header, description = "The token access"),
+),
+responses(
+(status = 204, description = "The delivery is back in the outbox"),
+(status = 401, description = "Unauthorized user"),
+(status = 404, description = "Delivery not found")
+),
+)]
+#[post("/dead/<id>/retry")]
+pub fn retry_delivery(
+  wh_state: State<Box<dyn WebhookService>>,
+  user: AuthenticatedUser,
+  id: i32,
+) -> ApplicationResult<NoContent> {
+  let webhook_service = wh_state.inner();
+
+  webhook_service.retry(id, user.get_id()).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    let err_msg = format!("Cannot retry the delivery because {}", err);
+    ErrorResponse::from_service_error(&err_msg, &err)
+  })?;
+  Ok(NoContent)
+}
+
+#[derive(Deserialize, Component)]
+#[component(example = json!({
+  "url": "https://example.com/hooks",
+  "events": ["message.created", "message.read"]
+}))]
+pub struct CreateWebhookDto {
+  url: String,
+  events: Vec<String>,
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "id": 1,
+  "url": "https://example.com/hooks",
+  "events": ["message.created", "message.read"],
+  "created_at": "2022-09-10T09:00:00+00:00"
+}))]
+pub struct WebhookDto {
+  id: i32,
+  url: String,
+  events: Vec<String>,
+  created_at: String,
+}
+
+/// A page of webhooks, with the cursors of the next and previous pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "id": 1,
+    "url": "https://example.com/hooks",
+    "events": ["message.created"],
+    "created_at": "2022-09-10T09:00:00+00:00"
+  }],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct WebhookPageDto {
+  items: Vec<WebhookDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl WebhookPageDto {
+  pub fn new(the_items: Vec<WebhookDto>, links: &PageLinks) -> Self {
+    WebhookPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&Webhook> for WebhookDto {
+  fn from(webhook: &Webhook) -> Self {
+    WebhookDto {
+      id: webhook.get_id(),
+      url: webhook.get_url(),
+      events: webhook
+        .get_events()
+        .iter()
+        .map(WebhookEvent::to_string)
+        .collect(),
+      created_at: to_rfc3339(webhook.get_created_at()),
+    }
+  }
+}
+
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "id": 8,
+  "webhook_id": 1,
+  "event": "message.created",
+  "payload": "{\"event\":\"message.created\",\"data\":{}}",
+  "attempts": 8,
+  "last_error": "https://example.com/hooks: status code 503",
+  "created_at": "2022-09-10T09:00:00+00:00",
+  "dead_at": "2022-09-10T11:07:30+00:00"
+}))]
+pub struct DeliveryDto {
+  id: i32,
+  webhook_id: i32,
+  event: String,
+  payload: String,
+  attempts: i32,
+  last_error: Option<String>,
+  created_at: String,
+  dead_at: Option<String>,
+}
+
+/// A page of dead deliveries, with the cursors of the next and previous
+/// pages.
+#[derive(Serialize, Component)]
+#[component(example = json!({
+  "items": [{
+    "id": 8,
+    "webhook_id": 1,
+    "event": "message.created",
+    "payload": "{\"event\":\"message.created\",\"data\":{}}",
+    "attempts": 8,
+    "last_error": "the webhook answered 503",
+    "created_at": "2022-09-10T09:00:00+00:00",
+    "dead_at": "2022-09-10T11:07:30+00:00"
+  }],
+  "next_cursor": "YToxMA.1kD8vwHMSkQDVdxBL7Vn4S3Gm3_wsU3cK0g5UQrzYLs"
+}))]
+pub struct DeliveryPageDto {
+  items: Vec<DeliveryDto>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  prev_cursor: Option<String>,
+}
+
+impl DeliveryPageDto {
+  pub fn new(the_items: Vec<DeliveryDto>, links: &PageLinks) -> Self {
+    DeliveryPageDto {
+      items: the_items,
+      next_cursor: links.get_next_cursor(),
+      prev_cursor: links.get_prev_cursor(),
+    }
+  }
+}
+
+impl From<&Delivery> for DeliveryDto {
+  fn from(delivery: &Delivery) -> Self {
+    DeliveryDto {
+      id: delivery.get_id(),
+      webhook_id: delivery.get_webhook_id(),
+      event: delivery.get_event(),
+      payload: delivery.get_payload(),
+      attempts: delivery.get_attempts(),
+      last_error: delivery.get_last_error(),
+      created_at: to_rfc3339(delivery.get_created_at()),
+      dead_at: delivery.get_dead_at().map(to_rfc3339),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::token::MockAuthenticator,
+    model::{
+      error::Error as ServiceError,
+      page::{Direction, Page, PageRequest},
+      webhook::{Builder, Webhook},
+      webhook_service::MockWebhookService,
+    },
+    Authenticator,
+  };
+  use mockall::predicate::eq;
+  use rocket::{
+    http::{ContentType, Header, Status},
+    local::Client,
+  };
+
+  fn client_with(mock_ws: MockWebhookService) -> Client {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_authenticate()
+      .times(1)
+      .returning(|_| Ok(1));
+
+    let rocket = rocket::ignite()
+      .manage(Box::new(mock_ws) as Box<dyn WebhookService>)
+      .manage(Box::new(mock_auth) as Box<dyn Authenticator>)
+      .manage(Cursors::new(b"secret"))
+      .mount(
+        "/webhooks",
+        routes![
+          list_webhooks,
+          create_webhook,
+          delete_webhook,
+          get_dead_letters,
+          retry_delivery
+        ],
+      );
+    Client::new(rocket).expect("valid rocket instance")
+  }
+
+  #[test]
+  fn list_webhooks_without_the_secret() {
+    let mut mock_ws = MockWebhookService::new();
+    mock_ws
+      .expect_list()
+      .with(eq(1), eq(PageRequest::first(Some(1))))
+      .times(1)
+      .returning(|_, page| {
+        let webhook = |hook_id| {
+          Builder::new()
+            .with_id(hook_id)
+            .with_url("https://localhost/hook")
+            .build()
+        };
+        let rows = vec![webhook(2), webhook(3)];
+        Ok(Page::from_rows(rows, page, Webhook::get_id))
+      });
+
+    let client = client_with(mock_ws);
+    let mut response = client
+      .get("/webhooks?limit=1")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    let next =
+      Cursors::new(b"secret").encode(WEBHOOKS_LIST, Direction::After, 2);
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(
+      response.body_string(),
+      Some(format!(
+        "{{\"items\":[{{\"id\":2,\"url\":\"https://localhost/hook\",\"\
+         events\":[\"message.created\"],\"created_at\":\"\
+         1970-01-01T00:00:00+00:00\"}}],\"next_cursor\":\"{}\"}}",
+        next
+      ))
+    )
+  }
+
+  #[test]
+  fn get_dead_letters_with_a_cursor_of_another_list() {
+    let mut mock_ws = MockWebhookService::new();
+    mock_ws.expect_dead_letters().times(0);
+
+    let cursor =
+      Cursors::new(b"secret").encode(WEBHOOKS_LIST, Direction::After, 2);
+    let client = client_with(mock_ws);
+    let response = client
+      .get(format!("/webhooks/dead?cursor={}", cursor))
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+  }
+
+  #[test]
+  fn create_webhook_with_an_unknown_event() {
+    let mut mock_ws = MockWebhookService::new();
+    mock_ws.expect_register().times(0);
+
+    let client = client_with(mock_ws);
+    let response = client
+      .post("/webhooks")
+      .header(ContentType::JSON)
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .body(
+        "{\"url\": \"https://localhost/hook\", \"events\": [\"user.deleted\"]}",
+      )
+      .dispatch();
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+  }
+
+  #[test]
+  fn retry_a_delivery_of_another_user() {
+    let mut mock_ws = MockWebhookService::new();
+    mock_ws
+      .expect_retry()
+      .with(eq(8), eq(1))
+      .times(1)
+      .returning(|_, _| Err(ServiceError::NotFound("delivery")));
+
+    let client = client_with(mock_ws);
+    let response = client
+      .post("/webhooks/dead/8/retry")
+      .header(Header::new("x-access-token", "Bearer 1"))
+      .dispatch();
+    assert_eq!(response.status(), Status::NotFound);
+  }
+}
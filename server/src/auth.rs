@@ -1,3 +1,5 @@
+pub mod cache;
 pub mod error;
+pub mod keys;
 pub mod middleware;
 pub mod token;
@@ -0,0 +1,115 @@
+use std::{
+  collections::HashMap,
+  sync::RwLock,
+  time::{Duration, Instant},
+};
+
+/// How long a status looked up in the login store is trusted.
+const TTL_SECONDS: u64 = 30;
+/// Max number of entries kept before the stale ones are purged.
+const MAX_ENTRIES: usize = 10_000;
+
+struct CacheEntry {
+  uid: i32,
+  active: bool,
+  checked_at: Instant,
+}
+
+/// An in-memory cache of the status (active or revoked) of the token ids, so
+/// every request doesn't need to hit the login store. The revocations made by
+/// this process evict the entries right away, the ones made by other
+/// processes are seen after at most `TTL_SECONDS`.
+pub struct TokenCache {
+  entries: RwLock<HashMap<String, CacheEntry>>,
+  ttl: Duration,
+}
+
+impl TokenCache {
+  pub fn new() -> Self {
+    TokenCache {
+      entries: RwLock::new(HashMap::new()),
+      ttl: Duration::from_secs(TTL_SECONDS),
+    }
+  }
+
+  /// Look for a fresh status of a token id.
+  ///
+  /// # Arguments
+  /// * `jti` - The id of the token.
+  ///
+  /// # Return
+  /// * The status if it's cached and not stale, None otherwise.
+  pub fn get(&self, jti: &str) -> Option<bool> {
+    let entries = self.entries.read().ok()?;
+    entries
+      .get(jti)
+      .filter(|entry| entry.checked_at.elapsed() < self.ttl)
+      .map(|entry| entry.active)
+  }
+
+  /// Stores the status of a token id.
+  ///
+  /// # Arguments
+  /// * `jti` - The id of the token.
+  /// * `uid` - The owner of the token.
+  /// * `active` - If the token can still be used.
+  pub fn insert(&self, jti: &str, uid: i32, active: bool) {
+    if let Ok(mut entries) = self.entries.write() {
+      if entries.len() >= MAX_ENTRIES {
+        let ttl = self.ttl;
+        entries.retain(|_, entry| entry.checked_at.elapsed() < ttl);
+      }
+      entries.insert(
+        jti.to_string(),
+        CacheEntry {
+          uid,
+          active,
+          checked_at: Instant::now(),
+        },
+      );
+    }
+  }
+
+  /// Removes the status of a token id.
+  ///
+  /// # Arguments
+  /// * `jti` - The id of the token.
+  pub fn evict(&self, jti: &str) {
+    if let Ok(mut entries) = self.entries.write() {
+      entries.remove(jti);
+    }
+  }
+
+  /// Removes the status of every token of a user.
+  ///
+  /// # Arguments
+  /// * `uid` - The owner of the tokens.
+  pub fn evict_uid(&self, uid: i32) {
+    if let Ok(mut entries) = self.entries.write() {
+      entries.retain(|_, entry| entry.uid != uid);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn evicts_by_jti_and_uid() {
+    let cache = TokenCache::new();
+    cache.insert("a", 1, true);
+    cache.insert("b", 1, true);
+    cache.insert("c", 2, false);
+
+    assert_eq!(cache.get("a"), Some(true));
+    assert_eq!(cache.get("c"), Some(false));
+
+    cache.evict("a");
+    assert_eq!(cache.get("a"), None);
+
+    cache.evict_uid(1);
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("c"), Some(false));
+  }
+}
@@ -16,4 +16,10 @@ pub enum Error {
   InvalidAuthHeaderError,
   #[error("no permission")]
   NoPermissionError,
+  #[error("jwt token revoked")]
+  RevokedTokenError,
+  #[error("cannot read the token store")]
+  TokenStoreError,
+  #[error("invalid jwt key")]
+  InvalidKeyError,
 }
@@ -1,14 +1,30 @@
 use std::{fmt, fmt::Formatter};
 
 use rocket::{
-  http::Status,
+  http::{Cookie, SameSite, Status},
   request::{FromRequest, Outcome},
-  Request,
+  Request, State,
 };
 
+use crate::{
+  auth::{
+    error::Error,
+    token::{Authenticator, BEARER},
+  },
+  model::role::Role,
+};
+
+/// The name of the HttpOnly cookie that carries the access token of the
+/// browser clients.
+pub const SESSION_COOKIE: &str = "session";
+
 pub struct AccessToken(String);
 
 impl AccessToken {
+  pub fn new(token: &str) -> Self {
+    AccessToken(token.to_string())
+  }
+
   pub fn get_token(&self) -> String {
     self.0.to_string()
   }
@@ -20,34 +36,218 @@ impl fmt::Display for AccessToken {
   }
 }
 
+/// Builds the session cookie that carries an access token for the browser
+/// clients. It's HttpOnly so scripts can't read it, SameSite strict so other
+/// sites can't send it and Secure so it's only sent over HTTPS.
+///
+/// # Arguments
+/// * `token` - The access token, without the Bearer prefix.
+///
+/// # Return
+/// * The cookie.
+pub fn session_cookie(token: String) -> Cookie<'static> {
+  Cookie::build(SESSION_COOKIE, token)
+    .path("/")
+    .http_only(true)
+    .secure(true)
+    .same_site(SameSite::Strict)
+    .finish()
+}
+
 #[derive(Debug)]
 pub enum AccessTokenError {
   BadCount,
   Missing,
 }
 
-/// Implements the FromRequest trait to make the header x-access-token appear in
-/// the guards of every endpoint.
+/// Implements the FromRequest trait to make the access token appear in the
+/// guards of every endpoint. The token is taken from the standard
+/// `Authorization` header, the x-access-token header or, for browser clients,
+/// the session cookie set by the login.
 ///
 /// # Return
-/// * Success and an AccessToken struct if the header is present.
-/// * Failure with AccessTokenError::Missing if there is no value for the
-///   header.
-/// * Failure with AccessTokenError::BasCount if there is more than one value
-///   for the header.
+/// * Success and an AccessToken struct, always in the Bearer form, if the token
+///   is present.
+/// * Failure with 401 Unauthorized and AccessTokenError::Missing if there is no
+///   token.
+/// * Failure with 400 Bad request and AccessTokenError::BadCount if there is
+///   more than one token in the headers.
 impl<'a, 'r> FromRequest<'a, 'r> for AccessToken {
   type Error = AccessTokenError;
 
   fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
-    let tokens: Vec<&str> = request.headers().get("x-access-token").collect();
-    let route = request.route().unwrap();
-    let uri = request.uri();
-    log::debug!("uri: {}, method: {}", uri.path(), route.name.unwrap());
+    let mut tokens: Vec<&str> =
+      request.headers().get("Authorization").collect();
+    tokens.extend(request.headers().get("x-access-token"));
+    log::debug!(
+      "uri: {}, method: {}",
+      request.uri().path(),
+      request.method()
+    );
 
     match tokens.len() {
-      0 => Outcome::Failure((Status::BadRequest, AccessTokenError::Missing)),
+      0 => match request.cookies().get(SESSION_COOKIE) {
+        Some(cookie) => {
+          Outcome::Success(AccessToken(format!("{}{}", BEARER, cookie.value())))
+        },
+        None => {
+          Outcome::Failure((Status::Unauthorized, AccessTokenError::Missing))
+        },
+      },
       1 => Outcome::Success(AccessToken(tokens[0].to_string())),
       _ => Outcome::Failure((Status::BadRequest, AccessTokenError::BadCount)),
     }
   }
 }
+
+/// The outcome of authenticating the access token of a request, kept in the
+/// request cache so the token is decoded only once.
+struct Authentication(Result<i32, Status>);
+
+/// Authenticates the access token of a request.
+///
+/// # Arguments
+/// * `request` - The incoming request.
+///
+/// # Return
+/// * The uid of the owner of the token.
+/// * The status of the failure, 401 Unauthorized if the token is missing or
+///   isn't valid, 400 Bad request if there are many tokens.
+fn authenticate(request: &Request) -> Result<i32, Status> {
+  let token = match request.guard::<AccessToken>() {
+    Outcome::Success(token) => token,
+    Outcome::Failure((status, _)) => return Err(status),
+    Outcome::Forward(_) => return Err(Status::Unauthorized),
+  };
+  let auth_state = match request.guard::<State<Box<dyn Authenticator>>>() {
+    Outcome::Success(auth_state) => auth_state,
+    _ => return Err(Status::InternalServerError),
+  };
+
+  auth_state.inner().authenticate(&token).map_err(|err| {
+    log::debug!("{}", err.to_string());
+    Status::Unauthorized
+  })
+}
+
+/// A request guard with the uid of the owner of a valid access token. The
+/// handlers use it instead of taking the uid from the request.
+pub struct AuthenticatedUser(i32);
+
+impl AuthenticatedUser {
+  pub fn get_id(&self) -> i32 {
+    self.0
+  }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AuthenticatedUser {
+  type Error = ();
+
+  fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+    let authentication =
+      request.local_cache(|| Authentication(authenticate(request)));
+
+    match authentication.0 {
+      Ok(uid) => Outcome::Success(AuthenticatedUser(uid)),
+      Err(status) => Outcome::Failure((status, ())),
+    }
+  }
+}
+
+/// The description of the client that makes a request, used to label the
+/// sessions.
+pub struct ClientInfo {
+  user_agent: Option<String>,
+  ip: Option<String>,
+}
+
+impl ClientInfo {
+  pub fn get_user_agent(&self) -> Option<String> {
+    self.user_agent.clone()
+  }
+
+  pub fn get_ip(&self) -> Option<String> {
+    self.ip.clone()
+  }
+}
+
+/// Implements the FromRequest trait to collect the user agent and the ip of
+/// the client. It never fails, the missing values are None.
+impl<'a, 'r> FromRequest<'a, 'r> for ClientInfo {
+  type Error = ();
+
+  fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+    Outcome::Success(ClientInfo {
+      user_agent: request.headers().get_one("User-Agent").map(String::from),
+      ip: request.client_ip().map(|ip| ip.to_string()),
+    })
+  }
+}
+
+#[derive(Debug)]
+pub enum RoleError {
+  Unauthenticated,
+  Forbidden,
+  Unavailable,
+}
+
+/// Authenticates the access token of a request and checks that its role
+/// grants the required one. Used by the guards of the restricted endpoints.
+///
+/// # Arguments
+/// * `request` - The incoming request.
+/// * `role` - The role required.
+///
+/// # Return
+/// * Success and the uid of the owner of the token.
+/// * Failure with 401 Unauthorized if the token is missing or invalid.
+/// * Failure with 403 Forbidden if the role of the token isn't enough.
+fn require_role(request: &Request, role: Role) -> Outcome<i32, RoleError> {
+  let token = match request.guard::<AccessToken>() {
+    Outcome::Success(token) => token,
+    _ => {
+      return Outcome::Failure((
+        Status::Unauthorized,
+        RoleError::Unauthenticated,
+      ))
+    },
+  };
+  let auth_state = match request.guard::<State<Box<dyn Authenticator>>>() {
+    Outcome::Success(auth_state) => auth_state,
+    _ => {
+      return Outcome::Failure((
+        Status::InternalServerError,
+        RoleError::Unavailable,
+      ))
+    },
+  };
+
+  match auth_state.inner().require_role(&token, role) {
+    Ok(uid) => Outcome::Success(uid),
+    Err(Error::NoPermissionError) => {
+      Outcome::Failure((Status::Forbidden, RoleError::Forbidden))
+    },
+    Err(err) => {
+      log::debug!("{}", err.to_string());
+      Outcome::Failure((Status::Unauthorized, RoleError::Unauthenticated))
+    },
+  }
+}
+
+/// A request guard that only lets through the requests made with the access
+/// token of an admin.
+pub struct AdminUser(i32);
+
+impl AdminUser {
+  pub fn get_id(&self) -> i32 {
+    self.0
+  }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AdminUser {
+  type Error = RoleError;
+
+  fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+    require_role(request, Role::Admin).map(AdminUser)
+  }
+}
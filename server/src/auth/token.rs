@@ -1,39 +1,106 @@
-use crate::auth::middleware::AccessToken;
-use chrono::prelude::*;
-use jsonwebtoken::{
-  decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+use crate::{
+  auth::{
+    cache::TokenCache,
+    keys::{load_key_store, JwkSet, KeyStore},
+    middleware::AccessToken,
+  },
+  model::{
+    refresh_token::generate_token,
+    repository::login_repository::LoginRepository, role::Role,
+  },
 };
+use chrono::prelude::*;
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 
 use crate::auth::error::{AuthResult, Error};
 
 use dotenv::dotenv;
-use std::env;
 
 #[cfg(test)]
 use mockall::automock;
 
-const BEARER: &str = "Bearer ";
+pub const BEARER: &str = "Bearer ";
+/// Lifetime of the access tokens, the clients renew them with a refresh token.
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Claims {
   sub: i32,
   exp: usize,
+  jti: String,
+  #[serde(default)]
+  role: Role,
+}
+
+/// A recently created access token along with its unique id (jti claim).
+#[derive(Debug, Clone)]
+pub struct IssuedToken {
+  token: String,
+  jti: String,
+}
+
+impl IssuedToken {
+  pub fn new(the_token: &str, the_jti: &str) -> Self {
+    IssuedToken {
+      token: the_token.to_string(),
+      jti: the_jti.to_string(),
+    }
+  }
+
+  pub fn get_token(&self) -> String {
+    self.token.to_string()
+  }
+
+  pub fn get_jti(&self) -> String {
+    self.jti.to_string()
+  }
+}
+
+/// The session of a long-lived connection: the user and the login it was
+/// opened with. The login outlives the expiry and the renewals of the access
+/// tokens, and is closed by a logout, a revocation or a suspension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Session {
+  uid: i32,
+  login: i32,
+}
+
+impl Session {
+  pub fn new(the_uid: i32, the_login: i32) -> Self {
+    Session {
+      uid: the_uid,
+      login: the_login,
+    }
+  }
+
+  pub fn get_uid(&self) -> i32 {
+    self.uid
+  }
+
+  pub fn get_login(&self) -> i32 {
+    self.login
+  }
 }
 
 #[cfg_attr(test, automock)]
 pub trait Authenticator: Send + Sync {
-  /// Create a Jason Web Token, based on a uid using the HS512 Algorithm.
+  /// Create a short lived Jason Web Token, based on a uid, signed with the
+  /// current signing key. The token expires after `ACCESS_TOKEN_MINUTES` and
+  /// carries a unique id that must be stored in the login store to be
+  /// accepted.
   ///
   /// # Arguments
   /// * `uid` - The uid of the entity that needs a token.
+  /// * `role` - The role of the entity, embedded in the token.
   ///
   /// # Return
-  /// * A string that represents the Jason Web Token.
+  /// * The Jason Web Token and its unique id.
   /// * A JWTTokenCreationError in case of failed.
-  fn create_token(&self, uid: i32) -> AuthResult<String>;
+  fn create_token(&self, uid: i32, role: Role) -> AuthResult<IssuedToken>;
 
-  /// Authorize an uid if the access token is valid and belongs to the uid.
+  /// Authorize an uid if the access token is valid, belongs to the uid and
+  /// it wasn't revoked or superseded by a newer login.
   ///
   /// # Arguments
   /// * `token` - The access token to validate. Must be in the Bearer form.
@@ -42,19 +109,124 @@ pub trait Authenticator: Send + Sync {
   /// # Return
   /// * Nothing if the validation was successful.
   /// * JWTTokenError if an error occur in the decode process.
+  /// * RevokedTokenError if the token is no longer in the login store.
   /// * NoPermissionError if the token doesn't belong to the uid.
   fn authorize(&self, token: &AccessToken, uid: i32) -> AuthResult<()>;
+
+  /// Authenticate an access token, checking that it's valid and that it wasn't
+  /// revoked or superseded by a newer login.
+  ///
+  /// # Arguments
+  /// * `token` - The access token to validate. Must be in the Bearer form.
+  ///
+  /// # Return
+  /// * The uid of the owner of the token.
+  /// * JWTTokenError if an error occur in the decode process.
+  /// * RevokedTokenError if the token is no longer in the login store.
+  fn authenticate(&self, token: &AccessToken) -> AuthResult<i32>;
+
+  /// Authenticate an access token and check that its role grants the required
+  /// one.
+  ///
+  /// # Arguments
+  /// * `token` - The access token to validate. Must be in the Bearer form.
+  /// * `role` - The role required.
+  ///
+  /// # Return
+  /// * The uid of the owner of the token.
+  /// * JWTTokenError or RevokedTokenError if the token isn't valid.
+  /// * NoPermissionError if the role of the token isn't enough.
+  fn require_role(&self, token: &AccessToken, role: Role) -> AuthResult<i32>;
+
+  /// Revokes the given access token.
+  ///
+  /// # Arguments
+  /// * `token` - The access token to revoke. Must be in the Bearer form.
+  ///
+  /// # Return
+  /// * The uid of the owner of the token.
+  /// * JWTTokenError or RevokedTokenError if the token isn't valid.
+  /// * TokenStoreError if the login store fails.
+  fn revoke(&self, token: &AccessToken) -> AuthResult<i32>;
+
+  /// Revokes every access token of the owner of the given token.
+  ///
+  /// # Arguments
+  /// * `token` - The access token used to identify the user. Must be in the
+  ///   Bearer form.
+  ///
+  /// # Return
+  /// * The uid of the owner of the tokens.
+  /// * JWTTokenError or RevokedTokenError if the token isn't valid.
+  /// * TokenStoreError if the login store fails.
+  fn revoke_all(&self, token: &AccessToken) -> AuthResult<i32>;
+
+  /// Opens the session of a long-lived connection with a valid access token.
+  /// The connection then checks the session instead of the token, so it
+  /// isn't closed when the token expires.
+  ///
+  /// # Arguments
+  /// * `token` - The access token of the connection. Must be in the Bearer
+  ///   form.
+  ///
+  /// # Return
+  /// * The session of the owner of the token.
+  /// * JWTTokenError or RevokedTokenError if the token isn't valid.
+  /// * TokenStoreError if the login store fails.
+  fn open_session(&self, token: &AccessToken) -> AuthResult<Session>;
+
+  /// Checks that the login of a session wasn't closed.
+  ///
+  /// # Arguments
+  /// * `session` - The session opened by `open_session`.
+  ///
+  /// # Return
+  /// * Nothing if the login is still open.
+  /// * RevokedTokenError if it was closed.
+  /// * TokenStoreError if the login store fails.
+  fn check_session(&self, session: &Session) -> AuthResult<()>;
+
+  /// Forgets the cached status of a token id, used after revoking a session
+  /// through another component.
+  ///
+  /// # Arguments
+  /// * `jti` - The unique id of the token.
+  fn forget(&self, jti: String);
+
+  /// Forgets the cached status of every token of a user, used after closing
+  /// all of its sessions through another component.
+  ///
+  /// # Arguments
+  /// * `uid` - The owner of the tokens.
+  fn forget_user(&self, uid: i32);
+
+  /// The public keys that verify the access tokens, to be published so other
+  /// services can validate them. Empty when a shared secret is used.
+  ///
+  /// # Return
+  /// * The set of JSON Web Keys.
+  fn jwks(&self) -> JwkSet;
 }
 
-#[derive(Debug)]
-pub struct BearerAuthenticator {
-  secret: String,
+pub struct BearerAuthenticator<LoginRepo> {
+  keys: KeyStore,
+  login_repository: LoginRepo,
+  cache: TokenCache,
 }
 
-impl BearerAuthenticator {
-  pub fn new() -> BearerAuthenticator {
+impl<LoginRepo> BearerAuthenticator<LoginRepo>
+where
+  LoginRepo: LoginRepository,
+{
+  pub fn new(login_repository: LoginRepo) -> Self {
+    BearerAuthenticator::with_keys(setup_jwt_config(), login_repository)
+  }
+
+  pub fn with_keys(keys: KeyStore, login_repository: LoginRepo) -> Self {
     BearerAuthenticator {
-      secret: setup_jwt_config(),
+      keys,
+      login_repository,
+      cache: TokenCache::new(),
     }
   }
 
@@ -73,43 +245,174 @@ impl BearerAuthenticator {
     }
     Ok(token.get_token().trim_start_matches(BEARER).to_string())
   }
+
+  /// Decode the claims of an access token, checking its signature with the key
+  /// named in its kid header, its expiration and that it's still active in the
+  /// login store.
+  ///
+  /// # Arguments
+  /// * `token` - The AccessToken to decode.
+  ///
+  /// # Return
+  /// * The claims of the token.
+  /// * JWTTokenError if an error occur in the decode process.
+  /// * RevokedTokenError if the token is no longer in the login store.
+  fn decode_claims(&self, token: &AccessToken) -> AuthResult<Claims> {
+    let token_as_string = self.jwt_from_header(token)?;
+    let header =
+      decode_header(&token_as_string).map_err(|_| Error::JWTTokenError)?;
+    let (algorithm, key) = self.keys.verification_key(header.kid.as_deref())?;
+    if header.alg != algorithm {
+      return Err(Error::JWTTokenError);
+    }
+    let decoded =
+      decode::<Claims>(&token_as_string, key, &Validation::new(algorithm))
+        .map_err(|_| Error::JWTTokenError)?;
+
+    if !self.is_active(&decoded.claims)? {
+      return Err(Error::RevokedTokenError);
+    }
+    Ok(decoded.claims)
+  }
+
+  /// Checks if a token id is still the current one of its login, first in the
+  /// cache and then in the login store.
+  ///
+  /// # Arguments
+  /// * `claims` - The claims of the token.
+  ///
+  /// # Return
+  /// * True if the token wasn't revoked nor superseded.
+  /// * TokenStoreError if the login store fails.
+  fn is_active(&self, claims: &Claims) -> AuthResult<bool> {
+    if let Some(active) = self.cache.get(&claims.jti) {
+      return Ok(active);
+    }
+    let active = self
+      .login_repository
+      .find_by_jti(claims.jti.to_string())
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        Error::TokenStoreError
+      })?
+      .is_some();
+    if active {
+      if let Err(err) = self.login_repository.touch(claims.jti.to_string()) {
+        log::warn!("cannot update the last seen of a session: {}", err);
+      }
+    }
+    self.cache.insert(&claims.jti, claims.sub, active);
+    Ok(active)
+  }
 }
 
-impl Authenticator for BearerAuthenticator {
-  fn create_token(&self, uid: i32) -> AuthResult<String> {
+impl<LoginRepo> Authenticator for BearerAuthenticator<LoginRepo>
+where
+  LoginRepo: LoginRepository + Send + Sync,
+{
+  fn create_token(&self, uid: i32, role: Role) -> AuthResult<IssuedToken> {
     let expiration = Utc::now()
-      .checked_add_signed(chrono::Duration::days(1))
+      .checked_add_signed(chrono::Duration::minutes(ACCESS_TOKEN_MINUTES))
       .expect("valid timestamp")
       .timestamp();
 
     let claims = Claims {
       sub: uid.to_owned(),
       exp: expiration as usize,
+      jti: generate_token(),
+      role,
     };
-    let header = Header::new(Algorithm::HS512);
-    let token_result = encode(
-      &header,
-      &claims,
-      &EncodingKey::from_secret(self.secret.as_ref()),
-    )
-    .map_err(|_| Error::JWTTokenCreationError);
-    token_result
+    let mut header = Header::new(self.keys.get_algorithm());
+    header.kid = self.keys.get_signing_kid();
+    let token = encode(&header, &claims, self.keys.get_signing_key())
+      .map_err(|_| Error::JWTTokenCreationError)?;
+    Ok(IssuedToken::new(&token, &claims.jti))
   }
 
   fn authorize(&self, token: &AccessToken, uid: i32) -> AuthResult<()> {
-    let token_as_string = self.jwt_from_header(token)?;
-    let decoded = decode::<Claims>(
-      &token_as_string,
-      &DecodingKey::from_secret(self.secret.as_ref()),
-      &Validation::new(Algorithm::HS512),
-    )
-    .map_err(|_| Error::JWTTokenError)?;
+    let claims = self.decode_claims(token)?;
 
-    if uid != decoded.claims.sub {
+    if uid != claims.sub {
       return Err(Error::NoPermissionError);
     }
     Ok(())
   }
+
+  fn authenticate(&self, token: &AccessToken) -> AuthResult<i32> {
+    Ok(self.decode_claims(token)?.sub)
+  }
+
+  fn require_role(&self, token: &AccessToken, role: Role) -> AuthResult<i32> {
+    let claims = self.decode_claims(token)?;
+
+    if !claims.role.grants(role) {
+      return Err(Error::NoPermissionError);
+    }
+    Ok(claims.sub)
+  }
+
+  fn revoke(&self, token: &AccessToken) -> AuthResult<i32> {
+    let claims = self.decode_claims(token)?;
+    self
+      .login_repository
+      .delete_by_jti(claims.jti.to_string())
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        Error::TokenStoreError
+      })?;
+    self.cache.evict(&claims.jti);
+    Ok(claims.sub)
+  }
+
+  fn revoke_all(&self, token: &AccessToken) -> AuthResult<i32> {
+    let claims = self.decode_claims(token)?;
+    self
+      .login_repository
+      .delete_all(claims.sub)
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        Error::TokenStoreError
+      })?;
+    self.cache.evict_uid(claims.sub);
+    Ok(claims.sub)
+  }
+
+  fn open_session(&self, token: &AccessToken) -> AuthResult<Session> {
+    let claims = self.decode_claims(token)?;
+    let login = self
+      .login_repository
+      .find_by_jti(claims.jti.to_string())
+      .map_err(|err| {
+        log::error!("error: {}", err.to_string());
+        Error::TokenStoreError
+      })?
+      .ok_or(Error::RevokedTokenError)?;
+    Ok(Session::new(claims.sub, login.get_id()))
+  }
+
+  fn check_session(&self, session: &Session) -> AuthResult<()> {
+    let logins = self.login_repository.find(session.uid).map_err(|err| {
+      log::error!("error: {}", err.to_string());
+      Error::TokenStoreError
+    })?;
+    if logins.iter().any(|login| login.get_id() == session.login) {
+      Ok(())
+    } else {
+      Err(Error::RevokedTokenError)
+    }
+  }
+
+  fn forget(&self, jti: String) {
+    self.cache.evict(&jti);
+  }
+
+  fn forget_user(&self, uid: i32) {
+    self.cache.evict_uid(uid);
+  }
+
+  fn jwks(&self) -> JwkSet {
+    self.keys.get_jwks()
+  }
 }
 
 /// Initialize the JwtConfig for the entire application.
@@ -117,13 +420,268 @@ impl Authenticator for BearerAuthenticator {
 /// # Arguments
 ///
 /// # Return
-/// * A string that represents the secret for a JWT.
-fn setup_jwt_config() -> String {
+/// * The keys that sign and verify the JWT.
+fn setup_jwt_config() -> KeyStore {
   if cfg!(test) {
-    "secret".to_string()
+    KeyStore::hmac("secret")
   } else {
     dotenv().ok();
 
-    env::var("jwt_secret").expect("jwt_secret must be set")
+    load_key_store()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::keys::tests::{
+      ED25519_PRIVATE, ED25519_PUBLIC, NEW_ED25519_PRIVATE, NEW_ED25519_PUBLIC,
+    },
+    model::{
+      login::Builder, repository::login_repository::MockLoginRepository,
+    },
+  };
+  use jsonwebtoken::Algorithm;
+  use mockall::{
+    predicate::{always, eq},
+    Sequence,
+  };
+
+  fn bearer(issued: &IssuedToken) -> AccessToken {
+    AccessToken::new(&format!("{}{}", BEARER, issued.get_token()))
+  }
+
+  #[test]
+  fn authorize_active_token_is_cached() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_touch().times(1).returning(|_| Ok(1));
+    mock_lr.expect_find_by_jti().times(1).returning(|the_jti| {
+      Ok(Some(
+        Builder::new()
+          .with_id(1)
+          .with_username("juan")
+          .with_token("token")
+          .with_jti(&the_jti)
+          .build(),
+      ))
+    });
+    let authenticator = BearerAuthenticator::new(mock_lr);
+    let issued = authenticator.create_token(1, Role::User).unwrap();
+
+    assert!(authenticator.authorize(&bearer(&issued), 1).is_ok());
+    assert!(authenticator.authorize(&bearer(&issued), 1).is_ok());
+    assert!(matches!(
+      authenticator.authorize(&bearer(&issued), 2),
+      Err(Error::NoPermissionError)
+    ));
+  }
+
+  #[test]
+  fn require_role_checks_the_claim() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_touch().times(2).returning(|_| Ok(1));
+    mock_lr.expect_find_by_jti().times(2).returning(|the_jti| {
+      Ok(Some(
+        Builder::new()
+          .with_id(1)
+          .with_username("juan")
+          .with_token("token")
+          .with_jti(&the_jti)
+          .build(),
+      ))
+    });
+    let authenticator = BearerAuthenticator::new(mock_lr);
+    let user = authenticator.create_token(1, Role::User).unwrap();
+    let admin = authenticator.create_token(2, Role::Admin).unwrap();
+
+    assert!(matches!(
+      authenticator.require_role(&bearer(&user), Role::Admin),
+      Err(Error::NoPermissionError)
+    ));
+    assert_eq!(
+      authenticator
+        .require_role(&bearer(&admin), Role::Admin)
+        .unwrap(),
+      2
+    );
+  }
+
+  #[test]
+  fn authorize_superseded_token() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr
+      .expect_find_by_jti()
+      .with(always())
+      .times(1)
+      .returning(|_| Ok(None));
+    mock_lr.expect_touch().times(0);
+    let authenticator = BearerAuthenticator::new(mock_lr);
+    let issued = authenticator.create_token(1, Role::User).unwrap();
+
+    assert!(matches!(
+      authenticator.authorize(&bearer(&issued), 1),
+      Err(Error::RevokedTokenError)
+    ));
+  }
+
+  #[test]
+  fn check_the_session_instead_of_the_token() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_touch().times(1).returning(|_| Ok(1));
+    mock_lr.expect_find_by_jti().times(2).returning(|the_jti| {
+      Ok(Some(
+        Builder::new()
+          .with_id(7)
+          .with_username("juan")
+          .with_token("token")
+          .with_jti(&the_jti)
+          .build(),
+      ))
+    });
+    let mut seq = Sequence::new();
+    mock_lr
+      .expect_find()
+      .with(eq(1))
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| {
+        Ok(vec![Builder::new()
+          .with_id(7)
+          .with_username("juan")
+          .with_token("renewed")
+          .with_jti("renewed")
+          .build()])
+      });
+    mock_lr
+      .expect_find()
+      .with(eq(1))
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Ok(vec![]));
+    let authenticator = BearerAuthenticator::new(mock_lr);
+    let issued = authenticator.create_token(1, Role::User).unwrap();
+
+    let session = authenticator.open_session(&bearer(&issued)).unwrap();
+    assert_eq!(session, Session::new(1, 7));
+    assert!(authenticator.check_session(&session).is_ok());
+    assert!(matches!(
+      authenticator.check_session(&session),
+      Err(Error::RevokedTokenError)
+    ));
+  }
+
+  #[test]
+  fn revoke_evicts_the_cache() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_touch().times(1).returning(|_| Ok(1));
+    let mut seq = mockall::Sequence::new();
+    mock_lr
+      .expect_find_by_jti()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|the_jti| {
+        Ok(Some(
+          Builder::new()
+            .with_id(1)
+            .with_username("juan")
+            .with_token("token")
+            .with_jti(&the_jti)
+            .build(),
+        ))
+      });
+    mock_lr
+      .expect_delete_by_jti()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Ok(1));
+    mock_lr
+      .expect_find_by_jti()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Ok(None));
+    let authenticator = BearerAuthenticator::new(mock_lr);
+    let issued = authenticator.create_token(1, Role::User).unwrap();
+
+    assert_eq!(authenticator.revoke(&bearer(&issued)).unwrap(), 1);
+    assert!(matches!(
+      authenticator.authorize(&bearer(&issued), 1),
+      Err(Error::RevokedTokenError)
+    ));
+  }
+
+  #[test]
+  fn eddsa_token_with_rotated_keys() {
+    let old_keys = KeyStore::asymmetric(
+      Algorithm::EdDSA,
+      "old",
+      ED25519_PRIVATE.as_bytes(),
+      &[(String::from("old"), ED25519_PUBLIC.as_bytes().to_vec())],
+    )
+    .unwrap();
+    let old_issued =
+      BearerAuthenticator::with_keys(old_keys, MockLoginRepository::new())
+        .create_token(1, Role::User)
+        .unwrap();
+
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_touch().times(2).returning(|_| Ok(1));
+    mock_lr.expect_find_by_jti().times(2).returning(|the_jti| {
+      Ok(Some(
+        Builder::new()
+          .with_id(1)
+          .with_username("juan")
+          .with_token("token")
+          .with_jti(&the_jti)
+          .build(),
+      ))
+    });
+    let public_keys = [
+      (String::from("old"), ED25519_PUBLIC.as_bytes().to_vec()),
+      (String::from("new"), NEW_ED25519_PUBLIC.as_bytes().to_vec()),
+    ];
+    let keys = KeyStore::asymmetric(
+      Algorithm::EdDSA,
+      "new",
+      NEW_ED25519_PRIVATE.as_bytes(),
+      &public_keys,
+    )
+    .unwrap();
+    let authenticator = BearerAuthenticator::with_keys(keys, mock_lr);
+    let issued = authenticator.create_token(1, Role::User).unwrap();
+
+    let header = decode_header(&issued.get_token()).unwrap();
+    assert_eq!(header.alg, Algorithm::EdDSA);
+    assert_eq!(header.kid, Some(String::from("new")));
+    assert_eq!(authenticator.authenticate(&bearer(&issued)).unwrap(), 1);
+    assert_eq!(authenticator.authenticate(&bearer(&old_issued)).unwrap(), 1);
+    assert_ne!(old_issued.get_token(), issued.get_token());
+    assert_eq!(
+      serde_json::to_value(authenticator.jwks()).unwrap()["keys"]
+        .as_array()
+        .unwrap()
+        .len(),
+      2
+    );
+  }
+
+  #[test]
+  fn hmac_token_is_rejected_by_an_asymmetric_store() {
+    let mock_lr = MockLoginRepository::new();
+    let hmac = BearerAuthenticator::new(MockLoginRepository::new());
+    let issued = hmac.create_token(1, Role::User).unwrap();
+    let keys = KeyStore::asymmetric(
+      Algorithm::EdDSA,
+      "key-1",
+      ED25519_PRIVATE.as_bytes(),
+      &[(String::from("key-1"), ED25519_PUBLIC.as_bytes().to_vec())],
+    )
+    .unwrap();
+    let authenticator = BearerAuthenticator::with_keys(keys, mock_lr);
+
+    assert!(matches!(
+      authenticator.authenticate(&bearer(&issued)),
+      Err(Error::JWTTokenError)
+    ));
   }
 }
@@ -0,0 +1,102 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Calculates the HMAC-SHA256 of a content.
+///
+/// # Arguments
+/// * `key` - The key.
+/// * `content` - The signed content.
+///
+/// # Return
+/// * The digest.
+pub fn hmac_sha256(key: &[u8], content: &[u8]) -> Vec<u8> {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+  mac.update(content);
+  mac.finalize().into_bytes().to_vec()
+}
+
+/// Checks the HMAC-SHA256 of a content in a time that doesn't depend on where
+/// the signatures differ.
+///
+/// # Arguments
+/// * `key` - The key.
+/// * `content` - The signed content.
+/// * `signature` - The digest to check.
+///
+/// # Return
+/// * True if the signature is the digest of the content.
+pub fn verify_hmac_sha256(
+  key: &[u8],
+  content: &[u8],
+  signature: &[u8],
+) -> bool {
+  let mut mac =
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+  mac.update(content);
+  mac.verify_slice(signature).is_ok()
+}
+
+/// Writes some bytes in lowercase hex.
+///
+/// # Arguments
+/// * `bytes` - The bytes, like a digest.
+///
+/// # Return
+/// * Two chars by byte.
+pub fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares two secrets in a time that doesn't depend on where they differ,
+/// so it can't be guessed byte after byte.
+///
+/// # Arguments
+/// * `first` - A secret.
+/// * `second` - The other secret.
+///
+/// # Return
+/// * True if they are equal.
+pub fn constant_time_eq(first: &[u8], second: &[u8]) -> bool {
+  if first.len() != second.len() {
+    return false;
+  }
+  first
+    .iter()
+    .zip(second)
+    .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+    == 0
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hmac_sha256_of_a_known_content() {
+    assert_eq!(
+      hex(&hmac_sha256(
+        b"key",
+        b"The quick brown fox jumps over the lazy dog"
+      )),
+      "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+    );
+  }
+
+  #[test]
+  fn verify_hmac_sha256_of_a_known_content() {
+    let digest = hmac_sha256(b"key", b"content");
+    assert!(verify_hmac_sha256(b"key", b"content", &digest));
+    assert!(!verify_hmac_sha256(b"key", b"Content", &digest));
+    assert!(!verify_hmac_sha256(b"other", b"content", &digest));
+    assert!(!verify_hmac_sha256(b"key", b"content", &digest[1..]));
+  }
+
+  #[test]
+  fn constant_time_eq_compares_the_whole_secrets() {
+    assert!(constant_time_eq(b"secret", b"secret"));
+    assert!(!constant_time_eq(b"secret", b"secreT"));
+    assert!(!constant_time_eq(b"secret", b"secret!"));
+    assert!(!constant_time_eq(b"", b"secret"));
+  }
+}
@@ -1,3 +1,5 @@
+#[cfg(not(feature = "postgres"))]
+use diesel::{connection::SimpleConnection, prelude::*};
 use diesel::{
   r2d2,
   r2d2::{ConnectionManager, PooledConnection},
@@ -5,10 +7,46 @@ use diesel::{
 use dotenv::dotenv;
 use std::env;
 
-use rocket_contrib::databases::diesel::SqliteConnection;
+use crate::{
+  db::migrations::run_pending,
+  model::repository::error::{Error, RepoResult},
+};
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("Enable the `sqlite` or the `postgres` feature");
+
+/// The connection of the backend the app was built for, PostgreSQL with the
+/// `postgres` feature and SQLite otherwise.
+#[cfg(feature = "postgres")]
+pub type BackendConnection = diesel::pg::PgConnection;
+#[cfg(not(feature = "postgres"))]
+pub type BackendConnection = diesel::sqlite::SqliteConnection;
+
+/// The backend of `BackendConnection`, for the boxed queries.
+#[cfg(feature = "postgres")]
+pub type Backend = diesel::pg::Pg;
+#[cfg(not(feature = "postgres"))]
+pub type Backend = diesel::sqlite::Sqlite;
+
+/// The name of the backend, for the error messages.
+#[cfg(feature = "postgres")]
+const BACKEND: &str = "PostgreSQL";
+#[cfg(not(feature = "postgres"))]
+const BACKEND: &str = "SQLite";
 
-type PoolType = r2d2::Pool<ConnectionManager<SqliteConnection>>;
-type PooledType = PooledConnection<ConnectionManager<SqliteConnection>>;
+/// How long a SQLite connection waits for the lock of another writer.
+#[cfg(not(feature = "postgres"))]
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+#[cfg(not(feature = "postgres"))]
+no_arg_sql_function!(
+  last_insert_rowid,
+  diesel::sql_types::Integer,
+  "The rowid of the last row inserted by the connection."
+);
+
+type PoolType = r2d2::Pool<ConnectionManager<BackendConnection>>;
+type PooledType = PooledConnection<ConnectionManager<BackendConnection>>;
 
 #[derive(Clone)]
 pub struct DbConnection {
@@ -22,30 +60,164 @@ impl DbConnection {
     }
   }
 
-  pub fn get(&self) -> Result<PooledType, diesel::result::Error> {
-    match self.pool.get() {
-      Ok(pool) => Ok(pool),
-      Err(_) => Err(diesel::result::Error::NotFound),
-    }
+  /// Take a connection from the pool.
+  ///
+  /// # Return
+  /// * The pooled connection.
+  /// * ConnectionError if the pool is exhausted or the database unreachable.
+  pub fn get(&self) -> RepoResult<PooledType> {
+    self.pool.get().map_err(|err| {
+      log::error!("cannot get a database connection: {}", err);
+      Error::ConnectionError
+    })
+  }
+}
+
+/// Get the id of the last row inserted by a connection. SQLite keeps it by
+/// connection, so right after an insert it's the id of that row whatever the
+/// other connections insert.
+///
+/// # Arguments
+/// * `conn` - The connection that inserted the row.
+///
+/// # Return
+/// * The id of the row.
+/// * A repository error.
+#[cfg(not(feature = "postgres"))]
+pub fn last_insert_id(conn: &BackendConnection) -> RepoResult<i32> {
+  let id = diesel::select(last_insert_rowid).get_result(conn)?;
+  Ok(id)
+}
+
+/// Sets up every SQLite connection of the pool: the foreign keys are enforced,
+/// SQLite doesn't by default, and the writers wait for the lock of another one
+/// instead of failing right away.
+#[cfg(not(feature = "postgres"))]
+#[derive(Debug)]
+struct SqliteSetup;
+
+#[cfg(not(feature = "postgres"))]
+impl r2d2::CustomizeConnection<BackendConnection, r2d2::Error> for SqliteSetup {
+  fn on_acquire(
+    &self,
+    conn: &mut BackendConnection,
+  ) -> Result<(), r2d2::Error> {
+    conn
+      .batch_execute(&format!(
+        "PRAGMA foreign_keys = ON; PRAGMA busy_timeout = {};",
+        BUSY_TIMEOUT_MS
+      ))
+      .map_err(r2d2::Error::QueryError)
   }
 }
 
+/// Creates the pool of connections to a database.
+///
+/// # Arguments
+/// * `database_url` - The URL of the database.
+///
+/// # Return
+/// * The pool.
+fn build_pool(database_url: &str) -> PoolType {
+  let manager = ConnectionManager::<BackendConnection>::new(database_url);
+  let builder = r2d2::Pool::builder();
+  #[cfg(not(feature = "postgres"))]
+  let builder = builder.connection_customizer(Box::new(SqliteSetup));
+
+  builder.build(manager).expect("Failed to create DB pool.")
+}
+
+/// Checks if an URL points to a PostgreSQL database, the other ones are paths
+/// of SQLite databases.
+///
+/// # Arguments
+/// * `database_url` - The URL of the database.
+///
+/// # Return
+/// * True for the `postgres://` and `postgresql://` URLs.
+fn is_postgres_url(database_url: &str) -> bool {
+  database_url.starts_with("postgres://")
+    || database_url.starts_with("postgresql://")
+}
+
 pub fn establish_connection() -> PoolType {
-  if cfg!(test) {
-    let manager = ConnectionManager::<SqliteConnection>::new(":memory:");
-    let pool = r2d2::Pool::builder()
-      .build(manager)
-      .expect("Failed to create DB pool.");
-    pool
-  } else {
-    dotenv().ok();
-
-    let database_url =
-      env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let manager = ConnectionManager::<SqliteConnection>::new(&database_url);
-
-    r2d2::Pool::builder()
-      .build(manager)
-      .expect("Failed to create DB pool.")
+  if cfg!(test) && !cfg!(feature = "postgres") {
+    return memory_pool();
+  }
+  dotenv().ok();
+  let database_url =
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+  if is_postgres_url(&database_url) != cfg!(feature = "postgres") {
+    panic!(
+      "DATABASE_URL doesn't point to a {} database, the backend this app was \
+       built for. Build with `--no-default-features --features postgres` for \
+       PostgreSQL.",
+      BACKEND
+    );
+  }
+  build_pool(&database_url)
+}
+
+/// Creates the pool of the tests, an in-memory SQLite database with the
+/// migrations run. Every connection to `:memory:` opens its own database, so
+/// the pool keeps a single one.
+///
+/// # Return
+/// * The pool.
+fn memory_pool() -> PoolType {
+  let builder = r2d2::Pool::builder().max_size(1);
+  #[cfg(not(feature = "postgres"))]
+  let builder = builder.connection_customizer(Box::new(SqliteSetup));
+  let pool = builder
+    .build(ConnectionManager::<BackendConnection>::new(":memory:"))
+    .expect("Failed to create DB pool.");
+  run_pending(&pool.get().expect("Cannot connect to the database"))
+    .expect("Cannot run the migrations");
+  pool
+}
+
+/// A SQLite database in the temporary directory, with the migrations applied,
+/// for the tests that need a real database. It's deleted when dropped.
+#[cfg(all(test, not(feature = "postgres")))]
+pub struct TestDatabase {
+  pool: PoolType,
+  path: std::path::PathBuf,
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+impl TestDatabase {
+  pub fn new(name: &str) -> Self {
+    let path =
+      env::temp_dir().join(format!("{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let pool = build_pool(path.to_str().expect("a valid path"));
+    run_pending(&pool.get().expect("a connection")).expect("the migrations");
+    TestDatabase {
+      pool,
+      path,
+    }
+  }
+
+  pub fn connection(&self) -> DbConnection {
+    DbConnection::new(self.pool.clone())
+  }
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+impl Drop for TestDatabase {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn recognize_the_postgres_urls() {
+    assert!(is_postgres_url("postgres://chat@localhost/chat"));
+    assert!(is_postgres_url("postgresql://localhost:5432/chat"));
+    assert!(!is_postgres_url("../database/testing_db.db"));
   }
 }
@@ -0,0 +1,340 @@
+#[cfg(not(feature = "postgres"))]
+use diesel::sql_types::{BigInt, Nullable, Text};
+use diesel::{connection::SimpleConnection, prelude::*};
+use diesel_migrations::{
+  setup_database, MigrationConnection, MigrationError, RunMigrationsError,
+};
+use dotenv::dotenv;
+use std::env;
+#[cfg(not(feature = "postgres"))]
+use std::io;
+
+use crate::db::database::{BackendConnection, DbConnection};
+
+/// The migrations of the backend, compiled into the binary. Every migration
+/// of the directory of the backend is embedded, and a new one goes in both
+/// directories.
+#[cfg(feature = "postgres")]
+mod backend {
+  embed_migrations!("migrations/postgres");
+  pub use self::embedded_migrations::run_with_output;
+}
+#[cfg(not(feature = "postgres"))]
+mod backend {
+  embed_migrations!("migrations/sqlite");
+  pub use self::embedded_migrations::run_with_output;
+}
+
+/// The name and the `down.sql` of every migration of the backend, in order,
+/// embedded by the build script.
+const MIGRATIONS: &[(&str, &str)] =
+  include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+/// What diesel writes before running a migration.
+const RUNNING: &str = "Running migration ";
+
+// The table where diesel keeps the versions of the migrations run.
+table! {
+  __diesel_schema_migrations (version) {
+    version -> VarChar,
+    run_on -> Timestamp,
+  }
+}
+
+/// Get the version of a migration from its name, the date without the
+/// dashes, like diesel does.
+///
+/// # Arguments
+/// * `name` - The name of the migration.
+///
+/// # Return
+/// * The version.
+fn version_of(name: &str) -> String {
+  name.split('_').next().unwrap_or_default().replace('-', "")
+}
+
+/// A row that references a missing one, from `PRAGMA foreign_key_check`.
+#[cfg(not(feature = "postgres"))]
+#[derive(QueryableByName)]
+struct Violation {
+  #[sql_type = "Text"]
+  table: String,
+  #[sql_type = "Nullable<BigInt>"]
+  rowid: Option<i64>,
+  #[sql_type = "Text"]
+  parent: String,
+}
+
+/// Checks that no row references a missing one. The migrations run with the
+/// foreign keys off, so a rebuilt table could have copied orphan rows.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+///
+/// # Return
+/// * An error naming the first orphan row if there is any.
+#[cfg(not(feature = "postgres"))]
+fn check_foreign_keys(
+  conn: &BackendConnection,
+) -> Result<(), RunMigrationsError> {
+  let violations =
+    diesel::sql_query("PRAGMA foreign_key_check").load::<Violation>(conn)?;
+  match violations.first() {
+    Some(violation) => Err(RunMigrationsError::from(io::Error::new(
+      io::ErrorKind::InvalidData,
+      format!(
+        "the foreign keys are violated {} times, first by the row {} of {} \
+         that references a missing row of {}",
+        violations.len(),
+        violation.rowid.unwrap_or_default(),
+        violation.table,
+        violation.parent
+      ),
+    ))),
+    None => Ok(()),
+  }
+}
+
+/// PostgreSQL checks the foreign keys while the migrations run.
+///
+/// # Arguments
+/// * `_conn` - A connection to the database.
+#[cfg(feature = "postgres")]
+fn check_foreign_keys(
+  _conn: &BackendConnection,
+) -> Result<(), RunMigrationsError> {
+  Ok(())
+}
+
+/// Runs migrations with the foreign keys off, the migrations that rebuild a
+/// SQLite table drop it while other tables reference it. SQLite ignores the
+/// pragma inside a transaction, so it's set around them.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `migrate` - Runs the migrations.
+///
+/// # Return
+/// * The result of the migrations.
+/// * A migration error.
+#[cfg(not(feature = "postgres"))]
+fn without_foreign_keys<T>(
+  conn: &BackendConnection,
+  migrate: impl FnOnce() -> Result<T, RunMigrationsError>,
+) -> Result<T, RunMigrationsError> {
+  conn.batch_execute("PRAGMA foreign_keys = OFF;")?;
+  let result = migrate();
+  conn.batch_execute("PRAGMA foreign_keys = ON;")?;
+  result
+}
+
+/// Runs migrations. The ones of PostgreSQL alter the tables instead of
+/// rebuilding them, so the foreign keys stay on.
+///
+/// # Arguments
+/// * `_conn` - A connection to the database.
+/// * `migrate` - Runs the migrations.
+///
+/// # Return
+/// * The result of the migrations.
+#[cfg(feature = "postgres")]
+fn without_foreign_keys<T>(
+  _conn: &BackendConnection,
+  migrate: impl FnOnce() -> Result<T, RunMigrationsError>,
+) -> Result<T, RunMigrationsError> {
+  migrate()
+}
+
+/// Get every embedded migration and whether it was run.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+///
+/// # Return
+/// * The name of every migration, in order, with true if it was run.
+/// * A migration error.
+pub fn status(
+  conn: &BackendConnection,
+) -> Result<Vec<(String, bool)>, RunMigrationsError> {
+  setup_database(conn)?;
+  let run = conn.previously_run_migration_versions()?;
+  let migrations = MIGRATIONS
+    .iter()
+    .map(|(name, _)| (name.to_string(), run.contains(&version_of(name))))
+    .collect();
+  Ok(migrations)
+}
+
+/// Runs the embedded migrations that weren't run yet, in order, each one in
+/// its own transaction, then checks the foreign keys before turning them on
+/// again.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+///
+/// # Return
+/// * The versions of the migrations run. Could be empty.
+/// * A migration error, the migrations before the failed one stay run, or the
+///   violations of the foreign keys left by the migrations.
+pub fn run_pending(
+  conn: &BackendConnection,
+) -> Result<Vec<String>, RunMigrationsError> {
+  let mut output = Vec::new();
+  without_foreign_keys(conn, || {
+    backend::run_with_output(conn, &mut output)?;
+    check_foreign_keys(conn)
+  })?;
+  let versions = String::from_utf8_lossy(&output)
+    .lines()
+    .filter_map(|line| line.strip_prefix(RUNNING))
+    .map(String::from)
+    .collect();
+  Ok(versions)
+}
+
+/// Reverts the last migration run, in a transaction, with its embedded
+/// `down.sql`, then checks the foreign keys before turning them on again.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+///
+/// # Return
+/// * The version of the reverted migration, None if no migration was run.
+/// * A migration error if the migration isn't embedded, or the violations of
+///   the foreign keys left by the migration, which stays reverted.
+pub fn revert_last(
+  conn: &BackendConnection,
+) -> Result<Option<String>, RunMigrationsError> {
+  setup_database(conn)?;
+  let version = match conn.latest_run_migration_version()? {
+    Some(version) => version,
+    None => return Ok(None),
+  };
+  let down = MIGRATIONS
+    .iter()
+    .find(|(name, _)| version_of(name) == version)
+    .map(|(_, down)| *down)
+    .ok_or_else(|| MigrationError::UnknownMigrationVersion(version.clone()))?;
+  without_foreign_keys(conn, || {
+    conn.transaction(|| {
+      conn.batch_execute(down)?;
+      diesel::delete(
+        __diesel_schema_migrations::table
+          .filter(__diesel_schema_migrations::version.eq(&version)),
+      )
+      .execute(conn)?;
+      Ok(())
+    })?;
+    check_foreign_keys(conn)
+  })?;
+  Ok(Some(version))
+}
+
+/// Runs the pending migrations when the app starts, unless the
+/// `run_migrations` variable is `false`.
+///
+/// # Arguments
+/// * `db_connection` - The pool of connections to the database.
+pub fn migrate_at_boot(db_connection: &DbConnection) {
+  dotenv().ok();
+  if env::var("run_migrations").as_deref() == Ok("false") {
+    log::info!("the migrations are not run at boot");
+    return;
+  }
+  let conn = db_connection.get().expect("Cannot connect to the database");
+  for version in run_pending(&conn).expect("Cannot run the migrations") {
+    log::info!("migration {} run", version);
+  }
+}
+
+/// Runs the `migrate` subcommand and prints what it did.
+///
+/// # Arguments
+/// * `db_connection` - The pool of connections to the database.
+/// * `action` - `status`, `up` to run the pending migrations or `down` to
+///   revert the last one.
+///
+/// # Return
+/// * The error to show if the action is unknown or fails.
+pub fn migrate(
+  db_connection: &DbConnection,
+  action: &str,
+) -> Result<(), String> {
+  let conn = db_connection.get().map_err(|err| err.to_string())?;
+  match action {
+    "status" => {
+      let migrations = status(&conn).map_err(|err| err.to_string())?;
+      for (name, run) in migrations {
+        println!("[{}] {}", if run { "X" } else { " " }, name);
+      }
+    },
+    "up" => {
+      let versions = run_pending(&conn).map_err(|err| err.to_string())?;
+      if versions.is_empty() {
+        println!("No pending migrations");
+      }
+      for version in versions {
+        println!("Ran {}", version);
+      }
+    },
+    "down" => match revert_last(&conn).map_err(|err| err.to_string())? {
+      Some(version) => println!("Reverted {}", version),
+      None => println!("No migration to revert"),
+    },
+    other => {
+      return Err(format!("unknown action {}, use status, up or down", other));
+    },
+  }
+  Ok(())
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+  use super::*;
+  use diesel::Connection;
+
+  #[test]
+  fn run_and_revert_the_migrations() {
+    let conn = BackendConnection::establish(":memory:").unwrap();
+    let total = status(&conn).unwrap().len();
+
+    assert_eq!(run_pending(&conn).unwrap().len(), total);
+    assert!(run_pending(&conn).unwrap().is_empty());
+    assert!(status(&conn).unwrap().iter().all(|(_, run)| *run));
+
+    let last = revert_last(&conn).unwrap().unwrap();
+    let (name, run) = status(&conn).unwrap().pop().unwrap();
+    assert_eq!(name.split('_').next().unwrap().replace('-', ""), last);
+    assert!(!run);
+    assert_eq!(run_pending(&conn).unwrap(), vec![last]);
+  }
+
+  #[test]
+  fn embed_the_down_sql_of_every_migration() {
+    assert!(MIGRATIONS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(MIGRATIONS.iter().all(|(_, down)| !down.trim().is_empty()));
+    assert_eq!(version_of(MIGRATIONS[0].0), "20220628213241");
+  }
+
+  #[test]
+  fn fail_on_an_orphan_row() {
+    let conn = BackendConnection::establish(":memory:").unwrap();
+    run_pending(&conn).unwrap();
+
+    conn
+      .batch_execute(
+        "PRAGMA foreign_keys = OFF;
+         INSERT INTO \"messages\" (\"from\", \"to\", \"message\")
+         VALUES (7, 8, 'orphan');",
+      )
+      .unwrap();
+    assert!(check_foreign_keys(&conn)
+      .unwrap_err()
+      .to_string()
+      .contains("references a missing row of users"));
+    assert!(revert_last(&conn)
+      .unwrap_err()
+      .to_string()
+      .contains("references a missing row of users"));
+  }
+}
@@ -4,35 +4,68 @@
 extern crate rocket;
 #[macro_use]
 extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
 
 mod application;
 mod auth;
+mod crypto;
 mod db;
 mod log;
+mod media;
 mod model;
 mod openapi;
+mod outbox;
+mod realtime;
 mod schema;
+mod storage;
 
 use crate::{
   auth::token::{Authenticator, BearerAuthenticator},
-  db::database::{establish_connection, DbConnection},
+  db::{
+    database::{establish_connection, DbConnection},
+    migrations::{migrate, migrate_at_boot},
+  },
   log::log::setup_logger,
+  media::{
+    expiry::{ttl_from_env, ExpiryWorker},
+    worker::ThumbnailWorker,
+  },
   model::{
+    attachment_service::{AttachmentService, AttachmentServiceImpl, Limits},
+    conversation_service::{ConversationService, ConversationServiceImpl},
     message_service::{MessageService, MessageServiceImpl},
-    password::SimpleHasher,
+    password::setup_password_hasher,
     repository::{
+      attachment_repository::AttachmentRepositoryImpl,
+      conversation_repository::ConversationRepositoryImpl,
       login_repository::LoginRepositoryImpl,
       message_repository::MessageRepositoryImpl,
+      receipt_repository::ReceiptRepositoryImpl,
+      refresh_token_repository::RefreshTokenRepositoryImpl,
       user_repository::UserRepositoryImpl,
+      webhook_repository::WebhookRepositoryImpl,
     },
     user_service::{UserService, UserServiceImpl},
+    webhook_service::{WebhookService, WebhookServiceImpl},
   },
   openapi::swagger,
+  outbox::worker::WebhookWorker,
+  realtime::{
+    hub::Hub,
+    server::{realtime_address, realtime_max_connections, RealtimeServer},
+  },
+  storage::blob_store::setup_blob_store,
 };
 
-use application::{health_handler, message_handler, user_handler};
+use application::{
+  admin_handler, attachment_handler, conversation_handler, error,
+  group_handler, health_handler, jwks_handler, message_handler,
+  pagination::Cursors, polling::Polls, session_handler, user_handler,
+  webhook_handler,
+};
 use rocket::routes;
-use std::sync::Arc;
+use std::{env, process, sync::Arc};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::Config;
 
@@ -40,40 +73,203 @@ fn main() {
   // Set up the logger
   setup_logger();
 
-  // Bearer token configuration
-  let authenticator = BearerAuthenticator::new();
-
   // Database pool
   let db_conn = DbConnection::new(establish_connection());
 
+  // `server migrate status|up|down` only manages the migrations
+  let args = env::args().collect::<Vec<String>>();
+  if args.get(1).map(String::as_str) == Some("migrate") {
+    let action = args.get(2).map_or("status", String::as_str);
+    if let Err(err) = migrate(&db_conn, action) {
+      eprintln!("Cannot migrate: {}", err);
+      process::exit(1);
+    }
+    return;
+  }
+  migrate_at_boot(&db_conn);
+
+  // Bearer token configuration
+  let authenticator =
+    BearerAuthenticator::new(LoginRepositoryImpl::new(db_conn.clone()));
+
   // Repository initialization
   let user_repository = UserRepositoryImpl::new(db_conn.clone());
   let login_repository = LoginRepositoryImpl::new(db_conn.clone());
   let message_repository = MessageRepositoryImpl::new(db_conn.clone());
+  let receipt_repository = ReceiptRepositoryImpl::new(db_conn.clone());
+  let refresh_token_repository =
+    RefreshTokenRepositoryImpl::new(db_conn.clone());
 
   // User related initialization
-  let password_hasher = SimpleHasher::default();
-  let user_service =
-    UserServiceImpl::new(user_repository, login_repository, password_hasher);
+  let password_hasher = setup_password_hasher();
+  let user_service = UserServiceImpl::new(
+    user_repository,
+    login_repository,
+    refresh_token_repository,
+    password_hasher,
+  );
 
   // Messages related initialization
-  let message_service = MessageServiceImpl::new(message_repository);
+  let hub = Arc::new(Hub::default());
+  let message_service = MessageServiceImpl::new(
+    message_repository,
+    receipt_repository,
+    ConversationRepositoryImpl::new(db_conn.clone()),
+    UserRepositoryImpl::new(db_conn.clone()),
+    AttachmentRepositoryImpl::new(db_conn.clone()),
+    hub.clone(),
+  );
+  let conversation_service = ConversationServiceImpl::new(
+    ConversationRepositoryImpl::new(db_conn.clone()),
+  );
+
+  // Attachments, their content is kept in the blob store
+  let attachment_service = AttachmentServiceImpl::new(
+    AttachmentRepositoryImpl::new(db_conn.clone()),
+    setup_blob_store(),
+    Limits::from_env(),
+  );
 
-  rocket::Rocket::ignite()
+  // Webhooks, the outbox is sent in the background
+  let webhook_service = WebhookServiceImpl::new(
+    WebhookRepositoryImpl::new(db_conn.clone()),
+    UserRepositoryImpl::new(db_conn.clone()),
+  );
+  WebhookWorker::new(WebhookRepositoryImpl::new(db_conn.clone())).start();
+
+  // Thumbnails of the images, generated in the background
+  ThumbnailWorker::new(
+    AttachmentRepositoryImpl::new(db_conn.clone()),
+    setup_blob_store(),
+  )
+  .start();
+
+  // Uploads never sent, deleted after a while
+  ExpiryWorker::new(
+    AttachmentRepositoryImpl::new(db_conn.clone()),
+    setup_blob_store(),
+    ttl_from_env(),
+  )
+  .start();
+
+  // Real-time notifications, on their own address
+  RealtimeServer::new(
+    hub.clone(),
+    Arc::new(BearerAuthenticator::new(LoginRepositoryImpl::new(
+      db_conn.clone(),
+    ))),
+    Arc::new(MessageServiceImpl::new(
+      MessageRepositoryImpl::new(db_conn.clone()),
+      ReceiptRepositoryImpl::new(db_conn.clone()),
+      ConversationRepositoryImpl::new(db_conn.clone()),
+      UserRepositoryImpl::new(db_conn.clone()),
+      AttachmentRepositoryImpl::new(db_conn),
+      hub,
+    )),
+  )
+  .with_max_connections(realtime_max_connections())
+  .start(&realtime_address())
+  .expect("Cannot start the real-time server");
+
+  let rocket = rocket::Rocket::ignite();
+  let polls = Polls::from_env(rocket.config().workers as usize);
+  rocket
     .manage(Box::new(authenticator) as Box<dyn Authenticator>)
     .manage(Box::new(user_service) as Box<dyn UserService>)
     .manage(Box::new(message_service) as Box<dyn MessageService>)
+    .manage(Box::new(conversation_service) as Box<dyn ConversationService>)
+    .manage(Box::new(webhook_service) as Box<dyn WebhookService>)
+    .manage(Box::new(attachment_service) as Box<dyn AttachmentService>)
+    .manage(Cursors::from_env())
+    .manage(polls)
     .manage(Arc::new(Config::from("/swagger/api-doc/openapi.json")))
     .manage(swagger::ApiDoc::openapi())
+    .register(catchers![
+      error::bad_request,
+      error::unauthorized,
+      error::forbidden,
+      error::not_found,
+      error::unprocessable_entity,
+      error::internal_error
+    ])
     .mount("/", routes![health_handler::ping,])
+    .mount("/.well-known", routes![jwks_handler::jwks,])
     .mount("/users", routes![user_handler::create_user,])
-    .mount("/login", routes![user_handler::login,])
+    .mount(
+      "/login",
+      routes![user_handler::login, user_handler::refresh],
+    )
+    .mount(
+      "/logout",
+      routes![user_handler::logout, user_handler::logout_all],
+    )
+    .mount(
+      "/sessions",
+      routes![
+        session_handler::list_sessions,
+        session_handler::revoke_session
+      ],
+    )
     .mount(
       "/message",
       routes![
         message_handler::send_message,
         message_handler::get_message,
-        message_handler::get_message_from
+        message_handler::get_message_from,
+        message_handler::get_inbox,
+        message_handler::poll_messages,
+        message_handler::edit_message,
+        message_handler::delete_message,
+        message_handler::read_message,
+        message_handler::get_unread,
+        message_handler::search_messages
+      ],
+    )
+    .mount(
+      "/attachments",
+      routes![
+        attachment_handler::upload_attachment,
+        attachment_handler::download_attachment,
+        attachment_handler::download_thumbnail
+      ],
+    )
+    .mount(
+      "/conversations",
+      routes![
+        conversation_handler::get_conversation,
+        conversation_handler::read_conversation
+      ],
+    )
+    .mount(
+      "/groups",
+      routes![
+        group_handler::list_groups,
+        group_handler::create_group,
+        group_handler::rename_group,
+        group_handler::list_members,
+        group_handler::invite_member,
+        group_handler::remove_member,
+        group_handler::leave_group,
+        group_handler::get_group_messages
+      ],
+    )
+    .mount(
+      "/admin",
+      routes![
+        admin_handler::list_users,
+        admin_handler::suspend_user,
+        admin_handler::reinstate_user,
+        admin_handler::get_any_message
+      ],
+    )
+    .mount(
+      "/webhooks",
+      routes![
+        webhook_handler::list_webhooks,
+        webhook_handler::create_webhook,
+        webhook_handler::delete_webhook,
+        webhook_handler::get_dead_letters,
+        webhook_handler::retry_delivery
       ],
     )
     .mount(
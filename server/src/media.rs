@@ -0,0 +1,4 @@
+pub mod exif;
+pub mod expiry;
+pub mod preview;
+pub mod worker;
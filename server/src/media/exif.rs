@@ -0,0 +1,322 @@
+use image::ImageFormat;
+use std::ops::Range;
+
+/// The tag of IFD0 that points to the GPS tags.
+const GPS_IFD_TAG: u16 = 0x8825;
+/// The tag of IFD0 with the orientation of the camera.
+const ORIENTATION_TAG: u16 = 0x0112;
+/// The prefix of the EXIF blocks of JPEG, and of some WebP.
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+/// An EXIF block found in an image.
+struct Block {
+  /// The TIFF structure with the tags.
+  tiff: Range<usize>,
+  /// The type and the data of the PNG chunk of the block, followed by their
+  /// CRC.
+  png_chunk: Option<Range<usize>>,
+}
+
+/// Removes the GPS tags of the EXIF blocks of an image, in place, so the
+/// size and the rest of the tags are kept. The block is wiped when it can't
+/// be parsed, a broken block is ignored by the readers anyway.
+///
+/// # Arguments
+/// * `format` - The format of the image, only JPEG, PNG and WebP have blocks.
+/// * `content` - The content of the image.
+pub fn remove_gps(format: ImageFormat, content: &mut [u8]) {
+  for block in blocks(format, content) {
+    let tiff = &mut content[block.tiff];
+    if remove_gps_ifd(tiff).is_none() {
+      tiff.fill(0);
+    }
+    if let Some(chunk) = block.png_chunk {
+      let crc = crc32fast::hash(&content[chunk.clone()]);
+      content[chunk.end..chunk.end + 4].copy_from_slice(&crc.to_be_bytes());
+    }
+  }
+}
+
+/// Get the orientation of the camera that took an image.
+///
+/// # Arguments
+/// * `format` - The format of the image.
+/// * `content` - The content of the image.
+///
+/// # Return
+/// * The EXIF orientation, from 1 to 8, 1 when it's unknown.
+pub fn orientation(format: ImageFormat, content: &[u8]) -> u16 {
+  blocks(format, content)
+    .first()
+    .and_then(|block| {
+      let tiff = &content[block.tiff.clone()];
+      let little = byte_order(tiff)?;
+      let ifd0 = read_u32(tiff, 4, little)? as usize;
+      let entry = find_tag(tiff, little, ifd0, ORIENTATION_TAG)?;
+      read_u16(tiff, entry + 8, little)
+    })
+    .filter(|orientation| (1..=8).contains(orientation))
+    .unwrap_or(1)
+}
+
+/// Look for the EXIF blocks of an image.
+///
+/// # Arguments
+/// * `format` - The format of the image.
+/// * `content` - The content of the image.
+///
+/// # Return
+/// * The blocks, inside the content.
+fn blocks(format: ImageFormat, content: &[u8]) -> Vec<Block> {
+  match format {
+    ImageFormat::Jpeg => jpeg_blocks(content),
+    ImageFormat::Png => png_blocks(content),
+    ImageFormat::WebP => webp_blocks(content),
+    _ => vec![],
+  }
+}
+
+/// The EXIF blocks of a JPEG are APP1 segments, before the image data.
+fn jpeg_blocks(content: &[u8]) -> Vec<Block> {
+  let mut found = Vec::new();
+  let mut at = 2;
+  while content.get(at) == Some(&0xff) {
+    let marker = match content.get(at + 1) {
+      Some(0xd9) | Some(0xda) | None => break,
+      Some(marker) => *marker,
+    };
+    // The markers without a segment
+    if marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+      at += 2;
+      continue;
+    }
+    let data = match read_u16(content, at + 2, false) {
+      Some(len) if len >= 2 => at + 4..at + 2 + len as usize,
+      _ => break,
+    };
+    if data.end > content.len() {
+      break;
+    }
+    if marker == 0xe1 && content[data.clone()].starts_with(EXIF_PREFIX) {
+      found.push(Block {
+        tiff: data.start + EXIF_PREFIX.len()..data.end,
+        png_chunk: None,
+      });
+    }
+    at = data.end;
+  }
+  found
+}
+
+/// The EXIF blocks of a PNG are `eXIf` chunks.
+fn png_blocks(content: &[u8]) -> Vec<Block> {
+  let mut found = Vec::new();
+  let mut at = 8;
+  while let Some(len) = read_u32(content, at, false) {
+    let chunk = at + 4..at + 8 + len as usize;
+    if chunk.end + 4 > content.len() {
+      break;
+    }
+    match &content[at + 4..at + 8] {
+      b"eXIf" => found.push(Block {
+        tiff: at + 8..chunk.end,
+        png_chunk: Some(chunk.clone()),
+      }),
+      b"IEND" => break,
+      _ => (),
+    }
+    at = chunk.end + 4;
+  }
+  found
+}
+
+/// The EXIF blocks of a WebP are `EXIF` chunks of its RIFF container.
+fn webp_blocks(content: &[u8]) -> Vec<Block> {
+  let mut found = Vec::new();
+  let mut at = 12;
+  while let Some(len) = read_u32(content, at + 4, true) {
+    let data = at + 8..at + 8 + len as usize;
+    if data.end > content.len() {
+      break;
+    }
+    if &content[at..at + 4] == b"EXIF" {
+      let skip = if content[data.clone()].starts_with(EXIF_PREFIX) {
+        EXIF_PREFIX.len()
+      } else {
+        0
+      };
+      found.push(Block {
+        tiff: data.start + skip..data.end,
+        png_chunk: None,
+      });
+    }
+    // The chunks are padded to an even size
+    at = data.end + (len as usize & 1);
+  }
+  found
+}
+
+/// Empties the GPS IFD of a TIFF structure, and wipes the values its tags
+/// point to.
+///
+/// # Arguments
+/// * `tiff` - The TIFF structure.
+///
+/// # Return
+/// * None if the structure isn't valid.
+fn remove_gps_ifd(tiff: &mut [u8]) -> Option<()> {
+  let little = byte_order(tiff)?;
+  let ifd0 = read_u32(tiff, 4, little)? as usize;
+  let pointer = match find_tag(tiff, little, ifd0, GPS_IFD_TAG) {
+    Some(pointer) => pointer,
+    None => return Some(()),
+  };
+  let gps = read_u32(tiff, pointer + 8, little)? as usize;
+  let count = read_u16(tiff, gps, little)? as usize;
+  for entry in (0..count).map(|index| gps + 2 + 12 * index) {
+    let kind = read_u16(tiff, entry + 2, little)?;
+    let values = read_u32(tiff, entry + 4, little)? as usize;
+    let size = type_size(kind).checked_mul(values)?;
+    // The bigger values are out of the entry, at an offset
+    if size > 4 {
+      let offset = read_u32(tiff, entry + 8, little)? as usize;
+      tiff.get_mut(offset..offset.checked_add(size)?)?.fill(0);
+    }
+    tiff.get_mut(entry..entry + 12)?.fill(0);
+  }
+  tiff.get_mut(gps..gps + 2)?.fill(0);
+  Some(())
+}
+
+/// Look for a tag in an IFD.
+///
+/// # Return
+/// * The position of the entry of the tag.
+fn find_tag(tiff: &[u8], little: bool, ifd: usize, tag: u16) -> Option<usize> {
+  let count = read_u16(tiff, ifd, little)? as usize;
+  (0..count)
+    .map(|index| ifd + 2 + 12 * index)
+    .take_while(|entry| entry + 12 <= tiff.len())
+    .find(|entry| read_u16(tiff, *entry, little) == Some(tag))
+}
+
+/// Get the byte order of a TIFF structure.
+///
+/// # Return
+/// * True for little endian, none if it isn't a TIFF structure.
+fn byte_order(tiff: &[u8]) -> Option<bool> {
+  match tiff.get(..4)? {
+    b"II*\0" => Some(true),
+    b"MM\0*" => Some(false),
+    _ => None,
+  }
+}
+
+/// Get the size of a value of a TIFF type, 0 for the unknown types.
+fn type_size(kind: u16) -> usize {
+  match kind {
+    1 | 2 | 6 | 7 => 1,
+    3 | 8 => 2,
+    4 | 9 | 11 => 4,
+    5 | 10 | 12 => 8,
+    _ => 0,
+  }
+}
+
+fn read_u16(content: &[u8], at: usize, little: bool) -> Option<u16> {
+  let bytes = [*content.get(at)?, *content.get(at + 1)?];
+  if little {
+    Some(u16::from_le_bytes(bytes))
+  } else {
+    Some(u16::from_be_bytes(bytes))
+  }
+}
+
+fn read_u32(content: &[u8], at: usize, little: bool) -> Option<u32> {
+  let bytes = content.get(at..at.checked_add(4)?)?.try_into().ok()?;
+  if little {
+    Some(u32::from_le_bytes(bytes))
+  } else {
+    Some(u32::from_be_bytes(bytes))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Builds a little endian TIFF structure with an orientation and a GPS
+  /// latitude.
+  fn tiff() -> Vec<u8> {
+    let mut tiff = b"II*\0".to_vec();
+    tiff.extend(8u32.to_le_bytes());
+    // IFD0 at 8: the orientation and the GPS pointer
+    tiff.extend(2u16.to_le_bytes());
+    tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+    tiff.extend([0x25, 0x88, 4, 0, 1, 0, 0, 0, 38, 0, 0, 0]);
+    tiff.extend(0u32.to_le_bytes());
+    // GPS IFD at 38: the latitude, 3 rationals at 56
+    tiff.extend(1u16.to_le_bytes());
+    tiff.extend([2, 0, 5, 0, 3, 0, 0, 0, 56, 0, 0, 0]);
+    tiff.extend(0u32.to_le_bytes());
+    for value in [48u32, 1, 51, 1, 24, 1] {
+      tiff.extend(value.to_le_bytes());
+    }
+    tiff
+  }
+
+  fn jpeg(tiff: &[u8]) -> Vec<u8> {
+    let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+    jpeg.extend(((tiff.len() + 8) as u16).to_be_bytes());
+    jpeg.extend(EXIF_PREFIX);
+    jpeg.extend(tiff);
+    jpeg.extend([0xff, 0xda, 0, 2, 0xff, 0xd9]);
+    jpeg
+  }
+
+  #[test]
+  fn remove_the_gps_of_a_jpeg() {
+    let mut content = jpeg(&tiff());
+    let size = content.len();
+    assert_eq!(orientation(ImageFormat::Jpeg, &content), 6);
+
+    remove_gps(ImageFormat::Jpeg, &mut content);
+    assert_eq!(content.len(), size);
+    assert_eq!(orientation(ImageFormat::Jpeg, &content), 6);
+    let gps = &content[12 + 38..];
+    assert!(gps[..gps.len() - 6].iter().all(|byte| *byte == 0));
+  }
+
+  #[test]
+  fn wipe_a_broken_block() {
+    let mut tiff = tiff();
+    // The latitude points out of the structure
+    tiff[38 + 2 + 8] = 0xf0;
+    let mut content = jpeg(&tiff);
+
+    remove_gps(ImageFormat::Jpeg, &mut content);
+    assert!(content[12..12 + tiff.len()].iter().all(|byte| *byte == 0));
+    assert_eq!(orientation(ImageFormat::Jpeg, &content), 1);
+  }
+
+  #[test]
+  fn remove_the_gps_of_a_png() {
+    let tiff = tiff();
+    let mut content = b"\x89PNG\r\n\x1a\n".to_vec();
+    content.extend((tiff.len() as u32).to_be_bytes());
+    content.extend(b"eXIf");
+    content.extend(&tiff);
+    content.extend(crc32fast::hash(&content[12..]).to_be_bytes());
+    content.extend([0, 0, 0, 0]);
+    content.extend(b"IEND");
+
+    remove_gps(ImageFormat::Png, &mut content);
+    let chunk_end = 16 + tiff.len();
+    assert_eq!(orientation(ImageFormat::Png, &content), 6);
+    assert_eq!(
+      content[chunk_end..chunk_end + 4],
+      crc32fast::hash(&content[12..chunk_end]).to_be_bytes()
+    );
+    assert!(content[16 + 56..chunk_end].iter().all(|byte| *byte == 0));
+  }
+}
@@ -0,0 +1,163 @@
+use std::{env, thread, time::Duration};
+
+use chrono::Utc;
+use dotenv::dotenv;
+
+use crate::{
+  model::repository::{
+    attachment_repository::AttachmentRepository, error::RepoResult,
+  },
+  storage::blob_store::BlobStore,
+};
+
+/// The quantity of uploads deleted in a round.
+const BATCH: i64 = 100;
+/// How long the worker sleeps when there isn't any expired upload.
+const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+/// How long an upload is kept without being sent, in seconds, when the
+/// `attachment_ttl` variable isn't set.
+const DEFAULT_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Get how long an upload is kept without being sent, from the
+/// `attachment_ttl` variable, in seconds, 24 hours when it isn't set.
+///
+/// # Return
+/// * The duration.
+pub fn ttl_from_env() -> chrono::Duration {
+  dotenv().ok();
+
+  let secs = env::var("attachment_ttl").map_or(DEFAULT_TTL_SECS, |value| {
+    value
+      .parse()
+      .expect("attachment_ttl must be a number of seconds")
+  });
+  chrono::Duration::seconds(secs)
+}
+
+/// Deletes the uploads that were never sent in a message, in its own thread,
+/// so they don't keep using the quota and the blob store. The files and
+/// thumbnails still used by other attachments are kept.
+pub struct ExpiryWorker<AttachmentRepo> {
+  attachment_repository: AttachmentRepo,
+  blob_store: Box<dyn BlobStore>,
+  ttl: chrono::Duration,
+}
+
+impl<AttachmentRepo> ExpiryWorker<AttachmentRepo>
+where
+  AttachmentRepo: AttachmentRepository + Send + 'static,
+{
+  pub fn new(
+    the_attachment_repository: AttachmentRepo,
+    the_blob_store: Box<dyn BlobStore>,
+    the_ttl: chrono::Duration,
+  ) -> Self {
+    ExpiryWorker {
+      attachment_repository: the_attachment_repository,
+      blob_store: the_blob_store,
+      ttl: the_ttl,
+    }
+  }
+
+  /// Starts deleting the expired uploads in a new thread.
+  pub fn start(self) {
+    thread::spawn(move || loop {
+      match self.run_once() {
+        Ok(0) => thread::sleep(IDLE_INTERVAL),
+        Ok(_) => (),
+        Err(err) => {
+          log::error!("cannot delete the expired uploads: {}", err);
+          thread::sleep(IDLE_INTERVAL);
+        },
+      }
+    });
+  }
+
+  /// Deletes a batch of expired uploads, then their content, unless an
+  /// upload of the same content referenced it meanwhile. A content that
+  /// can't be deleted is only logged, it's just space lost.
+  ///
+  /// # Return
+  /// * The quantity of contents deleted.
+  /// * A repository error if the uploads can't be deleted.
+  fn run_once(&self) -> RepoResult<usize> {
+    let before = Utc::now().naive_utc() - self.ttl;
+    let hashes = self.attachment_repository.remove_expired(before, BATCH)?;
+    self.attachment_repository.delete_unused(hashes, &|hash| {
+      if let Err(err) = self.blob_store.delete(hash) {
+        log::warn!("cannot delete the blob {}: {}", hash, err);
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    model::repository::attachment_repository::MockAttachmentRepository,
+    storage::blob_store::{Error, MockBlobStore},
+  };
+
+  #[test]
+  fn delete_the_content_of_the_expired_uploads() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_remove_expired()
+      .withf(|before, limit| {
+        let age = Utc::now().naive_utc() - *before;
+        age >= chrono::Duration::hours(1) && *limit == BATCH
+      })
+      .times(1)
+      .returning(|_, _| Ok(vec![String::from("abc"), String::from("def")]));
+    mock_repo
+      .expect_delete_unused()
+      .withf(|hashes, _| hashes == &[String::from("abc"), String::from("def")])
+      .times(1)
+      .returning(|hashes, delete| {
+        hashes.iter().for_each(|hash| delete(hash));
+        Ok(hashes.len())
+      });
+    let mut mock_store = MockBlobStore::new();
+    mock_store
+      .expect_delete()
+      .withf(|key| key == "abc")
+      .times(1)
+      .returning(|_| Err(Error::Io(String::from("down"))));
+    mock_store
+      .expect_delete()
+      .withf(|key| key == "def")
+      .times(1)
+      .returning(|_| Ok(()));
+
+    let worker = ExpiryWorker::new(
+      mock_repo,
+      Box::new(mock_store),
+      chrono::Duration::hours(1),
+    );
+    assert_eq!(worker.run_once().unwrap(), 2);
+  }
+
+  #[test]
+  fn keep_the_content_of_an_upload_made_during_the_expiry() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_remove_expired()
+      .times(1)
+      .returning(|_, _| Ok(vec![String::from("abc")]));
+    // The same content was uploaded again once the expired upload was removed
+    mock_repo
+      .expect_delete_unused()
+      .times(1)
+      .returning(|_, _| Ok(0));
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_delete().times(0);
+
+    let worker = ExpiryWorker::new(
+      mock_repo,
+      Box::new(mock_store),
+      chrono::Duration::hours(1),
+    );
+    assert_eq!(worker.run_once().unwrap(), 0);
+  }
+}
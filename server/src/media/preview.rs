@@ -0,0 +1,200 @@
+use crate::media::exif;
+
+use image::{
+  imageops::FilterType,
+  io::{Limits, Reader},
+  DynamicImage, ImageFormat, ImageOutputFormat, ImageResult,
+};
+use std::io::Cursor;
+
+/// The widest or tallest image decoded, in pixels.
+const MAX_SIDE: u32 = 16384;
+/// The quality of the JPEG thumbnails, from 1 to 100.
+const JPEG_QUALITY: u8 = 80;
+
+/// An uploaded image that gets thumbnails.
+#[derive(Debug, PartialEq)]
+pub struct Picture {
+  mime_type: &'static str,
+  width: i32,
+  height: i32,
+}
+
+impl Picture {
+  pub fn get_mime_type(&self) -> &'static str {
+    return self.mime_type;
+  }
+
+  /// The width as it's shown, after the orientation of the camera.
+  pub fn get_width(&self) -> i32 {
+    return self.width;
+  }
+
+  /// The height as it's shown, after the orientation of the camera.
+  pub fn get_height(&self) -> i32 {
+    return self.height;
+  }
+}
+
+/// A thumbnail, encoded as JPEG, or as PNG to keep the transparency.
+pub struct Rendered {
+  pub content: Vec<u8>,
+  pub mime_type: &'static str,
+  pub width: i32,
+  pub height: i32,
+}
+
+/// Inspects an uploaded file. The JPEG, PNG and WebP images lose their GPS
+/// tags, in place, before they are stored. Only the headers are read, the
+/// image is decoded later for the thumbnails.
+///
+/// # Arguments
+/// * `content` - The content of the file.
+///
+/// # Return
+/// * The picture, with its real type, or none if the file isn't one of those
+///   images.
+pub fn prepare(content: &mut [u8]) -> Option<Picture> {
+  let format = image::guess_format(content).ok()?;
+  let mime_type = match format {
+    ImageFormat::Jpeg => "image/jpeg",
+    ImageFormat::Png => "image/png",
+    ImageFormat::WebP => "image/webp",
+    _ => return None,
+  };
+  exif::remove_gps(format, content);
+
+  let (width, height) = Reader::with_format(Cursor::new(&*content), format)
+    .into_dimensions()
+    .ok()?;
+  let (width, height) = match exif::orientation(format, content) {
+    5..=8 => (height, width),
+    _ => (width, height),
+  };
+  Some(Picture {
+    mime_type,
+    width: width as i32,
+    height: height as i32,
+  })
+}
+
+/// Decodes an image, turned as the camera was.
+///
+/// # Arguments
+/// * `content` - The content of the image.
+///
+/// # Return
+/// * The image.
+/// * An error if it can't be decoded or it's too big.
+pub fn decode(content: &[u8]) -> ImageResult<DynamicImage> {
+  let format = image::guess_format(content)?;
+  let mut limits = Limits::default();
+  limits.max_image_width = Some(MAX_SIDE);
+  limits.max_image_height = Some(MAX_SIDE);
+  let mut reader = Reader::with_format(Cursor::new(content), format);
+  reader.limits(limits);
+  let image = reader.decode()?;
+
+  Ok(match exif::orientation(format, content) {
+    2 => image.fliph(),
+    3 => image.rotate180(),
+    4 => image.flipv(),
+    5 => image.rotate90().fliph(),
+    6 => image.rotate90(),
+    7 => image.rotate270().fliph(),
+    8 => image.rotate270(),
+    _ => image,
+  })
+}
+
+/// Renders a thumbnail of an image. A smaller image isn't enlarged, only
+/// encoded again, so the thumbnail has no metadata.
+///
+/// # Arguments
+/// * `image` - The decoded image.
+/// * `max_side` - The side of the square the thumbnail fits in.
+///
+/// # Return
+/// * The thumbnail.
+/// * An error if it can't be encoded.
+pub fn render(image: &DynamicImage, max_side: u32) -> ImageResult<Rendered> {
+  let resized = if image.width() > max_side || image.height() > max_side {
+    image.resize(max_side, max_side, FilterType::Triangle)
+  } else {
+    image.clone()
+  };
+
+  let mut content = Cursor::new(Vec::new());
+  let mime_type = if resized.color().has_alpha() {
+    resized.write_to(&mut content, ImageOutputFormat::Png)?;
+    "image/png"
+  } else {
+    DynamicImage::ImageRgb8(resized.to_rgb8())
+      .write_to(&mut content, ImageOutputFormat::Jpeg(JPEG_QUALITY))?;
+    "image/jpeg"
+  };
+  Ok(Rendered {
+    content: content.into_inner(),
+    mime_type,
+    width: resized.width() as i32,
+    height: resized.height() as i32,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+  fn encode(image: DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+    let mut content = Cursor::new(Vec::new());
+    image.write_to(&mut content, format).unwrap();
+    content.into_inner()
+  }
+
+  #[test]
+  fn prepare_only_the_images() {
+    let image = RgbImage::from_pixel(300, 200, Rgb([200, 10, 10]));
+    let mut content =
+      encode(DynamicImage::ImageRgb8(image), ImageOutputFormat::Png);
+
+    assert_eq!(
+      prepare(&mut content),
+      Some(Picture {
+        mime_type: "image/png",
+        width: 300,
+        height: 200,
+      })
+    );
+    assert_eq!(prepare(&mut b"<html></html>".to_vec()), None);
+  }
+
+  #[test]
+  fn render_the_thumbnails() {
+    let image = RgbImage::from_pixel(300, 200, Rgb([200, 10, 10]));
+    let content =
+      encode(DynamicImage::ImageRgb8(image), ImageOutputFormat::Jpeg(90));
+    let image = decode(&content).unwrap();
+
+    let small = render(&image, 128).unwrap();
+    assert_eq!((small.width, small.height), (128, 85));
+    assert_eq!(small.mime_type, "image/jpeg");
+    assert_eq!(
+      image::guess_format(&small.content).unwrap(),
+      ImageFormat::Jpeg
+    );
+    let large = render(&image, 1024).unwrap();
+    assert_eq!((large.width, large.height), (300, 200));
+  }
+
+  #[test]
+  fn keep_the_transparency() {
+    let image = RgbaImage::from_pixel(40, 20, Rgba([0, 0, 0, 0]));
+    let content =
+      encode(DynamicImage::ImageRgba8(image), ImageOutputFormat::Png);
+
+    let thumbnail = render(&decode(&content).unwrap(), 10).unwrap();
+    assert_eq!(thumbnail.mime_type, "image/png");
+    assert_eq!((thumbnail.width, thumbnail.height), (10, 5));
+  }
+}
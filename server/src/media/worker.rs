@@ -0,0 +1,394 @@
+use std::{thread, time::Duration};
+
+use chrono::Utc;
+
+use crate::{
+  media::preview::{decode, render},
+  model::{
+    attachment::{content_hash, Attachment},
+    repository::{
+      attachment_repository::AttachmentRepository, error::RepoResult,
+    },
+    thumbnail::{NewThumbnail, ThumbnailSize},
+  },
+  storage::blob_store::{BlobStore, Error as StorageError},
+};
+
+/// The quantity of images processed in a round.
+const BATCH: i64 = 10;
+/// How long the worker sleeps when there isn't any image.
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+/// The wait after the first attempt that failed to reach the blob store,
+/// doubled after every other one.
+const BASE_BACKOFF_SECS: i64 = 30;
+/// The longest wait between two attempts, in seconds.
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+/// Why the thumbnails of an image couldn't be generated.
+enum Failure {
+  /// The image isn't stored, can't be decoded or a thumbnail encoded,
+  /// another attempt would fail the same way.
+  Image(String),
+  /// The blob store failed, another attempt could work.
+  Storage(StorageError),
+}
+
+/// Generates the thumbnails of the uploaded images, in its own thread, so the
+/// uploads don't wait for them. An image that isn't stored or can't be
+/// decoded is marked as failed and isn't attempted again. When the blob store
+/// fails the image stays pending, and is attempted again with an exponential
+/// backoff.
+pub struct ThumbnailWorker<AttachmentRepo> {
+  attachment_repository: AttachmentRepo,
+  blob_store: Box<dyn BlobStore>,
+}
+
+impl<AttachmentRepo> ThumbnailWorker<AttachmentRepo>
+where
+  AttachmentRepo: AttachmentRepository + Send + 'static,
+{
+  pub fn new(
+    the_attachment_repository: AttachmentRepo,
+    the_blob_store: Box<dyn BlobStore>,
+  ) -> Self {
+    ThumbnailWorker {
+      attachment_repository: the_attachment_repository,
+      blob_store: the_blob_store,
+    }
+  }
+
+  /// Starts generating the thumbnails in a new thread.
+  pub fn start(self) {
+    thread::spawn(move || loop {
+      match self.run_once() {
+        Ok(0) => thread::sleep(IDLE_INTERVAL),
+        Ok(_) => (),
+        Err(err) => {
+          log::error!("cannot read the pending thumbnails: {}", err);
+          thread::sleep(IDLE_INTERVAL);
+        },
+      }
+    });
+  }
+
+  /// Generates the thumbnails of the pending images.
+  ///
+  /// # Return
+  /// * The quantity of images processed.
+  /// * A repository error if the pending images can't be read.
+  fn run_once(&self) -> RepoResult<usize> {
+    let now = Utc::now().naive_utc();
+    let pending = self.attachment_repository.find_unprocessed(now, BATCH)?;
+    for attachment in &pending {
+      let recorded = match self.generate(attachment) {
+        Ok(rendered) => self.record(attachment, rendered),
+        Err(Failure::Image(err)) => {
+          log::warn!(
+            "cannot generate the thumbnails of the attachment {}: {}",
+            attachment.get_id(),
+            err
+          );
+          self
+            .attachment_repository
+            .mark_thumbnails_failed(attachment.get_id())
+        },
+        Err(Failure::Storage(err)) => {
+          log::warn!(
+            "cannot reach the blob store for the attachment {}: {}",
+            attachment.get_id(),
+            err
+          );
+          let attempt = attachment.get_thumbnail_attempts() + 1;
+          self
+            .attachment_repository
+            .postpone_thumbnails(attachment.get_id(), now + backoff(attempt))
+        },
+      };
+      if let Err(err) = recorded {
+        log::error!(
+          "cannot update the attachment {}: {}",
+          attachment.get_id(),
+          err
+        );
+      }
+    }
+    Ok(pending.len())
+  }
+
+  /// Inserts the thumbnails of an image, then puts again the contents the
+  /// expiry deleted before the insert, when it removed an upload with the
+  /// same thumbnails. The inserted thumbnails keep their contents from then.
+  ///
+  /// # Arguments
+  /// * `attachment` - The attachment of the image.
+  /// * `rendered` - The thumbnails to be inserted, with their contents.
+  ///
+  /// # Return
+  /// * The quantity of images updated, 0 or 1.
+  /// * A repository error.
+  fn record(
+    &self,
+    attachment: &Attachment,
+    rendered: Vec<(NewThumbnail, Vec<u8>)>,
+  ) -> RepoResult<usize> {
+    let (thumbnails, contents): (Vec<NewThumbnail>, Vec<Vec<u8>>) =
+      rendered.into_iter().unzip();
+    let updated = self
+      .attachment_repository
+      .add_thumbnails(attachment.get_id(), thumbnails)?;
+    if updated == 0 {
+      return Ok(updated);
+    }
+    for content in &contents {
+      let hash = content_hash(content);
+      let restored = match self.blob_store.exists(&hash) {
+        Ok(false) => self.blob_store.put(&hash, content),
+        stored => stored.map(|_| ()),
+      };
+      if let Err(err) = restored {
+        log::error!("cannot put the thumbnail {} again: {}", hash, err);
+      }
+    }
+    Ok(updated)
+  }
+
+  /// Generates and stores the thumbnails of an image, one of each size. A
+  /// thumbnail is put even when it's stored already: the expiry could
+  /// delete it before it's inserted.
+  ///
+  /// # Arguments
+  /// * `attachment` - The attachment of the image.
+  ///
+  /// # Return
+  /// * The thumbnails to be inserted, with their contents.
+  /// * Image if the image isn't stored, can't be decoded or a thumbnail
+  ///   encoded.
+  /// * Storage if the blob store failed.
+  fn generate(
+    &self,
+    attachment: &Attachment,
+  ) -> Result<Vec<(NewThumbnail, Vec<u8>)>, Failure> {
+    let content =
+      self
+        .blob_store
+        .get(&attachment.get_hash())
+        .map_err(|err| match err {
+          StorageError::NotFound => Failure::Image(err.to_string()),
+          _ => Failure::Storage(err),
+        })?;
+    let image =
+      decode(&content).map_err(|err| Failure::Image(err.to_string()))?;
+
+    let mut thumbnails = Vec::new();
+    for size in ThumbnailSize::ALL {
+      let rendered = render(&image, size.max_side())
+        .map_err(|err| Failure::Image(err.to_string()))?;
+      let hash = content_hash(&rendered.content);
+      self
+        .blob_store
+        .put(&hash, &rendered.content)
+        .map_err(Failure::Storage)?;
+      let thumbnail = NewThumbnail::new(
+        attachment.get_id(),
+        size,
+        hash,
+        rendered.mime_type.to_string(),
+        rendered.width,
+        rendered.height,
+      );
+      thumbnails.push((thumbnail, rendered.content));
+    }
+    Ok(thumbnails)
+  }
+}
+
+/// Get the wait before an attempt, after the blob store failed.
+///
+/// # Arguments
+/// * `attempt` - The number of the failed attempt, from 1.
+///
+/// # Return
+/// * The wait before the next attempt.
+fn backoff(attempt: i32) -> chrono::Duration {
+  let secs = BASE_BACKOFF_SECS << (attempt - 1).clamp(0, 16);
+  chrono::Duration::seconds(secs.min(MAX_BACKOFF_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    model::{
+      attachment::Builder,
+      repository::attachment_repository::MockAttachmentRepository,
+      thumbnail::ThumbnailState,
+    },
+    storage::blob_store::MockBlobStore,
+  };
+  use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+  use mockall::predicate::{always, eq};
+  use std::io::Cursor;
+
+  fn image(width: u32, height: u32) -> Vec<u8> {
+    let image = RgbImage::from_pixel(width, height, Rgb([10, 200, 10]));
+    let mut content = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(image)
+      .write_to(&mut content, ImageOutputFormat::Png)
+      .unwrap();
+    content.into_inner()
+  }
+
+  #[test]
+  fn generate_a_thumbnail_of_each_size() {
+    let attachment = Builder::new()
+      .with_id(3)
+      .with_image(2000, 1000, ThumbnailState::Pending)
+      .build();
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_unprocessed()
+      .withf(|_, limit| *limit == BATCH)
+      .times(1)
+      .returning(move |_, _| Ok(vec![attachment.clone()]));
+    mock_repo
+      .expect_add_thumbnails()
+      .withf(|id, thumbnails| {
+        let sides = thumbnails
+          .iter()
+          .map(NewThumbnail::dimensions)
+          .collect::<Vec<(i32, i32)>>();
+        *id == 3 && sides == vec![(128, 64), (512, 256), (1024, 512)]
+      })
+      .times(1)
+      .returning(|_, _| Ok(1));
+    mock_repo.expect_mark_thumbnails_failed().times(0);
+    mock_repo.expect_postpone_thumbnails().times(0);
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_get().returning(|_| Ok(image(2000, 1000)));
+    mock_store.expect_exists().times(3).returning(|_| Ok(true));
+    mock_store.expect_put().times(3).returning(|_, _| Ok(()));
+
+    let worker = ThumbnailWorker::new(mock_repo, Box::new(mock_store));
+    assert_eq!(worker.run_once().unwrap(), 1);
+  }
+
+  #[test]
+  fn put_the_thumbnails_the_expiry_deleted_again() {
+    let attachment = Builder::new()
+      .with_id(3)
+      .with_image(20, 10, ThumbnailState::Pending)
+      .build();
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_unprocessed()
+      .returning(move |_, _| Ok(vec![attachment.clone()]));
+    mock_repo
+      .expect_add_thumbnails()
+      .with(eq(3), always())
+      .times(1)
+      .returning(|_, _| Ok(1));
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_get().returning(|_| Ok(image(20, 10)));
+    // An expired upload with the same thumbnails was deleted meanwhile
+    mock_store.expect_exists().times(3).returning(|_| Ok(false));
+    mock_store.expect_put().times(6).returning(|_, _| Ok(()));
+
+    let worker = ThumbnailWorker::new(mock_repo, Box::new(mock_store));
+    assert_eq!(worker.run_once().unwrap(), 1);
+  }
+
+  #[test]
+  fn mark_a_broken_image_as_failed() {
+    let attachment = Builder::new()
+      .with_id(3)
+      .with_image(20, 10, ThumbnailState::Pending)
+      .build();
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_unprocessed()
+      .returning(move |_, _| Ok(vec![attachment.clone()]));
+    mock_repo.expect_add_thumbnails().times(0);
+    mock_repo.expect_postpone_thumbnails().times(0);
+    mock_repo
+      .expect_mark_thumbnails_failed()
+      .with(eq(3))
+      .times(1)
+      .returning(|_| Ok(1));
+    let mut mock_store = MockBlobStore::new();
+    mock_store
+      .expect_get()
+      .returning(|_| Ok(b"\x89PNG\r\n\x1a\nbroken".to_vec()));
+    mock_store.expect_put().times(0);
+
+    let worker = ThumbnailWorker::new(mock_repo, Box::new(mock_store));
+    assert_eq!(worker.run_once().unwrap(), 1);
+  }
+
+  #[test]
+  fn postpone_an_image_when_the_blob_store_fails() {
+    let attachment = Builder::new()
+      .with_id(3)
+      .with_image(20, 10, ThumbnailState::Pending)
+      .with_thumbnail_attempts(2)
+      .build();
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_unprocessed()
+      .returning(move |_, _| Ok(vec![attachment.clone()]));
+    mock_repo.expect_add_thumbnails().times(0);
+    mock_repo.expect_mark_thumbnails_failed().times(0);
+    mock_repo
+      .expect_postpone_thumbnails()
+      .with(eq(3), always())
+      .times(1)
+      .returning(|_, retry_at| {
+        let wait = retry_at - Utc::now().naive_utc();
+        assert!(wait > chrono::Duration::seconds(110));
+        Ok(1)
+      });
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_get().returning(|_| Ok(image(20, 10)));
+    mock_store
+      .expect_put()
+      .times(1)
+      .returning(|_, _| Err(StorageError::Io(String::from("down"))));
+
+    let worker = ThumbnailWorker::new(mock_repo, Box::new(mock_store));
+    assert_eq!(worker.run_once().unwrap(), 1);
+  }
+
+  #[test]
+  fn mark_an_image_that_is_not_stored_as_failed() {
+    let attachment = Builder::new()
+      .with_id(3)
+      .with_image(20, 10, ThumbnailState::Pending)
+      .build();
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_unprocessed()
+      .returning(move |_, _| Ok(vec![attachment.clone()]));
+    mock_repo.expect_add_thumbnails().times(0);
+    mock_repo.expect_postpone_thumbnails().times(0);
+    mock_repo
+      .expect_mark_thumbnails_failed()
+      .with(eq(3))
+      .times(1)
+      .returning(|_| Ok(1));
+    let mut mock_store = MockBlobStore::new();
+    mock_store
+      .expect_get()
+      .times(1)
+      .returning(|_| Err(StorageError::NotFound));
+    mock_store.expect_put().times(0);
+
+    let worker = ThumbnailWorker::new(mock_repo, Box::new(mock_store));
+    assert_eq!(worker.run_once().unwrap(), 1);
+  }
+
+  #[test]
+  fn backoff_doubles_up_to_an_hour() {
+    assert_eq!(backoff(1), chrono::Duration::seconds(30));
+    assert_eq!(backoff(3), chrono::Duration::seconds(120));
+    assert_eq!(backoff(40), chrono::Duration::hours(1));
+  }
+}
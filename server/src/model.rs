@@ -1,8 +1,19 @@
+pub mod attachment;
+pub mod attachment_service;
+pub mod conversation;
+pub mod conversation_service;
 pub mod error;
 pub mod login;
 pub mod message;
 pub mod message_service;
+pub mod page;
 pub mod password;
+pub mod receipt;
+pub mod refresh_token;
 pub mod repository;
+pub mod role;
+pub mod thumbnail;
 pub mod user;
 pub mod user_service;
+pub mod webhook;
+pub mod webhook_service;
@@ -0,0 +1,266 @@
+use crate::{
+  crypto::hex, model::thumbnail::ThumbnailState, schema::attachments,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, Queryable};
+use sha2::{Digest, Sha256};
+
+/// A file uploaded by a user, attached to one of its messages once sent. The
+/// content is in the blob store under its hash, so the same file uploaded
+/// twice is stored once. The images also have their dimensions and
+/// thumbnails. The retry date of the thumbnails is only read by the queries,
+/// see `ATTACHMENT_COLUMNS`.
+#[derive(Identifiable, Queryable, Clone, Debug, PartialEq)]
+pub struct Attachment {
+  id: i32,
+  user_id: i32,
+  message_id: Option<i32>,
+  hash: String,
+  filename: String,
+  mime_type: String,
+  size: i64,
+  created_at: NaiveDateTime,
+  width: Option<i32>,
+  height: Option<i32>,
+  thumbnail_state: Option<String>,
+  thumbnail_attempts: i32,
+}
+
+impl Attachment {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  /// The message of the attachment, none while it isn't sent.
+  pub fn get_message_id(&self) -> Option<i32> {
+    return self.message_id;
+  }
+
+  /// The SHA-256 of the content, in hex, its key in the blob store.
+  pub fn get_hash(&self) -> String {
+    return self.hash.to_string();
+  }
+
+  pub fn get_filename(&self) -> String {
+    return self.filename.to_string();
+  }
+
+  pub fn get_mime_type(&self) -> String {
+    return self.mime_type.to_string();
+  }
+
+  pub fn get_size(&self) -> i64 {
+    return self.size;
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+
+  /// The width of an image, in pixels, none for the other files.
+  pub fn get_width(&self) -> Option<i32> {
+    return self.width;
+  }
+
+  /// The height of an image, in pixels, none for the other files.
+  pub fn get_height(&self) -> Option<i32> {
+    return self.height;
+  }
+
+  /// The state of the thumbnails of an image, none for the other files.
+  pub fn get_thumbnail_state(&self) -> Option<ThumbnailState> {
+    return self
+      .thumbnail_state
+      .as_deref()
+      .and_then(|state| state.parse().ok());
+  }
+
+  /// The attempts of the thumbnails that failed to reach the blob store.
+  pub fn get_thumbnail_attempts(&self) -> i32 {
+    return self.thumbnail_attempts;
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "attachments"]
+pub struct NewAttachment {
+  user_id: i32,
+  hash: String,
+  filename: String,
+  mime_type: String,
+  size: i64,
+  created_at: NaiveDateTime,
+  width: Option<i32>,
+  height: Option<i32>,
+  thumbnail_state: Option<String>,
+}
+
+impl NewAttachment {
+  pub fn new(
+    the_user_id: i32,
+    the_hash: String,
+    the_filename: String,
+    the_mime_type: String,
+    the_size: i64,
+  ) -> NewAttachment {
+    NewAttachment {
+      user_id: the_user_id,
+      hash: the_hash,
+      filename: the_filename,
+      mime_type: the_mime_type,
+      size: the_size,
+      created_at: Utc::now().naive_utc(),
+      width: None,
+      height: None,
+      thumbnail_state: None,
+    }
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  pub fn get_size(&self) -> i64 {
+    return self.size;
+  }
+
+  /// Marks the attachment as an image, its thumbnails are pending.
+  ///
+  /// # Arguments
+  /// * `the_width` - The width of the image, in pixels.
+  /// * `the_height` - The height of the image, in pixels.
+  pub fn with_dimensions(mut self, the_width: i32, the_height: i32) -> Self {
+    self.width = Some(the_width);
+    self.height = Some(the_height);
+    self.thumbnail_state = Some(ThumbnailState::Pending.as_str().to_string());
+    self
+  }
+
+  #[cfg(test)]
+  pub fn mime_type(&self) -> &str {
+    &self.mime_type
+  }
+
+  #[cfg(test)]
+  pub fn dimensions(&self) -> Option<(i32, i32)> {
+    self.width.zip(self.height)
+  }
+}
+
+/// Computes the key of a content in the blob store.
+///
+/// # Arguments
+/// * `content` - The content of a file.
+///
+/// # Return
+/// * The SHA-256 of the content, in lowercase hex.
+pub fn content_hash(content: &[u8]) -> String {
+  hex(&Sha256::digest(content))
+}
+
+#[cfg(test)]
+pub struct Builder {
+  id: i32,
+  user_id: i32,
+  message_id: Option<i32>,
+  hash: String,
+  filename: String,
+  mime_type: String,
+  size: i64,
+  dimensions: Option<(i32, i32)>,
+  thumbnail_state: Option<ThumbnailState>,
+  thumbnail_attempts: i32,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      id: 0,
+      user_id: 0,
+      message_id: None,
+      hash: content_hash(b"content"),
+      filename: String::from("file.txt"),
+      mime_type: String::from("text/plain"),
+      size: 7,
+      dimensions: None,
+      thumbnail_state: None,
+      thumbnail_attempts: 0,
+    }
+  }
+
+  pub fn with_id(mut self, the_id: i32) -> Builder {
+    self.id = the_id;
+    self
+  }
+
+  pub fn with_user_id(mut self, the_user_id: i32) -> Builder {
+    self.user_id = the_user_id;
+    self
+  }
+
+  pub fn with_message_id(mut self, the_message_id: i32) -> Builder {
+    self.message_id = Some(the_message_id);
+    self
+  }
+
+  pub fn with_file(mut self, the_filename: &str, the_mime_type: &str) -> Self {
+    self.filename = the_filename.to_string();
+    self.mime_type = the_mime_type.to_string();
+    self
+  }
+
+  pub fn with_image(
+    mut self,
+    the_width: i32,
+    the_height: i32,
+    the_state: ThumbnailState,
+  ) -> Self {
+    self.mime_type = String::from("image/png");
+    self.dimensions = Some((the_width, the_height));
+    self.thumbnail_state = Some(the_state);
+    self
+  }
+
+  pub fn with_thumbnail_attempts(mut self, the_attempts: i32) -> Self {
+    self.thumbnail_attempts = the_attempts;
+    self
+  }
+
+  pub fn build(&self) -> Attachment {
+    Attachment {
+      id: self.id,
+      user_id: self.user_id,
+      message_id: self.message_id,
+      hash: self.hash.to_string(),
+      filename: self.filename.to_string(),
+      mime_type: self.mime_type.to_string(),
+      size: self.size,
+      created_at: NaiveDateTime::from_timestamp(0, 0),
+      width: self.dimensions.map(|(width, _)| width),
+      height: self.dimensions.map(|(_, height)| height),
+      thumbnail_state: self
+        .thumbnail_state
+        .map(|state| state.as_str().to_string()),
+      thumbnail_attempts: self.thumbnail_attempts,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hash_of_a_content() {
+    assert_eq!(
+      content_hash(b"abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+}
@@ -0,0 +1,562 @@
+use crate::{
+  media::preview::prepare,
+  model::{
+    attachment::{content_hash, Attachment, NewAttachment},
+    error::{Error, ServiceResult},
+    repository::attachment_repository::AttachmentRepository,
+    thumbnail::{Thumbnail, ThumbnailSize, ThumbnailState},
+  },
+  storage::blob_store::{BlobStore, Error as StorageError},
+};
+
+use dotenv::dotenv;
+#[cfg(test)]
+use mockall::automock;
+use std::env;
+
+/// The biggest file, in bytes, when the `attachment_max_size` variable isn't
+/// set.
+const DEFAULT_MAX_SIZE: i64 = 10 * 1024 * 1024;
+/// The space of each user, in bytes, when the `attachment_quota` variable
+/// isn't set.
+const DEFAULT_QUOTA: i64 = 100 * 1024 * 1024;
+/// The longest file name kept, in chars.
+const MAX_FILENAME_LEN: usize = 255;
+
+/// The limits of the uploads, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+  max_size: i64,
+  quota: i64,
+}
+
+impl Limits {
+  pub fn new(the_max_size: i64, the_quota: i64) -> Self {
+    Limits {
+      max_size: the_max_size,
+      quota: the_quota,
+    }
+  }
+
+  /// Initialize the limits with the `attachment_max_size` and the
+  /// `attachment_quota` variables, 10 MiB and 100 MiB when they aren't set.
+  ///
+  /// # Return
+  /// * The limits.
+  pub fn from_env() -> Self {
+    dotenv().ok();
+
+    let var = |name: &str, default: i64| {
+      env::var(name).map_or(default, |value| {
+        value
+          .parse()
+          .unwrap_or_else(|_| panic!("{} must be a number of bytes", name))
+      })
+    };
+    Limits::new(
+      var("attachment_max_size", DEFAULT_MAX_SIZE),
+      var("attachment_quota", DEFAULT_QUOTA),
+    )
+  }
+}
+
+#[cfg_attr(test, automock)]
+pub trait AttachmentService: Sync + Send {
+  /// Uploads a file of a user, to be attached to one of its next messages.
+  /// The content is stored once, even if it's uploaded again. The JPEG, PNG
+  /// and WebP images are stored without their GPS tags, with their real
+  /// type, and get thumbnails in the background.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the uploader.
+  /// * `filename` - The name of the file, without its directories.
+  /// * `mime_type` - The MIME type of the file.
+  /// * `content` - The content of the file.
+  ///
+  /// # Return
+  /// * The attachment.
+  /// * LimitExceeded if the file is too big or the quota of the user is full.
+  /// * An error if the file is empty.
+  fn upload(
+    &self,
+    uid: i32,
+    filename: String,
+    mime_type: String,
+    content: Vec<u8>,
+  ) -> ServiceResult<Attachment>;
+
+  /// Get the content of an attachment.
+  ///
+  /// # Arguments
+  /// * `attachment` - The attachment.
+  ///
+  /// # Return
+  /// * The content.
+  /// * An error if the content isn't stored.
+  fn content(&self, attachment: &Attachment) -> ServiceResult<Vec<u8>>;
+
+  /// Get a thumbnail of an image.
+  ///
+  /// # Arguments
+  /// * `attachment` - The attachment of the image.
+  /// * `size` - The name of the size of the thumbnail.
+  ///
+  /// # Return
+  /// * The thumbnail.
+  /// * NotFound if the attachment isn't an image or its thumbnails aren't
+  ///   ready.
+  /// * An error if the size doesn't exist.
+  fn thumbnail(
+    &self,
+    attachment: &Attachment,
+    size: String,
+  ) -> ServiceResult<Thumbnail>;
+
+  /// Get the content of a thumbnail.
+  ///
+  /// # Arguments
+  /// * `thumbnail` - The thumbnail.
+  ///
+  /// # Return
+  /// * The content.
+  /// * An error if the content isn't stored.
+  fn thumbnail_content(&self, thumbnail: &Thumbnail) -> ServiceResult<Vec<u8>>;
+
+  /// Get the size of the biggest file, in bytes.
+  fn max_size(&self) -> i64;
+}
+
+pub struct AttachmentServiceImpl<AttachmentRepo> {
+  attachment_repository: AttachmentRepo,
+  blob_store: Box<dyn BlobStore>,
+  limits: Limits,
+}
+
+impl<AttachmentRepo> AttachmentServiceImpl<AttachmentRepo>
+where
+  AttachmentRepo: AttachmentRepository,
+{
+  pub fn new(
+    the_attachment_repository: AttachmentRepo,
+    the_blob_store: Box<dyn BlobStore>,
+    the_limits: Limits,
+  ) -> Self {
+    AttachmentServiceImpl {
+      attachment_repository: the_attachment_repository,
+      blob_store: the_blob_store,
+      limits: the_limits,
+    }
+  }
+
+  /// Stores a content in the blob store, even when it's stored already: the
+  /// expiry could delete it before the attachment was inserted.
+  ///
+  /// # Arguments
+  /// * `hash` - The hash of the content.
+  /// * `content` - The content.
+  ///
+  /// # Return
+  /// * An error if the blob store failed.
+  fn store(&self, hash: &str, content: &[u8]) -> ServiceResult<()> {
+    self.blob_store.put(hash, content).map_err(storage_error)
+  }
+}
+
+impl<AttachmentRepo> AttachmentService for AttachmentServiceImpl<AttachmentRepo>
+where
+  AttachmentRepo: AttachmentRepository + Send + Sync,
+{
+  fn upload(
+    &self,
+    uid: i32,
+    filename: String,
+    mime_type: String,
+    content: Vec<u8>,
+  ) -> ServiceResult<Attachment> {
+    let mut content = content;
+    if content.is_empty() {
+      return Err(Error::InvalidInput(String::from("the file is empty")));
+    }
+    let size = content.len() as i64;
+    if size > self.limits.max_size {
+      return Err(Error::LimitExceeded(format!(
+        "the file is bigger than {} bytes",
+        self.limits.max_size
+      )));
+    }
+    let picture = prepare(&mut content);
+    let hash = content_hash(&content);
+    let new_attachment = match picture {
+      Some(picture) => NewAttachment::new(
+        uid,
+        hash,
+        clean_filename(&filename),
+        picture.get_mime_type().to_string(),
+        size,
+      )
+      .with_dimensions(picture.get_width(), picture.get_height()),
+      None => NewAttachment::new(
+        uid,
+        hash,
+        clean_filename(&filename),
+        clean_mime_type(&mime_type),
+        size,
+      ),
+    };
+    let attachment = self
+      .attachment_repository
+      .add_within_quota(new_attachment, self.limits.quota)
+      .map_err(|err| Error::from_repo(err, "user"))?
+      .ok_or_else(|| {
+        Error::LimitExceeded(format!(
+          "the quota of {} bytes is full",
+          self.limits.quota
+        ))
+      })?;
+    if let Err(err) = self.store(&attachment.get_hash(), &content) {
+      self
+        .attachment_repository
+        .remove(attachment.get_id())
+        .map_err(Error::from)?;
+      return Err(err);
+    }
+    Ok(attachment)
+  }
+
+  fn content(&self, attachment: &Attachment) -> ServiceResult<Vec<u8>> {
+    self
+      .blob_store
+      .get(&attachment.get_hash())
+      .map_err(storage_error)
+  }
+
+  fn thumbnail(
+    &self,
+    attachment: &Attachment,
+    size: String,
+  ) -> ServiceResult<Thumbnail> {
+    let thumbnail_size = size.parse::<ThumbnailSize>().map_err(|_| {
+      Error::InvalidInput(String::from(
+        "the size must be small, medium or large",
+      ))
+    })?;
+    if attachment.get_thumbnail_state() != Some(ThumbnailState::Ready) {
+      return Err(Error::NotFound("thumbnail"));
+    }
+    self
+      .attachment_repository
+      .find_thumbnail(attachment.get_id(), thumbnail_size)
+      .map_err(|err| Error::from_repo(err, "thumbnail"))
+  }
+
+  fn thumbnail_content(&self, thumbnail: &Thumbnail) -> ServiceResult<Vec<u8>> {
+    self
+      .blob_store
+      .get(&thumbnail.get_hash())
+      .map_err(storage_error)
+  }
+
+  fn max_size(&self) -> i64 {
+    return self.limits.max_size;
+  }
+}
+
+/// Converts an error of the blob store.
+fn storage_error(err: StorageError) -> Error {
+  match err {
+    StorageError::NotFound => Error::NotFound("file"),
+    _ => Error::Internal(err.to_string()),
+  }
+}
+
+/// Keeps the name of an uploaded file without its directories and control
+/// chars, the clients send the full path sometimes.
+///
+/// # Arguments
+/// * `filename` - The name sent by the client.
+///
+/// # Return
+/// * The name, `file` if nothing is left.
+fn clean_filename(filename: &str) -> String {
+  let name = filename
+    .rsplit(&['/', '\\'][..])
+    .next()
+    .unwrap_or_default()
+    .chars()
+    .filter(|c| !c.is_control())
+    .take(MAX_FILENAME_LEN)
+    .collect::<String>();
+  match name.trim() {
+    "" | "." | ".." => String::from("file"),
+    name => name.to_string(),
+  }
+}
+
+/// Keeps a MIME type that looks like `type/subtype`.
+///
+/// # Arguments
+/// * `mime_type` - The MIME type sent by the client.
+///
+/// # Return
+/// * The MIME type in lowercase, `application/octet-stream` if it isn't valid.
+fn clean_mime_type(mime_type: &str) -> String {
+  let mime_type = mime_type.trim().to_lowercase();
+  let valid = match mime_type.split_once('/') {
+    Some((kind, subtype)) => {
+      !kind.is_empty()
+        && !subtype.is_empty()
+        && mime_type
+          .chars()
+          .all(|c| c.is_ascii_alphanumeric() || "/.+-_".contains(c))
+    },
+    None => false,
+  };
+  if !valid {
+    return String::from("application/octet-stream");
+  }
+  mime_type
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    model::{
+      attachment::Builder,
+      repository::attachment_repository::MockAttachmentRepository,
+      thumbnail::Builder as ThumbnailBuilder,
+    },
+    storage::blob_store::MockBlobStore,
+  };
+  use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+  use mockall::predicate::eq;
+  use std::io::Cursor;
+
+  fn service(
+    mock_repo: MockAttachmentRepository,
+    mock_store: MockBlobStore,
+  ) -> AttachmentServiceImpl<MockAttachmentRepository> {
+    AttachmentServiceImpl::new(
+      mock_repo,
+      Box::new(mock_store),
+      Limits::new(10, 20),
+    )
+  }
+
+  #[test]
+  fn upload_an_empty_or_a_big_file() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo.expect_add_within_quota().times(0);
+    let service = service(mock_repo, MockBlobStore::new());
+
+    let empty = service.upload(
+      1,
+      String::from("empty.txt"),
+      String::from("text/plain"),
+      vec![],
+    );
+    assert!(matches!(empty, Err(Error::InvalidInput(_))));
+    let big = service.upload(
+      1,
+      String::from("big.txt"),
+      String::from("text/plain"),
+      vec![0; 11],
+    );
+    assert!(matches!(big, Err(Error::LimitExceeded(_))));
+  }
+
+  #[test]
+  fn upload_with_a_full_quota() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_add_within_quota()
+      .withf(|new_attachment, quota| {
+        new_attachment.get_user_id() == 1 && *quota == 20
+      })
+      .times(1)
+      .returning(|_, _| Ok(None));
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_put().times(0);
+    let service = service(mock_repo, mock_store);
+
+    let result = service.upload(
+      1,
+      String::from("file.txt"),
+      String::from("text/plain"),
+      b"content".to_vec(),
+    );
+    assert!(matches!(result, Err(Error::LimitExceeded(_))));
+  }
+
+  #[test]
+  fn upload_a_stored_content_again() {
+    let hash = content_hash(b"content");
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_add_within_quota()
+      .times(1)
+      .returning(|_, _| Ok(Some(Builder::new().with_id(1).build())));
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_exists().times(0);
+    // Put again, the expiry could have deleted it before the insert
+    mock_store
+      .expect_put()
+      .withf(move |key, _| key == hash)
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let service = service(mock_repo, mock_store);
+
+    let attachment = service
+      .upload(
+        1,
+        String::from("file.txt"),
+        String::from("text/plain"),
+        b"content".to_vec(),
+      )
+      .unwrap();
+    assert_eq!(attachment.get_id(), 1);
+  }
+
+  #[test]
+  fn upload_a_new_content() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_add_within_quota()
+      .times(1)
+      .returning(|_, _| Ok(Some(Builder::new().with_id(1).build())));
+    let mut mock_store = MockBlobStore::new();
+    mock_store
+      .expect_put()
+      .withf(|_, content| content == b"content")
+      .times(1)
+      .returning(|_, _| Ok(()));
+    let service = service(mock_repo, mock_store);
+
+    assert!(service
+      .upload(
+        1,
+        String::from("file.txt"),
+        String::from("text/plain"),
+        b"content".to_vec(),
+      )
+      .is_ok());
+  }
+
+  #[test]
+  fn remove_an_upload_that_could_not_be_stored() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_add_within_quota()
+      .times(1)
+      .returning(|_, _| Ok(Some(Builder::new().with_id(1).build())));
+    mock_repo
+      .expect_remove()
+      .with(eq(1))
+      .times(1)
+      .returning(|_| Ok(1));
+    let mut mock_store = MockBlobStore::new();
+    mock_store
+      .expect_put()
+      .times(1)
+      .returning(|_, _| Err(StorageError::Io(String::from("down"))));
+    let service = service(mock_repo, mock_store);
+
+    let result = service.upload(
+      1,
+      String::from("file.txt"),
+      String::from("text/plain"),
+      b"content".to_vec(),
+    );
+    assert!(matches!(result, Err(Error::Internal(_))));
+  }
+
+  #[test]
+  fn upload_an_image() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_add_within_quota()
+      .withf(|new_attachment, _| {
+        new_attachment.mime_type() == "image/png"
+          && new_attachment.dimensions() == Some((3, 2))
+      })
+      .times(1)
+      .returning(|_, _| Ok(Some(Builder::new().with_id(1).build())));
+    let mut mock_store = MockBlobStore::new();
+    mock_store.expect_put().times(1).returning(|_, _| Ok(()));
+    let service = AttachmentServiceImpl::new(
+      mock_repo,
+      Box::new(mock_store),
+      Limits::new(1024, 1024),
+    );
+
+    let image = RgbImage::from_pixel(3, 2, Rgb([0, 0, 0]));
+    let mut content = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(image)
+      .write_to(&mut content, ImageOutputFormat::Png)
+      .unwrap();
+    assert!(service
+      .upload(
+        1,
+        String::from("photo.txt"),
+        String::from("text/plain"),
+        content.into_inner(),
+      )
+      .is_ok());
+  }
+
+  #[test]
+  fn thumbnail_of_an_image() {
+    let mut mock_repo = MockAttachmentRepository::new();
+    mock_repo
+      .expect_find_thumbnail()
+      .with(eq(3), eq(ThumbnailSize::Medium))
+      .times(1)
+      .returning(|id, size| {
+        Ok(
+          ThumbnailBuilder::new()
+            .with_attachment_id(id)
+            .with_size(size)
+            .build(),
+        )
+      });
+    let service = service(mock_repo, MockBlobStore::new());
+    let ready = Builder::new()
+      .with_id(3)
+      .with_image(600, 400, ThumbnailState::Ready)
+      .build();
+    let pending = Builder::new()
+      .with_id(4)
+      .with_image(600, 400, ThumbnailState::Pending)
+      .build();
+
+    assert_eq!(
+      service.thumbnail(&ready, String::from("medium")).unwrap(),
+      ThumbnailBuilder::new()
+        .with_attachment_id(3)
+        .with_size(ThumbnailSize::Medium)
+        .build()
+    );
+    assert!(matches!(
+      service.thumbnail(&ready, String::from("huge")),
+      Err(Error::InvalidInput(_))
+    ));
+    assert!(matches!(
+      service.thumbnail(&pending, String::from("small")),
+      Err(Error::NotFound("thumbnail"))
+    ));
+    assert!(matches!(
+      service.thumbnail(&Builder::new().build(), String::from("small")),
+      Err(Error::NotFound("thumbnail"))
+    ));
+  }
+
+  #[test]
+  fn clean_the_names_and_the_types() {
+    assert_eq!(clean_filename("C:\\Users\\me\\photo.png"), "photo.png");
+    assert_eq!(clean_filename("../../etc/passwd"), "passwd");
+    assert_eq!(clean_filename("dir/"), "file");
+    assert_eq!(clean_mime_type("Image/PNG"), "image/png");
+    assert_eq!(clean_mime_type("text/html; x"), "application/octet-stream");
+    assert_eq!(clean_mime_type("nothing"), "application/octet-stream");
+  }
+}
@@ -0,0 +1,247 @@
+use crate::schema::{conversation_members, conversations};
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// A group conversation, its messages are delivered to all its members.
+#[derive(Identifiable, Queryable, Clone)]
+pub struct Conversation {
+  id: i32,
+  name: String,
+  created_by: i32,
+  created_at: NaiveDateTime,
+}
+
+impl Conversation {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_name(&self) -> String {
+    return self.name.to_string();
+  }
+
+  pub fn get_created_by(&self) -> i32 {
+    return self.created_by;
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "conversations"]
+pub struct NewConversation {
+  name: String,
+  created_by: i32,
+  created_at: NaiveDateTime,
+}
+
+impl NewConversation {
+  pub fn new(the_name: String, the_owner: i32) -> NewConversation {
+    NewConversation {
+      name: the_name,
+      created_by: the_owner,
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+
+  pub fn get_created_by(&self) -> i32 {
+    return self.created_by;
+  }
+}
+
+/// The role of a member in a group conversation.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MemberRole {
+  Owner,
+  Admin,
+  #[default]
+  Member,
+}
+
+impl MemberRole {
+  /// Checks if the role can rename the conversation and invite or remove
+  /// members.
+  pub fn can_manage(&self) -> bool {
+    return *self != MemberRole::Member;
+  }
+
+  /// Checks if the role can grant or remove another role. The owner can do
+  /// everything, an admin only with plain members and nobody with the owner.
+  ///
+  /// # Arguments
+  /// * `other` - The role granted or removed.
+  ///
+  /// # Return
+  /// * True if the role is above the other one.
+  pub fn outranks(&self, other: MemberRole) -> bool {
+    match self {
+      MemberRole::Owner => other != MemberRole::Owner,
+      MemberRole::Admin => other == MemberRole::Member,
+      MemberRole::Member => false,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      MemberRole::Owner => "owner",
+      MemberRole::Admin => "admin",
+      MemberRole::Member => "member",
+    }
+  }
+}
+
+impl fmt::Display for MemberRole {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for MemberRole {
+  type Err = String;
+
+  fn from_str(role: &str) -> Result<Self, Self::Err> {
+    match role {
+      "owner" => Ok(MemberRole::Owner),
+      "admin" => Ok(MemberRole::Admin),
+      "member" => Ok(MemberRole::Member),
+      other => Err(format!("Unknown member role {}", other)),
+    }
+  }
+}
+
+/// A user that belongs to a group conversation.
+#[derive(Identifiable, Queryable, Clone)]
+#[table_name = "conversation_members"]
+pub struct Member {
+  id: i32,
+  conversation_id: i32,
+  user_id: i32,
+  role: String,
+  joined_at: NaiveDateTime,
+}
+
+impl Member {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  /// The role of the member, an unknown stored value is taken as a plain
+  /// member.
+  pub fn get_role(&self) -> MemberRole {
+    return self.role.parse().unwrap_or_default();
+  }
+
+  pub fn get_joined_at(&self) -> NaiveDateTime {
+    return self.joined_at;
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "conversation_members"]
+pub struct NewMember {
+  conversation_id: i32,
+  user_id: i32,
+  role: String,
+  joined_at: NaiveDateTime,
+}
+
+impl NewMember {
+  pub fn new(
+    the_conversation_id: i32,
+    the_user_id: i32,
+    the_role: MemberRole,
+  ) -> NewMember {
+    NewMember {
+      conversation_id: the_conversation_id,
+      user_id: the_user_id,
+      role: the_role.to_string(),
+      joined_at: Utc::now().naive_utc(),
+    }
+  }
+}
+
+#[cfg(test)]
+pub struct Builder {
+  conversation_id: Option<i32>,
+  user_id: Option<i32>,
+  role: Option<MemberRole>,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      conversation_id: None,
+      user_id: None,
+      role: None,
+    }
+  }
+
+  pub fn with_conversation_id(mut self, the_id: i32) -> Builder {
+    self.conversation_id = Some(the_id);
+    self
+  }
+
+  pub fn with_user_id(mut self, the_user_id: i32) -> Builder {
+    self.user_id = Some(the_user_id);
+    self
+  }
+
+  pub fn with_role(mut self, the_role: MemberRole) -> Builder {
+    self.role = Some(the_role);
+    self
+  }
+
+  pub fn build(&self) -> Member {
+    Member {
+      id: 0,
+      conversation_id: *self.conversation_id.as_ref().unwrap_or(&0),
+      user_id: *self.user_id.as_ref().unwrap_or(&0),
+      role: self.role.unwrap_or_default().to_string(),
+      joined_at: NaiveDateTime::from_timestamp(0, 0),
+    }
+  }
+
+  pub fn build_conversation(&self, the_name: &str) -> Conversation {
+    Conversation {
+      id: *self.conversation_id.as_ref().unwrap_or(&0),
+      name: the_name.to_string(),
+      created_by: *self.user_id.as_ref().unwrap_or(&0),
+      created_at: NaiveDateTime::from_timestamp(0, 0),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_higher_roles_manage_the_members() {
+    assert!(MemberRole::Owner.outranks(MemberRole::Admin));
+    assert!(MemberRole::Admin.outranks(MemberRole::Member));
+    assert!(!MemberRole::Admin.outranks(MemberRole::Admin));
+    assert!(!MemberRole::Admin.outranks(MemberRole::Owner));
+    assert!(!MemberRole::Member.outranks(MemberRole::Member));
+    assert!(!MemberRole::Member.can_manage());
+  }
+
+  #[test]
+  fn parse_member_role() {
+    assert_eq!("owner".parse::<MemberRole>(), Ok(MemberRole::Owner));
+    assert_eq!(MemberRole::Admin.to_string(), "admin");
+    assert!("guest".parse::<MemberRole>().is_err());
+  }
+}
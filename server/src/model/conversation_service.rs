@@ -0,0 +1,425 @@
+use crate::model::{
+  conversation::{
+    Conversation, Member, MemberRole, NewConversation, NewMember,
+  },
+  error::{Error, ServiceResult},
+  page::{Page, PageRequest},
+  repository::{
+    conversation_repository::ConversationRepository, error::Error as RepoError,
+  },
+};
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait ConversationService: Sync + Send {
+  /// Creates a group conversation owned by the given user, with its first
+  /// members.
+  ///
+  /// # Arguments
+  /// * `owner` - The user_id of the creator, who becomes the owner.
+  /// * `name` - The name of the conversation.
+  /// * `members` - The user_ids of the first members.
+  ///
+  /// # Return
+  /// * The id of the recently created conversation.
+  /// * An error if the name is empty.
+  fn create(
+    &self,
+    owner: i32,
+    name: String,
+    members: Vec<i32>,
+  ) -> ServiceResult<i32>;
+
+  /// Get a page of the group conversations a user is member of.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the member.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of conversations ordered by id. Could be empty.
+  /// * An error instead.
+  fn list(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Conversation>>;
+
+  /// Changes the name of a conversation. Only the owner and the admins can
+  /// rename it.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member who renames it.
+  /// * `name` - The new name.
+  ///
+  /// # Return
+  /// * The renamed conversation.
+  /// * An error if the name is empty or the user can't rename it.
+  fn rename(
+    &self,
+    id: i32,
+    uid: i32,
+    name: String,
+  ) -> ServiceResult<Conversation>;
+
+  /// Get a page of the members of a conversation. Only its members can see
+  /// them.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member who asks.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of members ordered by their join.
+  /// * An error if the user isn't a member.
+  fn members(
+    &self,
+    id: i32,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Member>>;
+
+  /// Adds a user to a conversation. Only the owner and the admins can invite,
+  /// and they can only grant a role below their own.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member who invites.
+  /// * `user_id` - The user_id of the new member.
+  /// * `role` - The role of the new member.
+  ///
+  /// # Return
+  /// * An error if the user can't invite with that role or is already a member.
+  fn invite(
+    &self,
+    id: i32,
+    uid: i32,
+    user_id: i32,
+    role: MemberRole,
+  ) -> ServiceResult<()>;
+
+  /// Removes a member from a conversation. The owner can remove anyone and
+  /// the admins only the plain members. Removing oneself is leaving.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member who removes.
+  /// * `user_id` - The user_id of the removed member.
+  ///
+  /// # Return
+  /// * An error if the user can't remove the member.
+  fn remove(&self, id: i32, uid: i32, user_id: i32) -> ServiceResult<()>;
+
+  /// Leaves a conversation. The owner can't leave it.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member who leaves.
+  ///
+  /// # Return
+  /// * An error if the user isn't a member or is the owner.
+  fn leave(&self, id: i32, uid: i32) -> ServiceResult<()>;
+}
+
+pub struct ConversationServiceImpl<ConversationRepo> {
+  conversation_repository: ConversationRepo,
+}
+
+impl<ConversationRepo> ConversationServiceImpl<ConversationRepo>
+where
+  ConversationRepo: ConversationRepository,
+{
+  pub fn new(the_conversation_repository: ConversationRepo) -> Self {
+    ConversationServiceImpl {
+      conversation_repository: the_conversation_repository,
+    }
+  }
+
+  /// Get the role of a user in a conversation. A user that isn't a member
+  /// doesn't see the conversation.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the conversation.
+  /// * `uid` - The user_id of the member.
+  ///
+  /// # Return
+  /// * The role of the member.
+  /// * An error if the user isn't a member.
+  fn role_of(&self, id: i32, uid: i32) -> ServiceResult<MemberRole> {
+    let member = self
+      .conversation_repository
+      .get_member(id, uid)
+      .map_err(Error::from)?
+      .ok_or(Error::NotFound("conversation"))?;
+    Ok(member.get_role())
+  }
+
+  /// Checks that a role can manage the members of a conversation.
+  fn check_manager(role: MemberRole) -> ServiceResult<()> {
+    if !role.can_manage() {
+      return Err(Error::Forbidden(String::from(
+        "Only the owner and the admins can manage the conversation",
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl<ConversationRepo> ConversationService
+  for ConversationServiceImpl<ConversationRepo>
+where
+  ConversationRepo: ConversationRepository + Send + Sync,
+{
+  fn create(
+    &self,
+    owner: i32,
+    name: String,
+    members: Vec<i32>,
+  ) -> ServiceResult<i32> {
+    if name.trim().is_empty() {
+      return Err(Error::InvalidInput(String::from("the name is empty")));
+    }
+    self
+      .conversation_repository
+      .add(NewConversation::new(name, owner), members)
+      .map_err(Error::from)
+  }
+
+  fn list(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Conversation>> {
+    self
+      .conversation_repository
+      .find_by_member(uid, page)
+      .map_err(Error::from)
+  }
+
+  fn rename(
+    &self,
+    id: i32,
+    uid: i32,
+    name: String,
+  ) -> ServiceResult<Conversation> {
+    if name.trim().is_empty() {
+      return Err(Error::InvalidInput(String::from("the name is empty")));
+    }
+    Self::check_manager(self.role_of(id, uid)?)?;
+    self
+      .conversation_repository
+      .rename(id, name)
+      .map_err(Error::from)?;
+    self
+      .conversation_repository
+      .get(id)
+      .map_err(|err| Error::from_repo(err, "conversation"))
+  }
+
+  fn members(
+    &self,
+    id: i32,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Member>> {
+    self.role_of(id, uid)?;
+    self
+      .conversation_repository
+      .find_members(id, page)
+      .map_err(Error::from)
+  }
+
+  fn invite(
+    &self,
+    id: i32,
+    uid: i32,
+    user_id: i32,
+    role: MemberRole,
+  ) -> ServiceResult<()> {
+    let own_role = self.role_of(id, uid)?;
+    Self::check_manager(own_role)?;
+    if !own_role.outranks(role) {
+      return Err(Error::Forbidden(format!("Cannot grant the role {}", role)));
+    }
+    self
+      .conversation_repository
+      .add_member(NewMember::new(id, user_id, role))
+      .map_err(|err| match err {
+        RepoError::UniqueViolation(_) => {
+          Error::AlreadyExists(format!("the member {}", user_id))
+        },
+        _ => Error::from(err),
+      })?;
+    Ok(())
+  }
+
+  fn remove(&self, id: i32, uid: i32, user_id: i32) -> ServiceResult<()> {
+    if uid == user_id {
+      return self.leave(id, uid);
+    }
+    let own_role = self.role_of(id, uid)?;
+    Self::check_manager(own_role)?;
+    let member = self
+      .conversation_repository
+      .get_member(id, user_id)
+      .map_err(Error::from)?
+      .ok_or(Error::NotFound("member"))?;
+    if !own_role.outranks(member.get_role()) {
+      return Err(Error::Forbidden(format!(
+        "Cannot remove a member with the role {}",
+        member.get_role()
+      )));
+    }
+    self
+      .conversation_repository
+      .remove_member(id, user_id)
+      .map_err(Error::from)?;
+    Ok(())
+  }
+
+  fn leave(&self, id: i32, uid: i32) -> ServiceResult<()> {
+    if self.role_of(id, uid)? == MemberRole::Owner {
+      return Err(Error::Forbidden(String::from(
+        "The owner cannot leave the conversation",
+      )));
+    }
+    self
+      .conversation_repository
+      .remove_member(id, uid)
+      .map_err(Error::from)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{
+    conversation::Builder,
+    repository::conversation_repository::MockConversationRepository,
+  };
+  use diesel::result::{DatabaseErrorKind, Error as DieselError};
+  use mockall::predicate::eq;
+
+  fn member_of(id: i32, uid: i32, role: MemberRole) -> Option<Member> {
+    Some(
+      Builder::new()
+        .with_conversation_id(id)
+        .with_user_id(uid)
+        .with_role(role)
+        .build(),
+    )
+  }
+
+  #[test]
+  fn create_conversation_without_name() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert_eq!(
+      service.create(1, String::from(" "), vec![2]).err(),
+      Some(Error::InvalidInput(String::from("the name is empty")))
+    );
+  }
+
+  #[test]
+  fn admin_cannot_grant_admin() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(2))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Admin)));
+    mock_repo.expect_add_member().times(0);
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert_eq!(
+      service.invite(7, 2, 5, MemberRole::Admin).err(),
+      Some(Error::Forbidden(String::from(
+        "Cannot grant the role admin"
+      )))
+    );
+  }
+
+  #[test]
+  fn invite_a_member_twice() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(1))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Owner)));
+    mock_repo.expect_add_member().times(1).returning(|_| {
+      Err(RepoError::from(DieselError::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new(String::from("UNIQUE constraint failed")),
+      )))
+    });
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert_eq!(
+      service.invite(7, 1, 5, MemberRole::Admin).err(),
+      Some(Error::AlreadyExists(String::from("the member 5")))
+    );
+  }
+
+  #[test]
+  fn admin_cannot_remove_the_owner() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(2))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Admin)));
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(1))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Owner)));
+    mock_repo.expect_remove_member().times(0);
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert!(matches!(service.remove(7, 2, 1), Err(Error::Forbidden(_))));
+  }
+
+  #[test]
+  fn owner_cannot_leave() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(1))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Owner)));
+    mock_repo.expect_remove_member().times(0);
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert_eq!(
+      service.leave(7, 1).err(),
+      Some(Error::Forbidden(String::from(
+        "The owner cannot leave the conversation"
+      )))
+    );
+  }
+
+  #[test]
+  fn member_leaves() {
+    let mut mock_repo = MockConversationRepository::new();
+    mock_repo
+      .expect_get_member()
+      .with(eq(7), eq(3))
+      .times(1)
+      .returning(|id, uid| Ok(member_of(id, uid, MemberRole::Member)));
+    mock_repo
+      .expect_remove_member()
+      .with(eq(7), eq(3))
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let service = ConversationServiceImpl::new(mock_repo);
+    assert!(service.remove(7, 3, 3).is_ok());
+  }
+}
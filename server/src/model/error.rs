@@ -1 +1,92 @@
-pub type ServiceResult<T> = Result<T, String>;
+use crate::model::repository::error::Error as RepoError;
+use thiserror::Error;
+
+pub type ServiceResult<T> = Result<T, Error>;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+  #[error("{0} not found")]
+  NotFound(&'static str),
+  #[error("{0} already exists")]
+  AlreadyExists(String),
+  #[error("invalid input: {0}")]
+  InvalidInput(String),
+  #[error("unknown {0}")]
+  UnknownReference(&'static str),
+  #[error("invalid credentials")]
+  InvalidCredentials,
+  #[error("{0}")]
+  Unauthorized(String),
+  #[error("{0}")]
+  Forbidden(String),
+  #[error("limit exceeded: {0}")]
+  LimitExceeded(String),
+  #[error("database unavailable")]
+  Unavailable,
+  #[error("{0}")]
+  Internal(String),
+}
+
+impl Error {
+  /// Converts a repository error naming the missing entity when the record
+  /// doesn't exist, or the referenced entity when a foreign key is violated.
+  ///
+  /// # Arguments
+  /// * `err` - The repository error.
+  /// * `entity` - The name of the entity that was looked for.
+  ///
+  /// # Return
+  /// * The service error.
+  pub fn from_repo(err: RepoError, entity: &'static str) -> Self {
+    match err {
+      RepoError::NotFound => Error::NotFound(entity),
+      RepoError::ForeignKeyViolation(_) => Error::UnknownReference(entity),
+      _ => Error::from(err),
+    }
+  }
+}
+
+impl From<RepoError> for Error {
+  fn from(err: RepoError) -> Self {
+    match err {
+      RepoError::ConnectionError => Error::Unavailable,
+      RepoError::NotFound => Error::NotFound("record"),
+      RepoError::UniqueViolation(detail) => Error::AlreadyExists(detail),
+      RepoError::ForeignKeyViolation(_) => Error::UnknownReference("reference"),
+      RepoError::DieselError(err) => Error::Internal(err.to_string()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+  #[test]
+  fn repository_errors_are_mapped() {
+    let violation = DieselError::DatabaseError(
+      DatabaseErrorKind::UniqueViolation,
+      Box::new(String::from("UNIQUE constraint failed: users.username")),
+    );
+    assert_eq!(
+      Error::from(RepoError::from(violation)),
+      Error::AlreadyExists(String::from(
+        "UNIQUE constraint failed: users.username"
+      ))
+    );
+    assert_eq!(Error::from(RepoError::ConnectionError), Error::Unavailable);
+    assert_eq!(
+      Error::from_repo(RepoError::from(DieselError::NotFound), "user"),
+      Error::NotFound("user")
+    );
+    let violation = DieselError::DatabaseError(
+      DatabaseErrorKind::ForeignKeyViolation,
+      Box::new(String::from("FOREIGN KEY constraint failed")),
+    );
+    assert_eq!(
+      Error::from_repo(RepoError::from(violation), "recipient"),
+      Error::UnknownReference("recipient")
+    );
+  }
+}
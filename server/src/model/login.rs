@@ -1,13 +1,24 @@
 use crate::schema::logins;
 
+use chrono::{NaiveDateTime, Utc};
 use diesel::{Identifiable, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
 
+/// A session of a user. Every login creates a new one, so a user can be
+/// logged in from many devices at the same time.
 #[derive(Identifiable, Queryable, Serialize, Clone)]
 pub struct Login {
   id: i32,
+  user_id: i32,
   username: String,
   token: String,
+  jti: String,
+  family: String,
+  device: Option<String>,
+  user_agent: Option<String>,
+  ip: Option<String>,
+  created_at: NaiveDateTime,
+  last_seen_at: NaiveDateTime,
 }
 
 impl Login {
@@ -15,52 +26,125 @@ impl Login {
     return self.id;
   }
 
-  pub fn get_username(&self) -> String {
-    return self.username.to_string();
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
   }
 
   pub fn get_token(&self) -> String {
     return self.token.to_string();
   }
 
-  pub fn update(&mut self, login: &NewLogin) -> Result<(), String> {
-    if self.username != login.get_username() {
-      return Err(String::from("Username doesn't match"));
+  pub fn get_jti(&self) -> String {
+    return self.jti.to_string();
+  }
+
+  pub fn get_family(&self) -> String {
+    return self.family.to_string();
+  }
+
+  pub fn get_device(&self) -> Option<String> {
+    return self.device.clone();
+  }
+
+  pub fn get_user_agent(&self) -> Option<String> {
+    return self.user_agent.clone();
+  }
+
+  pub fn get_ip(&self) -> Option<String> {
+    return self.ip.clone();
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+
+  pub fn get_last_seen_at(&self) -> NaiveDateTime {
+    return self.last_seen_at;
+  }
+
+  /// Replaces the access token of the session after a token rotation.
+  ///
+  /// # Arguments
+  /// * `the_token` - The new access token.
+  /// * `the_jti` - The unique id of the new access token.
+  pub fn renew(&mut self, the_token: String, the_jti: String) {
+    self.token = the_token;
+    self.jti = the_jti;
+    self.last_seen_at = Utc::now().naive_utc();
+  }
+}
+
+/// The description of the client that starts a session.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Device {
+  label: Option<String>,
+  user_agent: Option<String>,
+  ip: Option<String>,
+}
+
+impl Device {
+  pub fn new(
+    the_label: Option<String>,
+    the_user_agent: Option<String>,
+    the_ip: Option<String>,
+  ) -> Device {
+    Device {
+      label: the_label,
+      user_agent: the_user_agent,
+      ip: the_ip,
     }
-    self.token = login.token.to_string();
-    Ok(())
   }
 }
 
 #[derive(Insertable, Deserialize)]
 #[table_name = "logins"]
 pub struct NewLogin {
+  user_id: i32,
   username: String,
   token: String,
+  jti: String,
+  family: String,
+  device: Option<String>,
+  user_agent: Option<String>,
+  ip: Option<String>,
+  created_at: NaiveDateTime,
+  last_seen_at: NaiveDateTime,
 }
 
 impl NewLogin {
-  pub fn new(the_username: String, the_token: String) -> NewLogin {
+  pub fn new(
+    the_user_id: i32,
+    the_username: String,
+    the_token: String,
+    the_jti: String,
+    the_family: String,
+    the_device: Device,
+  ) -> NewLogin {
+    let now = Utc::now().naive_utc();
     NewLogin {
+      user_id: the_user_id,
       username: the_username,
       token: the_token,
+      jti: the_jti,
+      family: the_family,
+      device: the_device.label,
+      user_agent: the_device.user_agent,
+      ip: the_device.ip,
+      created_at: now,
+      last_seen_at: now,
     }
   }
-
-  pub fn get_username(&self) -> String {
-    return self.username.to_string();
-  }
-
-  pub fn get_token(&self) -> String {
-    return self.token.to_string();
-  }
 }
 
 #[cfg(test)]
 pub struct Builder {
   id: Option<i32>,
+  user_id: Option<i32>,
   username: Option<String>,
   token: Option<String>,
+  jti: Option<String>,
+  family: Option<String>,
+  device: Option<String>,
 }
 
 #[cfg(test)]
@@ -68,8 +152,12 @@ impl Builder {
   pub fn new() -> Self {
     Builder {
       id: None,
+      user_id: None,
       username: None,
       token: None,
+      jti: None,
+      family: None,
+      device: None,
     }
   }
 
@@ -78,6 +166,11 @@ impl Builder {
     self
   }
 
+  pub fn with_user_id(mut self, the_user_id: i32) -> Builder {
+    self.user_id = Some(the_user_id);
+    self
+  }
+
   pub fn with_username(mut self, name: &str) -> Builder {
     self.username = Some(name.to_owned());
     self
@@ -88,11 +181,35 @@ impl Builder {
     self
   }
 
+  pub fn with_jti(mut self, the_jti: &str) -> Builder {
+    self.jti = Some(the_jti.to_owned());
+    self
+  }
+
+  pub fn with_family(mut self, the_family: &str) -> Builder {
+    self.family = Some(the_family.to_owned());
+    self
+  }
+
+  pub fn with_device(mut self, the_device: &str) -> Builder {
+    self.device = Some(the_device.to_owned());
+    self
+  }
+
   pub fn build(&self) -> Login {
+    let epoch = NaiveDateTime::from_timestamp(0, 0);
     Login {
       id: *self.id.as_ref().unwrap_or(&0),
+      user_id: *self.user_id.as_ref().unwrap_or(&0),
       username: String::from(self.username.as_deref().unwrap()),
       token: String::from(self.token.as_deref().unwrap()),
+      jti: String::from(self.jti.as_deref().unwrap_or("")),
+      family: String::from(self.family.as_deref().unwrap_or("")),
+      device: self.device.clone(),
+      user_agent: None,
+      ip: None,
+      created_at: epoch,
+      last_seen_at: epoch,
     }
   }
 }
@@ -1,13 +1,21 @@
-use crate::schema::messages;
+use crate::schema::{message_revisions, messages};
+use chrono::{NaiveDateTime, Utc};
 use diesel::{Identifiable, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
 
+/// A message from a user to another one, or to the members of a group
+/// conversation. A deleted message is kept as a tombstone, with its deletion
+/// date and without its content for the readers.
 #[derive(Identifiable, Queryable, Serialize, Clone)]
 pub struct Message {
   id: i32,
   from: i32,
-  to: i32,
+  to: Option<i32>,
   message: String,
+  created_at: NaiveDateTime,
+  updated_at: NaiveDateTime,
+  deleted_at: Option<NaiveDateTime>,
+  conversation_id: Option<i32>,
 }
 
 impl Message {
@@ -15,34 +23,149 @@ impl Message {
     return self.id;
   }
 
+  pub fn get_from(&self) -> i32 {
+    return self.from;
+  }
+
   pub fn get_message(&self) -> String {
     return self.message.to_string();
   }
 
-  pub fn get_to(&self) -> i32 {
+  /// The recipient of a direct message, none for a group message.
+  pub fn get_to(&self) -> Option<i32> {
     return self.to;
   }
+
+  /// The group conversation of the message, none for a direct message.
+  pub fn get_conversation_id(&self) -> Option<i32> {
+    return self.conversation_id;
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+
+  pub fn get_updated_at(&self) -> NaiveDateTime {
+    return self.updated_at;
+  }
+
+  pub fn get_deleted_at(&self) -> Option<NaiveDateTime> {
+    return self.deleted_at;
+  }
+
+  pub fn is_deleted(&self) -> bool {
+    return self.deleted_at.is_some();
+  }
+
+  /// Checks if a user can read a direct message, only its sender and its
+  /// recipient can. The readers of a group message are the members of its
+  /// conversation.
+  ///
+  /// # Arguments
+  /// * `uid` - The user that wants to read the message.
+  ///
+  /// # Return
+  /// * True if the user is the sender or the recipient.
+  pub fn is_participant(&self, uid: i32) -> bool {
+    return self.from == uid || self.to == Some(uid);
+  }
+}
+
+/// A message found by a search, with the fragment of its content that
+/// matched. The matched words are between `<mark>` tags.
+#[derive(Clone)]
+pub struct SearchHit {
+  message: Message,
+  snippet: String,
+}
+
+impl SearchHit {
+  pub fn new(the_message: Message, the_snippet: String) -> SearchHit {
+    SearchHit {
+      message: the_message,
+      snippet: the_snippet,
+    }
+  }
+
+  pub fn get_message(&self) -> &Message {
+    return &self.message;
+  }
+
+  pub fn get_snippet(&self) -> String {
+    return self.snippet.to_string();
+  }
 }
 
 #[derive(Insertable, Deserialize)]
 #[table_name = "messages"]
 pub struct NewMessage {
   from: i32,
-  to: i32,
+  to: Option<i32>,
   message: String,
+  created_at: NaiveDateTime,
+  updated_at: NaiveDateTime,
+  deleted_at: Option<NaiveDateTime>,
+  conversation_id: Option<i32>,
 }
 
 impl NewMessage {
   pub fn new(from_user: i32, to_user: i32, the_message: String) -> NewMessage {
+    let now = Utc::now().naive_utc();
     NewMessage {
       from: from_user,
-      to: to_user,
+      to: Some(to_user),
       message: the_message,
+      created_at: now,
+      updated_at: now,
+      deleted_at: None,
+      conversation_id: None,
     }
   }
 
-  pub fn get_from(&self) -> i32 {
-    return self.from;
+  /// Creates a message for the members of a group conversation.
+  ///
+  /// # Arguments
+  /// * `from_user` - The user_id of the sender.
+  /// * `the_conversation_id` - The id of the conversation.
+  /// * `the_message` - The message.
+  pub fn for_conversation(
+    from_user: i32,
+    the_conversation_id: i32,
+    the_message: String,
+  ) -> NewMessage {
+    let now = Utc::now().naive_utc();
+    NewMessage {
+      from: from_user,
+      to: None,
+      message: the_message,
+      created_at: now,
+      updated_at: now,
+      deleted_at: None,
+      conversation_id: Some(the_conversation_id),
+    }
+  }
+}
+
+/// A previous content of an edited message.
+#[derive(Insertable)]
+#[table_name = "message_revisions"]
+pub struct NewMessageRevision {
+  message_id: i32,
+  message: String,
+  created_at: NaiveDateTime,
+}
+
+impl NewMessageRevision {
+  /// Keeps the content of a message before replacing it.
+  ///
+  /// # Arguments
+  /// * `msg` - The message before the edition.
+  pub fn new(msg: &Message) -> NewMessageRevision {
+    NewMessageRevision {
+      message_id: msg.get_id(),
+      message: msg.get_message(),
+      created_at: Utc::now().naive_utc(),
+    }
   }
 }
 
@@ -52,6 +175,9 @@ pub struct Builder {
   from: Option<i32>,
   to: Option<i32>,
   message: Option<String>,
+  updated_at: Option<NaiveDateTime>,
+  deleted_at: Option<NaiveDateTime>,
+  conversation_id: Option<i32>,
 }
 
 #[cfg(test)]
@@ -62,6 +188,9 @@ impl Builder {
       from: None,
       to: None,
       message: None,
+      updated_at: None,
+      deleted_at: None,
+      conversation_id: None,
     }
   }
 
@@ -85,12 +214,32 @@ impl Builder {
     self
   }
 
+  pub fn with_conversation_id(mut self, the_id: i32) -> Builder {
+    self.conversation_id = Some(the_id);
+    self
+  }
+
+  pub fn with_updated_at(mut self, the_updated_at: NaiveDateTime) -> Builder {
+    self.updated_at = Some(the_updated_at);
+    self
+  }
+
+  pub fn with_deleted_at(mut self, the_deleted_at: NaiveDateTime) -> Builder {
+    self.deleted_at = Some(the_deleted_at);
+    self
+  }
+
   pub fn build(&self) -> Message {
+    let epoch = NaiveDateTime::from_timestamp(0, 0);
     Message {
       id: *self.id.as_ref().unwrap_or(&0),
       from: *self.from.as_ref().unwrap_or(&0),
-      to: *self.to.as_ref().unwrap_or(&0),
+      to: self.to,
       message: String::from(self.message.as_deref().unwrap()),
+      created_at: epoch,
+      updated_at: self.updated_at.unwrap_or(epoch),
+      deleted_at: self.deleted_at,
+      conversation_id: self.conversation_id,
     }
   }
 }
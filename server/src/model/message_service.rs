@@ -1,11 +1,42 @@
-use crate::model::{
-  error::ServiceResult,
-  message::{Message, NewMessage},
-  repository::message_repository::MessageRepository,
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use crate::{
+  model::{
+    attachment::Attachment,
+    conversation::Member,
+    error::{Error, ServiceResult},
+    message::{Message, NewMessage, SearchHit},
+    page::{Page, PageRequest, MAX_PAGE_SIZE},
+    receipt::{Receipt, UnreadCount},
+    repository::{
+      attachment_repository::AttachmentRepository,
+      conversation_repository::ConversationRepository,
+      error::Error as RepoError, message_repository::MessageRepository,
+      receipt_repository::ReceiptRepository, user_repository::UserRepository,
+    },
+  },
+  realtime::{event::Event, hub::Hub},
 };
 #[cfg(test)]
 use mockall::automock;
 
+/// The quantity of messages retrieved when the limit isn't specified.
+const DEFAULT_LIMIT: i64 = 5;
+
+/// Get the quantity of messages to retrieve, lowered to `MAX_PAGE_SIZE`.
+///
+/// # Arguments
+/// * `limit` - The limit asked for, `DEFAULT_LIMIT` if it's not specified.
+///
+/// # Return
+/// * The limit, between 1 and `MAX_PAGE_SIZE`.
+fn checked_limit(limit: Option<i64>) -> i64 {
+  limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_PAGE_SIZE)
+}
+
 #[cfg_attr(test, automock)]
 pub trait MessageService: Sync + Send {
   /// Creates a new message from a user to another user. Both user must be in
@@ -15,11 +46,42 @@ pub trait MessageService: Sync + Send {
   /// * `from` - The user_id of the message's sender.
   /// * `to` - The user_id of the message's recipient.
   /// * `message` - The message.
+  /// * `attachments` - The ids of the files uploaded by the sender to attach.
   ///
   /// # Return
   /// * The id of the recently created message.
+  /// * UnknownReference if the sender or the recipient doesn't exist, or an
+  ///   attachment isn't a file of the sender not sent yet.
   /// * An error otherwise.
-  fn create(&self, from: i32, to: i32, message: String) -> ServiceResult<i32>;
+  fn create(
+    &self,
+    from: i32,
+    to: i32,
+    message: String,
+    attachments: Vec<i32>,
+  ) -> ServiceResult<i32>;
+
+  /// Creates a new message from a user to a group conversation, it's
+  /// delivered to all the other members. Only the members can send it.
+  ///
+  /// # Arguments
+  /// * `from` - The user_id of the message's sender.
+  /// * `conversation_id` - The id of the conversation.
+  /// * `message` - The message.
+  /// * `attachments` - The ids of the files uploaded by the sender to attach.
+  ///
+  /// # Return
+  /// * The id of the recently created message.
+  /// * UnknownReference if the conversation doesn't exist, or an attachment
+  ///   isn't a file of the sender not sent yet.
+  /// * An error if the sender isn't a member of the conversation.
+  fn create_in_conversation(
+    &self,
+    from: i32,
+    conversation_id: i32,
+    message: String,
+    attachments: Vec<i32>,
+  ) -> ServiceResult<i32>;
 
   /// Get the message from the given id.
   ///
@@ -31,68 +93,1521 @@ pub trait MessageService: Sync + Send {
   /// * An error instead.
   fn get(&self, id: i32) -> ServiceResult<Message>;
 
-  /// Finds the messages from a specific user, since the message_id specified
-  /// and with a limit. If the limit is none, then a default of 5 is used.
+  /// Get the message from the given id for one of its readers: the sender
+  /// and the recipient of a direct message, or the members of the
+  /// conversation of a group message.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message to retrieve.
+  /// * `uid` - The user_id of the reader.
+  ///
+  /// # Return
+  /// * The message.
+  /// * An error if it doesn't exist or the user can't read it.
+  fn read(&self, id: i32, uid: i32) -> ServiceResult<Message>;
+
+  /// Get the message from the given id for one of its readers, like `read`,
+  /// and mark it as delivered when the reader is one of its recipients.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message to retrieve.
+  /// * `uid` - The user_id of the reader.
+  ///
+  /// # Return
+  /// * The message.
+  /// * An error if it doesn't exist or the user can't read it.
+  fn open(&self, id: i32, uid: i32) -> ServiceResult<Message>;
+
+  /// Finds a page of the messages from a specific user.
   ///
   /// # Arguments
-  /// * `from_msg` - The message_id from to retrieve.
   /// * `from_user` - The user_id of the message's sender.
-  /// * `limit` - A limit of how many messages to retrieve.
+  /// * `page` - The page to retrieve.
   ///
   /// # Return
-  /// * A vector of messages in descending order from its id. Could be empty.
+  /// * A page of messages in descending order from its id. Could be empty.
   /// * An error instead.
   fn find(
     &self,
-    from_msg: i32,
     from_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>>;
+
+  /// Finds a page of the messages sent to a specific user. The messages are
+  /// marked as delivered to the user.
+  ///
+  /// # Arguments
+  /// * `to_user` - The user_id of the message's recipient.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of messages in descending order from its id. Could be empty.
+  /// * An error instead.
+  fn inbox(
+    &self,
+    to_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>>;
+
+  /// Finds a page of the messages exchanged between two users in both
+  /// directions. The messages received by the user are marked as delivered.
+  ///
+  /// # Arguments
+  /// * `user` - The user_id of one of the participants.
+  /// * `other_user` - The user_id of the other participant.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of messages in descending order from its id. Could be empty.
+  /// * An error instead.
+  fn conversation(
+    &self,
+    user: i32,
+    other_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>>;
+
+  /// Finds a page of the messages of a group conversation. Only the members
+  /// can read them, and the messages they received are marked as delivered.
+  ///
+  /// # Arguments
+  /// * `conversation_id` - The id of the conversation.
+  /// * `uid` - The user_id of the member.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of messages in descending order from its id. Could be empty.
+  /// * An error if the user isn't a member of the conversation.
+  fn conversation_messages(
+    &self,
+    conversation_id: i32,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>>;
+
+  /// Finds the messages received by a user after the given message_id,
+  /// direct or of its group conversations, to resume a connection. If the
+  /// limit is none, then a default of 5 is used. The messages are marked as
+  /// delivered to the user.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the recipient.
+  /// * `last_id` - The message_id of the last message received, excluded.
+  /// * `limit` - A limit of how many messages to retrieve, `MAX_PAGE_SIZE` at
+  ///   most.
+  ///
+  /// # Return
+  /// * A vector of messages in chronological order. Could be empty.
+  /// * An error instead.
+  fn missed(
+    &self,
+    uid: i32,
+    last_id: i32,
     limit: Option<i64>,
   ) -> ServiceResult<Vec<Message>>;
+
+  /// Waits for the messages received by a user after the given message_id,
+  /// like `missed`, and returns as soon as there is one.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the recipient.
+  /// * `after` - The message_id of the last message received, excluded.
+  /// * `limit` - A limit of how many messages to retrieve, `MAX_PAGE_SIZE` at
+  ///   most.
+  /// * `timeout` - How long to wait for a new message.
+  ///
+  /// # Return
+  /// * A vector of messages in chronological order, empty if none arrived in
+  ///   time.
+  /// * An error instead.
+  fn poll(
+    &self,
+    uid: i32,
+    after: i32,
+    limit: Option<i64>,
+    timeout: Duration,
+  ) -> ServiceResult<Vec<Message>>;
+
+  /// Looks for the messages a user sent or received whose content has all
+  /// the words of a query.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the user who searches.
+  /// * `query` - The words to look for.
+  /// * `limit` - A limit of how many messages to retrieve, `MAX_PAGE_SIZE` at
+  ///   most. The messages are ranked, not paged.
+  ///
+  /// # Return
+  /// * The messages with the matched fragment of their content, the best match
+  ///   first. Could be empty.
+  /// * An error if the query is empty.
+  fn search(
+    &self,
+    uid: i32,
+    query: String,
+    limit: Option<i64>,
+  ) -> ServiceResult<Vec<SearchHit>>;
+
+  /// Replaces the content of a message, keeping the previous one as a
+  /// revision. Only the sender can edit its message.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message to edit.
+  /// * `uid` - The user_id of the user who edits the message.
+  /// * `message` - The new content of the message.
+  ///
+  /// # Return
+  /// * The edited message.
+  /// * An error if the message doesn't exist, was deleted or belongs to another
+  ///   sender.
+  fn edit(&self, id: i32, uid: i32, message: String) -> ServiceResult<Message>;
+
+  /// Deletes a message, it's kept as a tombstone for the readers. Only the
+  /// sender can delete its message.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message to delete.
+  /// * `uid` - The user_id of the user who deletes the message.
+  ///
+  /// # Return
+  /// * An error if the message doesn't exist, was already deleted or belongs to
+  ///   another sender.
+  fn delete(&self, id: i32, uid: i32) -> ServiceResult<()>;
+
+  /// Marks the messages as delivered to one of their recipients. The messages
+  /// the user doesn't receive are ignored.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the recipient.
+  /// * `ids` - The message_ids of the messages.
+  ///
+  /// # Return
+  /// * An error if the receipts can't be updated.
+  fn deliver(&self, uid: i32, ids: Vec<i32>) -> ServiceResult<()>;
+
+  /// Marks a message as read by one of its recipients.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message.
+  /// * `uid` - The user_id of the recipient.
+  ///
+  /// # Return
+  /// * An error if the user isn't a recipient of the message.
+  fn mark_read(&self, id: i32, uid: i32) -> ServiceResult<()>;
+
+  /// Marks as read every message of a conversation sent by the other user,
+  /// up to the given message_id.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the recipient.
+  /// * `other_user` - The user_id of the sender.
+  /// * `until` - The message_id of the last message to mark.
+  ///
+  /// # Return
+  /// * The quantity of messages marked as read.
+  /// * An error instead.
+  fn mark_conversation_read(
+    &self,
+    uid: i32,
+    other_user: i32,
+    until: i32,
+  ) -> ServiceResult<usize>;
+
+  /// Counts the unread messages of a user grouped by their sender, a page of
+  /// senders at a time.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the recipient.
+  /// * `page` - The page to retrieve, keyed by the user_id of the sender.
+  ///
+  /// # Return
+  /// * The page of counts ordered by sender. Could be empty.
+  /// * An error instead.
+  fn unread(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<UnreadCount>>;
+
+  /// Get the delivery and read state of the messages for their recipients.
+  ///
+  /// # Arguments
+  /// * `ids` - The message_ids of the messages.
+  ///
+  /// # Return
+  /// * The receipts. Could be empty.
+  /// * An error instead.
+  fn receipts(&self, ids: Vec<i32>) -> ServiceResult<Vec<Receipt>>;
+
+  /// Get the attachments of the messages.
+  ///
+  /// # Arguments
+  /// * `ids` - The message_ids of the messages.
+  ///
+  /// # Return
+  /// * The attachments ordered by id. Could be empty.
+  /// * An error instead.
+  fn attachments(&self, ids: Vec<i32>) -> ServiceResult<Vec<Attachment>>;
+
+  /// Get an attachment for a user who can download it: its uploader, or a
+  /// reader of its message while the message isn't deleted.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the attachment.
+  /// * `uid` - The user_id of the user.
+  ///
+  /// # Return
+  /// * The attachment.
+  /// * An error if it doesn't exist or the user can't download it.
+  fn attachment(&self, id: i32, uid: i32) -> ServiceResult<Attachment>;
 }
 
-pub struct MessageServiceImpl<MessageRepo> {
+/// The message service. Every committed change is published to the hub for
+/// the users connected in real time.
+pub struct MessageServiceImpl<
+  MessageRepo,
+  ReceiptRepo,
+  ConversationRepo,
+  UserRepo,
+  AttachmentRepo,
+> {
   message_repository: MessageRepo,
+  receipt_repository: ReceiptRepo,
+  conversation_repository: ConversationRepo,
+  user_repository: UserRepo,
+  attachment_repository: AttachmentRepo,
+  hub: Arc<Hub>,
 }
 
-impl<MessageRepo> MessageServiceImpl<MessageRepo>
+impl<MessageRepo, ReceiptRepo, ConversationRepo, UserRepo, AttachmentRepo>
+  MessageServiceImpl<
+    MessageRepo,
+    ReceiptRepo,
+    ConversationRepo,
+    UserRepo,
+    AttachmentRepo,
+  >
 where
   MessageRepo: MessageRepository,
+  ReceiptRepo: ReceiptRepository,
+  ConversationRepo: ConversationRepository,
+  UserRepo: UserRepository,
+  AttachmentRepo: AttachmentRepository,
 {
-  pub fn new(the_message_repository: MessageRepo) -> Self {
+  pub fn new(
+    the_message_repository: MessageRepo,
+    the_receipt_repository: ReceiptRepo,
+    the_conversation_repository: ConversationRepo,
+    the_user_repository: UserRepo,
+    the_attachment_repository: AttachmentRepo,
+    the_hub: Arc<Hub>,
+  ) -> Self {
     MessageServiceImpl {
       message_repository: the_message_repository,
+      receipt_repository: the_receipt_repository,
+      conversation_repository: the_conversation_repository,
+      user_repository: the_user_repository,
+      attachment_repository: the_attachment_repository,
+      hub: the_hub,
+    }
+  }
+
+  /// Publishes a recently created message to its readers. The message is
+  /// already committed, so a failure only skips the publication.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message.
+  /// * `readers` - The user_ids of the sender and the recipients.
+  fn publish_created(&self, id: i32, readers: &[i32]) {
+    match self.message_repository.get(id) {
+      Ok(msg) => self.hub.publish(readers, &Event::message(&msg)),
+      Err(err) => log::warn!("cannot publish the message {}: {}", id, err),
+    }
+  }
+
+  /// Get the users who can read a message: the sender and the recipient of a
+  /// direct message, or the members of the conversation of a group message.
+  /// If the members can't be retrieved only the sender is returned.
+  ///
+  /// # Arguments
+  /// * `msg` - The message.
+  ///
+  /// # Return
+  /// * The user_ids of the readers.
+  fn readers(&self, msg: &Message) -> Vec<i32> {
+    match msg.get_conversation_id() {
+      Some(conversation_id) => self
+        .conversation_repository
+        .members(conversation_id)
+        .map(|members| members.iter().map(Member::get_user_id).collect())
+        .unwrap_or_else(|err| {
+          log::warn!("cannot get the members of {}: {}", conversation_id, err);
+          vec![msg.get_from()]
+        }),
+      None => msg.get_to().into_iter().chain([msg.get_from()]).collect(),
+    }
+  }
+
+  /// Checks that a user exists, a message can't reference an unknown one.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the user.
+  /// * `reference` - What the user is to the message, for the error.
+  ///
+  /// # Return
+  /// * UnknownReference if the user doesn't exist.
+  fn check_user(&self, uid: i32, reference: &'static str) -> ServiceResult<()> {
+    match self.user_repository.get(uid) {
+      Ok(_) => Ok(()),
+      Err(RepoError::NotFound) => Err(Error::UnknownReference(reference)),
+      Err(err) => Err(Error::from(err)),
+    }
+  }
+
+  /// Checks that the attachments of a new message are files uploaded by its
+  /// sender and not sent yet.
+  ///
+  /// # Arguments
+  /// * `from` - The user_id of the sender.
+  /// * `attachments` - The ids of the attachments.
+  ///
+  /// # Return
+  /// * The ids of the attachments, without the repeated ones.
+  /// * UnknownReference if an attachment isn't a pending one of the sender.
+  fn pending_attachments(
+    &self,
+    from: i32,
+    attachments: Vec<i32>,
+  ) -> ServiceResult<Vec<i32>> {
+    let mut ids = attachments;
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+      return Ok(ids);
+    }
+    let pending = self
+      .attachment_repository
+      .find_pending(from, ids.clone())
+      .map_err(Error::from)?;
+    if pending.len() != ids.len() {
+      return Err(Error::UnknownReference("attachment"));
+    }
+    Ok(ids)
+  }
+
+  /// Checks that a group conversation exists, a message can't be sent to an
+  /// unknown one.
+  ///
+  /// # Arguments
+  /// * `conversation_id` - The id of the conversation.
+  ///
+  /// # Return
+  /// * UnknownReference if the conversation doesn't exist.
+  fn check_conversation(&self, conversation_id: i32) -> ServiceResult<()> {
+    match self.conversation_repository.get(conversation_id) {
+      Ok(_) => Ok(()),
+      Err(RepoError::NotFound) => Err(Error::UnknownReference("conversation")),
+      Err(err) => Err(Error::from(err)),
+    }
+  }
+
+  /// Checks that a user is a member of a group conversation.
+  ///
+  /// # Arguments
+  /// * `conversation_id` - The id of the conversation.
+  /// * `uid` - The user_id of the user.
+  ///
+  /// # Return
+  /// * An error if the user isn't a member, or the conversation doesn't exist.
+  fn check_member(&self, conversation_id: i32, uid: i32) -> ServiceResult<()> {
+    self
+      .conversation_repository
+      .get_member(conversation_id, uid)
+      .map_err(Error::from)?
+      .ok_or_else(|| {
+        Error::Forbidden(String::from(
+          "Only the members can access the conversation",
+        ))
+      })?;
+    Ok(())
+  }
+
+  /// Get a message that isn't deleted and was sent by the given user.
+  ///
+  /// # Arguments
+  /// * `id` - The message_id of the message.
+  /// * `uid` - The user_id of the sender.
+  ///
+  /// # Return
+  /// * The message.
+  /// * An error if the message doesn't exist, was deleted or belongs to another
+  ///   sender.
+  fn get_own(&self, id: i32, uid: i32) -> ServiceResult<Message> {
+    let msg = self
+      .message_repository
+      .get(id)
+      .map_err(|err| Error::from_repo(err, "message"))?;
+    if msg.is_deleted() {
+      return Err(Error::NotFound("message"));
+    }
+    if msg.get_from() != uid {
+      return Err(Error::Forbidden(String::from(
+        "Only the sender can change the message",
+      )));
     }
+    Ok(msg)
   }
 }
 
-impl<MessageRepo> MessageService for MessageServiceImpl<MessageRepo>
+impl<MessageRepo, ReceiptRepo, ConversationRepo, UserRepo, AttachmentRepo>
+  MessageService
+  for MessageServiceImpl<
+    MessageRepo,
+    ReceiptRepo,
+    ConversationRepo,
+    UserRepo,
+    AttachmentRepo,
+  >
 where
   MessageRepo: MessageRepository + Send + Sync,
+  ReceiptRepo: ReceiptRepository + Send + Sync,
+  ConversationRepo: ConversationRepository + Send + Sync,
+  UserRepo: UserRepository + Send + Sync,
+  AttachmentRepo: AttachmentRepository + Send + Sync,
 {
-  fn create(&self, from: i32, to: i32, message: String) -> ServiceResult<i32> {
+  fn create(
+    &self,
+    from: i32,
+    to: i32,
+    message: String,
+    attachments: Vec<i32>,
+  ) -> ServiceResult<i32> {
+    self.check_user(from, "sender")?;
+    self.check_user(to, "recipient")?;
+    let attachments = self.pending_attachments(from, attachments)?;
     let new_message = NewMessage::new(from, to, message);
-    self
+    let id = self
+      .message_repository
+      .add(new_message, vec![to], attachments)
+      .map_err(|err| match err {
+        RepoError::NotFound => Error::UnknownReference("attachment"),
+        err => Error::from_repo(err, "recipient"),
+      })?;
+    self.publish_created(id, &[from, to]);
+    Ok(id)
+  }
+
+  fn create_in_conversation(
+    &self,
+    from: i32,
+    conversation_id: i32,
+    message: String,
+    attachments: Vec<i32>,
+  ) -> ServiceResult<i32> {
+    self.check_conversation(conversation_id)?;
+    self.check_member(conversation_id, from)?;
+    let attachments = self.pending_attachments(from, attachments)?;
+    let members = self
+      .conversation_repository
+      .members(conversation_id)
+      .map_err(Error::from)?
+      .iter()
+      .map(Member::get_user_id)
+      .collect::<Vec<i32>>();
+    let recipients = members
+      .iter()
+      .copied()
+      .filter(|member| *member != from)
+      .collect::<Vec<i32>>();
+    let new_message =
+      NewMessage::for_conversation(from, conversation_id, message);
+    let id = self
       .message_repository
-      .add(new_message)
-      .map_err(|err| err.to_string())
+      .add(new_message, recipients, attachments)
+      .map_err(|err| match err {
+        RepoError::NotFound => Error::UnknownReference("attachment"),
+        err => Error::from_repo(err, "conversation"),
+      })?;
+    self.publish_created(id, &members);
+    Ok(id)
   }
 
   fn get(&self, id: i32) -> ServiceResult<Message> {
     self
       .message_repository
       .get(id)
-      .map_err(|err| err.to_string())
+      .map_err(|err| Error::from_repo(err, "message"))
+  }
+
+  fn read(&self, id: i32, uid: i32) -> ServiceResult<Message> {
+    let msg = self.get(id)?;
+    match msg.get_conversation_id() {
+      Some(conversation_id) => self.check_member(conversation_id, uid)?,
+      None if !msg.is_participant(uid) => {
+        return Err(Error::Forbidden(String::from(
+          "The message belongs to other users",
+        )));
+      },
+      None => (),
+    }
+    Ok(msg)
+  }
+
+  fn open(&self, id: i32, uid: i32) -> ServiceResult<Message> {
+    let msg = self.read(id, uid)?;
+    if msg.get_from() != uid {
+      self.deliver(uid, vec![id])?;
+    }
+    Ok(msg)
   }
 
   fn find(
     &self,
-    from_msg: i32,
     from_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>> {
+    self
+      .message_repository
+      .find(from_user, page)
+      .map_err(Error::from)
+  }
+
+  fn inbox(
+    &self,
+    to_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>> {
+    let messages = self
+      .message_repository
+      .find_to(to_user, page)
+      .map_err(Error::from)?;
+    let ids = messages
+      .get_items()
+      .iter()
+      .map(Message::get_id)
+      .collect::<Vec<i32>>();
+    self.deliver(to_user, ids)?;
+    Ok(messages)
+  }
+
+  fn conversation(
+    &self,
+    user: i32,
+    other_user: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>> {
+    let messages = self
+      .message_repository
+      .find_conversation(user, other_user, page)
+      .map_err(Error::from)?;
+    let received = messages
+      .get_items()
+      .iter()
+      .filter(|msg| msg.get_to() == Some(user))
+      .map(Message::get_id)
+      .collect::<Vec<i32>>();
+    self.deliver(user, received)?;
+    Ok(messages)
+  }
+
+  fn conversation_messages(
+    &self,
+    conversation_id: i32,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Message>> {
+    self.check_member(conversation_id, uid)?;
+    let messages = self
+      .message_repository
+      .find_in_conversation(conversation_id, page)
+      .map_err(Error::from)?;
+    let received = messages
+      .get_items()
+      .iter()
+      .filter(|msg| msg.get_from() != uid)
+      .map(Message::get_id)
+      .collect::<Vec<i32>>();
+    self.deliver(uid, received)?;
+    Ok(messages)
+  }
+
+  fn missed(
+    &self,
+    uid: i32,
+    last_id: i32,
+    limit: Option<i64>,
+  ) -> ServiceResult<Vec<Message>> {
+    let messages = self
+      .message_repository
+      .find_received(last_id, uid, checked_limit(limit))
+      .map_err(Error::from)?;
+    let ids = messages.iter().map(Message::get_id).collect::<Vec<i32>>();
+    self.deliver(uid, ids)?;
+    Ok(messages)
+  }
+
+  fn poll(
+    &self,
+    uid: i32,
+    after: i32,
     limit: Option<i64>,
+    timeout: Duration,
   ) -> ServiceResult<Vec<Message>> {
+    let subscription = self.hub.subscribe(uid);
+    let deadline = Instant::now() + timeout;
+    loop {
+      let messages = self.missed(uid, after, limit)?;
+      if !messages.is_empty() {
+        return Ok(messages);
+      }
+      loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match subscription.wait(left) {
+          Some(Event::Message {
+            from, ..
+          }) if from != uid => break,
+          Some(_) => (),
+          None => return Ok(vec![]),
+        }
+      }
+    }
+  }
+
+  fn search(
+    &self,
+    uid: i32,
+    query: String,
+    limit: Option<i64>,
+  ) -> ServiceResult<Vec<SearchHit>> {
+    if query.trim().is_empty() {
+      return Err(Error::InvalidInput(String::from("the query is empty")));
+    }
     self
       .message_repository
-      .find(from_msg, from_user, limit.unwrap_or(5))
-      .map_err(|err| err.to_string())
+      .search(query, uid, checked_limit(limit))
+      .map_err(Error::from)
+  }
+
+  fn edit(&self, id: i32, uid: i32, message: String) -> ServiceResult<Message> {
+    if message.trim().is_empty() {
+      return Err(Error::InvalidInput(String::from("the message is empty")));
+    }
+    let msg = self.get_own(id, uid)?;
+    let edited = self
+      .message_repository
+      .update(&msg, message)
+      .map_err(|err| Error::from_repo(err, "message"))?;
+    self
+      .hub
+      .publish(&self.readers(&edited), &Event::edited(&edited));
+    Ok(edited)
+  }
+
+  fn delete(&self, id: i32, uid: i32) -> ServiceResult<()> {
+    let msg = self.get_own(id, uid)?;
+    let deleted = self
+      .message_repository
+      .soft_delete(id)
+      .map_err(Error::from)?;
+    if deleted == 0 {
+      return Err(Error::NotFound("message"));
+    }
+    self.hub.publish(
+      &self.readers(&msg),
+      &Event::Deleted {
+        id,
+      },
+    );
+    Ok(())
+  }
+
+  fn deliver(&self, uid: i32, ids: Vec<i32>) -> ServiceResult<()> {
+    if ids.is_empty() {
+      return Ok(());
+    }
+    self
+      .receipt_repository
+      .mark_delivered(uid, ids)
+      .map_err(Error::from)?;
+    Ok(())
+  }
+
+  fn mark_read(&self, id: i32, uid: i32) -> ServiceResult<()> {
+    let receipt = self
+      .receipt_repository
+      .get(id, uid)
+      .map_err(Error::from)?
+      .ok_or(Error::NotFound("message"))?;
+    if receipt.is_read() {
+      return Ok(());
+    }
+    self
+      .receipt_repository
+      .mark_read(uid, id)
+      .map_err(Error::from)?;
+    match self.message_repository.get(id) {
+      Ok(msg) => self.hub.publish(
+        &[msg.get_from()],
+        &Event::Read {
+          message_id: id,
+          user_id: uid,
+        },
+      ),
+      Err(err) => log::warn!("cannot publish the read of {}: {}", id, err),
+    }
+    Ok(())
+  }
+
+  fn mark_conversation_read(
+    &self,
+    uid: i32,
+    other_user: i32,
+    until: i32,
+  ) -> ServiceResult<usize> {
+    let read = self
+      .receipt_repository
+      .mark_read_until(uid, other_user, until)
+      .map_err(Error::from)?;
+    if read > 0 {
+      self.hub.publish(
+        &[other_user],
+        &Event::ConversationRead {
+          user_id: uid,
+          until,
+        },
+      );
+    }
+    Ok(read)
+  }
+
+  fn unread(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<UnreadCount>> {
+    self
+      .receipt_repository
+      .unread_counts(uid, page)
+      .map_err(Error::from)
+  }
+
+  fn receipts(&self, ids: Vec<i32>) -> ServiceResult<Vec<Receipt>> {
+    if ids.is_empty() {
+      return Ok(vec![]);
+    }
+    self
+      .receipt_repository
+      .find_by_messages(ids)
+      .map_err(Error::from)
+  }
+
+  fn attachments(&self, ids: Vec<i32>) -> ServiceResult<Vec<Attachment>> {
+    if ids.is_empty() {
+      return Ok(vec![]);
+    }
+    self
+      .attachment_repository
+      .find_by_messages(ids)
+      .map_err(Error::from)
+  }
+
+  fn attachment(&self, id: i32, uid: i32) -> ServiceResult<Attachment> {
+    let attachment = self
+      .attachment_repository
+      .get(id)
+      .map_err(|err| Error::from_repo(err, "attachment"))?;
+    if attachment.get_user_id() == uid {
+      return Ok(attachment);
+    }
+    // The files not sent yet are only seen by their uploader
+    match attachment.get_message_id() {
+      Some(message_id) if !self.read(message_id, uid)?.is_deleted() => {
+        Ok(attachment)
+      },
+      _ => Err(Error::NotFound("attachment")),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{
+    attachment::Builder as AttachmentBuilder,
+    conversation::{Builder as MemberBuilder, MemberRole},
+    message::Builder,
+    receipt::Builder as ReceiptBuilder,
+    repository::{
+      attachment_repository::MockAttachmentRepository,
+      conversation_repository::MockConversationRepository,
+      message_repository::MockMessageRepository,
+      receipt_repository::MockReceiptRepository,
+      user_repository::MockUserRepository,
+    },
+    user::Builder as UserBuilder,
+  };
+  use chrono::NaiveDateTime;
+  use mockall::predicate::eq;
+  use std::thread;
+
+  #[test]
+  fn edit_message_of_another_sender() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_get().with(eq(1)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(1)
+          .with_from(2)
+          .with_to(1)
+          .with_message("Some message")
+          .build(),
+      )
+    });
+    mock_repo.expect_update().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    let result = service.edit(1, 1, String::from("Edited"));
+    assert_eq!(
+      result.err(),
+      Some(Error::Forbidden(String::from(
+        "Only the sender can change the message"
+      )))
+    );
+  }
+
+  #[test]
+  fn edit_message_ok() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_get().with(eq(1)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(1)
+          .with_from(1)
+          .with_to(2)
+          .with_message("Some message")
+          .build(),
+      )
+    });
+    mock_repo
+      .expect_update()
+      .withf(|msg, new_message| msg.get_id() == 1 && new_message == "Edited")
+      .times(1)
+      .returning(|_, _| {
+        Ok(
+          Builder::new()
+            .with_id(1)
+            .with_from(1)
+            .with_to(2)
+            .with_message("Edited")
+            .with_updated_at(NaiveDateTime::from_timestamp(60, 0))
+            .build(),
+        )
+      });
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    let msg = service.edit(1, 1, String::from("Edited")).unwrap();
+    assert_eq!(msg.get_message(), "Edited");
+    assert!(msg.get_updated_at() > msg.get_created_at());
+  }
+
+  #[test]
+  fn delete_deleted_message() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_get().with(eq(1)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(1)
+          .with_from(1)
+          .with_to(2)
+          .with_message("Some message")
+          .with_deleted_at(NaiveDateTime::from_timestamp(60, 0))
+          .build(),
+      )
+    });
+    mock_repo.expect_soft_delete().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(service.delete(1, 1).err(), Some(Error::NotFound("message")));
+  }
+
+  #[test]
+  fn inbox_marks_the_messages_as_delivered() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_find_to()
+      .with(eq(1), eq(PageRequest::first(None)))
+      .times(1)
+      .returning(|_, page| {
+        let received = Builder::new()
+          .with_id(4)
+          .with_from(2)
+          .with_to(1)
+          .with_message("Some message")
+          .build();
+        Ok(Page::from_rows(vec![received], page, Message::get_id))
+      });
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts
+      .expect_mark_delivered()
+      .with(eq(1), eq(vec![4]))
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    let messages = service.inbox(1, PageRequest::first(None)).unwrap();
+    assert_eq!(messages.get_items().len(), 1);
+  }
+
+  #[test]
+  fn mark_read_of_another_recipient() {
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts
+      .expect_get()
+      .with(eq(1), eq(3))
+      .times(1)
+      .returning(|_, _| Ok(None));
+    mock_receipts.expect_mark_read().times(0);
+
+    let service = MessageServiceImpl::new(
+      MockMessageRepository::new(),
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service.mark_read(1, 3).err(),
+      Some(Error::NotFound("message"))
+    );
+  }
+
+  #[test]
+  fn mark_read_twice() {
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts.expect_get().times(1).returning(|_, _| {
+      Ok(Some(
+        ReceiptBuilder::new()
+          .with_message_id(1)
+          .with_user_id(2)
+          .with_delivered_at(NaiveDateTime::from_timestamp(30, 0))
+          .with_read_at(NaiveDateTime::from_timestamp(60, 0))
+          .build(),
+      ))
+    });
+    mock_receipts.expect_mark_read().times(0);
+
+    let service = MessageServiceImpl::new(
+      MockMessageRepository::new(),
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert!(service.mark_read(1, 2).is_ok());
+  }
+
+  #[test]
+  fn send_to_conversation_fans_out_to_the_members() {
+    let mut mock_conv = MockConversationRepository::new();
+    mock_conv.expect_get().with(eq(7)).times(1).returning(|_| {
+      Ok(
+        MemberBuilder::new()
+          .with_conversation_id(7)
+          .build_conversation("Team"),
+      )
+    });
+    mock_conv
+      .expect_get_member()
+      .with(eq(7), eq(1))
+      .times(1)
+      .returning(|_, _| {
+        Ok(Some(
+          MemberBuilder::new()
+            .with_conversation_id(7)
+            .with_user_id(1)
+            .with_role(MemberRole::Owner)
+            .build(),
+        ))
+      });
+    mock_conv
+      .expect_members()
+      .with(eq(7))
+      .times(1)
+      .returning(|_| {
+        Ok(
+          [1, 2, 3]
+            .iter()
+            .map(|uid| {
+              MemberBuilder::new()
+                .with_conversation_id(7)
+                .with_user_id(*uid)
+                .build()
+            })
+            .collect(),
+        )
+      });
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_add()
+      .withf(|_, recipients, _| recipients == &vec![2, 3])
+      .times(1)
+      .returning(|_, _, _| Ok(10));
+    mock_repo.expect_get().with(eq(10)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(10)
+          .with_from(1)
+          .with_conversation_id(7)
+          .with_message("Hi all")
+          .build(),
+      )
+    });
+
+    let hub = Arc::new(Hub::default());
+    let member = hub.subscribe(3);
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      mock_conv,
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      hub.clone(),
+    );
+    let result =
+      service.create_in_conversation(1, 7, String::from("Hi all"), vec![]);
+    assert_eq!(result, Ok(10));
+    assert!(matches!(
+      member.pending().next(),
+      Some(Event::Message {
+        id: 10,
+        conversation_id: Some(7),
+        ..
+      })
+    ));
+  }
+
+  #[test]
+  fn read_conversation_is_published_to_the_sender() {
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts
+      .expect_mark_read_until()
+      .with(eq(2), eq(1), eq(4))
+      .times(1)
+      .returning(|_, _, _| Ok(3));
+
+    let hub = Arc::new(Hub::default());
+    let sender = hub.subscribe(1);
+    let service = MessageServiceImpl::new(
+      MockMessageRepository::new(),
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      hub.clone(),
+    );
+    assert_eq!(service.mark_conversation_read(2, 1, 4), Ok(3));
+    assert_eq!(
+      sender.pending().collect::<Vec<Event>>(),
+      vec![Event::ConversationRead {
+        user_id: 2,
+        until: 4
+      }]
+    );
+  }
+
+  #[test]
+  fn search_with_an_empty_query() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_search().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service.search(1, String::from("  "), None).err(),
+      Some(Error::InvalidInput(String::from("the query is empty")))
+    );
+  }
+
+  #[test]
+  fn search_lowers_the_limit() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_search()
+      .withf(|query, uid, limit| {
+        query == "hello" && *uid == 1 && *limit == MAX_PAGE_SIZE
+      })
+      .times(1)
+      .returning(|_, _, _| Ok(vec![]));
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert!(service
+      .search(1, String::from("hello"), Some(i64::MAX))
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn poll_without_new_messages() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_find_received()
+      .with(eq(3), eq(1), eq(DEFAULT_LIMIT))
+      .times(1)
+      .returning(|_, _, _| Ok(vec![]));
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts.expect_mark_delivered().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    let messages = service.poll(1, 3, None, Duration::from_millis(20));
+    assert!(messages.unwrap().is_empty());
+  }
+
+  #[test]
+  fn missed_lowers_the_limit() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_find_received()
+      .with(eq(3), eq(1), eq(MAX_PAGE_SIZE))
+      .times(1)
+      .returning(|_, _, _| Ok(vec![]));
+    mock_repo
+      .expect_find_received()
+      .with(eq(3), eq(1), eq(1))
+      .times(1)
+      .returning(|_, _, _| Ok(vec![]));
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert!(service.missed(1, 3, Some(1000)).unwrap().is_empty());
+    assert!(service.missed(1, 3, Some(-5)).unwrap().is_empty());
+  }
+
+  #[test]
+  fn poll_wakes_up_on_a_new_message() {
+    let received = Builder::new().with_id(4).with_from(2).with_to(1).build();
+    let event = Event::message(&received);
+    let mut calls = 0;
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_find_received()
+      .with(eq(3), eq(1), eq(DEFAULT_LIMIT))
+      .times(2)
+      .returning(move |_, _, _| {
+        calls += 1;
+        Ok(if calls == 1 {
+          vec![]
+        } else {
+          vec![received.clone()]
+        })
+      });
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts
+      .expect_mark_delivered()
+      .with(eq(1), eq(vec![4]))
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let hub = Arc::new(Hub::default());
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      hub.clone(),
+    );
+    let publisher = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(50));
+      hub.publish(&[1], &event);
+    });
+    let messages = service.poll(1, 3, None, Duration::from_secs(5)).unwrap();
+    publisher.join().unwrap();
+    assert_eq!(
+      messages.iter().map(Message::get_id).collect::<Vec<i32>>(),
+      vec![4]
+    );
+  }
+
+  #[test]
+  fn send_to_conversation_of_another_member() {
+    let mut mock_conv = MockConversationRepository::new();
+    mock_conv.expect_get().with(eq(7)).times(1).returning(|_| {
+      Ok(
+        MemberBuilder::new()
+          .with_conversation_id(7)
+          .build_conversation("Team"),
+      )
+    });
+    mock_conv
+      .expect_get_member()
+      .with(eq(7), eq(4))
+      .times(1)
+      .returning(|_, _| Ok(None));
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      mock_conv,
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service
+        .create_in_conversation(4, 7, String::from("Hi all"), vec![])
+        .err(),
+      Some(Error::Forbidden(String::from(
+        "Only the members can access the conversation"
+      )))
+    );
+  }
+
+  #[test]
+  fn send_to_an_unknown_recipient() {
+    let mut mock_users = MockUserRepository::new();
+    mock_users.expect_get().returning(|uid| match uid {
+      1 => Ok(
+        UserBuilder::new()
+          .with_id(1)
+          .with_username("sender")
+          .with_hashed_password("hash")
+          .build(),
+      ),
+      _ => Err(RepoError::NotFound),
+    });
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      mock_users,
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service.create(1, 99, String::from("Hi"), vec![]).err(),
+      Some(Error::UnknownReference("recipient"))
+    );
+  }
+
+  #[test]
+  fn send_to_an_unknown_conversation() {
+    let mut mock_conv = MockConversationRepository::new();
+    mock_conv
+      .expect_get()
+      .with(eq(99))
+      .times(1)
+      .returning(|_| Err(RepoError::NotFound));
+    mock_conv.expect_get_member().times(0);
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      mock_conv,
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service
+        .create_in_conversation(1, 99, String::from("Hi all"), vec![])
+        .err(),
+      Some(Error::UnknownReference("conversation"))
+    );
+  }
+
+  #[test]
+  fn send_an_attachment_of_another_user() {
+    let mut mock_users = MockUserRepository::new();
+    mock_users
+      .expect_get()
+      .returning(|uid| Ok(UserBuilder::new().with_id(uid).build()));
+    let mut mock_attachments = MockAttachmentRepository::new();
+    mock_attachments
+      .expect_find_pending()
+      .with(eq(1), eq(vec![3, 4]))
+      .times(1)
+      .returning(|_, _| {
+        let pending = AttachmentBuilder::new().with_id(3).with_user_id(1);
+        Ok(vec![pending.build()])
+      });
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      mock_users,
+      mock_attachments,
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(
+      service
+        .create(1, 2, String::from("Hi"), vec![4, 3, 4])
+        .err(),
+      Some(Error::UnknownReference("attachment"))
+    );
+  }
+
+  #[test]
+  fn download_an_attachment_of_a_deleted_message() {
+    let mut mock_attachments = MockAttachmentRepository::new();
+    mock_attachments.expect_get().with(eq(3)).returning(|id| {
+      Ok(
+        AttachmentBuilder::new()
+          .with_id(id)
+          .with_user_id(1)
+          .with_message_id(10)
+          .build(),
+      )
+    });
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo
+      .expect_get()
+      .with(eq(10))
+      .times(1)
+      .returning(|id| {
+        Ok(
+          Builder::new()
+            .with_id(id)
+            .with_from(1)
+            .with_to(2)
+            .with_deleted_at(NaiveDateTime::from_timestamp(0, 0))
+            .build(),
+        )
+      });
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      mock_attachments,
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(service.attachment(3, 1).unwrap().get_id(), 3);
+    assert_eq!(
+      service.attachment(3, 2).err(),
+      Some(Error::NotFound("attachment"))
+    );
+  }
+
+  #[test]
+  fn read_group_message_of_another_member() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_get().with(eq(10)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(10)
+          .with_from(1)
+          .with_conversation_id(7)
+          .with_message("Hi all")
+          .build(),
+      )
+    });
+    let mut mock_conv = MockConversationRepository::new();
+    mock_conv
+      .expect_get_member()
+      .with(eq(7), eq(4))
+      .times(1)
+      .returning(|_, _| Ok(None));
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      MockReceiptRepository::new(),
+      mock_conv,
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert!(matches!(service.read(10, 4), Err(Error::Forbidden(_))));
+  }
+
+  #[test]
+  fn open_a_direct_message_as_its_recipient() {
+    let mut mock_repo = MockMessageRepository::new();
+    mock_repo.expect_get().with(eq(10)).times(2).returning(|_| {
+      Ok(Builder::new().with_id(10).with_from(1).with_to(2).build())
+    });
+    let mut mock_receipts = MockReceiptRepository::new();
+    mock_receipts
+      .expect_mark_delivered()
+      .with(eq(2), eq(vec![10]))
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let service = MessageServiceImpl::new(
+      mock_repo,
+      mock_receipts,
+      MockConversationRepository::new(),
+      MockUserRepository::new(),
+      MockAttachmentRepository::new(),
+      Arc::new(Hub::default()),
+    );
+    assert_eq!(service.open(10, 2).unwrap().get_id(), 10);
+    assert_eq!(service.open(10, 1).unwrap().get_id(), 10);
   }
 }
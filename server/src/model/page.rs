@@ -0,0 +1,184 @@
+/// The quantity of rows of a page when the size isn't specified.
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+/// The biggest page, a bigger size is lowered to it.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// The side of the key where a page is, following the order of the list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+  Before,
+  After,
+}
+
+/// A page of a list ordered by id. The page starts next to the row of the
+/// key, excluded, so a row added or removed in another page doesn't move the
+/// rows of this one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+  key: Option<i32>,
+  direction: Direction,
+  size: i64,
+}
+
+impl PageRequest {
+  /// Creates the request of the first page of a list.
+  ///
+  /// # Arguments
+  /// * `size` - The quantity of rows, the default one if it's not specified.
+  pub fn first(size: Option<i64>) -> Self {
+    PageRequest {
+      key: None,
+      direction: Direction::After,
+      size: Self::checked_size(size),
+    }
+  }
+
+  /// Creates the request of the page on a side of a row.
+  ///
+  /// # Arguments
+  /// * `direction` - The side of the row.
+  /// * `the_key` - The id of the row, excluded from the page.
+  /// * `size` - The quantity of rows, the default one if it's not specified.
+  pub fn next_to(
+    direction: Direction,
+    the_key: i32,
+    size: Option<i64>,
+  ) -> Self {
+    PageRequest {
+      key: Some(the_key),
+      direction,
+      size: Self::checked_size(size),
+    }
+  }
+
+  fn checked_size(size: Option<i64>) -> i64 {
+    size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+  }
+
+  /// The id of the row next to the page, none for the first page.
+  pub fn get_key(&self) -> Option<i32> {
+    return self.key;
+  }
+
+  pub fn get_direction(&self) -> Direction {
+    return self.direction;
+  }
+
+  pub fn get_size(&self) -> i64 {
+    return self.size;
+  }
+
+  /// The quantity of rows to retrieve, one more than the size to know if
+  /// there are more rows after the page.
+  pub fn get_fetch_size(&self) -> i64 {
+    return self.size + 1;
+  }
+
+  /// Checks if the rows are retrieved in the reverse order of the list, the
+  /// nearest to the key first, as happens for the page before a row.
+  pub fn is_backwards(&self) -> bool {
+    return self.key.is_some() && self.direction == Direction::Before;
+  }
+}
+
+/// The rows of a page, in the order of the list, with the ids of the rows
+/// next to it when there are more rows on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+  items: Vec<T>,
+  prev_key: Option<i32>,
+  next_key: Option<i32>,
+}
+
+impl<T> Page<T> {
+  /// Creates a page from the rows retrieved for a request.
+  ///
+  /// # Arguments
+  /// * `rows` - Up to `get_fetch_size` rows, in the order they were retrieved.
+  /// * `request` - The request of the page.
+  /// * `key_of` - Get the id of a row.
+  ///
+  /// # Return
+  /// * The page, in the order of the list.
+  pub fn from_rows(
+    mut rows: Vec<T>,
+    request: PageRequest,
+    key_of: impl Fn(&T) -> i32,
+  ) -> Self {
+    let more = rows.len() as i64 > request.get_size();
+    rows.truncate(request.get_size() as usize);
+    if request.is_backwards() {
+      rows.reverse();
+    }
+    let first = rows.first().map(&key_of);
+    let last = rows.last().map(&key_of);
+    let (prev_key, next_key) = match request.get_key() {
+      None => (None, last.filter(|_| more)),
+      Some(_) if request.is_backwards() => (first.filter(|_| more), last),
+      Some(_) => (first, last.filter(|_| more)),
+    };
+    Page {
+      items: rows,
+      prev_key,
+      next_key,
+    }
+  }
+
+  pub fn get_items(&self) -> &[T] {
+    return &self.items;
+  }
+
+  /// The id of the first row, to get the previous page, none if the page is
+  /// the first one.
+  pub fn get_prev_key(&self) -> Option<i32> {
+    return self.prev_key;
+  }
+
+  /// The id of the last row, to get the next page, none if the page is the
+  /// last one.
+  pub fn get_next_key(&self) -> Option<i32> {
+    return self.next_key;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn size_is_bounded() {
+    assert_eq!(PageRequest::first(None).get_size(), DEFAULT_PAGE_SIZE);
+    assert_eq!(PageRequest::first(Some(0)).get_size(), 1);
+    assert_eq!(PageRequest::first(Some(1000)).get_size(), MAX_PAGE_SIZE);
+  }
+
+  #[test]
+  fn first_page_has_only_a_next_key() {
+    let request = PageRequest::first(Some(2));
+    let page = Page::from_rows(vec![9, 8, 7], request, |id| *id);
+
+    assert_eq!(page.get_items(), &[9, 8]);
+    assert_eq!(page.get_prev_key(), None);
+    assert_eq!(page.get_next_key(), Some(8));
+  }
+
+  #[test]
+  fn page_after_a_row() {
+    let request = PageRequest::next_to(Direction::After, 8, Some(2));
+    let page = Page::from_rows(vec![7, 6], request, |id| *id);
+
+    assert_eq!(page.get_items(), &[7, 6]);
+    assert_eq!(page.get_prev_key(), Some(7));
+    assert_eq!(page.get_next_key(), None);
+  }
+
+  #[test]
+  fn page_before_a_row_is_reversed() {
+    let request = PageRequest::next_to(Direction::Before, 6, Some(2));
+    let page = Page::from_rows(vec![7, 8, 9], request, |id| *id);
+
+    assert_eq!(page.get_items(), &[8, 7]);
+    assert_eq!(page.get_prev_key(), Some(8));
+    assert_eq!(page.get_next_key(), Some(7));
+  }
+}
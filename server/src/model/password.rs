@@ -1,27 +1,258 @@
+use argon2::{
+  password_hash::{
+    rand_core::OsRng, PasswordHash, PasswordHasher as PhcHasher,
+    PasswordVerifier, SaltString,
+  },
+  Argon2,
+};
+use dotenv::dotenv;
 #[cfg(test)]
 use mockall::automock;
 use sha2::{Digest, Sha256};
+use std::env;
+
+use crate::{
+  crypto::constant_time_eq,
+  model::error::{Error, ServiceResult},
+};
+
+const ARGON2_PREFIX: &str = "$argon2";
+const BCRYPT_PREFIXES: [&str; 3] = ["$2a$", "$2b$", "$2y$"];
 
 #[cfg_attr(test, automock)]
 pub trait PasswordHasher {
-  /// Calculate hash for any given string using SHA256.
+  /// Calculate the hash for any given password, returning a self-describing
+  /// string that carries the algorithm, its parameters and the salt.
   ///
   /// # Arguments
-  /// * `some_string` - The string to hashed.
+  /// * `password` - The password to hash.
   ///
   /// # Return
-  /// * A string that represents the hash of the given one.
-  fn hash(&self, some_string: &str) -> String;
+  /// * A string that represents the hash of the given password.
+  /// * An error if the hash cannot be calculated.
+  fn hash(&self, password: &str) -> ServiceResult<String>;
+
+  /// Checks if a password matches a stored hash. Any of the supported formats
+  /// (argon2, bcrypt and the legacy SHA-256 hex digest) is accepted.
+  ///
+  /// # Arguments
+  /// * `password` - The password in plain text.
+  /// * `hashed` - The stored hash.
+  ///
+  /// # Return
+  /// * True if the password matches.
+  fn verify(&self, password: &str, hashed: &str) -> bool;
+
+  /// Checks if a stored hash was produced by another algorithm and must be
+  /// replaced with a fresh hash from this hasher.
+  ///
+  /// # Arguments
+  /// * `hashed` - The stored hash.
+  ///
+  /// # Return
+  /// * True if the hash must be recalculated.
+  fn needs_rehash(&self, hashed: &str) -> bool;
+}
+
+impl<T: PasswordHasher + ?Sized> PasswordHasher for Box<T> {
+  fn hash(&self, password: &str) -> ServiceResult<String> {
+    (**self).hash(password)
+  }
+
+  fn verify(&self, password: &str, hashed: &str) -> bool {
+    (**self).verify(password, hashed)
+  }
+
+  fn needs_rehash(&self, hashed: &str) -> bool {
+    (**self).needs_rehash(hashed)
+  }
 }
 
+/// Legacy hasher, an unsalted SHA-256 uppercase hex digest. It's only kept to
+/// verify the passwords stored before the adaptive hashers existed.
 #[derive(Default)]
 pub struct SimpleHasher;
 
 impl PasswordHasher for SimpleHasher {
-  fn hash(&self, some_string: &str) -> String {
+  fn hash(&self, password: &str) -> ServiceResult<String> {
     let mut hasher = Sha256::new();
-    // hasher.update(password.as_ref());
-    hasher.update(<String as AsRef<[u8]>>::as_ref(&some_string.to_string()));
-    format!("{:X}", hasher.finalize())
+    hasher.update(password.as_bytes());
+    Ok(format!("{:X}", hasher.finalize()))
+  }
+
+  fn verify(&self, password: &str, hashed: &str) -> bool {
+    match self.hash(password) {
+      Ok(calculated) => {
+        constant_time_eq(calculated.as_bytes(), hashed.as_bytes())
+      },
+      Err(_) => false,
+    }
+  }
+
+  fn needs_rehash(&self, _hashed: &str) -> bool {
+    false
+  }
+}
+
+/// Argon2id hasher with the default parameters of the argon2 crate. The hashes
+/// are stored as PHC strings.
+#[derive(Default)]
+pub struct Argon2Hasher;
+
+impl PasswordHasher for Argon2Hasher {
+  fn hash(&self, password: &str) -> ServiceResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+      .hash_password(password.as_bytes(), &salt)
+      .map(|hash| hash.to_string())
+      .map_err(|err| Error::Internal(err.to_string()))
+  }
+
+  fn verify(&self, password: &str, hashed: &str) -> bool {
+    verify_any(password, hashed)
+  }
+
+  fn needs_rehash(&self, hashed: &str) -> bool {
+    let defaults = Argon2::default();
+    match PasswordHash::new(hashed) {
+      Ok(parsed) => {
+        parsed.algorithm != argon2::Algorithm::Argon2id.ident()
+          || parsed.version != Some(argon2::Version::default().into())
+          || argon2::Params::try_from(&parsed)
+            .map(|params| params != *defaults.params())
+            .unwrap_or(true)
+      },
+      Err(_) => true,
+    }
+  }
+}
+
+/// Bcrypt hasher with a configurable cost.
+pub struct BcryptHasher {
+  cost: u32,
+}
+
+impl BcryptHasher {
+  pub fn new(cost: u32) -> Self {
+    BcryptHasher {
+      cost,
+    }
+  }
+}
+
+impl Default for BcryptHasher {
+  fn default() -> Self {
+    BcryptHasher::new(bcrypt::DEFAULT_COST)
+  }
+}
+
+impl PasswordHasher for BcryptHasher {
+  fn hash(&self, password: &str) -> ServiceResult<String> {
+    bcrypt::hash(password, self.cost)
+      .map_err(|err| Error::Internal(err.to_string()))
+  }
+
+  fn verify(&self, password: &str, hashed: &str) -> bool {
+    verify_any(password, hashed)
+  }
+
+  fn needs_rehash(&self, hashed: &str) -> bool {
+    match bcrypt_cost(hashed) {
+      Some(cost) => cost != self.cost,
+      None => true,
+    }
+  }
+}
+
+/// Verify a password against a hash in any of the supported formats.
+///
+/// # Arguments
+/// * `password` - The password in plain text.
+/// * `hashed` - The stored hash.
+///
+/// # Return
+/// * True if the password matches.
+fn verify_any(password: &str, hashed: &str) -> bool {
+  if hashed.starts_with(ARGON2_PREFIX) {
+    match PasswordHash::new(hashed) {
+      Ok(parsed) => Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok(),
+      Err(_) => false,
+    }
+  } else if bcrypt_cost(hashed).is_some() {
+    bcrypt::verify(password, hashed).unwrap_or(false)
+  } else {
+    SimpleHasher::default().verify(password, hashed)
+  }
+}
+
+/// Extract the cost of a bcrypt hash.
+///
+/// # Arguments
+/// * `hashed` - The stored hash.
+///
+/// # Return
+/// * The cost if the hash is a bcrypt one, None otherwise.
+fn bcrypt_cost(hashed: &str) -> Option<u32> {
+  BCRYPT_PREFIXES
+    .iter()
+    .find(|prefix| hashed.starts_with(*prefix))
+    .and_then(|prefix| hashed[prefix.len()..].get(..2))
+    .and_then(|cost| cost.parse::<u32>().ok())
+}
+
+/// Initialize the password hasher for the entire application. The algorithm is
+/// taken from the `password_hasher` variable (`argon2` or `bcrypt`), argon2 is
+/// used when it's not set.
+///
+/// # Return
+/// * The password hasher.
+pub fn setup_password_hasher() -> Box<dyn PasswordHasher + Send + Sync> {
+  dotenv().ok();
+
+  match env::var("password_hasher").as_deref() {
+    Ok("bcrypt") => Box::new(BcryptHasher::default()),
+    Ok("argon2") | Err(_) => Box::new(Argon2Hasher::default()),
+    Ok(other) => panic!("Unknown password_hasher {}", other),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn argon2_hash_is_salted_and_verifiable() {
+    let hasher = Argon2Hasher::default();
+    let first = hasher.hash("password").unwrap();
+    let second = hasher.hash("password").unwrap();
+
+    assert!(first.starts_with("$argon2id$"));
+    assert_ne!(first, second);
+    assert!(hasher.verify("password", &first));
+    assert!(!hasher.verify("other", &first));
+    assert!(!hasher.needs_rehash(&first));
+  }
+
+  #[test]
+  fn bcrypt_hash_is_verifiable() {
+    let hasher = BcryptHasher::new(4);
+    let hashed = hasher.hash("password").unwrap();
+
+    assert!(hasher.verify("password", &hashed));
+    assert!(!hasher.verify("other", &hashed));
+    assert!(!hasher.needs_rehash(&hashed));
+    assert!(BcryptHasher::new(5).needs_rehash(&hashed));
+  }
+
+  #[test]
+  fn legacy_hash_is_verified_and_flagged_for_rehash() {
+    let legacy = SimpleHasher::default().hash("password").unwrap();
+
+    assert!(Argon2Hasher::default().verify("password", &legacy));
+    assert!(!Argon2Hasher::default().verify("other", &legacy));
+    assert!(Argon2Hasher::default().needs_rehash(&legacy));
+    assert!(BcryptHasher::default().needs_rehash(&legacy));
   }
 }
@@ -0,0 +1,128 @@
+use crate::schema::message_receipts;
+
+use chrono::NaiveDateTime;
+use diesel::{Identifiable, Insertable, Queryable};
+
+/// The delivery and read state of a message for one of its recipients.
+#[derive(Identifiable, Queryable, Clone)]
+#[table_name = "message_receipts"]
+pub struct Receipt {
+  id: i32,
+  message_id: i32,
+  user_id: i32,
+  delivered_at: Option<NaiveDateTime>,
+  read_at: Option<NaiveDateTime>,
+}
+
+impl Receipt {
+  pub fn get_message_id(&self) -> i32 {
+    return self.message_id;
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  pub fn get_delivered_at(&self) -> Option<NaiveDateTime> {
+    return self.delivered_at;
+  }
+
+  pub fn get_read_at(&self) -> Option<NaiveDateTime> {
+    return self.read_at;
+  }
+
+  pub fn is_read(&self) -> bool {
+    return self.read_at.is_some();
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "message_receipts"]
+pub struct NewReceipt {
+  message_id: i32,
+  user_id: i32,
+}
+
+impl NewReceipt {
+  pub fn new(the_message_id: i32, the_user_id: i32) -> NewReceipt {
+    NewReceipt {
+      message_id: the_message_id,
+      user_id: the_user_id,
+    }
+  }
+}
+
+/// The quantity of unread messages of a user sent by another one.
+#[derive(Queryable, Debug, Clone, PartialEq)]
+pub struct UnreadCount {
+  from: i32,
+  count: i64,
+}
+
+impl UnreadCount {
+  #[cfg(test)]
+  pub fn new(the_from: i32, the_count: i64) -> UnreadCount {
+    UnreadCount {
+      from: the_from,
+      count: the_count,
+    }
+  }
+
+  pub fn get_from(&self) -> i32 {
+    return self.from;
+  }
+
+  pub fn get_count(&self) -> i64 {
+    return self.count;
+  }
+}
+
+#[cfg(test)]
+pub struct Builder {
+  message_id: Option<i32>,
+  user_id: Option<i32>,
+  delivered_at: Option<NaiveDateTime>,
+  read_at: Option<NaiveDateTime>,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      message_id: None,
+      user_id: None,
+      delivered_at: None,
+      read_at: None,
+    }
+  }
+
+  pub fn with_message_id(mut self, the_message_id: i32) -> Builder {
+    self.message_id = Some(the_message_id);
+    self
+  }
+
+  pub fn with_user_id(mut self, the_user_id: i32) -> Builder {
+    self.user_id = Some(the_user_id);
+    self
+  }
+
+  pub fn with_delivered_at(mut self, the_date: NaiveDateTime) -> Builder {
+    self.delivered_at = Some(the_date);
+    self
+  }
+
+  pub fn with_read_at(mut self, the_date: NaiveDateTime) -> Builder {
+    self.read_at = Some(the_date);
+    self
+  }
+
+  pub fn build(&self) -> Receipt {
+    Receipt {
+      id: 0,
+      message_id: *self.message_id.as_ref().unwrap_or(&0),
+      user_id: *self.user_id.as_ref().unwrap_or(&0),
+      delivered_at: self.delivered_at,
+      read_at: self.read_at,
+    }
+  }
+}
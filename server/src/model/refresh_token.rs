@@ -0,0 +1,157 @@
+use crate::schema::refresh_tokens;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, Queryable};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const TOKEN_BYTES: usize = 32;
+
+#[derive(Identifiable, Queryable, Clone)]
+pub struct RefreshToken {
+  id: i32,
+  user_id: i32,
+  token_hash: String,
+  family: String,
+  expires_at: NaiveDateTime,
+  used_at: Option<NaiveDateTime>,
+  revoked: bool,
+}
+
+impl RefreshToken {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  pub fn get_family(&self) -> String {
+    return self.family.to_string();
+  }
+
+  pub fn is_used(&self) -> bool {
+    return self.used_at.is_some();
+  }
+
+  pub fn is_revoked(&self) -> bool {
+    return self.revoked;
+  }
+
+  pub fn is_expired(&self) -> bool {
+    return self.expires_at <= Utc::now().naive_utc();
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "refresh_tokens"]
+pub struct NewRefreshToken {
+  user_id: i32,
+  token_hash: String,
+  family: String,
+  expires_at: NaiveDateTime,
+}
+
+impl NewRefreshToken {
+  pub fn new(
+    the_user_id: i32,
+    the_token_hash: String,
+    the_family: String,
+    the_expires_at: NaiveDateTime,
+  ) -> NewRefreshToken {
+    NewRefreshToken {
+      user_id: the_user_id,
+      token_hash: the_token_hash,
+      family: the_family,
+      expires_at: the_expires_at,
+    }
+  }
+}
+
+/// Generates a new opaque token, 32 random bytes encoded in url safe base64.
+///
+/// # Return
+/// * The token.
+pub fn generate_token() -> String {
+  let mut bytes = [0u8; TOKEN_BYTES];
+  OsRng.fill_bytes(&mut bytes);
+  base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Calculates the hash used to store an opaque token. Only the hashes are
+/// persisted so a leak of the table doesn't leak usable tokens.
+///
+/// # Arguments
+/// * `token` - The token in plain text.
+///
+/// # Return
+/// * The SHA-256 hex digest of the token.
+pub fn hash_token(token: &str) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(token.as_bytes());
+  format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+pub struct Builder {
+  id: Option<i32>,
+  user_id: Option<i32>,
+  family: Option<String>,
+  expires_at: Option<NaiveDateTime>,
+  used_at: Option<NaiveDateTime>,
+  revoked: bool,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      id: None,
+      user_id: None,
+      family: None,
+      expires_at: None,
+      used_at: None,
+      revoked: false,
+    }
+  }
+
+  pub fn with_id(mut self, the_id: i32) -> Builder {
+    self.id = Some(the_id);
+    self
+  }
+
+  pub fn with_user_id(mut self, the_user_id: i32) -> Builder {
+    self.user_id = Some(the_user_id);
+    self
+  }
+
+  pub fn with_family(mut self, the_family: &str) -> Builder {
+    self.family = Some(the_family.to_owned());
+    self
+  }
+
+  pub fn with_expires_at(mut self, the_expires_at: NaiveDateTime) -> Builder {
+    self.expires_at = Some(the_expires_at);
+    self
+  }
+
+  pub fn with_used_at(mut self, the_used_at: NaiveDateTime) -> Builder {
+    self.used_at = Some(the_used_at);
+    self
+  }
+
+  pub fn build(&self) -> RefreshToken {
+    RefreshToken {
+      id: *self.id.as_ref().unwrap_or(&0),
+      user_id: *self.user_id.as_ref().unwrap_or(&0),
+      token_hash: String::new(),
+      family: String::from(self.family.as_deref().unwrap_or("family")),
+      expires_at: self
+        .expires_at
+        .unwrap_or_else(|| Utc::now().naive_utc() + chrono::Duration::days(1)),
+      used_at: self.used_at,
+      revoked: self.revoked,
+    }
+  }
+}
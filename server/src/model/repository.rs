@@ -1,4 +1,9 @@
+pub mod attachment_repository;
+pub mod conversation_repository;
 pub mod error;
 pub mod login_repository;
 pub mod message_repository;
+pub mod receipt_repository;
+pub mod refresh_token_repository;
 pub mod user_repository;
+pub mod webhook_repository;
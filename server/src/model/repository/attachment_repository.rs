@@ -0,0 +1,949 @@
+use std::ops::Deref;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+#[cfg(not(feature = "postgres"))]
+use crate::db::database::last_insert_id;
+#[cfg(feature = "postgres")]
+use crate::schema::users;
+use crate::{
+  db::database::BackendConnection,
+  model::{
+    attachment::{Attachment, NewAttachment},
+    repository::error::{Error, RepoResult},
+    thumbnail::{NewThumbnail, Thumbnail, ThumbnailSize, ThumbnailState},
+  },
+  schema::{
+    attachments,
+    attachments::{
+      created_at, filename, hash, height, id, message_id, mime_type, size,
+      thumbnail_attempts, thumbnail_retry_at, thumbnail_state, user_id, width,
+    },
+    thumbnails,
+  },
+  DbConnection,
+};
+#[cfg(test)]
+use mockall::automock;
+
+/// The columns of an attachment, without the retry date of its thumbnails.
+const ATTACHMENT_COLUMNS: (
+  id,
+  user_id,
+  message_id,
+  hash,
+  filename,
+  mime_type,
+  size,
+  created_at,
+  width,
+  height,
+  thumbnail_state,
+  thumbnail_attempts,
+) = (
+  id,
+  user_id,
+  message_id,
+  hash,
+  filename,
+  mime_type,
+  size,
+  created_at,
+  width,
+  height,
+  thumbnail_state,
+  thumbnail_attempts,
+);
+
+#[cfg_attr(test, automock)]
+pub trait AttachmentRepository {
+  /// Insert an attachment in the database, not attached to any message yet,
+  /// if it fits in the quota of its uploader. The space used is read and the
+  /// attachment inserted in the same transaction, with the uploads of the
+  /// user locked, so parallel uploads can't exceed the quota. A file uploaded
+  /// twice counts twice, even if it's stored once.
+  ///
+  /// # Arguments
+  /// * `new_attachment` - The attachment to be inserted.
+  /// * `quota` - The space of the user, in bytes.
+  ///
+  /// # Return
+  /// * The inserted attachment, None if the quota is full.
+  /// * A repository error.
+  fn add_within_quota(
+    &self,
+    new_attachment: NewAttachment,
+    quota: i64,
+  ) -> RepoResult<Option<Attachment>>;
+
+  /// Delete an attachment that couldn't be stored.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the attachment.
+  ///
+  /// # Return
+  /// * The quantity of attachments deleted.
+  /// * A repository error.
+  fn remove(&self, id_attachment: i32) -> RepoResult<usize>;
+
+  /// Delete the attachments uploaded before a date and never sent, with
+  /// their thumbnails, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `before` - The date of the oldest upload kept.
+  /// * `limit` - The max quantity of attachments deleted.
+  ///
+  /// # Return
+  /// * The hashes of the files and thumbnails deleted that nothing else
+  ///   references anymore, to be deleted from the blob store.
+  /// * A repository error.
+  fn remove_expired(
+    &self,
+    before: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<String>>;
+
+  /// Delete the contents that no attachment and no thumbnail references,
+  /// in a transaction that no upload and no thumbnail insert runs along, so
+  /// a content can't be referenced again while it's deleted. The uploads put
+  /// their content after their insert, so a content deleted before is put
+  /// again.
+  ///
+  /// # Arguments
+  /// * `hashes` - The hashes of the contents.
+  /// * `delete` - Deletes a content from the blob store.
+  ///
+  /// # Return
+  /// * The quantity of contents deleted.
+  /// * A repository error.
+  fn delete_unused(
+    &self,
+    hashes: Vec<String>,
+    delete: &dyn Fn(&str),
+  ) -> RepoResult<usize>;
+
+  /// Retrieve an attachment from its id.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the attachment to look for.
+  ///
+  /// # Return
+  /// * The attachment.
+  /// * A repository error.
+  fn get(&self, id_attachment: i32) -> RepoResult<Attachment>;
+
+  /// Look for the attachments of some messages, ordered by id.
+  ///
+  /// # Arguments
+  /// * `ids` - The ids of the messages.
+  ///
+  /// # Return
+  /// * A vector of attachments. Could be empty.
+  /// * A repository error.
+  fn find_by_messages(&self, ids: Vec<i32>) -> RepoResult<Vec<Attachment>>;
+
+  /// Look for some attachments a user uploaded and didn't send yet.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the uploader.
+  /// * `ids` - The ids of the attachments.
+  ///
+  /// # Return
+  /// * A vector of attachments, without the ones that aren't pending
+  ///   attachments of the user. Could be empty.
+  /// * A repository error.
+  fn find_pending(
+    &self,
+    uid: i32,
+    ids: Vec<i32>,
+  ) -> RepoResult<Vec<Attachment>>;
+
+  /// Look for the images whose thumbnails are pending and not postponed
+  /// past a date, the oldest first.
+  ///
+  /// # Arguments
+  /// * `now` - The current date.
+  /// * `limit` - The max quantity of images.
+  ///
+  /// # Return
+  /// * A vector of attachments. Could be empty.
+  /// * A repository error.
+  fn find_unprocessed(
+    &self,
+    now: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<Attachment>>;
+
+  /// Insert the thumbnails of an image and mark them as ready, in the same
+  /// transaction. Nothing is inserted if they aren't pending anymore.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the image.
+  /// * `new_thumbnails` - The thumbnails to be inserted.
+  ///
+  /// # Return
+  /// * The quantity of images updated, 0 or 1.
+  /// * A repository error.
+  fn add_thumbnails(
+    &self,
+    id_attachment: i32,
+    new_thumbnails: Vec<NewThumbnail>,
+  ) -> RepoResult<usize>;
+
+  /// Mark the pending thumbnails of an image as failed.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the image.
+  ///
+  /// # Return
+  /// * The quantity of images updated, 0 or 1.
+  /// * A repository error.
+  fn mark_thumbnails_failed(&self, id_attachment: i32) -> RepoResult<usize>;
+
+  /// Count a failed attempt of the pending thumbnails of an image and
+  /// postpone the next one.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the image.
+  /// * `retry_at` - The date of the next attempt.
+  ///
+  /// # Return
+  /// * The quantity of images updated, 0 or 1.
+  /// * A repository error.
+  fn postpone_thumbnails(
+    &self,
+    id_attachment: i32,
+    retry_at: NaiveDateTime,
+  ) -> RepoResult<usize>;
+
+  /// Retrieve a thumbnail of an image.
+  ///
+  /// # Arguments
+  /// * `id_attachment` - The id of the image.
+  /// * `thumbnail_size` - The size of the thumbnail.
+  ///
+  /// # Return
+  /// * The thumbnail.
+  /// * A repository error.
+  fn find_thumbnail(
+    &self,
+    id_attachment: i32,
+    thumbnail_size: ThumbnailSize,
+  ) -> RepoResult<Thumbnail>;
+}
+
+/// Attaches some pending attachments of a user to a recently inserted
+/// message. It takes the connection of the transaction that inserted the
+/// message, so the attachments are only sent with it.
+///
+/// # Arguments
+/// * `conn` - The connection of the transaction.
+/// * `uid` - The id of the sender, who uploaded the attachments.
+/// * `id_msg` - The id of the message.
+/// * `ids` - The ids of the attachments.
+///
+/// # Return
+/// * NotFound if an attachment isn't a pending one of the user.
+/// * A repository error.
+pub fn attach(
+  conn: &BackendConnection,
+  uid: i32,
+  id_msg: i32,
+  ids: &[i32],
+) -> RepoResult<()> {
+  if ids.is_empty() {
+    return Ok(());
+  }
+  let attached = diesel::update(
+    attachments::table.filter(
+      id.eq_any(ids.to_vec())
+        .and(user_id.eq(uid))
+        .and(message_id.is_null()),
+    ),
+  )
+  .set(message_id.eq(id_msg))
+  .execute(conn)?;
+  if attached != ids.len() {
+    return Err(Error::NotFound);
+  }
+  Ok(())
+}
+
+/// Runs the upload of a user in a transaction that no other upload of the
+/// user runs along. SQLite takes the lock of the writers when the
+/// transaction begins, instead of at its first write, so the space read
+/// can't change before the insert.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `_uid` - The id of the uploader.
+/// * `upload` - Reads the space used and inserts the attachment.
+///
+/// # Return
+/// * The result of the upload.
+/// * A repository error.
+#[cfg(not(feature = "postgres"))]
+fn locking_uploads<T>(
+  conn: &BackendConnection,
+  _uid: i32,
+  upload: impl FnOnce() -> RepoResult<T>,
+) -> RepoResult<T> {
+  conn.immediate_transaction::<_, Error, _>(upload)
+}
+
+/// Runs the upload of a user in a transaction that no other upload of the
+/// user runs along. The row of the user is locked until the transaction
+/// ends, so the space read can't change before the insert.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `uid` - The id of the uploader.
+/// * `upload` - Reads the space used and inserts the attachment.
+///
+/// # Return
+/// * The result of the upload.
+/// * A repository error.
+#[cfg(feature = "postgres")]
+fn locking_uploads<T>(
+  conn: &BackendConnection,
+  uid: i32,
+  upload: impl FnOnce() -> RepoResult<T>,
+) -> RepoResult<T> {
+  conn.transaction::<_, Error, _>(|| {
+    users::table
+      .find(uid)
+      .select(users::id)
+      .for_update()
+      .first::<i32>(conn)?;
+    upload()
+  })
+}
+
+/// Runs the deletion of some contents in a transaction that no insert of an
+/// attachment or a thumbnail runs along. SQLite takes the lock of the
+/// writers when the transaction begins.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `deletion` - Reads the references and deletes the unused contents.
+///
+/// # Return
+/// * The result of the deletion.
+/// * A repository error.
+#[cfg(not(feature = "postgres"))]
+fn locking_contents<T>(
+  conn: &BackendConnection,
+  deletion: impl FnOnce() -> RepoResult<T>,
+) -> RepoResult<T> {
+  conn.immediate_transaction::<_, Error, _>(deletion)
+}
+
+/// Runs the deletion of some contents in a transaction that no insert of an
+/// attachment or a thumbnail runs along. The share mode of the tables
+/// blocks their writers until the transaction ends.
+///
+/// # Arguments
+/// * `conn` - A connection to the database.
+/// * `deletion` - Reads the references and deletes the unused contents.
+///
+/// # Return
+/// * The result of the deletion.
+/// * A repository error.
+#[cfg(feature = "postgres")]
+fn locking_contents<T>(
+  conn: &BackendConnection,
+  deletion: impl FnOnce() -> RepoResult<T>,
+) -> RepoResult<T> {
+  conn.transaction::<_, Error, _>(|| {
+    diesel::sql_query("LOCK TABLE attachments, thumbnails IN SHARE MODE")
+      .execute(conn)?;
+    deletion()
+  })
+}
+
+pub struct AttachmentRepositoryImpl {
+  db_connection: DbConnection,
+}
+
+impl AttachmentRepositoryImpl {
+  pub fn new(db_connection: DbConnection) -> Self {
+    AttachmentRepositoryImpl {
+      db_connection,
+    }
+  }
+
+  /// Insert an attachment and get it back, with its id and date.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_attachment` - The attachment to be inserted.
+  ///
+  /// # Return
+  /// * The inserted attachment.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_attachment: &NewAttachment,
+  ) -> RepoResult<Attachment> {
+    let attachment = diesel::insert_into(attachments::table)
+      .values(new_attachment)
+      .returning(ATTACHMENT_COLUMNS)
+      .get_result(conn)?;
+    Ok(attachment)
+  }
+
+  /// Insert an attachment and get it back, with its id and date. SQLite
+  /// can't return the inserted row, so it reads the row of the id the
+  /// connection just inserted, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_attachment` - The attachment to be inserted.
+  ///
+  /// # Return
+  /// * The inserted attachment.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_attachment: &NewAttachment,
+  ) -> RepoResult<Attachment> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(attachments::table)
+        .values(new_attachment)
+        .execute(conn)?;
+      let attachment = attachments::table
+        .find(last_insert_id(conn)?)
+        .select(ATTACHMENT_COLUMNS)
+        .first(conn)?;
+      Ok(attachment)
+    })
+  }
+
+  /// Look for the hashes an attachment or a thumbnail references.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `hashes` - The hashes to look for.
+  ///
+  /// # Return
+  /// * The hashes referenced. Could be empty.
+  /// * A repository error.
+  fn used_hashes(
+    conn: &BackendConnection,
+    hashes: &[String],
+  ) -> RepoResult<Vec<String>> {
+    let mut used = attachments::table
+      .filter(hash.eq_any(hashes.to_vec()))
+      .select(hash)
+      .load::<String>(conn)?;
+    used.extend(
+      thumbnails::table
+        .filter(thumbnails::hash.eq_any(hashes.to_vec()))
+        .select(thumbnails::hash)
+        .load::<String>(conn)?,
+    );
+    Ok(used)
+  }
+}
+
+impl AttachmentRepository for AttachmentRepositoryImpl {
+  fn add_within_quota(
+    &self,
+    new_attachment: NewAttachment,
+    quota: i64,
+  ) -> RepoResult<Option<Attachment>> {
+    let conn = self.db_connection.get()?;
+    let uid = new_attachment.get_user_id();
+    locking_uploads(conn.deref(), uid, || {
+      let used = attachments::table
+        .filter(user_id.eq(uid))
+        .select(size)
+        .load::<i64>(conn.deref())?
+        .iter()
+        .sum::<i64>();
+      if used + new_attachment.get_size() > quota {
+        return Ok(None);
+      }
+      Self::insert(conn.deref(), &new_attachment).map(Some)
+    })
+  }
+
+  fn remove(&self, id_attachment: i32) -> RepoResult<usize> {
+    let deleted = diesel::delete(attachments::table.find(id_attachment))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(deleted)
+  }
+
+  fn remove_expired(
+    &self,
+    before: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<String>> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let expired = attachments::table
+        .filter(message_id.is_null().and(created_at.lt(before)))
+        .select((id, hash))
+        .order(id.asc())
+        .limit(limit)
+        .load::<(i32, String)>(conn.deref())?;
+      if expired.is_empty() {
+        return Ok(vec![]);
+      }
+      let ids = expired
+        .iter()
+        .map(|(id_attachment, _)| *id_attachment)
+        .collect::<Vec<i32>>();
+      let mut hashes = thumbnails::table
+        .filter(thumbnails::attachment_id.eq_any(ids.clone()))
+        .select(thumbnails::hash)
+        .load::<String>(conn.deref())?;
+      diesel::delete(
+        thumbnails::table.filter(thumbnails::attachment_id.eq_any(ids.clone())),
+      )
+      .execute(conn.deref())?;
+      diesel::delete(attachments::table.filter(id.eq_any(ids)))
+        .execute(conn.deref())?;
+
+      hashes.extend(expired.into_iter().map(|(_, the_hash)| the_hash));
+      hashes.sort();
+      hashes.dedup();
+      let used = Self::used_hashes(conn.deref(), &hashes)?;
+      hashes.retain(|the_hash| !used.contains(the_hash));
+      Ok(hashes)
+    })
+  }
+
+  fn delete_unused(
+    &self,
+    hashes: Vec<String>,
+    delete: &dyn Fn(&str),
+  ) -> RepoResult<usize> {
+    if hashes.is_empty() {
+      return Ok(0);
+    }
+    let conn = self.db_connection.get()?;
+    locking_contents(conn.deref(), || {
+      let used = Self::used_hashes(conn.deref(), &hashes)?;
+      let unused = hashes
+        .iter()
+        .filter(|the_hash| !used.contains(the_hash))
+        .collect::<Vec<&String>>();
+      for the_hash in &unused {
+        delete(the_hash);
+      }
+      Ok(unused.len())
+    })
+  }
+
+  fn get(&self, id_attachment: i32) -> RepoResult<Attachment> {
+    let attachment = attachments::table
+      .find(id_attachment)
+      .select(ATTACHMENT_COLUMNS)
+      .first(self.db_connection.get()?.deref())?;
+    Ok(attachment)
+  }
+
+  fn find_by_messages(&self, ids: Vec<i32>) -> RepoResult<Vec<Attachment>> {
+    if ids.is_empty() {
+      return Ok(vec![]);
+    }
+    let found = attachments::table
+      .filter(message_id.eq_any(ids))
+      .select(ATTACHMENT_COLUMNS)
+      .order(id.asc())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(found)
+  }
+
+  fn find_pending(
+    &self,
+    uid: i32,
+    ids: Vec<i32>,
+  ) -> RepoResult<Vec<Attachment>> {
+    let found = attachments::table
+      .filter(
+        id.eq_any(ids)
+          .and(user_id.eq(uid))
+          .and(message_id.is_null()),
+      )
+      .select(ATTACHMENT_COLUMNS)
+      .order(id.asc())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(found)
+  }
+
+  fn find_unprocessed(
+    &self,
+    now: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<Attachment>> {
+    let found = attachments::table
+      .filter(
+        thumbnail_state
+          .eq(ThumbnailState::Pending.as_str())
+          .and(thumbnail_retry_at.is_null().or(thumbnail_retry_at.le(now))),
+      )
+      .select(ATTACHMENT_COLUMNS)
+      .order(id.asc())
+      .limit(limit)
+      .load(self.db_connection.get()?.deref())?;
+    Ok(found)
+  }
+
+  fn add_thumbnails(
+    &self,
+    id_attachment: i32,
+    new_thumbnails: Vec<NewThumbnail>,
+  ) -> RepoResult<usize> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let updated = diesel::update(
+        attachments::table.filter(
+          id.eq(id_attachment)
+            .and(thumbnail_state.eq(ThumbnailState::Pending.as_str())),
+        ),
+      )
+      .set(thumbnail_state.eq(ThumbnailState::Ready.as_str()))
+      .execute(conn.deref())?;
+      if updated > 0 {
+        diesel::insert_into(thumbnails::table)
+          .values(&new_thumbnails)
+          .execute(conn.deref())?;
+      }
+      Ok(updated)
+    })
+  }
+
+  fn mark_thumbnails_failed(&self, id_attachment: i32) -> RepoResult<usize> {
+    let updated = diesel::update(
+      attachments::table.filter(
+        id.eq(id_attachment)
+          .and(thumbnail_state.eq(ThumbnailState::Pending.as_str())),
+      ),
+    )
+    .set(thumbnail_state.eq(ThumbnailState::Failed.as_str()))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn postpone_thumbnails(
+    &self,
+    id_attachment: i32,
+    retry_at: NaiveDateTime,
+  ) -> RepoResult<usize> {
+    let updated = diesel::update(
+      attachments::table.filter(
+        id.eq(id_attachment)
+          .and(thumbnail_state.eq(ThumbnailState::Pending.as_str())),
+      ),
+    )
+    .set((
+      thumbnail_attempts.eq(thumbnail_attempts + 1),
+      thumbnail_retry_at.eq(retry_at),
+    ))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn find_thumbnail(
+    &self,
+    id_attachment: i32,
+    thumbnail_size: ThumbnailSize,
+  ) -> RepoResult<Thumbnail> {
+    let thumbnail = thumbnails::table
+      .filter(
+        thumbnails::attachment_id
+          .eq(id_attachment)
+          .and(thumbnails::size.eq(thumbnail_size.as_str())),
+      )
+      .first(self.db_connection.get()?.deref())?;
+    Ok(thumbnail)
+  }
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+  use super::*;
+  use crate::{
+    db::database::TestDatabase,
+    model::{
+      attachment::content_hash,
+      message::NewMessage,
+      repository::{
+        message_repository::{MessageRepository, MessageRepositoryImpl},
+        user_repository::{UserRepository, UserRepositoryImpl},
+      },
+      user::NewUser,
+    },
+  };
+  use chrono::Utc;
+  use std::cell::RefCell;
+
+  #[test]
+  fn attach_the_pending_attachments_with_the_message() {
+    let db = TestDatabase::new("attachments");
+    let users = UserRepositoryImpl::new(db.connection());
+    let sender = users
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let recipient = users
+      .add(NewUser::new(
+        String::from("recipient"),
+        String::from("hash"),
+      ))
+      .unwrap();
+    let repository = AttachmentRepositoryImpl::new(db.connection());
+    let upload = |owner: i32, content: &[u8]| {
+      let new_attachment = NewAttachment::new(
+        owner,
+        content_hash(content),
+        String::from("file.txt"),
+        String::from("text/plain"),
+        content.len() as i64,
+      );
+      repository.add_within_quota(new_attachment, 20).unwrap()
+    };
+    let first = upload(sender, b"first").unwrap().get_id();
+    let second = upload(sender, b"second file").unwrap().get_id();
+    let other = upload(recipient, b"other").unwrap().get_id();
+    assert_eq!(upload(sender, b"too much"), None);
+    assert!(upload(sender, b"fits").is_some());
+
+    let messages = MessageRepositoryImpl::new(db.connection());
+    let new_message = NewMessage::new(sender, recipient, String::from("Hi"));
+    let refused =
+      messages.add(new_message, vec![recipient], vec![first, other]);
+    assert!(matches!(refused, Err(Error::NotFound)));
+    assert_eq!(repository.get(first).unwrap().get_message_id(), None);
+
+    let new_message = NewMessage::new(sender, recipient, String::from("Hi"));
+    let id_msg = messages
+      .add(new_message, vec![recipient], vec![first, second])
+      .unwrap();
+    let attached = repository.find_by_messages(vec![id_msg]).unwrap();
+    assert_eq!(
+      attached
+        .iter()
+        .map(Attachment::get_id)
+        .collect::<Vec<i32>>(),
+      vec![first, second]
+    );
+    assert!(repository
+      .find_pending(sender, vec![first, second])
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn add_the_thumbnails_of_a_pending_image() {
+    let db = TestDatabase::new("thumbnails");
+    let uid = UserRepositoryImpl::new(db.connection())
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let repository = AttachmentRepositoryImpl::new(db.connection());
+    let new_attachment = NewAttachment::new(
+      uid,
+      content_hash(b"image"),
+      String::from("photo.png"),
+      String::from("image/png"),
+      5,
+    )
+    .with_dimensions(300, 200);
+    let image = repository
+      .add_within_quota(new_attachment, 10)
+      .unwrap()
+      .unwrap();
+    assert_eq!(image.get_thumbnail_state(), Some(ThumbnailState::Pending));
+    let now = Utc::now().naive_utc();
+    assert_eq!(
+      repository.find_unprocessed(now, 10).unwrap(),
+      vec![image.clone()]
+    );
+
+    let thumbnail = |thumbnail_size| {
+      NewThumbnail::new(
+        image.get_id(),
+        thumbnail_size,
+        content_hash(b"thumbnail"),
+        String::from("image/jpeg"),
+        128,
+        85,
+      )
+    };
+    let new_thumbnails = vec![
+      thumbnail(ThumbnailSize::Small),
+      thumbnail(ThumbnailSize::Medium),
+    ];
+    assert_eq!(
+      repository
+        .add_thumbnails(image.get_id(), new_thumbnails)
+        .unwrap(),
+      1
+    );
+    assert_eq!(
+      repository
+        .add_thumbnails(image.get_id(), vec![thumbnail(ThumbnailSize::Large)])
+        .unwrap(),
+      0
+    );
+    assert!(repository.find_unprocessed(now, 10).unwrap().is_empty());
+    assert_eq!(
+      repository
+        .find_thumbnail(image.get_id(), ThumbnailSize::Medium)
+        .unwrap()
+        .get_mime_type(),
+      "image/jpeg"
+    );
+    assert!(matches!(
+      repository.find_thumbnail(image.get_id(), ThumbnailSize::Large),
+      Err(Error::NotFound)
+    ));
+    assert_eq!(
+      repository.mark_thumbnails_failed(image.get_id()).unwrap(),
+      0
+    );
+  }
+
+  #[test]
+  fn postpone_the_thumbnails_of_an_image() {
+    let db = TestDatabase::new("postponed_thumbnails");
+    let uid = UserRepositoryImpl::new(db.connection())
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let repository = AttachmentRepositoryImpl::new(db.connection());
+    let new_attachment = NewAttachment::new(
+      uid,
+      content_hash(b"image"),
+      String::from("photo.png"),
+      String::from("image/png"),
+      5,
+    )
+    .with_dimensions(300, 200);
+    let image = repository
+      .add_within_quota(new_attachment, 10)
+      .unwrap()
+      .unwrap();
+    assert_eq!(image.get_thumbnail_attempts(), 0);
+
+    let now = Utc::now().naive_utc();
+    let retry_at = now + chrono::Duration::seconds(30);
+    assert_eq!(
+      repository
+        .postpone_thumbnails(image.get_id(), retry_at)
+        .unwrap(),
+      1
+    );
+    assert!(repository.find_unprocessed(now, 10).unwrap().is_empty());
+    let postponed = repository.find_unprocessed(retry_at, 10).unwrap();
+    assert_eq!(postponed.len(), 1);
+    assert_eq!(postponed[0].get_thumbnail_attempts(), 1);
+    assert_eq!(
+      postponed[0].get_thumbnail_state(),
+      Some(ThumbnailState::Pending)
+    );
+  }
+
+  #[test]
+  fn remove_the_expired_uploads() {
+    let db = TestDatabase::new("expired_attachments");
+    let users = UserRepositoryImpl::new(db.connection());
+    let sender = users
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let recipient = users
+      .add(NewUser::new(
+        String::from("recipient"),
+        String::from("hash"),
+      ))
+      .unwrap();
+    let repository = AttachmentRepositoryImpl::new(db.connection());
+    let upload = |content: &[u8]| {
+      let new_attachment = NewAttachment::new(
+        sender,
+        content_hash(content),
+        String::from("photo.png"),
+        String::from("image/png"),
+        content.len() as i64,
+      )
+      .with_dimensions(300, 200);
+      repository
+        .add_within_quota(new_attachment, 100)
+        .unwrap()
+        .unwrap()
+        .get_id()
+    };
+    let sent = upload(b"sent");
+    let shared = upload(b"sent");
+    let expired = upload(b"expired");
+    let new_thumbnail = NewThumbnail::new(
+      expired,
+      ThumbnailSize::Small,
+      content_hash(b"thumbnail"),
+      String::from("image/jpeg"),
+      128,
+      85,
+    );
+    repository
+      .add_thumbnails(expired, vec![new_thumbnail])
+      .unwrap();
+    let new_message = NewMessage::new(sender, recipient, String::from("Hi"));
+    MessageRepositoryImpl::new(db.connection())
+      .add(new_message, vec![recipient], vec![sent])
+      .unwrap();
+
+    let before = Utc::now().naive_utc() + chrono::Duration::seconds(1);
+    let mut hashes = repository.remove_expired(before, 10).unwrap();
+    hashes.sort();
+    let mut expected =
+      vec![content_hash(b"expired"), content_hash(b"thumbnail")];
+    expected.sort();
+    assert_eq!(hashes, expected);
+    assert!(matches!(repository.get(shared), Err(Error::NotFound)));
+    assert!(matches!(repository.get(expired), Err(Error::NotFound)));
+    assert!(matches!(
+      repository.find_thumbnail(expired, ThumbnailSize::Small),
+      Err(Error::NotFound)
+    ));
+    assert!(repository.get(sent).is_ok());
+    assert!(repository.remove_expired(before, 10).unwrap().is_empty());
+  }
+
+  #[test]
+  fn keep_a_content_uploaded_again_during_the_expiry() {
+    let db = TestDatabase::new("expired_contents");
+    let uploader = UserRepositoryImpl::new(db.connection())
+      .add(NewUser::new(String::from("uploader"), String::from("hash")))
+      .unwrap();
+    let repository = AttachmentRepositoryImpl::new(db.connection());
+    let upload = |content: &[u8]| {
+      let new_attachment = NewAttachment::new(
+        uploader,
+        content_hash(content),
+        String::from("file.txt"),
+        String::from("text/plain"),
+        content.len() as i64,
+      );
+      repository.add_within_quota(new_attachment, 100).unwrap();
+    };
+    upload(b"again");
+    upload(b"expired");
+    let before = Utc::now().naive_utc() + chrono::Duration::seconds(1);
+    let hashes = repository.remove_expired(before, 10).unwrap();
+    assert_eq!(hashes.len(), 2);
+
+    // Uploaded again between the deletion of the rows and of the contents
+    upload(b"again");
+    let deleted = RefCell::new(vec![]);
+    let quantity = repository
+      .delete_unused(hashes, &|the_hash| {
+        deleted.borrow_mut().push(the_hash.to_string())
+      })
+      .unwrap();
+    assert_eq!(quantity, 1);
+    assert_eq!(deleted.into_inner(), vec![content_hash(b"expired")]);
+  }
+}
@@ -0,0 +1,415 @@
+use std::ops::Deref;
+
+use diesel::prelude::*;
+
+#[cfg(not(feature = "postgres"))]
+use crate::db::database::last_insert_id;
+use crate::{
+  db::database::BackendConnection,
+  model::{
+    conversation::{
+      Conversation, Member, MemberRole, NewConversation, NewMember,
+    },
+    page::{Direction, Page, PageRequest},
+    repository::error::{Error, RepoResult},
+  },
+  schema::{
+    conversation_members,
+    conversation_members::{conversation_id, user_id},
+    conversations,
+    conversations::{id, name},
+  },
+  DbConnection,
+};
+
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait ConversationRepository {
+  /// Insert a group conversation with its owner and its first members, in
+  /// the same transaction.
+  ///
+  /// # Arguments
+  /// * `new_conversation` - The conversation to be inserted.
+  /// * `members` - The ids of the first members. The owner and the repeated ids
+  ///   are skipped.
+  ///
+  /// # Return
+  /// * The id of the conversation.
+  /// * A repository error.
+  fn add(
+    &self,
+    new_conversation: NewConversation,
+    members: Vec<i32>,
+  ) -> RepoResult<i32>;
+
+  /// Retrieve a conversation from its id.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  ///
+  /// # Return
+  /// * The conversation.
+  /// * A repository error.
+  fn get(&self, id_conv: i32) -> RepoResult<Conversation>;
+
+  /// Change the name of a conversation.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  /// * `new_name` - The new name.
+  ///
+  /// # Return
+  /// * The quantity of conversations renamed.
+  /// * A repository error.
+  fn rename(&self, id_conv: i32, new_name: String) -> RepoResult<usize>;
+
+  /// Look for a page of the conversations a user is member of, ordered by
+  /// id.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the user.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of conversations. Could be empty.
+  /// * A repository error.
+  fn find_by_member(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Conversation>>;
+
+  /// Look for a page of the members of a conversation, ordered by their
+  /// join.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of members. Could be empty.
+  /// * A repository error.
+  fn find_members(
+    &self,
+    id_conv: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Member>>;
+
+  /// Look for every member of a conversation, ordered by their join.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  ///
+  /// # Return
+  /// * The members. Could be empty.
+  /// * A repository error.
+  fn members(&self, id_conv: i32) -> RepoResult<Vec<Member>>;
+
+  /// Look for a member of a conversation.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  /// * `uid` - The id of the user.
+  ///
+  /// # Return
+  /// * An Option for the member, none if the user isn't a member.
+  /// * A repository error.
+  fn get_member(&self, id_conv: i32, uid: i32) -> RepoResult<Option<Member>>;
+
+  /// Insert a member in a conversation.
+  ///
+  /// # Arguments
+  /// * `new_member` - The member to be inserted.
+  ///
+  /// # Return
+  /// * The quantity of members inserted.
+  /// * UniqueViolation if the user is already a member.
+  fn add_member(&self, new_member: NewMember) -> RepoResult<usize>;
+
+  /// Delete a member of a conversation.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  /// * `uid` - The id of the user.
+  ///
+  /// # Return
+  /// * The quantity of members deleted.
+  /// * A repository error.
+  fn remove_member(&self, id_conv: i32, uid: i32) -> RepoResult<usize>;
+}
+
+pub struct ConversationRepositoryImpl {
+  db_connection: DbConnection,
+}
+
+impl ConversationRepositoryImpl {
+  pub fn new(db_connection: DbConnection) -> Self {
+    ConversationRepositoryImpl {
+      db_connection,
+    }
+  }
+
+  /// Insert a conversation and get its id.
+  ///
+  /// # Arguments
+  /// * `conn` - The connection of the transaction that inserts it.
+  /// * `new_conversation` - The conversation to be inserted.
+  ///
+  /// # Return
+  /// * The id of the conversation.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_conversation: &NewConversation,
+  ) -> RepoResult<i32> {
+    let id_conv = diesel::insert_into(conversations::table)
+      .values(new_conversation)
+      .returning(id)
+      .get_result(conn)?;
+    Ok(id_conv)
+  }
+
+  /// Insert a conversation and get its id. SQLite can't return the inserted
+  /// row, so it reads the id the connection just inserted, in the same
+  /// transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - The connection of the transaction that inserts it.
+  /// * `new_conversation` - The conversation to be inserted.
+  ///
+  /// # Return
+  /// * The id of the conversation.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_conversation: &NewConversation,
+  ) -> RepoResult<i32> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(conversations::table)
+        .values(new_conversation)
+        .execute(conn)?;
+      last_insert_id(conn)
+    })
+  }
+}
+
+impl ConversationRepository for ConversationRepositoryImpl {
+  fn add(
+    &self,
+    new_conversation: NewConversation,
+    members: Vec<i32>,
+  ) -> RepoResult<i32> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let id_conv = Self::insert(&conn, &new_conversation)?;
+      let owner = new_conversation.get_created_by();
+
+      let mut members = members;
+      members.sort_unstable();
+      members.dedup();
+      let mut new_members =
+        vec![NewMember::new(id_conv, owner, MemberRole::Owner)];
+      new_members.extend(
+        members
+          .into_iter()
+          .filter(|member| *member != owner)
+          .map(|member| NewMember::new(id_conv, member, MemberRole::Member)),
+      );
+      diesel::insert_into(conversation_members::table)
+        .values(&new_members)
+        .execute(conn.deref())?;
+      Ok(id_conv)
+    })
+  }
+
+  fn get(&self, id_conv: i32) -> RepoResult<Conversation> {
+    let conversation = conversations::table
+      .find(id_conv)
+      .get_result(self.db_connection.get()?.deref())?;
+    Ok(conversation)
+  }
+
+  fn rename(&self, id_conv: i32, new_name: String) -> RepoResult<usize> {
+    let renamed = diesel::update(conversations::table.find(id_conv))
+      .set(name.eq(new_name))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(renamed)
+  }
+
+  fn find_by_member(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Conversation>> {
+    let query = conversations::table
+      .inner_join(conversation_members::table)
+      .filter(user_id.eq(uid))
+      .select(conversations::all_columns)
+      .into_boxed();
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(id.asc()),
+      (Some(key), Direction::After) => query.filter(id.gt(key)).order(id.asc()),
+      (Some(key), Direction::Before) => {
+        query.filter(id.lt(key)).order(id.desc())
+      },
+    };
+    let found = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(found, page, Conversation::get_id))
+  }
+
+  fn find_members(
+    &self,
+    id_conv: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Member>> {
+    let query = conversation_members::table
+      .filter(conversation_id.eq(id_conv))
+      .into_boxed();
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(conversation_members::id.asc()),
+      (Some(key), Direction::After) => query
+        .filter(conversation_members::id.gt(key))
+        .order(conversation_members::id.asc()),
+      (Some(key), Direction::Before) => query
+        .filter(conversation_members::id.lt(key))
+        .order(conversation_members::id.desc()),
+    };
+    let members = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(members, page, Member::get_id))
+  }
+
+  fn members(&self, id_conv: i32) -> RepoResult<Vec<Member>> {
+    let members = conversation_members::table
+      .filter(conversation_id.eq(id_conv))
+      .order(conversation_members::id.asc())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(members)
+  }
+
+  fn get_member(&self, id_conv: i32, uid: i32) -> RepoResult<Option<Member>> {
+    let member = conversation_members::table
+      .filter(conversation_id.eq(id_conv).and(user_id.eq(uid)))
+      .first::<Member>(self.db_connection.get()?.deref())
+      .optional()?;
+    Ok(member)
+  }
+
+  fn add_member(&self, new_member: NewMember) -> RepoResult<usize> {
+    let inserted = diesel::insert_into(conversation_members::table)
+      .values(new_member)
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(inserted)
+  }
+
+  fn remove_member(&self, id_conv: i32, uid: i32) -> RepoResult<usize> {
+    let removed = diesel::delete(
+      conversation_members::table
+        .filter(conversation_id.eq(id_conv).and(user_id.eq(uid))),
+    )
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(removed)
+  }
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+  use super::*;
+  use crate::{
+    db::database::TestDatabase,
+    model::{
+      repository::user_repository::{UserRepository, UserRepositoryImpl},
+      user::NewUser,
+    },
+  };
+
+  #[test]
+  fn add_a_group_with_repeated_members() {
+    let db = TestDatabase::new("repeated-members");
+    let users = UserRepositoryImpl::new(db.connection());
+    let owner = users
+      .add(NewUser::new(String::from("owner"), String::from("hash")))
+      .unwrap();
+    let member = users
+      .add(NewUser::new(String::from("member"), String::from("hash")))
+      .unwrap();
+    let repository = ConversationRepositoryImpl::new(db.connection());
+
+    let group = repository
+      .add(
+        NewConversation::new(String::from("team"), owner),
+        vec![member, owner, member],
+      )
+      .unwrap();
+    let members = repository
+      .members(group)
+      .unwrap()
+      .iter()
+      .map(|member| (member.get_user_id(), member.get_role()))
+      .collect::<Vec<(i32, MemberRole)>>();
+    assert_eq!(
+      members,
+      vec![(owner, MemberRole::Owner), (member, MemberRole::Member)]
+    );
+  }
+
+  #[test]
+  fn page_the_groups_and_the_members() {
+    let db = TestDatabase::new("paged-groups");
+    let users = UserRepositoryImpl::new(db.connection());
+    let user = |name: &str| {
+      users
+        .add(NewUser::new(String::from(name), String::from("hash")))
+        .unwrap()
+    };
+    let (owner, first, second) = (user("owner"), user("first"), user("second"));
+    let repository = ConversationRepositoryImpl::new(db.connection());
+    let groups = ["team", "family", "friends"]
+      .iter()
+      .map(|group| {
+        repository
+          .add(
+            NewConversation::new(String::from(*group), owner),
+            vec![first, second],
+          )
+          .unwrap()
+      })
+      .collect::<Vec<i32>>();
+
+    let page = repository
+      .find_by_member(owner, PageRequest::first(Some(2)))
+      .unwrap();
+    let ids = page.get_items().iter().map(Conversation::get_id);
+    assert_eq!(ids.collect::<Vec<i32>>(), groups[..2].to_vec());
+    let next = PageRequest::next_to(
+      Direction::After,
+      page.get_next_key().unwrap(),
+      Some(2),
+    );
+    let page = repository.find_by_member(owner, next).unwrap();
+    let ids = page.get_items().iter().map(Conversation::get_id);
+    assert_eq!(ids.collect::<Vec<i32>>(), groups[2..].to_vec());
+    assert_eq!(page.get_next_key(), None);
+
+    let page = repository
+      .find_members(groups[0], PageRequest::first(Some(2)))
+      .unwrap();
+    let uids = page.get_items().iter().map(Member::get_user_id);
+    assert_eq!(uids.collect::<Vec<i32>>(), vec![owner, first]);
+    let next = PageRequest::next_to(
+      Direction::After,
+      page.get_next_key().unwrap(),
+      Some(2),
+    );
+    let page = repository.find_members(groups[0], next).unwrap();
+    let uids = page.get_items().iter().map(Member::get_user_id);
+    assert_eq!(uids.collect::<Vec<i32>>(), vec![second]);
+  }
+}
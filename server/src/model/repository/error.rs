@@ -1,10 +1,34 @@
-pub type RepoResult<T> = Result<T, diesel::result::Error>;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use thiserror::Error;
 
-// use thiserror::Error;
-// #[derive(Error, Debug)]
-// pub enum Error {
-// #[error("cannot open database connection")]
-// ConnectionError,
-// #[error("diesel error")]
-// DieselError(diesel::result::Error),
-// }
+pub type RepoResult<T> = Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+  #[error("cannot open database connection")]
+  ConnectionError,
+  #[error("record not found")]
+  NotFound,
+  #[error("unique constraint violated: {0}")]
+  UniqueViolation(String),
+  #[error("foreign key constraint violated: {0}")]
+  ForeignKeyViolation(String),
+  #[error("diesel error: {0}")]
+  DieselError(DieselError),
+}
+
+impl From<DieselError> for Error {
+  fn from(err: DieselError) -> Self {
+    match err {
+      DieselError::NotFound => Error::NotFound,
+      DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+        Error::UniqueViolation(info.message().to_string())
+      },
+      DieselError::DatabaseError(
+        DatabaseErrorKind::ForeignKeyViolation,
+        info,
+      ) => Error::ForeignKeyViolation(info.message().to_string()),
+      _ => Error::DieselError(err),
+    }
+  }
+}
@@ -1,16 +1,21 @@
+#[cfg(not(feature = "postgres"))]
+use crate::{db::database::last_insert_id, model::repository::error::Error};
 use crate::{
+  db::database::BackendConnection,
   model::{
     login::{Login, NewLogin},
+    page::{Direction, Page, PageRequest},
     repository::error::RepoResult,
   },
   schema::{
     logins,
-    logins::{id, token, username},
+    logins::{family, id, jti, last_seen_at, token, user_id},
   },
   DbConnection,
 };
-use diesel::{prelude::*, result::Error};
-use std::{borrow::Borrow, ops::Deref};
+use chrono::Utc;
+use diesel::prelude::*;
+use std::ops::Deref;
 
 #[cfg(test)]
 use mockall::automock;
@@ -24,18 +29,44 @@ pub trait LoginRepository {
   ///
   /// # Return
   /// * The login struct.
-  /// * A diesel error.
+  /// * A repository error.
   fn add(&self, new_login: NewLogin) -> RepoResult<Login>;
 
-  /// Look for a login for the given username.
+  /// Look for every login of a user, the most recently used first.
   ///
   /// # Arguments
-  /// * `the_username` - The username to look for.
+  /// * `the_user_id` - The id of the user to look for.
+  ///
+  /// # Return
+  /// * A vector of logins. Could be empty.
+  /// * A repository error.
+  fn find(&self, the_user_id: i32) -> RepoResult<Vec<Login>>;
+
+  /// Look for a page of the logins of a user, the most recently opened
+  /// first. They are ordered by id, the last use changes on every refresh.
+  ///
+  /// # Arguments
+  /// * `the_user_id` - The id of the user to look for.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of logins. Could be empty.
+  /// * A repository error.
+  fn find_page(
+    &self,
+    the_user_id: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Login>>;
+
+  /// Look for the login bound to a refresh token family.
+  ///
+  /// # Arguments
+  /// * `the_family` - The refresh token family.
   ///
   /// # Return
   /// * An Option for the login struct.
-  /// * A diesel error.
-  fn find(&self, the_username: String) -> RepoResult<Option<Login>>;
+  /// * A repository error.
+  fn find_by_family(&self, the_family: String) -> RepoResult<Option<Login>>;
 
   /// Updates the login in the database
   ///
@@ -44,8 +75,60 @@ pub trait LoginRepository {
   ///
   /// # Return
   /// * The login struct.
-  /// * A diesel error.
+  /// * A repository error.
   fn update(&self, login: &Login) -> RepoResult<Login>;
+
+  /// Look for the login that issued the token with the given id.
+  ///
+  /// # Arguments
+  /// * `the_jti` - The id of the token (jti claim).
+  ///
+  /// # Return
+  /// * An Option for the login struct.
+  /// * A repository error.
+  fn find_by_jti(&self, the_jti: String) -> RepoResult<Option<Login>>;
+
+  /// Deletes the login that issued the token with the given id.
+  ///
+  /// # Arguments
+  /// * `the_jti` - The id of the token (jti claim).
+  ///
+  /// # Return
+  /// * The number of deleted logins.
+  /// * A repository error.
+  fn delete_by_jti(&self, the_jti: String) -> RepoResult<usize>;
+
+  /// Deletes a login of a user.
+  ///
+  /// # Arguments
+  /// * `id_login` - The id of the login.
+  /// * `the_user_id` - The owner of the login.
+  ///
+  /// # Return
+  /// * The number of deleted logins.
+  /// * A repository error.
+  fn delete(&self, id_login: i32, the_user_id: i32) -> RepoResult<usize>;
+
+  /// Deletes every login of a user.
+  ///
+  /// # Arguments
+  /// * `the_user_id` - The user to logout.
+  ///
+  /// # Return
+  /// * The number of deleted logins.
+  /// * A repository error.
+  fn delete_all(&self, the_user_id: i32) -> RepoResult<usize>;
+
+  /// Sets the last seen timestamp of the login that issued the token with the
+  /// given id to now.
+  ///
+  /// # Arguments
+  /// * `the_jti` - The id of the token (jti claim).
+  ///
+  /// # Return
+  /// * The number of updated logins.
+  /// * A repository error.
+  fn touch(&self, the_jti: String) -> RepoResult<usize>;
 }
 
 pub struct LoginRepositoryImpl {
@@ -61,54 +144,176 @@ impl LoginRepositoryImpl {
 }
 
 impl LoginRepositoryImpl {
-  /// Look for a login based on its natural keys (username, token).
+  /// Insert a login and get it back, with its id.
   ///
   /// # Arguments
-  /// * `the_username` - The username to look for.
-  /// * `the_token` - The token to look for.
+  /// * `conn` - A connection to the database.
+  /// * `new_login` - The new login to be inserted.
   ///
   /// # Return
-  /// * The login struct.
-  /// * A diesel error.
-  fn find_by_natural_key(
-    &self,
-    the_username: String,
-    the_token: String,
+  /// * The inserted login.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_login: &NewLogin,
   ) -> RepoResult<Login> {
-    let login_updated = logins::table
-      .filter(username.eq(the_username).and(token.eq(the_token)))
-      .first(self.db_connection.get()?.deref())?;
+    let login = diesel::insert_into(logins::table)
+      .values(new_login)
+      .get_result(conn)?;
+    Ok(login)
+  }
+
+  /// Insert a login and get it back, with its id. SQLite can't return the
+  /// inserted row, so it reads the row of the id the connection just
+  /// inserted, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_login` - The new login to be inserted.
+  ///
+  /// # Return
+  /// * The inserted login.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_login: &NewLogin,
+  ) -> RepoResult<Login> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(logins::table)
+        .values(new_login)
+        .execute(conn)?;
+      let login = logins::table.find(last_insert_id(conn)?).first(conn)?;
+      Ok(login)
+    })
+  }
+
+  /// Store the rotated token of a login and get it back.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `login` - The login with its new token, jti and last use.
+  ///
+  /// # Return
+  /// * The updated login.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn save(conn: &BackendConnection, login: &Login) -> RepoResult<Login> {
+    let login_updated = diesel::update(logins::table.find(login.get_id()))
+      .set((
+        token.eq(login.get_token()),
+        jti.eq(login.get_jti()),
+        last_seen_at.eq(login.get_last_seen_at()),
+      ))
+      .get_result(conn)?;
     Ok(login_updated)
   }
+
+  /// Store the rotated token of a login and get it back. SQLite can't return
+  /// the updated row, so it reads it by its id, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `login` - The login with its new token, jti and last use.
+  ///
+  /// # Return
+  /// * The updated login.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn save(conn: &BackendConnection, login: &Login) -> RepoResult<Login> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::update(logins::table.find(login.get_id()))
+        .set((
+          token.eq(login.get_token()),
+          jti.eq(login.get_jti()),
+          last_seen_at.eq(login.get_last_seen_at()),
+        ))
+        .execute(conn)?;
+      let login_updated = logins::table.find(login.get_id()).first(conn)?;
+      Ok(login_updated)
+    })
+  }
 }
 
 impl LoginRepository for LoginRepositoryImpl {
   fn add(&self, new_login: NewLogin) -> RepoResult<Login> {
-    diesel::insert_into(logins::table)
-      .values(new_login.borrow())
-      .execute(self.db_connection.get()?.deref())?;
+    Self::insert(self.db_connection.get()?.deref(), &new_login)
+  }
 
-    self.find_by_natural_key(new_login.get_username(), new_login.get_token())
+  fn find(&self, the_user_id: i32) -> RepoResult<Vec<Login>> {
+    let logins = logins::table
+      .filter(user_id.eq(the_user_id))
+      .order(last_seen_at.desc())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(logins)
   }
 
-  fn find(&self, the_username: String) -> RepoResult<Option<Login>> {
-    match logins::table
-      .filter(username.eq(the_username))
-      .first::<Login>(self.db_connection.get()?.deref())
-    {
-      Ok(login_found) => Ok(Option::from(login_found)),
-      Err(err) => match err {
-        Error::NotFound => Ok(None),
-        _ => Err(err),
+  fn find_page(
+    &self,
+    the_user_id: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Login>> {
+    let query = logins::table.filter(user_id.eq(the_user_id)).into_boxed();
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(id.desc()),
+      (Some(key), Direction::After) => {
+        query.filter(id.lt(key)).order(id.desc())
       },
-    }
+      (Some(key), Direction::Before) => {
+        query.filter(id.gt(key)).order(id.asc())
+      },
+    };
+    let logins = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(logins, page, Login::get_id))
+  }
+
+  fn find_by_family(&self, the_family: String) -> RepoResult<Option<Login>> {
+    let login = logins::table
+      .filter(family.eq(the_family))
+      .first::<Login>(self.db_connection.get()?.deref())
+      .optional()?;
+    Ok(login)
   }
 
   fn update(&self, login: &Login) -> RepoResult<Login> {
-    diesel::update(logins::table.filter(id.eq(login.get_id())))
-      .set(token.eq(login.get_token()))
+    Self::save(self.db_connection.get()?.deref(), login)
+  }
+
+  fn find_by_jti(&self, the_jti: String) -> RepoResult<Option<Login>> {
+    let login = logins::table
+      .filter(jti.eq(the_jti))
+      .first::<Login>(self.db_connection.get()?.deref())
+      .optional()?;
+    Ok(login)
+  }
+
+  fn delete_by_jti(&self, the_jti: String) -> RepoResult<usize> {
+    let deleted = diesel::delete(logins::table.filter(jti.eq(the_jti)))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(deleted)
+  }
+
+  fn delete(&self, id_login: i32, the_user_id: i32) -> RepoResult<usize> {
+    let deleted = diesel::delete(
+      logins::table.filter(id.eq(id_login).and(user_id.eq(the_user_id))),
+    )
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(deleted)
+  }
+
+  fn delete_all(&self, the_user_id: i32) -> RepoResult<usize> {
+    let deleted = diesel::delete(logins::table.filter(user_id.eq(the_user_id)))
       .execute(self.db_connection.get()?.deref())?;
+    Ok(deleted)
+  }
 
-    self.find_by_natural_key(login.get_username(), login.get_token())
+  fn touch(&self, the_jti: String) -> RepoResult<usize> {
+    let updated = diesel::update(logins::table.filter(jti.eq(the_jti)))
+      .set(last_seen_at.eq(Utc::now().naive_utc()))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
   }
 }
@@ -1,15 +1,31 @@
-use std::{borrow::Borrow, ops::Deref};
+use std::{collections::HashMap, ops::Deref};
 
-use diesel::prelude::*;
+use chrono::Utc;
+use diesel::{
+  prelude::*,
+  sql_types::{BigInt, Integer, Text},
+};
 
+#[cfg(not(feature = "postgres"))]
+use crate::db::database::last_insert_id;
 use crate::{
+  db::database::{Backend, BackendConnection},
   model::{
-    message::{Message, NewMessage},
-    repository::error::RepoResult,
+    message::{Message, NewMessage, NewMessageRevision, SearchHit},
+    page::{Direction, Page, PageRequest},
+    receipt::NewReceipt,
+    repository::{
+      attachment_repository::attach,
+      error::{Error, RepoResult},
+      webhook_repository::enqueue,
+    },
+    webhook::{message_created, WebhookEvent},
   },
   schema::{
-    messages,
-    messages::{from, id},
+    message_receipts, message_revisions, messages,
+    messages::{
+      conversation_id, deleted_at, from, id, message, to, updated_at,
+    },
   },
   DbConnection,
 };
@@ -18,15 +34,25 @@ use mockall::automock;
 
 #[cfg_attr(test, automock)]
 pub trait MessageRepository {
-  /// Insert a message in the database
+  /// Insert a message in the database, with a receipt for each one of its
+  /// recipients, its attachments and its `message.created` event in the
+  /// webhook outbox, in the same transaction.
   ///
   /// # Arguments
   /// * `new_message` - The new message to be inserted.
+  /// * `recipients` - The ids of the users who receive the message.
+  /// * `attachments` - The ids of the pending attachments of the sender.
   ///
   /// # Return
   /// * The id of the message.
-  /// * A diesel error.
-  fn add(&self, new_message: NewMessage) -> RepoResult<i32>;
+  /// * NotFound if an attachment isn't a pending one of the sender.
+  /// * A repository error.
+  fn add(
+    &self,
+    new_message: NewMessage,
+    recipients: Vec<i32>,
+    attachments: Vec<i32>,
+  ) -> RepoResult<i32>;
 
   /// Retrieve a message from its id.
   ///
@@ -35,28 +61,272 @@ pub trait MessageRepository {
   ///
   /// # Return
   /// * The message struct.
-  /// * A diesel error.
+  /// * A repository error.
   fn get(&self, id_msg: i32) -> RepoResult<Message>;
 
-  /// Look for messages based on the parameters. The messages are return in
-  /// order descending by its ids.
+  /// Look for a page of the messages sent by a user. The messages are
+  /// ordered descending by its ids, the newest first.
   ///
   /// # Arguments
-  /// * `from_msg` - The id of the message from which start the search. Could
-  ///   not
-  /// be the id of a message from the user.
   /// * `from_user` - The id of the user to look the message for.
-  /// * `limit` - max quantity of retrieve message.
+  /// * `page` - The page to retrieve.
   ///
   /// # Return
-  /// * A sorted vector of message. Could be empty.
-  /// * A diesel error.
+  /// * The page of messages. Could be empty.
+  /// * A repository error.
   fn find(
     &self,
-    from_msg: i32,
     from_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>>;
+
+  /// Look for a page of the messages sent to a user. The messages are ordered
+  /// descending by its ids, the newest first.
+  ///
+  /// # Arguments
+  /// * `to_user` - The id of the recipient of the messages.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of messages. Could be empty.
+  /// * A repository error.
+  fn find_to(
+    &self,
+    to_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>>;
+
+  /// Look for the messages received by a user after the given one, direct
+  /// or of its group conversations. The messages are return in chronological
+  /// order, ascending by its ids.
+  ///
+  /// # Arguments
+  /// * `after_msg` - The id of the last message already received, excluded.
+  /// * `to_user` - The id of the recipient of the messages.
+  /// * `limit` - max quantity of retrieve message.
+  ///
+  /// # Return
+  /// * A sorted vector of message. Could be empty.
+  /// * A repository error.
+  fn find_received(
+    &self,
+    after_msg: i32,
+    to_user: i32,
     limit: i64,
   ) -> RepoResult<Vec<Message>>;
+
+  /// Look for a page of the messages exchanged between two users, in both
+  /// directions. The messages are ordered descending by its ids, the newest
+  /// first.
+  ///
+  /// # Arguments
+  /// * `user` - The id of one of the users.
+  /// * `other_user` - The id of the other user.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of messages. Could be empty.
+  /// * A repository error.
+  fn find_conversation(
+    &self,
+    user: i32,
+    other_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>>;
+
+  /// Look for a page of the messages of a group conversation. The messages
+  /// are ordered descending by its ids, the newest first.
+  ///
+  /// # Arguments
+  /// * `id_conv` - The id of the conversation.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of messages. Could be empty.
+  /// * A repository error.
+  fn find_in_conversation(
+    &self,
+    id_conv: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>>;
+
+  /// Look for the messages whose content has all the words of a search,
+  /// among the ones a user sent or received. The deleted messages aren't
+  /// searched.
+  ///
+  /// # Arguments
+  /// * `terms` - The words to look for.
+  /// * `uid` - The id of the user.
+  /// * `limit` - max quantity of retrieve message.
+  ///
+  /// # Return
+  /// * The messages with their snippets, the best match first. Could be empty.
+  /// * A repository error.
+  fn search(
+    &self,
+    terms: String,
+    uid: i32,
+    limit: i64,
+  ) -> RepoResult<Vec<SearchHit>>;
+
+  /// Replace the content of a message, keeping the previous one as a
+  /// revision. Both changes are made in the same transaction.
+  ///
+  /// # Arguments
+  /// * `msg` - The message to edit.
+  /// * `new_message` - The new content of the message.
+  ///
+  /// # Return
+  /// * The edited message.
+  /// * A repository error.
+  fn update(&self, msg: &Message, new_message: String) -> RepoResult<Message>;
+
+  /// Mark a message as deleted, its row is kept as a tombstone.
+  ///
+  /// # Arguments
+  /// * `id_msg` - The id of the message to delete.
+  ///
+  /// # Return
+  /// * The quantity of deleted messages, 0 if it was already deleted.
+  /// * A repository error.
+  fn soft_delete(&self, id_msg: i32) -> RepoResult<usize>;
+}
+
+/// Finds the messages, ranked by BM25, in the FTS5 index kept up to date by
+/// the triggers of `messages`. The deleted messages aren't indexed, and the
+/// messages of a group are only searched by its current members. The matched
+/// words are between `MARK_START` and `MARK_STOP`, see `highlight`.
+#[cfg(not(feature = "postgres"))]
+const SEARCH_QUERY: &str = r#"
+  SELECT "messages"."id" AS "id",
+    snippet("messages_fts", 0, char(2), char(3), '...', 16) AS "snippet"
+  FROM "messages_fts"
+  JOIN "messages" ON "messages"."id" = "messages_fts"."rowid"
+  WHERE "messages_fts" MATCH ?1
+    AND ("messages"."from" = ?2 OR "messages"."id" IN (
+      SELECT "message_id" FROM "message_receipts" WHERE "user_id" = ?2))
+    AND ("messages"."conversation_id" IS NULL
+      OR "messages"."conversation_id" IN (
+        SELECT "conversation_id" FROM "conversation_members"
+        WHERE "user_id" = ?2))
+  ORDER BY bm25("messages_fts")
+  LIMIT ?3"#;
+
+/// Finds the messages, ranked by `ts_rank`, with the `tsvector` of their
+/// content, the expression of the `messages_search` index. The messages of a
+/// group are only searched by its current members. The matched words are
+/// between `MARK_START` and `MARK_STOP`, see `highlight`.
+#[cfg(feature = "postgres")]
+const SEARCH_QUERY: &str = r#"
+  SELECT "id",
+    ts_headline('simple', "message", "query",
+      'StartSel=' || chr(2) || ', StopSel=' || chr(3)
+        || ', MaxWords=16, MinWords=8')
+      AS "snippet"
+  FROM "messages", plainto_tsquery('simple', $1) AS "query"
+  WHERE to_tsvector('simple', "message") @@ "query"
+    AND "deleted_at" IS NULL
+    AND ("from" = $2 OR "id" IN (
+      SELECT "message_id" FROM "message_receipts" WHERE "user_id" = $2))
+    AND ("conversation_id" IS NULL OR "conversation_id" IN (
+      SELECT "conversation_id" FROM "conversation_members"
+      WHERE "user_id" = $2))
+  ORDER BY ts_rank(to_tsvector('simple', "message"), "query") DESC
+  LIMIT $3"#;
+
+/// What the search query puts before a matched word, a control character
+/// rather than a tag so the content can be escaped around it.
+const MARK_START: char = '\u{2}';
+
+/// What the search query puts after a matched word.
+const MARK_STOP: char = '\u{3}';
+
+/// A row of the search query.
+#[derive(QueryableByName)]
+struct Match {
+  #[sql_type = "Integer"]
+  id: i32,
+  #[sql_type = "Text"]
+  snippet: String,
+}
+
+/// Turns the words of a search in a FTS5 query, all of them required. Every
+/// word is quoted, so its punctuation isn't taken as the syntax of FTS5, and
+/// the words without letters nor digits are left out.
+///
+/// # Arguments
+/// * `terms` - The words to look for.
+///
+/// # Return
+/// * The query, empty if there isn't any word to look for.
+#[cfg(not(feature = "postgres"))]
+fn search_terms(terms: &str) -> String {
+  terms
+    .split_whitespace()
+    .filter(|word| word.chars().any(char::is_alphanumeric))
+    .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+
+/// Escapes a snippet for HTML and puts its matched words between `<mark>`
+/// tags, so a message can't inject markup in the results. The markers typed
+/// in a message become tags too, which can't do more than highlight.
+///
+/// # Arguments
+/// * `snippet` - The snippet of the search query.
+///
+/// # Return
+/// * The snippet, safe to render as HTML.
+fn highlight(snippet: &str) -> String {
+  let mut html = String::with_capacity(snippet.len());
+  for character in snippet.chars() {
+    match character {
+      MARK_START => html.push_str("<mark>"),
+      MARK_STOP => html.push_str("</mark>"),
+      '&' => html.push_str("&amp;"),
+      '<' => html.push_str("&lt;"),
+      '>' => html.push_str("&gt;"),
+      '"' => html.push_str("&quot;"),
+      '\'' => html.push_str("&#39;"),
+      other => html.push(other),
+    }
+  }
+  html
+}
+
+/// PostgreSQL parses the words of a search with `plainto_tsquery`, which
+/// ignores the punctuation.
+///
+/// # Arguments
+/// * `terms` - The words to look for.
+///
+/// # Return
+/// * The words, trimmed.
+#[cfg(feature = "postgres")]
+fn search_terms(terms: &str) -> String {
+  terms.trim().to_string()
+}
+
+/// Restricts a query of messages to a page of a list ordered by id, the
+/// newest first.
+///
+/// # Arguments
+/// * `query` - The query of every message of the list.
+/// * `page` - The page to retrieve.
+///
+/// # Return
+/// * The query of the rows of the page, see `Page::from_rows`.
+fn newest_first(
+  query: messages::BoxedQuery<'static, Backend>,
+  page: PageRequest,
+) -> messages::BoxedQuery<'static, Backend> {
+  let query = match (page.get_key(), page.get_direction()) {
+    (None, _) => query.order(id.desc()),
+    (Some(key), Direction::After) => query.filter(id.lt(key)).order(id.desc()),
+    (Some(key), Direction::Before) => query.filter(id.gt(key)).order(id.asc()),
+  };
+  query.limit(page.get_fetch_size())
 }
 
 pub struct MessageRepositoryImpl {
@@ -70,30 +340,80 @@ impl MessageRepositoryImpl {
     }
   }
 
-  /// Look for the last inserted message from a specific user.
+  /// Insert a message and get it back, with its id and dates.
   ///
   /// # Arguments
-  /// * `from_user` - The id of the user to look for the message.
+  /// * `conn` - The connection of the transaction that inserts the message.
+  /// * `new_message` - The new message to be inserted.
   ///
   /// # Return
-  /// * The message struct.
-  /// * A diesel error.
-  fn find_latest_msg(&self, from_user: i32) -> RepoResult<Message> {
-    let msg = messages::table
-      .filter(from.eq(from_user))
-      .order(id.desc())
-      .first(self.db_connection.get()?.deref())?;
+  /// * The inserted message.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_message: &NewMessage,
+  ) -> RepoResult<Message> {
+    let msg = diesel::insert_into(messages::table)
+      .values(new_message)
+      .get_result(conn)?;
     Ok(msg)
   }
+
+  /// Insert a message and get it back, with its id and dates. SQLite can't
+  /// return the inserted row, so it reads the row of the id the connection
+  /// just inserted, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - The connection of the transaction that inserts the message.
+  /// * `new_message` - The new message to be inserted.
+  ///
+  /// # Return
+  /// * The inserted message.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_message: &NewMessage,
+  ) -> RepoResult<Message> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(messages::table)
+        .values(new_message)
+        .execute(conn)?;
+      let msg = messages::table.find(last_insert_id(conn)?).first(conn)?;
+      Ok(msg)
+    })
+  }
 }
 
 impl MessageRepository for MessageRepositoryImpl {
-  fn add(&self, new_message: NewMessage) -> RepoResult<i32> {
-    diesel::insert_into(messages::table)
-      .values(new_message.borrow())
-      .execute(self.db_connection.get()?.deref())?;
-    let msg = self.find_latest_msg(new_message.get_from())?;
-    Ok(msg.get_id())
+  fn add(
+    &self,
+    new_message: NewMessage,
+    recipients: Vec<i32>,
+    attachments: Vec<i32>,
+  ) -> RepoResult<i32> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let msg = Self::insert(&conn, &new_message)?;
+      let receipts = recipients
+        .iter()
+        .map(|recipient| NewReceipt::new(msg.get_id(), *recipient))
+        .collect::<Vec<NewReceipt>>();
+      diesel::insert_into(message_receipts::table)
+        .values(&receipts)
+        .execute(conn.deref())?;
+      attach(&conn, msg.get_from(), msg.get_id(), &attachments)?;
+      let mut audience = recipients;
+      audience.push(msg.get_from());
+      enqueue(
+        &conn,
+        WebhookEvent::MessageCreated,
+        &audience,
+        &message_created(&msg),
+      )?;
+      Ok(msg.get_id())
+    })
   }
 
   fn get(&self, id_msg: i32) -> RepoResult<Message> {
@@ -105,15 +425,415 @@ impl MessageRepository for MessageRepositoryImpl {
 
   fn find(
     &self,
-    from_msg: i32,
     from_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>> {
+    let query = messages::table.filter(from.eq(from_user)).into_boxed();
+    let messages =
+      newest_first(query, page).load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(messages, page, Message::get_id))
+  }
+
+  fn find_to(
+    &self,
+    to_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>> {
+    let query = messages::table.filter(to.eq(to_user)).into_boxed();
+    let messages =
+      newest_first(query, page).load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(messages, page, Message::get_id))
+  }
+
+  fn find_received(
+    &self,
+    after_msg: i32,
+    to_user: i32,
     limit: i64,
   ) -> RepoResult<Vec<Message>> {
     let messages = messages::table
-      .filter(id.ge(from_msg).and(from.eq(from_user)))
+      .inner_join(message_receipts::table)
+      .filter(id.gt(after_msg).and(message_receipts::user_id.eq(to_user)))
+      .select(messages::all_columns)
       .limit(limit)
-      .order(id.desc())
+      .order(id.asc())
       .load(self.db_connection.get()?.deref())?;
     Ok(messages)
   }
+
+  fn find_conversation(
+    &self,
+    user: i32,
+    other_user: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>> {
+    let sent = from.eq(user).and(to.eq(other_user));
+    let received = from.eq(other_user).and(to.eq(user));
+    let query = messages::table.filter(sent.or(received)).into_boxed();
+    let messages =
+      newest_first(query, page).load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(messages, page, Message::get_id))
+  }
+
+  fn find_in_conversation(
+    &self,
+    id_conv: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Message>> {
+    let query = messages::table
+      .filter(conversation_id.eq(id_conv))
+      .into_boxed();
+    let messages =
+      newest_first(query, page).load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(messages, page, Message::get_id))
+  }
+
+  fn search(
+    &self,
+    terms: String,
+    uid: i32,
+    limit: i64,
+  ) -> RepoResult<Vec<SearchHit>> {
+    let query = search_terms(&terms);
+    if query.is_empty() {
+      return Ok(vec![]);
+    }
+    let conn = self.db_connection.get()?;
+    let matches = diesel::sql_query(SEARCH_QUERY)
+      .bind::<Text, _>(query)
+      .bind::<Integer, _>(uid)
+      .bind::<BigInt, _>(limit)
+      .load::<Match>(conn.deref())?;
+    let mut found = messages::table
+      .filter(id.eq_any(matches.iter().map(|hit| hit.id).collect::<Vec<i32>>()))
+      .load::<Message>(conn.deref())?
+      .into_iter()
+      .map(|msg| (msg.get_id(), msg))
+      .collect::<HashMap<i32, Message>>();
+    let hits = matches
+      .into_iter()
+      .filter_map(|hit| {
+        let msg = found.remove(&hit.id)?;
+        Some(SearchHit::new(msg, highlight(&hit.snippet)))
+      })
+      .collect();
+    Ok(hits)
+  }
+
+  fn update(&self, msg: &Message, new_message: String) -> RepoResult<Message> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(message_revisions::table)
+        .values(NewMessageRevision::new(msg))
+        .execute(conn.deref())?;
+      let edited = diesel::update(messages::table.find(msg.get_id()))
+        .set((
+          message.eq(new_message),
+          updated_at.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn.deref())?;
+      if edited == 0 {
+        return Err(Error::NotFound);
+      }
+      let msg = messages::table
+        .find(msg.get_id())
+        .get_result(conn.deref())?;
+      Ok(msg)
+    })
+  }
+
+  fn soft_delete(&self, id_msg: i32) -> RepoResult<usize> {
+    let deleted = diesel::update(
+      messages::table.filter(id.eq(id_msg).and(deleted_at.is_null())),
+    )
+    .set(deleted_at.eq(Utc::now().naive_utc()))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(deleted)
+  }
+}
+
+#[cfg(all(test, not(feature = "postgres")))]
+mod tests {
+  use super::*;
+  use crate::{
+    db::database::TestDatabase,
+    model::{
+      conversation::NewConversation,
+      repository::{
+        conversation_repository::{
+          ConversationRepository, ConversationRepositoryImpl,
+        },
+        user_repository::{UserRepository, UserRepositoryImpl},
+      },
+      user::NewUser,
+    },
+  };
+  use std::{collections::HashSet, thread};
+
+  #[test]
+  fn add_messages_in_parallel() {
+    let db = TestDatabase::new("parallel-messages");
+    let users = UserRepositoryImpl::new(db.connection());
+    let sender = users
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let recipient = users
+      .add(NewUser::new(
+        String::from("recipient"),
+        String::from("hash"),
+      ))
+      .unwrap();
+
+    let threads = (0..8)
+      .map(|thread_nb| {
+        let repository = MessageRepositoryImpl::new(db.connection());
+        thread::spawn(move || {
+          (0..10)
+            .map(|msg_nb| {
+              let text = format!("message {}-{}", thread_nb, msg_nb);
+              let new_message =
+                NewMessage::new(sender, recipient, text.to_string());
+              let id_msg = repository
+                .add(new_message, vec![recipient], vec![])
+                .unwrap();
+              (id_msg, text)
+            })
+            .collect::<Vec<(i32, String)>>()
+        })
+      })
+      .collect::<Vec<thread::JoinHandle<Vec<(i32, String)>>>>();
+    let added = threads
+      .into_iter()
+      .flat_map(|handle| handle.join().unwrap())
+      .collect::<Vec<(i32, String)>>();
+
+    let repository = MessageRepositoryImpl::new(db.connection());
+    let ids = added
+      .iter()
+      .map(|(id_msg, _)| *id_msg)
+      .collect::<HashSet<i32>>();
+    assert_eq!(ids.len(), 80);
+    for (id_msg, text) in added {
+      assert_eq!(repository.get(id_msg).unwrap().get_message(), text);
+    }
+  }
+
+  #[test]
+  fn add_a_message_to_an_unknown_user() {
+    let db = TestDatabase::new("unknown-recipient");
+    let sender = UserRepositoryImpl::new(db.connection())
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+
+    let repository = MessageRepositoryImpl::new(db.connection());
+    let new_message = NewMessage::new(sender, 99, String::from("Hi"));
+    let added = repository.add(new_message, vec![99], vec![]);
+    assert!(matches!(added, Err(Error::ForeignKeyViolation(_))));
+  }
+
+  #[test]
+  fn find_the_pages_of_the_sent_messages() {
+    let db = TestDatabase::new("message-pages");
+    let users = UserRepositoryImpl::new(db.connection());
+    let sender = users
+      .add(NewUser::new(String::from("sender"), String::from("hash")))
+      .unwrap();
+    let recipient = users
+      .add(NewUser::new(
+        String::from("recipient"),
+        String::from("hash"),
+      ))
+      .unwrap();
+    let repository = MessageRepositoryImpl::new(db.connection());
+    let sent = (0..5)
+      .map(|msg_nb| {
+        let text = format!("message {}", msg_nb);
+        let new_message = NewMessage::new(sender, recipient, text);
+        repository
+          .add(new_message, vec![recipient], vec![])
+          .unwrap()
+      })
+      .collect::<Vec<i32>>();
+    let ids = |page: &Page<Message>| {
+      page
+        .get_items()
+        .iter()
+        .map(Message::get_id)
+        .collect::<Vec<i32>>()
+    };
+
+    let first = repository
+      .find(sender, PageRequest::first(Some(2)))
+      .unwrap();
+    assert_eq!(ids(&first), vec![sent[4], sent[3]]);
+    assert_eq!(first.get_prev_key(), None);
+
+    let next_key = first.get_next_key().unwrap();
+    let request = PageRequest::next_to(Direction::After, next_key, Some(2));
+    let second = repository.find(sender, request).unwrap();
+    assert_eq!(ids(&second), vec![sent[2], sent[1]]);
+
+    let next_key = second.get_next_key().unwrap();
+    let request = PageRequest::next_to(Direction::After, next_key, Some(2));
+    let last = repository.find(sender, request).unwrap();
+    assert_eq!(ids(&last), vec![sent[0]]);
+    assert_eq!(last.get_next_key(), None);
+
+    let prev_key = second.get_prev_key().unwrap();
+    let request = PageRequest::next_to(Direction::Before, prev_key, Some(2));
+    let back = repository.find(sender, request).unwrap();
+    assert_eq!(ids(&back), ids(&first));
+    assert_eq!(back.get_prev_key(), None);
+    assert!(repository
+      .find(recipient, PageRequest::first(None))
+      .unwrap()
+      .get_items()
+      .is_empty());
+  }
+
+  #[test]
+  fn find_a_conversation_by_pages() {
+    let db = TestDatabase::new("conversation-pages");
+    let users = UserRepositoryImpl::new(db.connection());
+    let alice = users
+      .add(NewUser::new(String::from("alice"), String::from("hash")))
+      .unwrap();
+    let bob = users
+      .add(NewUser::new(String::from("bob"), String::from("hash")))
+      .unwrap();
+    let carol = users
+      .add(NewUser::new(String::from("carol"), String::from("hash")))
+      .unwrap();
+    let repository = MessageRepositoryImpl::new(db.connection());
+    let send = |from_user: i32, to_user: i32| {
+      let new_message = NewMessage::new(from_user, to_user, String::from("Hi"));
+      repository.add(new_message, vec![to_user], vec![]).unwrap()
+    };
+    let first = send(alice, bob);
+    send(alice, carol);
+    let second = send(bob, alice);
+    let third = send(alice, bob);
+    let ids = |page: &Page<Message>| {
+      page
+        .get_items()
+        .iter()
+        .map(Message::get_id)
+        .collect::<Vec<i32>>()
+    };
+
+    let page = repository
+      .find_conversation(bob, alice, PageRequest::first(Some(2)))
+      .unwrap();
+    assert_eq!(ids(&page), vec![third, second]);
+    let next_key = page.get_next_key().unwrap();
+    let request = PageRequest::next_to(Direction::After, next_key, None);
+    let page = repository.find_conversation(alice, bob, request).unwrap();
+    assert_eq!(ids(&page), vec![first]);
+    assert_eq!(page.get_next_key(), None);
+  }
+
+  #[test]
+  fn search_terms_are_quoted() {
+    assert_eq!(search_terms("lunch  at noon"), "\"lunch\" \"at\" \"noon\"");
+    assert_eq!(
+      search_terms("say \"hi\" - OR"),
+      "\"say\" \"\"\"hi\"\"\" \"OR\""
+    );
+    assert_eq!(search_terms(" * - "), "");
+  }
+
+  #[test]
+  fn highlight_escapes_the_snippet() {
+    assert_eq!(
+      highlight("<b>\u{2}lunch\u{3}</b> & \"it's\""),
+      "&lt;b&gt;<mark>lunch</mark>&lt;/b&gt; &amp; &quot;it&#39;s&quot;"
+    );
+  }
+
+  #[test]
+  fn search_the_messages_of_the_user() {
+    let db = TestDatabase::new("search-messages");
+    let users = UserRepositoryImpl::new(db.connection());
+    let alice = users
+      .add(NewUser::new(String::from("alice"), String::from("hash")))
+      .unwrap();
+    let bob = users
+      .add(NewUser::new(String::from("bob"), String::from("hash")))
+      .unwrap();
+    let carol = users
+      .add(NewUser::new(String::from("carol"), String::from("hash")))
+      .unwrap();
+    let repository = MessageRepositoryImpl::new(db.connection());
+    let send = |from: i32, to: i32, text: &str| {
+      repository
+        .add(
+          NewMessage::new(from, to, String::from(text)),
+          vec![to],
+          vec![],
+        )
+        .unwrap()
+    };
+    let once = send(alice, bob, "The lunch is moved, see the new lunch place");
+    let twice = send(bob, alice, "lunch lunch lunch at noon?");
+    send(bob, carol, "A lunch for carol only");
+    let deleted = send(alice, bob, "Forget this lunch");
+    repository.soft_delete(deleted).unwrap();
+    let edited = send(bob, alice, "Some lunch typo");
+    let msg = repository.get(edited).unwrap();
+    repository
+      .update(&msg, String::from("Some dinner"))
+      .unwrap();
+
+    let hits = repository.search(String::from("LUNCH"), alice, 10).unwrap();
+    let ids = hits
+      .iter()
+      .map(|hit| hit.get_message().get_id())
+      .collect::<Vec<i32>>();
+    assert_eq!(ids, vec![twice, once]);
+    assert!(hits[0].get_snippet().contains("<mark>lunch</mark>"));
+
+    let hits = repository
+      .search(String::from("dinner"), alice, 10)
+      .unwrap();
+    assert_eq!(hits.len(), 1);
+    let hits = repository.search(String::from("-"), alice, 10).unwrap();
+    assert!(hits.is_empty());
+
+    let scripted = send(bob, alice, "<script>alert(1)</script> lunch");
+    let hits = repository.search(String::from("alert"), alice, 10).unwrap();
+    assert_eq!(hits[0].get_message().get_id(), scripted);
+    assert!(!hits[0].get_snippet().contains("<script>"));
+    assert!(hits[0].get_snippet().contains("&lt;script&gt;"));
+  }
+
+  #[test]
+  fn search_only_the_groups_of_the_user() {
+    let db = TestDatabase::new("search-groups");
+    let users = UserRepositoryImpl::new(db.connection());
+    let alice = users
+      .add(NewUser::new(String::from("alice"), String::from("hash")))
+      .unwrap();
+    let bob = users
+      .add(NewUser::new(String::from("bob"), String::from("hash")))
+      .unwrap();
+    let groups = ConversationRepositoryImpl::new(db.connection());
+    let group = groups
+      .add(NewConversation::new(String::from("team"), alice), vec![bob])
+      .unwrap();
+    let repository = MessageRepositoryImpl::new(db.connection());
+    repository
+      .add(
+        NewMessage::for_conversation(alice, group, String::from("Team lunch")),
+        vec![bob],
+        vec![],
+      )
+      .unwrap();
+
+    let hits = repository.search(String::from("lunch"), bob, 10).unwrap();
+    assert_eq!(hits.len(), 1);
+
+    groups.remove_member(group, bob).unwrap();
+    let hits = repository.search(String::from("lunch"), bob, 10).unwrap();
+    assert!(hits.is_empty());
+  }
 }
@@ -0,0 +1,281 @@
+use std::ops::Deref;
+
+use chrono::Utc;
+use diesel::{dsl::count_star, prelude::*};
+
+use crate::{
+  model::{
+    page::{Direction, Page, PageRequest},
+    receipt::{Receipt, UnreadCount},
+    repository::{
+      error::{Error, RepoResult},
+      webhook_repository::enqueue,
+    },
+    webhook::{message_read, WebhookEvent},
+  },
+  schema::{
+    message_receipts,
+    message_receipts::{delivered_at, message_id, read_at, user_id},
+    messages,
+  },
+  DbConnection,
+};
+
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait ReceiptRepository {
+  /// Look for the receipt of a message for one of its recipients.
+  ///
+  /// # Arguments
+  /// * `id_msg` - The id of the message.
+  /// * `uid` - The id of the recipient.
+  ///
+  /// # Return
+  /// * An Option for the receipt, none if the user isn't a recipient.
+  /// * A repository error.
+  fn get(&self, id_msg: i32, uid: i32) -> RepoResult<Option<Receipt>>;
+
+  /// Look for the receipts of many messages, for all their recipients.
+  ///
+  /// # Arguments
+  /// * `ids_msg` - The ids of the messages.
+  ///
+  /// # Return
+  /// * The receipts. Could be empty.
+  /// * A repository error.
+  fn find_by_messages(&self, ids_msg: Vec<i32>) -> RepoResult<Vec<Receipt>>;
+
+  /// Mark the messages as delivered to a recipient, the messages already
+  /// delivered keep their date.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the recipient.
+  /// * `ids_msg` - The ids of the messages.
+  ///
+  /// # Return
+  /// * The quantity of receipts updated.
+  /// * A repository error.
+  fn mark_delivered(&self, uid: i32, ids_msg: Vec<i32>) -> RepoResult<usize>;
+
+  /// Mark a message as read by a recipient, it's also marked as delivered if
+  /// it wasn't. The `message.read` event is written in the webhook outbox in
+  /// the same transaction.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the recipient.
+  /// * `id_msg` - The id of the message.
+  ///
+  /// # Return
+  /// * The quantity of receipts updated, 0 if it was already read.
+  /// * A repository error.
+  fn mark_read(&self, uid: i32, id_msg: i32) -> RepoResult<usize>;
+
+  /// Mark as read every direct message sent by a user to a recipient up to
+  /// the given message, included. A `message.read` event for each one is
+  /// written in the webhook outbox in the same transaction.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the recipient.
+  /// * `from_user` - The id of the sender.
+  /// * `until_msg` - The id of the last message to mark.
+  ///
+  /// # Return
+  /// * The quantity of receipts updated.
+  /// * A repository error.
+  fn mark_read_until(
+    &self,
+    uid: i32,
+    from_user: i32,
+    until_msg: i32,
+  ) -> RepoResult<usize>;
+
+  /// Count the unread messages of a recipient grouped by their sender, a
+  /// page of senders at a time. The deleted messages aren't counted.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the recipient.
+  /// * `page` - The page to retrieve, keyed by the id of the sender.
+  ///
+  /// # Return
+  /// * The page of counts ordered by sender. Could be empty.
+  /// * A repository error.
+  fn unread_counts(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<UnreadCount>>;
+}
+
+pub struct ReceiptRepositoryImpl {
+  db_connection: DbConnection,
+}
+
+impl ReceiptRepositoryImpl {
+  pub fn new(db_connection: DbConnection) -> Self {
+    ReceiptRepositoryImpl {
+      db_connection,
+    }
+  }
+}
+
+impl ReceiptRepository for ReceiptRepositoryImpl {
+  fn get(&self, id_msg: i32, uid: i32) -> RepoResult<Option<Receipt>> {
+    let receipt = message_receipts::table
+      .filter(message_id.eq(id_msg).and(user_id.eq(uid)))
+      .first::<Receipt>(self.db_connection.get()?.deref())
+      .optional()?;
+    Ok(receipt)
+  }
+
+  fn find_by_messages(&self, ids_msg: Vec<i32>) -> RepoResult<Vec<Receipt>> {
+    let receipts = message_receipts::table
+      .filter(message_id.eq_any(ids_msg))
+      .load(self.db_connection.get()?.deref())?;
+    Ok(receipts)
+  }
+
+  fn mark_delivered(&self, uid: i32, ids_msg: Vec<i32>) -> RepoResult<usize> {
+    let updated = diesel::update(
+      message_receipts::table.filter(
+        user_id
+          .eq(uid)
+          .and(message_id.eq_any(ids_msg))
+          .and(delivered_at.is_null()),
+      ),
+    )
+    .set(delivered_at.eq(Utc::now().naive_utc()))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn mark_read(&self, uid: i32, id_msg: i32) -> RepoResult<usize> {
+    let now = Utc::now().naive_utc();
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      diesel::update(
+        message_receipts::table.filter(
+          user_id
+            .eq(uid)
+            .and(message_id.eq(id_msg))
+            .and(delivered_at.is_null()),
+        ),
+      )
+      .set(delivered_at.eq(now))
+      .execute(conn.deref())?;
+      let updated = diesel::update(
+        message_receipts::table.filter(
+          user_id
+            .eq(uid)
+            .and(message_id.eq(id_msg))
+            .and(read_at.is_null()),
+        ),
+      )
+      .set(read_at.eq(now))
+      .execute(conn.deref())?;
+      if updated > 0 {
+        let sender = messages::table
+          .find(id_msg)
+          .select(messages::from)
+          .first::<i32>(conn.deref())?;
+        enqueue(
+          &conn,
+          WebhookEvent::MessageRead,
+          &[sender, uid],
+          &message_read(id_msg, sender, uid),
+        )?;
+      }
+      Ok(updated)
+    })
+  }
+
+  fn mark_read_until(
+    &self,
+    uid: i32,
+    from_user: i32,
+    until_msg: i32,
+  ) -> RepoResult<usize> {
+    let now = Utc::now().naive_utc();
+    let conn = self.db_connection.get()?;
+    let sent = || {
+      messages::table
+        .select(messages::id)
+        .filter(messages::from.eq(from_user).and(messages::id.le(until_msg)))
+        .filter(messages::conversation_id.is_null())
+    };
+    conn.transaction::<_, Error, _>(|| {
+      diesel::update(
+        message_receipts::table.filter(
+          user_id
+            .eq(uid)
+            .and(message_id.eq_any(sent()))
+            .and(delivered_at.is_null()),
+        ),
+      )
+      .set(delivered_at.eq(now))
+      .execute(conn.deref())?;
+      let unread = message_receipts::table
+        .select(message_id)
+        .filter(
+          user_id
+            .eq(uid)
+            .and(message_id.eq_any(sent()))
+            .and(read_at.is_null()),
+        )
+        .load::<i32>(conn.deref())?;
+      let updated = diesel::update(
+        message_receipts::table
+          .filter(user_id.eq(uid).and(message_id.eq_any(&unread))),
+      )
+      .set(read_at.eq(now))
+      .execute(conn.deref())?;
+      for id_msg in unread {
+        enqueue(
+          &conn,
+          WebhookEvent::MessageRead,
+          &[from_user, uid],
+          &message_read(id_msg, from_user, uid),
+        )?;
+      }
+      Ok(updated)
+    })
+  }
+
+  fn unread_counts(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<UnreadCount>> {
+    // Diesel can't box a grouped query, so the key bounds both sides.
+    let (after, before) = match (page.get_key(), page.get_direction()) {
+      (None, _) => (i32::MIN, i32::MAX),
+      (Some(key), Direction::After) => (key, i32::MAX),
+      (Some(key), Direction::Before) => (i32::MIN, key),
+    };
+    let query = message_receipts::table
+      .inner_join(messages::table)
+      .filter(
+        user_id
+          .eq(uid)
+          .and(read_at.is_null())
+          .and(messages::deleted_at.is_null())
+          .and(messages::from.gt(after))
+          .and(messages::from.lt(before)),
+      )
+      .group_by(messages::from)
+      .select((messages::from, count_star()))
+      .limit(page.get_fetch_size());
+    let conn = self.db_connection.get()?;
+    let counts = if page.is_backwards() {
+      query
+        .order(messages::from.desc())
+        .load::<UnreadCount>(conn.deref())?
+    } else {
+      query
+        .order(messages::from.asc())
+        .load::<UnreadCount>(conn.deref())?
+    };
+    Ok(Page::from_rows(counts, page, UnreadCount::get_from))
+  }
+}
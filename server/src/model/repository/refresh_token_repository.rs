@@ -0,0 +1,183 @@
+use std::ops::Deref;
+
+use chrono::Utc;
+use diesel::prelude::*;
+
+#[cfg(not(feature = "postgres"))]
+use crate::{db::database::last_insert_id, model::repository::error::Error};
+use crate::{
+  db::database::BackendConnection,
+  model::{
+    refresh_token::{NewRefreshToken, RefreshToken},
+    repository::error::RepoResult,
+  },
+  schema::{
+    refresh_tokens,
+    refresh_tokens::{family, id, revoked, token_hash, used_at, user_id},
+  },
+  DbConnection,
+};
+
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait RefreshTokenRepository {
+  /// Insert a refresh token in the database.
+  ///
+  /// # Arguments
+  /// * `new_token` - The new refresh token to be inserted.
+  ///
+  /// # Return
+  /// * The id of the refresh token.
+  /// * A repository error.
+  fn add(&self, new_token: NewRefreshToken) -> RepoResult<i32>;
+
+  /// Look for a refresh token from the hash of its value.
+  ///
+  /// # Arguments
+  /// * `the_hash` - The hash of the token to look for.
+  ///
+  /// # Return
+  /// * An Option for the refresh token struct.
+  /// * A repository error.
+  fn find(&self, the_hash: String) -> RepoResult<Option<RefreshToken>>;
+
+  /// Marks a refresh token as used, only if nobody has used it before.
+  ///
+  /// # Arguments
+  /// * `id_token` - The id of the refresh token.
+  ///
+  /// # Return
+  /// * True if this call marked the token, false if it was already used.
+  /// * A repository error.
+  fn mark_used(&self, id_token: i32) -> RepoResult<bool>;
+
+  /// Revokes every refresh token of a family, only if it belongs to the
+  /// given user.
+  ///
+  /// # Arguments
+  /// * `the_family` - The family to revoke.
+  /// * `the_user_id` - The owner of the family.
+  ///
+  /// # Return
+  /// * The number of revoked tokens, 0 if the family belongs to another user.
+  /// * A repository error.
+  fn revoke_family(
+    &self,
+    the_family: String,
+    the_user_id: i32,
+  ) -> RepoResult<usize>;
+
+  /// Revokes every refresh token of a user.
+  ///
+  /// # Arguments
+  /// * `the_user_id` - The owner of the tokens.
+  ///
+  /// # Return
+  /// * The number of revoked tokens.
+  /// * A repository error.
+  fn revoke_user(&self, the_user_id: i32) -> RepoResult<usize>;
+}
+
+pub struct RefreshTokenRepositoryImpl {
+  db_connection: DbConnection,
+}
+
+impl RefreshTokenRepositoryImpl {
+  pub fn new(db_connection: DbConnection) -> Self {
+    RefreshTokenRepositoryImpl {
+      db_connection,
+    }
+  }
+
+  /// Insert a refresh token and get its id.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_token` - The new refresh token to be inserted.
+  ///
+  /// # Return
+  /// * The id of the refresh token.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_token: &NewRefreshToken,
+  ) -> RepoResult<i32> {
+    let id_token = diesel::insert_into(refresh_tokens::table)
+      .values(new_token)
+      .returning(id)
+      .get_result(conn)?;
+    Ok(id_token)
+  }
+
+  /// Insert a refresh token and get its id. SQLite can't return the inserted
+  /// row, so it reads the id the connection just inserted, in the same
+  /// transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_token` - The new refresh token to be inserted.
+  ///
+  /// # Return
+  /// * The id of the refresh token.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_token: &NewRefreshToken,
+  ) -> RepoResult<i32> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(refresh_tokens::table)
+        .values(new_token)
+        .execute(conn)?;
+      last_insert_id(conn)
+    })
+  }
+}
+
+impl RefreshTokenRepository for RefreshTokenRepositoryImpl {
+  fn add(&self, new_token: NewRefreshToken) -> RepoResult<i32> {
+    Self::insert(self.db_connection.get()?.deref(), &new_token)
+  }
+
+  fn find(&self, the_hash: String) -> RepoResult<Option<RefreshToken>> {
+    let token = refresh_tokens::table
+      .filter(token_hash.eq(the_hash))
+      .first::<RefreshToken>(self.db_connection.get()?.deref())
+      .optional()?;
+    Ok(token)
+  }
+
+  fn mark_used(&self, id_token: i32) -> RepoResult<bool> {
+    let updated = diesel::update(
+      refresh_tokens::table.filter(id.eq(id_token).and(used_at.is_null())),
+    )
+    .set(used_at.eq(Utc::now().naive_utc()))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated == 1)
+  }
+
+  fn revoke_family(
+    &self,
+    the_family: String,
+    the_user_id: i32,
+  ) -> RepoResult<usize> {
+    let updated = diesel::update(
+      refresh_tokens::table
+        .filter(family.eq(the_family).and(user_id.eq(the_user_id))),
+    )
+    .set(revoked.eq(true))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn revoke_user(&self, the_user_id: i32) -> RepoResult<usize> {
+    let updated =
+      diesel::update(refresh_tokens::table.filter(user_id.eq(the_user_id)))
+        .set(revoked.eq(true))
+        .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+}
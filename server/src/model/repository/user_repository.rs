@@ -1,15 +1,23 @@
-use std::{borrow::Borrow, ops::Deref};
+use std::ops::Deref;
 
 use diesel::{dsl::count_star, prelude::*};
 
+#[cfg(not(feature = "postgres"))]
+use crate::db::database::last_insert_id;
 use crate::{
+  db::database::BackendConnection,
   model::{
-    repository::error::RepoResult,
+    page::{Direction, Page, PageRequest},
+    repository::{
+      error::{Error, RepoResult},
+      webhook_repository::enqueue,
+    },
     user::{NewUser, User},
+    webhook::{user_created, WebhookEvent},
   },
   schema::{
     users,
-    users::{hashed_password, username},
+    users::{hashed_password, id, suspended, username},
   },
   DbConnection,
 };
@@ -19,26 +27,72 @@ use mockall::automock;
 
 #[cfg_attr(test, automock)]
 pub trait UserRepository {
-  /// Insert a user in the database
+  /// Insert a user in the database, with its `user.created` event in the
+  /// webhook outbox in the same transaction.
   ///
   /// # Arguments
   /// * `new_user` - The new user to be inserted.
   ///
   /// # Return
   /// * The id of the user.
-  /// * A diesel error.
+  /// * A repository error.
   fn add(&self, new_user: NewUser) -> RepoResult<i32>;
 
-  /// Search a user by its parameters.
+  /// Retrieve a user from its id.
+  ///
+  /// # Arguments
+  /// * `id_user` - The id of the user to look for.
+  ///
+  /// # Return
+  /// * A user struct.
+  /// * A repository error.
+  fn get(&self, id_user: i32) -> RepoResult<User>;
+
+  /// Search a user by its username.
   ///
   /// # Arguments
   /// * `the_username` - The username of the user to look for.
-  /// * `password` - The hashed password of the user to look for.
   ///
   /// # Return
   /// * A user struct.
-  /// * A diesel error.
-  fn find(&self, the_username: String, password: String) -> RepoResult<User>;
+  /// * A repository error.
+  fn find(&self, the_username: String) -> RepoResult<User>;
+
+  /// Replace the stored password hash of a user.
+  ///
+  /// # Arguments
+  /// * `id_user` - The id of the user to update.
+  /// * `password` - The new hashed password.
+  ///
+  /// # Return
+  /// * Nothing if the update was successful.
+  /// * A repository error.
+  fn update_password(&self, id_user: i32, password: String) -> RepoResult<()>;
+
+  /// Retrieve a page of every user ordered by id.
+  ///
+  /// # Arguments
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of users. Could be empty.
+  /// * A repository error.
+  fn all(&self, page: PageRequest) -> RepoResult<Page<User>>;
+
+  /// Suspends or reinstates a user.
+  ///
+  /// # Arguments
+  /// * `id_user` - The id of the user to update.
+  /// * `is_suspended` - True to suspend the user, false to reinstate it.
+  ///
+  /// # Return
+  /// * The number of updated users.
+  /// * A repository error.
+  fn update_suspended(
+    &self,
+    id_user: i32,
+    is_suspended: bool,
+  ) -> RepoResult<usize>;
 
   /// Get the total number of users in the database.
   ///
@@ -46,7 +100,7 @@ pub trait UserRepository {
   ///
   /// # Return
   /// * The number of users.
-  /// * A diesel error.
+  /// * A repository error.
   fn total(&self) -> RepoResult<i64>;
 }
 
@@ -60,26 +114,109 @@ impl UserRepositoryImpl {
       db_connection,
     }
   }
+
+  /// Insert a user and get it back, with its id.
+  ///
+  /// # Arguments
+  /// * `conn` - The connection of the transaction that inserts the user.
+  /// * `new_user` - The new user to be inserted.
+  ///
+  /// # Return
+  /// * The inserted user.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(conn: &BackendConnection, new_user: &NewUser) -> RepoResult<User> {
+    let user = diesel::insert_into(users::table)
+      .values(new_user)
+      .get_result(conn)?;
+    Ok(user)
+  }
+
+  /// Insert a user and get it back, with its id. SQLite can't return the
+  /// inserted row, so it reads the row of the id the connection just
+  /// inserted, in the same transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - The connection of the transaction that inserts the user.
+  /// * `new_user` - The new user to be inserted.
+  ///
+  /// # Return
+  /// * The inserted user.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(conn: &BackendConnection, new_user: &NewUser) -> RepoResult<User> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(users::table)
+        .values(new_user)
+        .execute(conn)?;
+      let user = users::table.find(last_insert_id(conn)?).first(conn)?;
+      Ok(user)
+    })
+  }
 }
 
 impl UserRepository for UserRepositoryImpl {
   fn add(&self, new_user: NewUser) -> RepoResult<i32> {
-    diesel::insert_into(users::table)
-      .values(new_user.borrow())
-      .execute(self.db_connection.get()?.deref())?;
-    let user: User = users::table
-      .filter(username.eq(new_user.get_username()))
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let user = Self::insert(&conn, &new_user)?;
+      enqueue(
+        &conn,
+        WebhookEvent::UserCreated,
+        &[],
+        &user_created(user.get_id(), &user.get_username()),
+      )?;
+      Ok(user.get_id())
+    })
+  }
+
+  fn get(&self, id_user: i32) -> RepoResult<User> {
+    let user = users::table
+      .find(id_user)
       .get_result(self.db_connection.get()?.deref())?;
-    Ok(user.get_id())
+    Ok(user)
   }
 
-  fn find(&self, the_username: String, password: String) -> RepoResult<User> {
+  fn find(&self, the_username: String) -> RepoResult<User> {
     let user = users::table
-      .filter(username.eq(the_username).and(hashed_password.eq(password)))
+      .filter(username.eq(the_username))
       .first(self.db_connection.get()?.deref())?;
     Ok(user)
   }
 
+  fn update_password(&self, id_user: i32, password: String) -> RepoResult<()> {
+    diesel::update(users::table.filter(id.eq(id_user)))
+      .set(hashed_password.eq(password))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(())
+  }
+
+  fn all(&self, page: PageRequest) -> RepoResult<Page<User>> {
+    let query = users::table.into_boxed();
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(id.asc()),
+      (Some(key), Direction::After) => query.filter(id.gt(key)).order(id.asc()),
+      (Some(key), Direction::Before) => {
+        query.filter(id.lt(key)).order(id.desc())
+      },
+    };
+    let users = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(users, page, User::get_id))
+  }
+
+  fn update_suspended(
+    &self,
+    id_user: i32,
+    is_suspended: bool,
+  ) -> RepoResult<usize> {
+    let updated = diesel::update(users::table.filter(id.eq(id_user)))
+      .set(suspended.eq(is_suspended))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
   fn total(&self) -> RepoResult<i64> {
     let size = users::table
       .select(count_star())
@@ -87,3 +224,23 @@ impl UserRepository for UserRepositoryImpl {
     Ok(size)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::db::database::establish_connection;
+
+  #[test]
+  fn add_and_find_a_user() {
+    let repository =
+      UserRepositoryImpl::new(DbConnection::new(establish_connection()));
+
+    let id_user = repository
+      .add(NewUser::new(String::from("someone"), String::from("hash")))
+      .unwrap();
+
+    let user = repository.find(String::from("someone")).unwrap();
+    assert_eq!(user.get_id(), id_user);
+    assert_eq!(repository.total().unwrap(), 1);
+  }
+}
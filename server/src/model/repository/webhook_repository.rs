@@ -0,0 +1,417 @@
+use std::ops::Deref;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+#[cfg(not(feature = "postgres"))]
+use crate::db::database::last_insert_id;
+use crate::{
+  db::database::BackendConnection,
+  model::{
+    page::{Direction, Page, PageRequest},
+    repository::error::{Error, RepoResult},
+    role::Role,
+    webhook::{Delivery, NewDelivery, NewWebhook, Webhook, WebhookEvent},
+  },
+  schema::{
+    users, webhook_outbox,
+    webhook_outbox::{
+      attempts, dead_at, delivered_at, last_error, next_attempt_at, webhook_id,
+    },
+    webhooks,
+    webhooks::{id, user_id},
+  },
+  DbConnection,
+};
+#[cfg(test)]
+use mockall::automock;
+
+/// The columns of a delivery, without its schedule.
+const DELIVERY_COLUMNS: (
+  webhook_outbox::id,
+  webhook_id,
+  webhook_outbox::event,
+  webhook_outbox::payload,
+  attempts,
+  last_error,
+  dead_at,
+  webhook_outbox::created_at,
+) = (
+  webhook_outbox::id,
+  webhook_id,
+  webhook_outbox::event,
+  webhook_outbox::payload,
+  attempts,
+  last_error,
+  dead_at,
+  webhook_outbox::created_at,
+);
+
+#[cfg_attr(test, automock)]
+pub trait WebhookRepository {
+  /// Insert a webhook in the database.
+  ///
+  /// # Arguments
+  /// * `new_webhook` - The webhook to be inserted.
+  ///
+  /// # Return
+  /// * The id of the webhook.
+  /// * A repository error.
+  fn add(&self, new_webhook: NewWebhook) -> RepoResult<i32>;
+
+  /// Look for a page of the webhooks of a user, ordered by id.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the owner.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of webhooks. Could be empty.
+  /// * A repository error.
+  fn find_by_user(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Webhook>>;
+
+  /// Delete a webhook of a user with its pending and dead deliveries, in the
+  /// same transaction.
+  ///
+  /// # Arguments
+  /// * `id_hook` - The id of the webhook.
+  /// * `uid` - The id of the owner.
+  ///
+  /// # Return
+  /// * The quantity of webhooks deleted, 0 if the user doesn't own it.
+  /// * A repository error.
+  fn remove(&self, id_hook: i32, uid: i32) -> RepoResult<usize>;
+
+  /// Look for the deliveries due at the given date, with their webhooks. The
+  /// oldest come first.
+  ///
+  /// # Arguments
+  /// * `now` - The current date.
+  /// * `limit` - max quantity of retrieve deliveries.
+  ///
+  /// # Return
+  /// * A vector of deliveries and their webhooks. Could be empty.
+  /// * A repository error.
+  fn find_due(
+    &self,
+    now: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<(Delivery, Webhook)>>;
+
+  /// Mark a delivery as sent.
+  ///
+  /// # Arguments
+  /// * `id_delivery` - The id of the delivery.
+  ///
+  /// # Return
+  /// * The quantity of deliveries updated.
+  /// * A repository error.
+  fn mark_delivered(&self, id_delivery: i32) -> RepoResult<usize>;
+
+  /// Count a failed attempt of a delivery and schedule the next one.
+  ///
+  /// # Arguments
+  /// * `id_delivery` - The id of the delivery.
+  /// * `next_attempt` - The date of the next attempt.
+  /// * `error` - Why the attempt failed.
+  ///
+  /// # Return
+  /// * The quantity of deliveries updated.
+  /// * A repository error.
+  fn reschedule(
+    &self,
+    id_delivery: i32,
+    next_attempt: NaiveDateTime,
+    error: String,
+  ) -> RepoResult<usize>;
+
+  /// Count the last failed attempt of a delivery and move it to the
+  /// dead-letter list.
+  ///
+  /// # Arguments
+  /// * `id_delivery` - The id of the delivery.
+  /// * `error` - Why the attempt failed.
+  ///
+  /// # Return
+  /// * The quantity of deliveries updated.
+  /// * A repository error.
+  fn mark_dead(&self, id_delivery: i32, error: String) -> RepoResult<usize>;
+
+  /// Look for a page of the dead deliveries of the webhooks of a user. The
+  /// newest come first.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the owner of the webhooks.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * The page of deliveries. Could be empty.
+  /// * A repository error.
+  fn find_dead(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Delivery>>;
+
+  /// Move a dead delivery of a webhook of a user back to the outbox, to be
+  /// sent right away.
+  ///
+  /// # Arguments
+  /// * `id_delivery` - The id of the delivery.
+  /// * `uid` - The id of the owner of the webhook.
+  ///
+  /// # Return
+  /// * The quantity of deliveries updated, 0 if it isn't a dead delivery of the
+  ///   user.
+  /// * A repository error.
+  fn retry(&self, id_delivery: i32, uid: i32) -> RepoResult<usize>;
+}
+
+/// Writes an event in the outbox for the webhooks subscribed to it, the ones
+/// of the given users, or of the admins for the events that go to them. It
+/// takes the connection of the transaction that made the change, so the event
+/// is only sent if the change is committed.
+///
+/// # Arguments
+/// * `conn` - The connection of the transaction.
+/// * `event` - The event.
+/// * `audience` - The ids of the users concerned by the event, the participants
+///   of a message.
+/// * `payload` - The body to send.
+///
+/// # Return
+/// * The quantity of deliveries written.
+/// * A repository error.
+pub fn enqueue(
+  conn: &BackendConnection,
+  event: WebhookEvent,
+  audience: &[i32],
+  payload: &str,
+) -> RepoResult<usize> {
+  let recipients = if event.for_admins() {
+    users::table
+      .select(users::id)
+      .filter(users::role.eq(Role::Admin.as_str()))
+      .load::<i32>(conn)?
+  } else {
+    audience.to_vec()
+  };
+  let subscribed = webhooks::table
+    .filter(user_id.eq_any(recipients))
+    .load::<Webhook>(conn)?
+    .into_iter()
+    .filter(|webhook| webhook.subscribes(event))
+    .map(|webhook| NewDelivery::new(webhook.get_id(), event, payload))
+    .collect::<Vec<NewDelivery>>();
+  if subscribed.is_empty() {
+    return Ok(0);
+  }
+  let written = diesel::insert_into(webhook_outbox::table)
+    .values(&subscribed)
+    .execute(conn)?;
+  Ok(written)
+}
+
+pub struct WebhookRepositoryImpl {
+  db_connection: DbConnection,
+}
+
+impl WebhookRepositoryImpl {
+  pub fn new(db_connection: DbConnection) -> Self {
+    WebhookRepositoryImpl {
+      db_connection,
+    }
+  }
+
+  /// Insert a webhook and get its id.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_webhook` - The webhook to be inserted.
+  ///
+  /// # Return
+  /// * The id of the webhook.
+  /// * A repository error.
+  #[cfg(feature = "postgres")]
+  fn insert(
+    conn: &BackendConnection,
+    new_webhook: &NewWebhook,
+  ) -> RepoResult<i32> {
+    let id_hook = diesel::insert_into(webhooks::table)
+      .values(new_webhook)
+      .returning(id)
+      .get_result(conn)?;
+    Ok(id_hook)
+  }
+
+  /// Insert a webhook and get its id. SQLite can't return the inserted row,
+  /// so it reads the id the connection just inserted, in the same
+  /// transaction.
+  ///
+  /// # Arguments
+  /// * `conn` - A connection to the database.
+  /// * `new_webhook` - The webhook to be inserted.
+  ///
+  /// # Return
+  /// * The id of the webhook.
+  /// * A repository error.
+  #[cfg(not(feature = "postgres"))]
+  fn insert(
+    conn: &BackendConnection,
+    new_webhook: &NewWebhook,
+  ) -> RepoResult<i32> {
+    conn.transaction::<_, Error, _>(|| {
+      diesel::insert_into(webhooks::table)
+        .values(new_webhook)
+        .execute(conn)?;
+      last_insert_id(conn)
+    })
+  }
+}
+
+impl WebhookRepository for WebhookRepositoryImpl {
+  fn add(&self, new_webhook: NewWebhook) -> RepoResult<i32> {
+    Self::insert(self.db_connection.get()?.deref(), &new_webhook)
+  }
+
+  fn find_by_user(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Webhook>> {
+    let query = webhooks::table.filter(user_id.eq(uid)).into_boxed();
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(id.asc()),
+      (Some(key), Direction::After) => query.filter(id.gt(key)).order(id.asc()),
+      (Some(key), Direction::Before) => {
+        query.filter(id.lt(key)).order(id.desc())
+      },
+    };
+    let webhooks = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(webhooks, page, Webhook::get_id))
+  }
+
+  fn remove(&self, id_hook: i32, uid: i32) -> RepoResult<usize> {
+    let conn = self.db_connection.get()?;
+    conn.transaction::<_, Error, _>(|| {
+      let owned = webhooks::table
+        .filter(id.eq(id_hook).and(user_id.eq(uid)))
+        .select(id);
+      diesel::delete(webhook_outbox::table.filter(webhook_id.eq_any(owned)))
+        .execute(conn.deref())?;
+      let deleted = diesel::delete(
+        webhooks::table.filter(id.eq(id_hook).and(user_id.eq(uid))),
+      )
+      .execute(conn.deref())?;
+      Ok(deleted)
+    })
+  }
+
+  fn find_due(
+    &self,
+    now: NaiveDateTime,
+    limit: i64,
+  ) -> RepoResult<Vec<(Delivery, Webhook)>> {
+    let due = webhook_outbox::table
+      .inner_join(webhooks::table)
+      .filter(
+        delivered_at
+          .is_null()
+          .and(dead_at.is_null())
+          .and(next_attempt_at.le(now)),
+      )
+      .select((DELIVERY_COLUMNS, webhooks::all_columns))
+      .order(next_attempt_at.asc())
+      .limit(limit)
+      .load(self.db_connection.get()?.deref())?;
+    Ok(due)
+  }
+
+  fn mark_delivered(&self, id_delivery: i32) -> RepoResult<usize> {
+    let updated = diesel::update(webhook_outbox::table.find(id_delivery))
+      .set((
+        attempts.eq(attempts + 1),
+        delivered_at.eq(Utc::now().naive_utc()),
+      ))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn reschedule(
+    &self,
+    id_delivery: i32,
+    next_attempt: NaiveDateTime,
+    error: String,
+  ) -> RepoResult<usize> {
+    let updated = diesel::update(webhook_outbox::table.find(id_delivery))
+      .set((
+        attempts.eq(attempts + 1),
+        next_attempt_at.eq(next_attempt),
+        last_error.eq(error),
+      ))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn mark_dead(&self, id_delivery: i32, error: String) -> RepoResult<usize> {
+    let updated = diesel::update(webhook_outbox::table.find(id_delivery))
+      .set((
+        attempts.eq(attempts + 1),
+        dead_at.eq(Utc::now().naive_utc()),
+        last_error.eq(error),
+      ))
+      .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+
+  fn find_dead(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> RepoResult<Page<Delivery>> {
+    let query = webhook_outbox::table
+      .inner_join(webhooks::table)
+      .filter(user_id.eq(uid).and(dead_at.is_not_null()))
+      .select(DELIVERY_COLUMNS)
+      .into_boxed();
+    let delivery_id = webhook_outbox::id;
+    let query = match (page.get_key(), page.get_direction()) {
+      (None, _) => query.order(delivery_id.desc()),
+      (Some(key), Direction::After) => {
+        query.filter(delivery_id.lt(key)).order(delivery_id.desc())
+      },
+      (Some(key), Direction::Before) => {
+        query.filter(delivery_id.gt(key)).order(delivery_id.asc())
+      },
+    };
+    let dead = query
+      .limit(page.get_fetch_size())
+      .load(self.db_connection.get()?.deref())?;
+    Ok(Page::from_rows(dead, page, Delivery::get_id))
+  }
+
+  fn retry(&self, id_delivery: i32, uid: i32) -> RepoResult<usize> {
+    let owned = webhooks::table.filter(user_id.eq(uid)).select(id);
+    let updated = diesel::update(
+      webhook_outbox::table.filter(
+        webhook_outbox::id
+          .eq(id_delivery)
+          .and(dead_at.is_not_null())
+          .and(webhook_id.eq_any(owned)),
+      ),
+    )
+    .set((
+      attempts.eq(0),
+      next_attempt_at.eq(Utc::now().naive_utc()),
+      dead_at.eq(None::<NaiveDateTime>),
+    ))
+    .execute(self.db_connection.get()?.deref())?;
+    Ok(updated)
+  }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The role of a user, it's stored with the user and embedded in the access
+/// tokens.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+  #[default]
+  User,
+  Admin,
+}
+
+impl Role {
+  /// Checks if this role is enough for an endpoint that requires another one.
+  /// An admin can do everything a user can.
+  ///
+  /// # Arguments
+  /// * `required` - The role required.
+  ///
+  /// # Return
+  /// * True if the role satisfies the required one.
+  pub fn grants(&self, required: Role) -> bool {
+    match required {
+      Role::User => true,
+      Role::Admin => *self == Role::Admin,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Role::User => "user",
+      Role::Admin => "admin",
+    }
+  }
+}
+
+impl fmt::Display for Role {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for Role {
+  type Err = String;
+
+  fn from_str(role: &str) -> Result<Self, Self::Err> {
+    match role {
+      "user" => Ok(Role::User),
+      "admin" => Ok(Role::Admin),
+      other => Err(format!("Unknown role {}", other)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn admin_grants_every_role() {
+    assert!(Role::Admin.grants(Role::Admin));
+    assert!(Role::Admin.grants(Role::User));
+    assert!(Role::User.grants(Role::User));
+    assert!(!Role::User.grants(Role::Admin));
+  }
+
+  #[test]
+  fn parse_role() {
+    assert_eq!("admin".parse::<Role>(), Ok(Role::Admin));
+    assert_eq!(Role::User.to_string(), "user");
+    assert!("root".parse::<Role>().is_err());
+  }
+}
@@ -0,0 +1,191 @@
+use crate::schema::thumbnails;
+
+use diesel::{Identifiable, Insertable, Queryable};
+use std::{fmt, str::FromStr};
+
+/// The sizes of the thumbnails of an image. A thumbnail fits in a square of
+/// its side and keeps the proportions of the image, a smaller image isn't
+/// enlarged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailSize {
+  Small,
+  Medium,
+  Large,
+}
+
+impl ThumbnailSize {
+  /// Every size, an image gets a thumbnail of each one.
+  pub const ALL: [ThumbnailSize; 3] = [
+    ThumbnailSize::Small,
+    ThumbnailSize::Medium,
+    ThumbnailSize::Large,
+  ];
+
+  /// The side of the square the thumbnail fits in, in pixels.
+  pub fn max_side(&self) -> u32 {
+    match self {
+      ThumbnailSize::Small => 128,
+      ThumbnailSize::Medium => 512,
+      ThumbnailSize::Large => 1024,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ThumbnailSize::Small => "small",
+      ThumbnailSize::Medium => "medium",
+      ThumbnailSize::Large => "large",
+    }
+  }
+}
+
+impl fmt::Display for ThumbnailSize {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for ThumbnailSize {
+  type Err = String;
+
+  fn from_str(size: &str) -> Result<Self, Self::Err> {
+    match size {
+      "small" => Ok(ThumbnailSize::Small),
+      "medium" => Ok(ThumbnailSize::Medium),
+      "large" => Ok(ThumbnailSize::Large),
+      other => Err(format!("Unknown thumbnail size {}", other)),
+    }
+  }
+}
+
+/// The state of the thumbnails of an image, they are generated in the
+/// background after the upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailState {
+  Pending,
+  Ready,
+  Failed,
+}
+
+impl ThumbnailState {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ThumbnailState::Pending => "pending",
+      ThumbnailState::Ready => "ready",
+      ThumbnailState::Failed => "failed",
+    }
+  }
+}
+
+impl FromStr for ThumbnailState {
+  type Err = String;
+
+  fn from_str(state: &str) -> Result<Self, Self::Err> {
+    match state {
+      "pending" => Ok(ThumbnailState::Pending),
+      "ready" => Ok(ThumbnailState::Ready),
+      "failed" => Ok(ThumbnailState::Failed),
+      other => Err(format!("Unknown thumbnail state {}", other)),
+    }
+  }
+}
+
+/// A reduced copy of an image attachment. Its content is in the blob store
+/// under its hash, like the attachments.
+#[derive(Identifiable, Queryable, Clone, Debug, PartialEq)]
+pub struct Thumbnail {
+  id: i32,
+  attachment_id: i32,
+  size: String,
+  hash: String,
+  mime_type: String,
+  width: i32,
+  height: i32,
+}
+
+impl Thumbnail {
+  /// The SHA-256 of the content, in hex, its key in the blob store.
+  pub fn get_hash(&self) -> String {
+    return self.hash.to_string();
+  }
+
+  pub fn get_mime_type(&self) -> String {
+    return self.mime_type.to_string();
+  }
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[table_name = "thumbnails"]
+pub struct NewThumbnail {
+  attachment_id: i32,
+  size: String,
+  hash: String,
+  mime_type: String,
+  width: i32,
+  height: i32,
+}
+
+impl NewThumbnail {
+  pub fn new(
+    the_attachment_id: i32,
+    the_size: ThumbnailSize,
+    the_hash: String,
+    the_mime_type: String,
+    the_width: i32,
+    the_height: i32,
+  ) -> NewThumbnail {
+    NewThumbnail {
+      attachment_id: the_attachment_id,
+      size: the_size.to_string(),
+      hash: the_hash,
+      mime_type: the_mime_type,
+      width: the_width,
+      height: the_height,
+    }
+  }
+
+  #[cfg(test)]
+  pub fn dimensions(&self) -> (i32, i32) {
+    (self.width, self.height)
+  }
+}
+
+#[cfg(test)]
+pub struct Builder {
+  attachment_id: i32,
+  size: ThumbnailSize,
+  hash: String,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      attachment_id: 0,
+      size: ThumbnailSize::Small,
+      hash: crate::model::attachment::content_hash(b"thumbnail"),
+    }
+  }
+
+  pub fn with_attachment_id(mut self, the_attachment_id: i32) -> Builder {
+    self.attachment_id = the_attachment_id;
+    self
+  }
+
+  pub fn with_size(mut self, the_size: ThumbnailSize) -> Builder {
+    self.size = the_size;
+    self
+  }
+
+  pub fn build(&self) -> Thumbnail {
+    Thumbnail {
+      id: 0,
+      attachment_id: self.attachment_id,
+      size: self.size.to_string(),
+      hash: self.hash.to_string(),
+      mime_type: String::from("image/jpeg"),
+      width: self.size.max_side() as i32,
+      height: self.size.max_side() as i32 / 2,
+    }
+  }
+}
@@ -1,4 +1,4 @@
-use crate::schema::users;
+use crate::{model::role::Role, schema::users};
 
 use diesel::{Identifiable, Insertable, Queryable};
 use serde::{Deserialize, Serialize};
@@ -8,6 +8,8 @@ pub struct User {
   id: i32,
   username: String,
   hashed_password: String,
+  role: String,
+  suspended: bool,
 }
 
 impl User {
@@ -18,6 +20,19 @@ impl User {
   pub fn get_username(&self) -> String {
     return self.username.to_string();
   }
+
+  pub fn get_hashed_password(&self) -> String {
+    return self.hashed_password.to_string();
+  }
+
+  /// The role of the user, an unknown stored value is taken as a plain user.
+  pub fn get_role(&self) -> Role {
+    return self.role.parse().unwrap_or_default();
+  }
+
+  pub fn is_suspended(&self) -> bool {
+    return self.suspended;
+  }
 }
 
 #[derive(Insertable, Deserialize)]
@@ -35,10 +50,6 @@ impl NewUser {
     }
   }
 
-  pub fn get_username(&self) -> String {
-    return self.username.to_string();
-  }
-
   pub fn get_password(&self) -> String {
     return self.hashed_password.to_string();
   }
@@ -49,6 +60,8 @@ pub struct Builder {
   id: Option<i32>,
   username: Option<String>,
   hashed_password: Option<String>,
+  role: Option<Role>,
+  suspended: bool,
 }
 
 #[cfg(test)]
@@ -58,6 +71,8 @@ impl Builder {
       id: None,
       username: None,
       hashed_password: None,
+      role: None,
+      suspended: false,
     }
   }
 
@@ -76,11 +91,23 @@ impl Builder {
     self
   }
 
+  pub fn with_role(mut self, role: Role) -> Builder {
+    self.role = Some(role);
+    self
+  }
+
+  pub fn with_suspended(mut self, suspended: bool) -> Builder {
+    self.suspended = suspended;
+    self
+  }
+
   pub fn build(&self) -> User {
     User {
       id: *self.id.as_ref().unwrap_or(&0),
       username: String::from(self.username.as_deref().unwrap()),
       hashed_password: String::from(self.hashed_password.as_deref().unwrap()),
+      role: self.role.unwrap_or_default().to_string(),
+      suspended: self.suspended,
     }
   }
 }
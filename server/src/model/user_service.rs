@@ -1,10 +1,19 @@
-use std::borrow::Borrow;
+use std::{
+  borrow::Borrow,
+  sync::{Mutex, PoisonError},
+};
+
+use chrono::Utc;
 
 use crate::model::{
-  error::ServiceResult,
-  login::{Login, NewLogin},
+  error::{Error, ServiceResult},
+  login::{Device, Login, NewLogin},
+  page::{Page, PageRequest},
+  refresh_token::{generate_token, hash_token, NewRefreshToken},
   repository::{
-    login_repository::LoginRepository, user_repository::UserRepository,
+    error::Error as RepoError, login_repository::LoginRepository,
+    refresh_token_repository::RefreshTokenRepository,
+    user_repository::UserRepository,
   },
   user::{NewUser, User},
 };
@@ -31,6 +40,9 @@ pub trait UserService: Sync + Send {
   ) -> ServiceResult<i32>;
 
   /// Finds and return an existing user if the username and password matchs.
+  /// If the stored hash was made with an outdated algorithm, it's replaced with
+  /// a new one calculated from the given password. Suspended users are
+  /// rejected.
   ///
   /// # Arguments
   /// * `username` - A string that represents the username.
@@ -44,16 +56,159 @@ pub trait UserService: Sync + Send {
     password: String,
   ) -> ServiceResult<User>;
 
-  /// Creates or updates the a login for a specific user.
+  /// Retrieve a user from its id.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the user.
+  ///
+  /// # Return
+  /// * A User struct from the database.
+  /// * An error instead.
+  fn get_user(&self, id: i32) -> ServiceResult<User>;
+
+  /// Get a page of the registered users.
+  ///
+  /// # Arguments
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of users ordered by id. Could be empty.
+  /// * An error instead.
+  fn users(&self, page: PageRequest) -> ServiceResult<Page<User>>;
+
+  /// Suspends or reinstates a user. Suspending a user closes all of its
+  /// sessions and revokes its refresh tokens, and it can't login until it's
+  /// reinstated.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the user.
+  /// * `suspended` - True to suspend the user, false to reinstate it.
+  ///
+  /// # Return
+  /// * The updated user.
+  /// * An error if the user doesn't exist.
+  fn suspend_user(&self, id: i32, suspended: bool) -> ServiceResult<User>;
+
+  /// Creates a new login (session) for a specific user. The other sessions
+  /// of the user are kept.
   ///
   /// # Arguments
   /// * `user` - The user that is going to be logged in.
   /// * `token` - The token authentication for the user.
+  /// * `jti` - The unique id of the token.
+  /// * `device` - The description of the client.
   ///
   /// # Return
   /// * A login if it was successful.
   /// An error instead.
-  fn login(&self, user: &User, token: String) -> ServiceResult<Login>;
+  fn login(
+    &self,
+    user: &User,
+    token: String,
+    jti: String,
+    device: Device,
+  ) -> ServiceResult<Login>;
+
+  /// Issues the first refresh token of a session.
+  ///
+  /// # Arguments
+  /// * `login` - The session that has just started.
+  ///
+  /// # Return
+  /// * The opaque refresh token.
+  /// * An error instead.
+  fn issue_refresh_token(&self, login: &Login) -> ServiceResult<String>;
+
+  /// Exchanges a refresh token for a new one of the same family. Every refresh
+  /// token can be used only once, if an already used token is presented the
+  /// whole family is revoked.
+  ///
+  /// # Arguments
+  /// * `refresh_token` - The opaque refresh token.
+  ///
+  /// # Return
+  /// * The session bound to the token and the new refresh token.
+  /// * An error if the token is unknown, expired, revoked, reused or its
+  ///   session was closed.
+  fn rotate_refresh_token(
+    &self,
+    refresh_token: String,
+  ) -> ServiceResult<(Login, String)>;
+
+  /// Replaces the access token of a session after a token rotation.
+  ///
+  /// # Arguments
+  /// * `login` - The session to renew.
+  /// * `token` - The new access token.
+  /// * `jti` - The unique id of the new access token.
+  ///
+  /// # Return
+  /// * The updated login.
+  /// * An error instead.
+  fn renew_session(
+    &self,
+    login: &Login,
+    token: String,
+    jti: String,
+  ) -> ServiceResult<Login>;
+
+  /// Get a page of the open sessions of a user, the most recently opened
+  /// first.
+  ///
+  /// # Arguments
+  /// * `user_id` - The owner of the sessions.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of logins. Could be empty.
+  /// * An error instead.
+  fn sessions(
+    &self,
+    user_id: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Login>>;
+
+  /// Closes a session of a user and revokes its refresh tokens.
+  ///
+  /// # Arguments
+  /// * `user_id` - The owner of the session.
+  /// * `session_id` - The id of the session to close.
+  ///
+  /// # Return
+  /// * The closed session.
+  /// * An error if the session doesn't exist or belongs to another user.
+  fn revoke_session(
+    &self,
+    user_id: i32,
+    session_id: i32,
+  ) -> ServiceResult<Login>;
+
+  /// Revokes the family of the given refresh token, so neither it nor any
+  /// token rotated from it can be used.
+  ///
+  /// # Arguments
+  /// * `user_id` - The user who logs out, the owner of the token.
+  /// * `refresh_token` - The opaque refresh token.
+  ///
+  /// # Return
+  /// * Nothing if it was successful, an unknown token or a token of another
+  ///   user is ignored.
+  /// * An error instead.
+  fn revoke_refresh_token(
+    &self,
+    user_id: i32,
+    refresh_token: String,
+  ) -> ServiceResult<()>;
+
+  /// Revokes every refresh token of a user.
+  ///
+  /// # Arguments
+  /// * `user_id` - The owner of the tokens.
+  ///
+  /// # Return
+  /// * Nothing if it was successful.
+  /// * An error instead.
+  fn revoke_refresh_tokens(&self, user_id: i32) -> ServiceResult<()>;
 
   /// Get the total number of register users.
   ///
@@ -64,36 +219,120 @@ pub trait UserService: Sync + Send {
   fn total(&self) -> ServiceResult<i64>;
 }
 
-pub struct UserServiceImpl<UserRepo, LoginRepo, PwdHash> {
+const REFRESH_TOKEN_DAYS: i64 = 30;
+/// The password of the hash verified when a user doesn't exist.
+const DUMMY_PASSWORD: &str = "dummy password";
+
+pub struct UserServiceImpl<UserRepo, LoginRepo, RefreshRepo, PwdHash> {
   user_repository: UserRepo,
   login_repository: LoginRepo,
+  refresh_token_repository: RefreshRepo,
   password_hasher: PwdHash,
+  /// A hash of the current algorithm, made on the first unknown user.
+  dummy_hash: Mutex<Option<String>>,
 }
 
-impl<UserRepo, LoginRepo, PwdHash> UserServiceImpl<UserRepo, LoginRepo, PwdHash>
+impl<UserRepo, LoginRepo, RefreshRepo, PwdHash>
+  UserServiceImpl<UserRepo, LoginRepo, RefreshRepo, PwdHash>
 where
   UserRepo: UserRepository,
   LoginRepo: LoginRepository,
+  RefreshRepo: RefreshTokenRepository,
   PwdHash: PasswordHasher,
 {
   pub fn new(
     user_repository: UserRepo,
     login_repository: LoginRepo,
+    refresh_token_repository: RefreshRepo,
     password_hasher: PwdHash,
   ) -> Self {
     UserServiceImpl {
       user_repository,
       login_repository,
+      refresh_token_repository,
       password_hasher,
+      dummy_hash: Mutex::new(None),
+    }
+  }
+
+  /// Verifies a password against a hash that matches no user, so a login
+  /// of an unknown user takes as long as a wrong password, and the timing
+  /// doesn't tell which usernames exist.
+  ///
+  /// # Arguments
+  /// * `password` - The password in plain text.
+  fn verify_dummy(&self, password: &str) {
+    let dummy = {
+      let mut dummy = self
+        .dummy_hash
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+      if dummy.is_none() {
+        *dummy = self.password_hasher.hash(DUMMY_PASSWORD).ok();
+      }
+      dummy.clone()
+    };
+    if let Some(hashed) = dummy {
+      self.password_hasher.verify(password, &hashed);
     }
   }
+
+  /// Creates and stores a new refresh token for a user in the given family.
+  ///
+  /// # Arguments
+  /// * `user_id` - The owner of the token.
+  /// * `family` - The family of the token.
+  ///
+  /// # Return
+  /// * The opaque refresh token.
+  /// * An error instead.
+  fn store_refresh_token(
+    &self,
+    user_id: i32,
+    family: String,
+  ) -> ServiceResult<String> {
+    let token = generate_token();
+    let expires_at =
+      Utc::now().naive_utc() + chrono::Duration::days(REFRESH_TOKEN_DAYS);
+    let new_token =
+      NewRefreshToken::new(user_id, hash_token(&token), family, expires_at);
+    self
+      .refresh_token_repository
+      .add(new_token)
+      .map_err(Error::from)?;
+    Ok(token)
+  }
+
+  /// Revokes a whole family after detecting the reuse of one of its tokens.
+  ///
+  /// # Arguments
+  /// * `family` - The family to revoke.
+  /// * `user_id` - The owner of the family.
+  ///
+  /// # Return
+  /// * Always an error describing the reuse.
+  fn revoke_reused_family<T>(
+    &self,
+    family: String,
+    user_id: i32,
+  ) -> ServiceResult<T> {
+    log::warn!("refresh token reuse detected, revoking family {}", family);
+    self
+      .refresh_token_repository
+      .revoke_family(family, user_id)
+      .map_err(Error::from)?;
+    Err(Error::Unauthorized(String::from(
+      "Refresh token already used",
+    )))
+  }
 }
 
-impl<UserRepo, LoginRepo, PwdHash> UserService
-  for UserServiceImpl<UserRepo, LoginRepo, PwdHash>
+impl<UserRepo, LoginRepo, RefreshRepo, PwdHash> UserService
+  for UserServiceImpl<UserRepo, LoginRepo, RefreshRepo, PwdHash>
 where
   UserRepo: UserRepository + Send + Sync,
   LoginRepo: LoginRepository + Send + Sync,
+  RefreshRepo: RefreshTokenRepository + Send + Sync,
   PwdHash: PasswordHasher + Send + Sync,
 {
   fn create_user(
@@ -101,12 +340,14 @@ where
     username: String,
     password: String,
   ) -> ServiceResult<i32> {
-    let hashed = self.password_hasher.hash(password.as_str());
-    let new_user = NewUser::new(username, hashed);
-    self
-      .user_repository
-      .add(new_user)
-      .map_err(|err| err.to_string())
+    let hashed = self.password_hasher.hash(password.as_str())?;
+    let new_user = NewUser::new(username.to_string(), hashed);
+    self.user_repository.add(new_user).map_err(|err| match err {
+      RepoError::UniqueViolation(_) => {
+        Error::AlreadyExists(format!("the username {}", username))
+      },
+      _ => Error::from(err),
+    })
   }
 
   fn find_user(
@@ -114,31 +355,574 @@ where
     username: String,
     password: String,
   ) -> ServiceResult<User> {
-    let hashed = self.password_hasher.hash(password.as_str());
-    let search_user = NewUser::new(username, hashed);
+    let user =
+      self
+        .user_repository
+        .find(username)
+        .map_err(|err| match err {
+          RepoError::NotFound => {
+            self.verify_dummy(&password);
+            Error::InvalidCredentials
+          },
+          _ => Error::from(err),
+        })?;
+
+    let stored_hash = user.get_hashed_password();
+    if !self.password_hasher.verify(password.as_str(), &stored_hash) {
+      return Err(Error::InvalidCredentials);
+    }
+    if user.is_suspended() {
+      return Err(Error::Forbidden(String::from("User suspended")));
+    }
+
+    if self.password_hasher.needs_rehash(&stored_hash) {
+      let rehashed =
+        self
+          .password_hasher
+          .hash(password.as_str())
+          .and_then(|new_hash| {
+            self
+              .user_repository
+              .update_password(user.get_id(), new_hash)
+              .map_err(Error::from)
+          });
+      if let Err(err) = rehashed {
+        log::warn!(
+          "cannot upgrade the password hash of user {} because {}",
+          user.get_id(),
+          err
+        );
+      }
+    }
+    Ok(user)
+  }
+
+  fn get_user(&self, id: i32) -> ServiceResult<User> {
     self
       .user_repository
-      .find(search_user.get_username(), search_user.get_password())
-      .map_err(|err| err.to_string())
+      .get(id)
+      .map_err(|err| Error::from_repo(err, "user"))
   }
 
-  fn login(&self, user: &User, token: String) -> ServiceResult<Login> {
-    let new_login = NewLogin::new(user.get_username(), token);
-    let login_result = self.login_repository.find(user.get_username());
+  fn users(&self, page: PageRequest) -> ServiceResult<Page<User>> {
+    self.user_repository.all(page).map_err(Error::from)
+  }
+
+  fn suspend_user(&self, id: i32, suspended: bool) -> ServiceResult<User> {
+    let updated = self
+      .user_repository
+      .update_suspended(id, suspended)
+      .map_err(Error::from)?;
+    if updated == 0 {
+      return Err(Error::NotFound("user"));
+    }
+    if suspended {
+      self.login_repository.delete_all(id).map_err(Error::from)?;
+      self.revoke_refresh_tokens(id)?;
+    }
+    self.get_user(id)
+  }
+
+  fn login(
+    &self,
+    user: &User,
+    token: String,
+    jti: String,
+    device: Device,
+  ) -> ServiceResult<Login> {
+    let new_login = NewLogin::new(
+      user.get_id(),
+      user.get_username(),
+      token,
+      jti,
+      generate_token(),
+      device,
+    );
+    self.login_repository.add(new_login).map_err(Error::from)
+  }
+
+  fn issue_refresh_token(&self, login: &Login) -> ServiceResult<String> {
+    self.store_refresh_token(login.get_user_id(), login.get_family())
+  }
+
+  fn rotate_refresh_token(
+    &self,
+    refresh_token: String,
+  ) -> ServiceResult<(Login, String)> {
+    let stored = self
+      .refresh_token_repository
+      .find(hash_token(&refresh_token))
+      .map_err(Error::from)?
+      .ok_or_else(|| {
+        Error::Unauthorized(String::from("Invalid refresh token"))
+      })?;
+
+    if stored.is_revoked() {
+      return Err(Error::Unauthorized(String::from("Refresh token revoked")));
+    }
+    if stored.is_used() {
+      return self
+        .revoke_reused_family(stored.get_family(), stored.get_user_id());
+    }
+    if stored.is_expired() {
+      return Err(Error::Unauthorized(String::from("Refresh token expired")));
+    }
+    let marked = self
+      .refresh_token_repository
+      .mark_used(stored.get_id())
+      .map_err(Error::from)?;
+    if !marked {
+      return self
+        .revoke_reused_family(stored.get_family(), stored.get_user_id());
+    }
 
-    match login_result {
-      Ok(login_found) => match login_found {
-        Some(mut login) => {
-          login.update(&new_login)?;
-          Ok(self.login_repository.update(login.borrow()).unwrap())
-        },
-        None => Ok(self.login_repository.add(new_login).unwrap()),
+    let session = self
+      .login_repository
+      .find_by_family(stored.get_family())
+      .map_err(Error::from)?;
+    let session = match session {
+      Some(session) => session,
+      None => {
+        self
+          .refresh_token_repository
+          .revoke_family(stored.get_family(), stored.get_user_id())
+          .map_err(Error::from)?;
+        return Err(Error::Unauthorized(String::from("Session closed")));
       },
-      Err(err) => Err(err.to_string()),
+    };
+    let new_token =
+      self.store_refresh_token(stored.get_user_id(), stored.get_family())?;
+    Ok((session, new_token))
+  }
+
+  fn renew_session(
+    &self,
+    login: &Login,
+    token: String,
+    jti: String,
+  ) -> ServiceResult<Login> {
+    let mut renewed = login.clone();
+    renewed.renew(token, jti);
+    self
+      .login_repository
+      .update(renewed.borrow())
+      .map_err(Error::from)
+  }
+
+  fn sessions(
+    &self,
+    user_id: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Login>> {
+    self
+      .login_repository
+      .find_page(user_id, page)
+      .map_err(Error::from)
+  }
+
+  fn revoke_session(
+    &self,
+    user_id: i32,
+    session_id: i32,
+  ) -> ServiceResult<Login> {
+    let session = self
+      .login_repository
+      .find(user_id)
+      .map_err(Error::from)?
+      .into_iter()
+      .find(|session| session.get_id() == session_id)
+      .ok_or(Error::NotFound("session"))?;
+    self
+      .login_repository
+      .delete(session_id, user_id)
+      .map_err(Error::from)?;
+    self
+      .refresh_token_repository
+      .revoke_family(session.get_family(), user_id)
+      .map_err(Error::from)?;
+    Ok(session)
+  }
+
+  fn revoke_refresh_token(
+    &self,
+    user_id: i32,
+    refresh_token: String,
+  ) -> ServiceResult<()> {
+    let stored = self
+      .refresh_token_repository
+      .find(hash_token(&refresh_token))
+      .map_err(Error::from)?;
+    if let Some(token) = stored {
+      self
+        .refresh_token_repository
+        .revoke_family(token.get_family(), user_id)
+        .map_err(Error::from)?;
     }
+    Ok(())
+  }
+
+  fn revoke_refresh_tokens(&self, user_id: i32) -> ServiceResult<()> {
+    self
+      .refresh_token_repository
+      .revoke_user(user_id)
+      .map(|_| ())
+      .map_err(Error::from)
   }
 
   fn total(&self) -> ServiceResult<i64> {
-    self.user_repository.total().map_err(|err| err.to_string())
+    self.user_repository.total().map_err(Error::from)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{
+    login::Builder as LoginBuilder,
+    password::MockPasswordHasher,
+    refresh_token::Builder as RefreshTokenBuilder,
+    repository::{
+      login_repository::MockLoginRepository,
+      refresh_token_repository::MockRefreshTokenRepository,
+      user_repository::MockUserRepository,
+    },
+    user::Builder,
+  };
+  use diesel::result::Error as DieselError;
+  use mockall::predicate::eq;
+
+  fn a_user(hashed_password: &str) -> User {
+    Builder::new()
+      .with_id(1)
+      .with_username("juan")
+      .with_hashed_password(hashed_password)
+      .build()
+  }
+
+  #[test]
+  fn find_user_rehashes_outdated_hash() {
+    let user = a_user("LEGACY");
+    let mut mock_ur = MockUserRepository::new();
+    mock_ur
+      .expect_find()
+      .with(eq(String::from("juan")))
+      .times(1)
+      .returning(move |_| Ok(user.clone()));
+    mock_ur
+      .expect_update_password()
+      .with(eq(1), eq(String::from("$argon2id$new")))
+      .times(1)
+      .returning(|_, _| Ok(()));
+
+    let mut mock_hasher = MockPasswordHasher::new();
+    mock_hasher.expect_verify().times(1).returning(|_, _| true);
+    mock_hasher
+      .expect_needs_rehash()
+      .times(1)
+      .returning(|_| true);
+    mock_hasher
+      .expect_hash()
+      .times(1)
+      .returning(|_| Ok(String::from("$argon2id$new")));
+
+    let service = UserServiceImpl::new(
+      mock_ur,
+      MockLoginRepository::new(),
+      MockRefreshTokenRepository::new(),
+      mock_hasher,
+    );
+    let found = service
+      .find_user(String::from("juan"), String::from("password"))
+      .unwrap();
+    assert_eq!(found.get_id(), 1);
+  }
+
+  #[test]
+  fn find_user_unknown_verifies_a_dummy_hash() {
+    let mut mock_ur = MockUserRepository::new();
+    mock_ur
+      .expect_find()
+      .times(2)
+      .returning(|_| Err(RepoError::NotFound));
+
+    let mut mock_hasher = MockPasswordHasher::new();
+    mock_hasher
+      .expect_hash()
+      .withf(|password| password == DUMMY_PASSWORD)
+      .times(1)
+      .returning(|_| Ok(String::from("$argon2id$dummy")));
+    mock_hasher
+      .expect_verify()
+      .withf(|password, hashed| {
+        password == "password" && hashed == "$argon2id$dummy"
+      })
+      .times(2)
+      .returning(|_, _| false);
+
+    let service = UserServiceImpl::new(
+      mock_ur,
+      MockLoginRepository::new(),
+      MockRefreshTokenRepository::new(),
+      mock_hasher,
+    );
+    for _ in 0..2 {
+      assert!(matches!(
+        service.find_user(String::from("pedro"), String::from("password")),
+        Err(Error::InvalidCredentials)
+      ));
+    }
+  }
+
+  #[test]
+  fn find_user_wrong_password() {
+    let user = a_user("$argon2id$stored");
+    let mut mock_ur = MockUserRepository::new();
+    mock_ur
+      .expect_find()
+      .times(1)
+      .returning(move |_| Ok(user.clone()));
+    mock_ur.expect_update_password().times(0);
+
+    let mut mock_hasher = MockPasswordHasher::new();
+    mock_hasher.expect_verify().times(1).returning(|_, _| false);
+    mock_hasher.expect_needs_rehash().times(0);
+
+    let service = UserServiceImpl::new(
+      mock_ur,
+      MockLoginRepository::new(),
+      MockRefreshTokenRepository::new(),
+      mock_hasher,
+    );
+    assert!(service
+      .find_user(String::from("juan"), String::from("wrong"))
+      .is_err());
+  }
+
+  #[test]
+  fn find_user_suspended() {
+    let user = Builder::new()
+      .with_id(1)
+      .with_username("juan")
+      .with_hashed_password("$argon2id$stored")
+      .with_suspended(true)
+      .build();
+    let mut mock_ur = MockUserRepository::new();
+    mock_ur
+      .expect_find()
+      .times(1)
+      .returning(move |_| Ok(user.clone()));
+
+    let mut mock_hasher = MockPasswordHasher::new();
+    mock_hasher.expect_verify().times(1).returning(|_, _| true);
+    mock_hasher.expect_needs_rehash().times(0);
+
+    let service = UserServiceImpl::new(
+      mock_ur,
+      MockLoginRepository::new(),
+      MockRefreshTokenRepository::new(),
+      mock_hasher,
+    );
+    assert_eq!(
+      service
+        .find_user(String::from("juan"), String::from("password"))
+        .err(),
+      Some(Error::Forbidden(String::from("User suspended")))
+    );
+  }
+
+  #[test]
+  fn suspend_user_closes_the_sessions() {
+    let mut mock_ur = MockUserRepository::new();
+    mock_ur
+      .expect_update_suspended()
+      .with(eq(1), eq(true))
+      .times(1)
+      .returning(|_, _| Ok(1));
+    mock_ur.expect_get().with(eq(1)).times(1).returning(|_| {
+      Ok(
+        Builder::new()
+          .with_id(1)
+          .with_username("juan")
+          .with_hashed_password("$argon2id$stored")
+          .with_suspended(true)
+          .build(),
+      )
+    });
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr
+      .expect_delete_all()
+      .with(eq(1))
+      .times(1)
+      .returning(|_| Ok(2));
+    let mut mock_rr = MockRefreshTokenRepository::new();
+    mock_rr
+      .expect_revoke_user()
+      .with(eq(1))
+      .times(1)
+      .returning(|_| Ok(2));
+
+    let service = UserServiceImpl::new(
+      mock_ur,
+      mock_lr,
+      mock_rr,
+      MockPasswordHasher::new(),
+    );
+    assert!(service.suspend_user(1, true).unwrap().is_suspended());
+  }
+
+  #[test]
+  fn rotate_refresh_token_ok() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr
+      .expect_find_by_family()
+      .with(eq(String::from("family")))
+      .times(1)
+      .returning(|_| {
+        Ok(Some(
+          LoginBuilder::new()
+            .with_id(3)
+            .with_user_id(1)
+            .with_username("juan")
+            .with_token("token")
+            .with_family("family")
+            .build(),
+        ))
+      });
+
+    let mut mock_rr = MockRefreshTokenRepository::new();
+    mock_rr
+      .expect_find()
+      .with(eq(hash_token("refresh")))
+      .times(1)
+      .returning(|_| {
+        Ok(Some(
+          RefreshTokenBuilder::new()
+            .with_id(7)
+            .with_user_id(1)
+            .with_family("family")
+            .build(),
+        ))
+      });
+    mock_rr
+      .expect_mark_used()
+      .with(eq(7))
+      .times(1)
+      .returning(|_| Ok(true));
+    mock_rr.expect_add().times(1).returning(|_| Ok(8));
+    mock_rr.expect_revoke_family().times(0);
+
+    let service = UserServiceImpl::new(
+      MockUserRepository::new(),
+      mock_lr,
+      mock_rr,
+      MockPasswordHasher::new(),
+    );
+    let (session, new_token) = service
+      .rotate_refresh_token(String::from("refresh"))
+      .unwrap();
+    assert_eq!(session.get_id(), 3);
+    assert_ne!(new_token, String::from("refresh"));
+  }
+
+  #[test]
+  fn rotate_refresh_token_reused_revokes_family() {
+    let mut mock_rr = MockRefreshTokenRepository::new();
+    mock_rr.expect_find().times(1).returning(|_| {
+      Ok(Some(
+        RefreshTokenBuilder::new()
+          .with_id(7)
+          .with_user_id(1)
+          .with_family("family")
+          .with_used_at(Utc::now().naive_utc())
+          .build(),
+      ))
+    });
+    mock_rr.expect_mark_used().times(0);
+    mock_rr.expect_add().times(0);
+    mock_rr
+      .expect_revoke_family()
+      .with(eq(String::from("family")), eq(1))
+      .times(1)
+      .returning(|_, _| Ok(2));
+
+    let service = UserServiceImpl::new(
+      MockUserRepository::new(),
+      MockLoginRepository::new(),
+      mock_rr,
+      MockPasswordHasher::new(),
+    );
+    assert!(service
+      .rotate_refresh_token(String::from("refresh"))
+      .is_err());
+  }
+
+  #[test]
+  fn revoke_refresh_token_only_of_its_owner() {
+    let mut mock_rr = MockRefreshTokenRepository::new();
+    mock_rr.expect_find().times(1).returning(|_| {
+      Ok(Some(
+        RefreshTokenBuilder::new()
+          .with_id(7)
+          .with_user_id(1)
+          .with_family("family")
+          .build(),
+      ))
+    });
+    mock_rr
+      .expect_revoke_family()
+      .with(eq(String::from("family")), eq(2))
+      .times(1)
+      .returning(|_, _| Ok(0));
+
+    let service = UserServiceImpl::new(
+      MockUserRepository::new(),
+      MockLoginRepository::new(),
+      mock_rr,
+      MockPasswordHasher::new(),
+    );
+    assert!(service
+      .revoke_refresh_token(2, String::from("refresh"))
+      .is_ok());
+  }
+
+  #[test]
+  fn revoke_session_of_another_user() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr
+      .expect_find()
+      .with(eq(2))
+      .times(1)
+      .returning(|_| Ok(vec![]));
+    mock_lr.expect_delete().times(0);
+    let mut mock_rr = MockRefreshTokenRepository::new();
+    mock_rr.expect_revoke_family().times(0);
+
+    let service = UserServiceImpl::new(
+      MockUserRepository::new(),
+      mock_lr,
+      mock_rr,
+      MockPasswordHasher::new(),
+    );
+    assert!(service.revoke_session(2, 3).is_err());
+  }
+
+  #[test]
+  fn revoke_session_with_a_store_failure() {
+    let mut mock_lr = MockLoginRepository::new();
+    mock_lr.expect_find().times(1).returning(|_| {
+      Err(RepoError::DieselError(DieselError::RollbackTransaction))
+    });
+    mock_lr.expect_delete().times(0);
+
+    let service = UserServiceImpl::new(
+      MockUserRepository::new(),
+      mock_lr,
+      MockRefreshTokenRepository::new(),
+      MockPasswordHasher::new(),
+    );
+    assert!(matches!(
+      service.revoke_session(2, 3),
+      Err(Error::Internal(_))
+    ));
   }
 }
@@ -0,0 +1,353 @@
+use crate::{
+  model::{message::Message, role::Role},
+  schema::{webhook_outbox, webhooks},
+};
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use diesel::{Identifiable, Insertable, Queryable};
+use serde_json::json;
+use std::{fmt, str::FromStr};
+
+/// The events a webhook can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+  MessageCreated,
+  MessageRead,
+  UserCreated,
+}
+
+impl WebhookEvent {
+  /// Checks if the event goes to the admins. The messages are only notified
+  /// to their sender and recipients, but a new user concerns everyone so
+  /// only the admins get it.
+  ///
+  /// # Return
+  /// * True if the event goes to the webhooks of every admin, false if only to
+  ///   the ones of the users it concerns.
+  pub fn for_admins(&self) -> bool {
+    matches!(self, WebhookEvent::UserCreated)
+  }
+
+  /// Checks if a role can subscribe to the event.
+  ///
+  /// # Arguments
+  /// * `role` - The role of the owner of the webhook.
+  ///
+  /// # Return
+  /// * True if the role can subscribe.
+  pub fn allowed_for(&self, role: Role) -> bool {
+    !self.for_admins() || role.grants(Role::Admin)
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      WebhookEvent::MessageCreated => "message.created",
+      WebhookEvent::MessageRead => "message.read",
+      WebhookEvent::UserCreated => "user.created",
+    }
+  }
+}
+
+impl fmt::Display for WebhookEvent {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+impl FromStr for WebhookEvent {
+  type Err = String;
+
+  fn from_str(event: &str) -> Result<Self, Self::Err> {
+    match event {
+      "message.created" => Ok(WebhookEvent::MessageCreated),
+      "message.read" => Ok(WebhookEvent::MessageRead),
+      "user.created" => Ok(WebhookEvent::UserCreated),
+      _ => Err(format!("unknown event {}", event)),
+    }
+  }
+}
+
+/// A URL of a user notified of the events it subscribed to. The requests are
+/// signed with its secret.
+#[derive(Identifiable, Queryable, Clone)]
+pub struct Webhook {
+  id: i32,
+  user_id: i32,
+  url: String,
+  secret: String,
+  events: String,
+  created_at: NaiveDateTime,
+}
+
+impl Webhook {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_user_id(&self) -> i32 {
+    return self.user_id;
+  }
+
+  pub fn get_url(&self) -> String {
+    return self.url.to_string();
+  }
+
+  pub fn get_secret(&self) -> String {
+    return self.secret.to_string();
+  }
+
+  /// The subscribed events, the unknown stored values are ignored.
+  pub fn get_events(&self) -> Vec<WebhookEvent> {
+    return self
+      .events
+      .split(',')
+      .filter_map(|event| event.parse().ok())
+      .collect();
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+
+  pub fn subscribes(&self, event: WebhookEvent) -> bool {
+    return self.get_events().contains(&event);
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+pub struct NewWebhook {
+  user_id: i32,
+  url: String,
+  secret: String,
+  events: String,
+  created_at: NaiveDateTime,
+}
+
+impl NewWebhook {
+  pub fn new(
+    the_user_id: i32,
+    the_url: String,
+    the_secret: String,
+    the_events: &[WebhookEvent],
+  ) -> NewWebhook {
+    NewWebhook {
+      user_id: the_user_id,
+      url: the_url,
+      secret: the_secret,
+      events: the_events
+        .iter()
+        .map(WebhookEvent::as_str)
+        .collect::<Vec<&str>>()
+        .join(","),
+      created_at: Utc::now().naive_utc(),
+    }
+  }
+}
+
+/// An event waiting in the outbox to be sent to a webhook. A delivery that
+/// keeps failing goes to the dead-letter list, with its `dead_at` date. Its
+/// schedule is only read by the queries, see `DELIVERY_COLUMNS`.
+#[derive(Identifiable, Queryable, Clone)]
+#[table_name = "webhook_outbox"]
+pub struct Delivery {
+  id: i32,
+  webhook_id: i32,
+  event: String,
+  payload: String,
+  attempts: i32,
+  last_error: Option<String>,
+  dead_at: Option<NaiveDateTime>,
+  created_at: NaiveDateTime,
+}
+
+impl Delivery {
+  pub fn get_id(&self) -> i32 {
+    return self.id;
+  }
+
+  pub fn get_webhook_id(&self) -> i32 {
+    return self.webhook_id;
+  }
+
+  pub fn get_event(&self) -> String {
+    return self.event.to_string();
+  }
+
+  pub fn get_payload(&self) -> String {
+    return self.payload.to_string();
+  }
+
+  pub fn get_attempts(&self) -> i32 {
+    return self.attempts;
+  }
+
+  pub fn get_last_error(&self) -> Option<String> {
+    return self.last_error.clone();
+  }
+
+  pub fn get_dead_at(&self) -> Option<NaiveDateTime> {
+    return self.dead_at;
+  }
+
+  pub fn get_created_at(&self) -> NaiveDateTime {
+    return self.created_at;
+  }
+}
+
+#[derive(Insertable)]
+#[table_name = "webhook_outbox"]
+pub struct NewDelivery {
+  webhook_id: i32,
+  event: String,
+  payload: String,
+  next_attempt_at: NaiveDateTime,
+  created_at: NaiveDateTime,
+}
+
+impl NewDelivery {
+  pub fn new(
+    the_webhook_id: i32,
+    the_event: WebhookEvent,
+    the_payload: &str,
+  ) -> NewDelivery {
+    let now = Utc::now().naive_utc();
+    NewDelivery {
+      webhook_id: the_webhook_id,
+      event: the_event.to_string(),
+      payload: the_payload.to_string(),
+      next_attempt_at: now,
+      created_at: now,
+    }
+  }
+}
+
+/// Builds the body sent to the webhooks, the event with its data.
+///
+/// # Arguments
+/// * `event` - The event.
+/// * `data` - The data of the event.
+///
+/// # Return
+/// * The JSON body.
+fn payload(event: WebhookEvent, data: serde_json::Value) -> String {
+  json!({
+    "event": event.as_str(),
+    "created_at": Utc::now().to_rfc3339(),
+    "data": data,
+  })
+  .to_string()
+}
+
+/// The body of a `message.created` event.
+pub fn message_created(msg: &Message) -> String {
+  payload(
+    WebhookEvent::MessageCreated,
+    json!({
+      "id": msg.get_id(),
+      "from": msg.get_from(),
+      "to": msg.get_to(),
+      "conversation_id": msg.get_conversation_id(),
+      "message": msg.get_message(),
+      "created_at": Utc.from_utc_datetime(&msg.get_created_at()).to_rfc3339(),
+    }),
+  )
+}
+
+/// The body of a `message.read` event.
+pub fn message_read(message_id: i32, from: i32, reader: i32) -> String {
+  payload(
+    WebhookEvent::MessageRead,
+    json!({"message_id": message_id, "from": from, "user_id": reader}),
+  )
+}
+
+/// The body of a `user.created` event.
+pub fn user_created(id: i32, username: &str) -> String {
+  payload(
+    WebhookEvent::UserCreated,
+    json!({"id": id, "username": username}),
+  )
+}
+
+#[cfg(test)]
+pub struct Builder {
+  id: i32,
+  user_id: i32,
+  url: String,
+  secret: String,
+  events: Vec<WebhookEvent>,
+}
+
+#[cfg(test)]
+impl Builder {
+  pub fn new() -> Self {
+    Builder {
+      id: 0,
+      user_id: 0,
+      url: String::from("http://localhost/hook"),
+      secret: String::from("secret"),
+      events: vec![WebhookEvent::MessageCreated],
+    }
+  }
+
+  pub fn with_id(mut self, the_id: i32) -> Builder {
+    self.id = the_id;
+    self
+  }
+
+  pub fn with_url(mut self, the_url: &str) -> Builder {
+    self.url = the_url.to_string();
+    self
+  }
+
+  /// Builds a delivery of the webhook, never attempted.
+  pub fn delivery(&self, id: i32, attempts: i32, payload: &str) -> Delivery {
+    Delivery {
+      id,
+      webhook_id: self.id,
+      event: self.events[0].to_string(),
+      payload: payload.to_string(),
+      attempts,
+      last_error: None,
+      dead_at: None,
+      created_at: NaiveDateTime::from_timestamp(0, 0),
+    }
+  }
+
+  pub fn build(&self) -> Webhook {
+    Webhook {
+      id: self.id,
+      user_id: self.user_id,
+      url: self.url.to_string(),
+      secret: self.secret.to_string(),
+      events: NewWebhook::new(0, String::new(), String::new(), &self.events)
+        .events,
+      created_at: NaiveDateTime::from_timestamp(0, 0),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn events_round_trip() {
+    let webhook = Builder::new().build();
+    assert_eq!(webhook.get_events(), vec![WebhookEvent::MessageCreated]);
+    assert!(webhook.subscribes(WebhookEvent::MessageCreated));
+    assert!(!webhook.subscribes(WebhookEvent::UserCreated));
+    assert_eq!("user.created".parse(), Ok(WebhookEvent::UserCreated));
+    assert!("user.deleted".parse::<WebhookEvent>().is_err());
+  }
+
+  #[test]
+  fn only_admins_get_the_new_users() {
+    assert!(!WebhookEvent::UserCreated.allowed_for(Role::User));
+    assert!(WebhookEvent::UserCreated.allowed_for(Role::Admin));
+    assert!(WebhookEvent::MessageRead.allowed_for(Role::User));
+    assert!(WebhookEvent::UserCreated.for_admins());
+    assert!(!WebhookEvent::MessageCreated.for_admins());
+  }
+}
@@ -0,0 +1,294 @@
+use crate::{
+  model::{
+    error::{Error, ServiceResult},
+    page::{Page, PageRequest},
+    refresh_token::generate_token,
+    repository::{
+      user_repository::UserRepository, webhook_repository::WebhookRepository,
+    },
+    webhook::{Delivery, NewWebhook, Webhook, WebhookEvent},
+  },
+  outbox::target::check_url,
+};
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait WebhookService: Sync + Send {
+  /// Registers a webhook for a user. Only the admins can subscribe to
+  /// `user.created`.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the owner.
+  /// * `url` - The http or https URL notified of the events, its host must only
+  ///   resolve to public addresses.
+  /// * `events` - The events to subscribe to.
+  ///
+  /// # Return
+  /// * The id of the webhook and the secret that signs its requests.
+  /// * An error if the URL or the events aren't valid.
+  fn register(
+    &self,
+    uid: i32,
+    url: String,
+    events: Vec<WebhookEvent>,
+  ) -> ServiceResult<(i32, String)>;
+
+  /// Get the webhooks of a user.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the owner.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of webhooks ordered by id. Could be empty.
+  /// * An error instead.
+  fn list(&self, uid: i32, page: PageRequest) -> ServiceResult<Page<Webhook>>;
+
+  /// Deletes a webhook of a user, with the deliveries still in its outbox.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the webhook.
+  /// * `uid` - The user_id of the owner.
+  ///
+  /// # Return
+  /// * An error if the user doesn't own the webhook.
+  fn remove(&self, id: i32, uid: i32) -> ServiceResult<()>;
+
+  /// Get a page of the deliveries to the webhooks of a user that failed every
+  /// attempt.
+  ///
+  /// # Arguments
+  /// * `uid` - The user_id of the owner.
+  /// * `page` - The page to retrieve.
+  ///
+  /// # Return
+  /// * A page of deliveries, the newest first. Could be empty.
+  /// * An error instead.
+  fn dead_letters(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Delivery>>;
+
+  /// Sends again a dead delivery, with a new round of attempts.
+  ///
+  /// # Arguments
+  /// * `id` - The id of the delivery.
+  /// * `uid` - The user_id of the owner of its webhook.
+  ///
+  /// # Return
+  /// * An error if it isn't a dead delivery of the user.
+  fn retry(&self, id: i32, uid: i32) -> ServiceResult<()>;
+}
+
+pub struct WebhookServiceImpl<WebhookRepo, UserRepo> {
+  webhook_repository: WebhookRepo,
+  user_repository: UserRepo,
+}
+
+impl<WebhookRepo, UserRepo> WebhookServiceImpl<WebhookRepo, UserRepo>
+where
+  WebhookRepo: WebhookRepository,
+  UserRepo: UserRepository,
+{
+  pub fn new(
+    the_webhook_repository: WebhookRepo,
+    the_user_repository: UserRepo,
+  ) -> Self {
+    WebhookServiceImpl {
+      webhook_repository: the_webhook_repository,
+      user_repository: the_user_repository,
+    }
+  }
+}
+
+impl<WebhookRepo, UserRepo> WebhookService
+  for WebhookServiceImpl<WebhookRepo, UserRepo>
+where
+  WebhookRepo: WebhookRepository + Send + Sync,
+  UserRepo: UserRepository + Send + Sync,
+{
+  fn register(
+    &self,
+    uid: i32,
+    url: String,
+    events: Vec<WebhookEvent>,
+  ) -> ServiceResult<(i32, String)> {
+    check_url(&url).map_err(Error::InvalidInput)?;
+    if events.is_empty() {
+      return Err(Error::InvalidInput(String::from("there are no events")));
+    }
+    let role = self
+      .user_repository
+      .get(uid)
+      .map_err(|err| Error::from_repo(err, "user"))?
+      .get_role();
+    if let Some(event) = events.iter().find(|event| !event.allowed_for(role)) {
+      return Err(Error::Forbidden(format!(
+        "Only the admins can subscribe to {}",
+        event
+      )));
+    }
+    let secret = generate_token();
+    let id = self
+      .webhook_repository
+      .add(NewWebhook::new(uid, url, secret.to_string(), &events))
+      .map_err(Error::from)?;
+    Ok((id, secret))
+  }
+
+  fn list(&self, uid: i32, page: PageRequest) -> ServiceResult<Page<Webhook>> {
+    self
+      .webhook_repository
+      .find_by_user(uid, page)
+      .map_err(Error::from)
+  }
+
+  fn remove(&self, id: i32, uid: i32) -> ServiceResult<()> {
+    let removed = self
+      .webhook_repository
+      .remove(id, uid)
+      .map_err(Error::from)?;
+    if removed == 0 {
+      return Err(Error::NotFound("webhook"));
+    }
+    Ok(())
+  }
+
+  fn dead_letters(
+    &self,
+    uid: i32,
+    page: PageRequest,
+  ) -> ServiceResult<Page<Delivery>> {
+    self
+      .webhook_repository
+      .find_dead(uid, page)
+      .map_err(Error::from)
+  }
+
+  fn retry(&self, id: i32, uid: i32) -> ServiceResult<()> {
+    let retried = self
+      .webhook_repository
+      .retry(id, uid)
+      .map_err(Error::from)?;
+    if retried == 0 {
+      return Err(Error::NotFound("delivery"));
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{
+    repository::{
+      user_repository::MockUserRepository,
+      webhook_repository::MockWebhookRepository,
+    },
+    role::Role,
+    user::Builder,
+  };
+  use mockall::predicate::eq;
+
+  fn user_with(role: Role) -> MockUserRepository {
+    let mut mock_users = MockUserRepository::new();
+    mock_users
+      .expect_get()
+      .with(eq(1))
+      .times(1)
+      .returning(move |uid| {
+        Ok(Builder::new().with_id(uid).with_role(role).build())
+      });
+    mock_users
+  }
+
+  #[test]
+  fn register_without_a_valid_url() {
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = WebhookServiceImpl::new(mock_repo, MockUserRepository::new());
+    assert_eq!(
+      service
+        .register(
+          1,
+          String::from("ftp://localhost"),
+          vec![WebhookEvent::MessageCreated]
+        )
+        .err(),
+      Some(Error::InvalidInput(String::from(
+        "the url must be http or https"
+      )))
+    );
+  }
+
+  #[test]
+  fn register_a_private_url() {
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = WebhookServiceImpl::new(mock_repo, MockUserRepository::new());
+    for url in ["http://127.0.0.1:8081/admin", "http://169.254.169.254/"] {
+      assert_eq!(
+        service
+          .register(1, String::from(url), vec![WebhookEvent::MessageCreated])
+          .err(),
+        Some(Error::InvalidInput(String::from(
+          "the url must only reach public addresses"
+        )))
+      );
+    }
+  }
+
+  #[test]
+  fn user_cannot_subscribe_to_the_new_users() {
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_add().times(0);
+
+    let service = WebhookServiceImpl::new(mock_repo, user_with(Role::User));
+    assert_eq!(
+      service
+        .register(
+          1,
+          String::from("https://93.184.216.34/hook"),
+          vec![WebhookEvent::MessageRead, WebhookEvent::UserCreated]
+        )
+        .err(),
+      Some(Error::Forbidden(String::from(
+        "Only the admins can subscribe to user.created"
+      )))
+    );
+  }
+
+  #[test]
+  fn admin_registers_a_webhook() {
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_add().times(1).returning(|_| Ok(3));
+
+    let service = WebhookServiceImpl::new(mock_repo, user_with(Role::Admin));
+    let (id, secret) = service
+      .register(
+        1,
+        String::from("https://93.184.216.34/hook"),
+        vec![WebhookEvent::UserCreated],
+      )
+      .unwrap();
+    assert_eq!(id, 3);
+    assert!(!secret.is_empty());
+  }
+
+  #[test]
+  fn remove_a_webhook_of_another_user() {
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo
+      .expect_remove()
+      .with(eq(3), eq(1))
+      .times(1)
+      .returning(|_, _| Ok(0));
+
+    let service = WebhookServiceImpl::new(mock_repo, MockUserRepository::new());
+    assert_eq!(service.remove(3, 1).err(), Some(Error::NotFound("webhook")));
+  }
+}
@@ -7,28 +7,113 @@ use utoipa::OpenApi;
 use utoipa_swagger_ui::Config;
 
 use crate::{
-  application::{health_handler, message_handler, user_handler},
-  message_handler::{MessageDto, ResponseMessageDto, SearchMessageDto},
-  user_handler::{LoginDto, ResponseUserDto, UserDto},
+  admin_handler::{AdminMessageDto, AdminUserDto, AdminUserPageDto},
+  application::{
+    admin_handler, attachment_handler, conversation_handler,
+    error::{ErrorResponse, FieldError},
+    group_handler, health_handler, jwks_handler, message_handler,
+    session_handler, user_handler, webhook_handler,
+  },
+  attachment_handler::{AttachmentDto, ThumbnailsDto},
+  conversation_handler::ReadConversationDto,
+  group_handler::{
+    CreateGroupDto, GroupDto, GroupPageDto, InviteDto, MemberDto,
+    MemberPageDto, RenameGroupDto,
+  },
+  message_handler::{
+    EditMessageDto, MessageDto, MessagePageDto, ResponseMessageDto,
+    SearchResultDto, UnreadDto, UnreadPageDto,
+  },
+  session_handler::{SessionDto, SessionPageDto},
+  user_handler::{
+    LoginDto, LoginRequestDto, LogoutDto, RefreshDto, ResponseUserDto, UserDto,
+  },
+  webhook_handler::{
+    CreateWebhookDto, DeliveryDto, DeliveryPageDto, WebhookDto, WebhookPageDto,
+  },
 };
 
 #[derive(OpenApi)]
 #[openapi(
   handlers(
     health_handler::ping,
+    jwks_handler::jwks,
     message_handler::send_message,
     message_handler::get_message,
     message_handler::get_message_from,
+    message_handler::get_inbox,
+    message_handler::poll_messages,
+    message_handler::edit_message,
+    message_handler::delete_message,
+    message_handler::read_message,
+    message_handler::get_unread,
+    message_handler::search_messages,
+    attachment_handler::upload_attachment,
+    attachment_handler::download_attachment,
+    attachment_handler::download_thumbnail,
+    conversation_handler::get_conversation,
+    conversation_handler::read_conversation,
+    group_handler::list_groups,
+    group_handler::create_group,
+    group_handler::rename_group,
+    group_handler::list_members,
+    group_handler::invite_member,
+    group_handler::remove_member,
+    group_handler::leave_group,
+    group_handler::get_group_messages,
     user_handler::create_user,
     user_handler::login,
+    user_handler::refresh,
+    user_handler::logout,
+    user_handler::logout_all,
+    session_handler::list_sessions,
+    session_handler::revoke_session,
+    admin_handler::list_users,
+    admin_handler::suspend_user,
+    admin_handler::reinstate_user,
+    admin_handler::get_any_message,
+    webhook_handler::list_webhooks,
+    webhook_handler::create_webhook,
+    webhook_handler::delete_webhook,
+    webhook_handler::get_dead_letters,
+    webhook_handler::retry_delivery,
   ),
   components(
     MessageDto,
+    EditMessageDto,
     ResponseMessageDto,
-    SearchMessageDto,
+    MessagePageDto,
+    SearchResultDto,
+    UnreadDto,
+    UnreadPageDto,
+    AttachmentDto,
+    ThumbnailsDto,
+    ReadConversationDto,
+    CreateGroupDto,
+    RenameGroupDto,
+    InviteDto,
+    GroupDto,
+    GroupPageDto,
+    MemberDto,
+    MemberPageDto,
     UserDto,
     ResponseUserDto,
-    LoginDto
+    LoginDto,
+    RefreshDto,
+    LogoutDto,
+    LoginRequestDto,
+    SessionDto,
+    SessionPageDto,
+    AdminUserDto,
+    AdminUserPageDto,
+    AdminMessageDto,
+    CreateWebhookDto,
+    WebhookDto,
+    WebhookPageDto,
+    DeliveryDto,
+    DeliveryPageDto,
+    ErrorResponse,
+    FieldError
   )
 )]
 pub struct ApiDoc;
@@ -0,0 +1,3 @@
+pub mod signature;
+pub mod target;
+pub mod worker;
@@ -0,0 +1,39 @@
+use crate::crypto::{hex, hmac_sha256};
+
+/// The name of the header with the signature of a webhook request.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// The name of the header with the unix timestamp of a webhook request, it's
+/// part of the signed content so an old request can't be replayed.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Signs the body of a webhook request. The receivers calculate the HMAC of
+/// `<timestamp>.<body>` with the secret of the webhook and compare it with
+/// the signature.
+///
+/// # Arguments
+/// * `secret` - The secret of the webhook.
+/// * `timestamp` - The unix timestamp of the request.
+/// * `body` - The body of the request.
+///
+/// # Return
+/// * The signature, as `sha256=<hex digest>`.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+  let content = format!("{}.{}", timestamp, body);
+  format!(
+    "sha256={}",
+    hex(&hmac_sha256(secret.as_bytes(), content.as_bytes()))
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sign_the_timestamp_and_the_body() {
+    assert_eq!(
+      sign("key", 10, "{}"),
+      format!("sha256={}", hex(&hmac_sha256(b"key", b"10.{}")))
+    );
+  }
+}
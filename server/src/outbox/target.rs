@@ -0,0 +1,199 @@
+use std::{
+  io,
+  net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+};
+
+use ureq::Resolver;
+use url::{Host, Url};
+
+/// Resolves the hosts of the webhooks to their public addresses only, so a
+/// webhook can't reach the server itself, its private network or the
+/// metadata service of its cloud, even if its DNS changes after it was
+/// registered.
+pub struct PublicResolver;
+
+impl Resolver for PublicResolver {
+  fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    public_addresses(netloc.to_socket_addrs()?)
+  }
+}
+
+/// Checks that a webhook URL is http or https and that its host resolves to
+/// public addresses only.
+///
+/// # Arguments
+/// * `url` - The URL of the webhook.
+///
+/// # Return
+/// * Why the URL isn't valid.
+pub fn check_url(url: &str) -> Result<(), String> {
+  let url = Url::parse(url).map_err(|_| String::from("the url isn't valid"))?;
+  if url.scheme() != "http" && url.scheme() != "https" {
+    return Err(String::from("the url must be http or https"));
+  }
+  let port = url.port_or_known_default().unwrap_or(80);
+  let resolved = match url.host() {
+    Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+    Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+    Some(Host::Domain(domain)) => (domain, port)
+      .to_socket_addrs()
+      .map_err(|_| String::from("the host of the url can't be resolved"))?
+      .collect(),
+    None => return Err(String::from("the url has no host")),
+  };
+  if resolved.iter().any(|address| !is_public(address.ip())) {
+    return Err(String::from("the url must only reach public addresses"));
+  }
+  Ok(())
+}
+
+/// Keeps the public addresses of a host.
+///
+/// # Arguments
+/// * `addresses` - The addresses the host resolves to.
+///
+/// # Return
+/// * The public addresses.
+/// * An error if there is none.
+fn public_addresses(
+  addresses: impl Iterator<Item = SocketAddr>,
+) -> io::Result<Vec<SocketAddr>> {
+  let public = addresses
+    .filter(|address| is_public(address.ip()))
+    .collect::<Vec<SocketAddr>>();
+  if public.is_empty() {
+    return Err(io::Error::new(
+      io::ErrorKind::PermissionDenied,
+      "the host has no public address",
+    ));
+  }
+  Ok(public)
+}
+
+/// Checks if an address is reachable from the internet: not a loopback,
+/// private, link-local, shared, benchmarking, multicast or reserved address.
+/// The IPv6 addresses that embed an IPv4 one, mapped, NAT64 or 6to4, are
+/// checked by the embedded address.
+///
+/// # Arguments
+/// * `ip` - The address.
+///
+/// # Return
+/// * True if it's public.
+fn is_public(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [first, second, third, _] = ip.octets();
+      !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || first == 0
+        || first >= 240
+        || (first == 100 && second & 0xc0 == 64)
+        || (first == 192 && second == 0 && third == 0)
+        || (first == 198 && second & 0xfe == 18))
+    },
+    IpAddr::V6(ip) => {
+      if let Some(mapped) = ip.to_ipv4() {
+        return is_public(IpAddr::V4(mapped));
+      }
+      let segments = ip.segments();
+      let embedded = match segments {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some((high, low)),
+        [0x2002, high, low, ..] => Some((high, low)),
+        _ => None,
+      };
+      if let Some((high, low)) = embedded {
+        let ipv4 = Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+        return is_public(IpAddr::V4(ipv4));
+      }
+      let first = segments[0];
+      !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || first & 0xfe00 == 0xfc00
+        || first & 0xffc0 == 0xfe80)
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn private_addresses() {
+    for ip in [
+      "127.0.0.1",
+      "10.1.2.3",
+      "172.16.0.1",
+      "192.168.1.1",
+      "169.254.169.254",
+      "100.64.0.1",
+      "0.0.0.0",
+      "::1",
+      "fd00::1",
+      "fe80::1",
+      "::ffff:127.0.0.1",
+    ] {
+      assert!(!is_public(ip.parse().unwrap()), "{} is private", ip);
+    }
+    assert!(is_public("93.184.216.34".parse().unwrap()));
+    assert!(is_public("2606:2800:220:1::1".parse().unwrap()));
+  }
+
+  #[test]
+  fn reserved_addresses() {
+    for ip in ["192.0.0.8", "192.0.0.170", "198.18.0.1", "198.19.255.254"] {
+      assert!(!is_public(ip.parse().unwrap()), "{} is reserved", ip);
+    }
+    assert!(is_public("192.0.1.1".parse().unwrap()));
+    assert!(is_public("198.20.0.1".parse().unwrap()));
+  }
+
+  #[test]
+  fn nat64_addresses() {
+    for ip in ["64:ff9b::7f00:1", "64:ff9b::a9fe:a9fe", "64:ff9b::c612:1"] {
+      assert!(!is_public(ip.parse().unwrap()), "{} is private", ip);
+    }
+    assert!(is_public("64:ff9b::5db8:d822".parse().unwrap()));
+  }
+
+  #[test]
+  fn six_to_four_addresses() {
+    for ip in ["2002:7f00:1::", "2002:a00:1::1", "2002:a9fe:a9fe::1"] {
+      assert!(!is_public(ip.parse().unwrap()), "{} is private", ip);
+    }
+    assert!(is_public("2002:5db8:d822::1".parse().unwrap()));
+  }
+
+  #[test]
+  fn check_the_urls() {
+    assert_eq!(check_url("https://93.184.216.34/hook"), Ok(()));
+    assert_eq!(
+      check_url("ftp://93.184.216.34/hook"),
+      Err(String::from("the url must be http or https"))
+    );
+    assert_eq!(
+      check_url("http://169.254.169.254/latest/meta-data"),
+      Err(String::from("the url must only reach public addresses"))
+    );
+    assert_eq!(
+      check_url("http://[::1]:8081/hook"),
+      Err(String::from("the url must only reach public addresses"))
+    );
+  }
+
+  #[test]
+  fn resolve_only_the_public_addresses() {
+    assert!(PublicResolver.resolve("127.0.0.1:80").is_err());
+    assert_eq!(
+      PublicResolver.resolve("93.184.216.34:443").unwrap(),
+      vec!["93.184.216.34:443".parse::<SocketAddr>().unwrap()]
+    );
+  }
+}
@@ -0,0 +1,362 @@
+use std::{thread, time::Duration};
+
+use chrono::Utc;
+use ureq::{Agent, AgentBuilder, ErrorKind};
+
+use crate::{
+  model::{
+    repository::{error::RepoResult, webhook_repository::WebhookRepository},
+    webhook::{Delivery, Webhook},
+  },
+  outbox::{
+    signature::{sign, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    target::PublicResolver,
+  },
+};
+
+/// The quantity of deliveries sent in a round.
+const BATCH: i64 = 20;
+/// How long the worker sleeps when the outbox is empty.
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a webhook can take to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The attempts of a delivery before it goes to the dead-letter list.
+const MAX_ATTEMPTS: i32 = 8;
+/// The wait after the first failed attempt, doubled after every other one.
+const BASE_BACKOFF_SECS: i64 = 30;
+
+/// Sends the deliveries of the outbox to their webhooks, in its own thread.
+/// A failed delivery is attempted again with an exponential backoff, and
+/// after 8 attempts it goes to the dead-letter list. The deliveries are sent
+/// at least once, the receivers discard the duplicates by the
+/// `X-Webhook-Id` header. The requests only reach public addresses and
+/// don't follow redirects, an answer other than 2xx is a failed attempt.
+pub struct WebhookWorker<WebhookRepo> {
+  webhook_repository: WebhookRepo,
+  agent: Agent,
+}
+
+impl<WebhookRepo> WebhookWorker<WebhookRepo>
+where
+  WebhookRepo: WebhookRepository + Send + 'static,
+{
+  pub fn new(the_webhook_repository: WebhookRepo) -> Self {
+    WebhookWorker {
+      webhook_repository: the_webhook_repository,
+      agent: AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .redirects(0)
+        .resolver(PublicResolver)
+        .build(),
+    }
+  }
+
+  /// Starts sending the outbox in a new thread.
+  pub fn start(self) {
+    thread::spawn(move || loop {
+      match self.run_once() {
+        Ok(0) => thread::sleep(IDLE_INTERVAL),
+        Ok(_) => (),
+        Err(err) => {
+          log::error!("cannot read the webhook outbox: {}", err);
+          thread::sleep(IDLE_INTERVAL);
+        },
+      }
+    });
+  }
+
+  /// Sends the deliveries that are due.
+  ///
+  /// # Return
+  /// * The quantity of deliveries attempted.
+  /// * A repository error if the outbox can't be read.
+  fn run_once(&self) -> RepoResult<usize> {
+    let due = self
+      .webhook_repository
+      .find_due(Utc::now().naive_utc(), BATCH)?;
+    for (delivery, webhook) in &due {
+      if let Err(err) = self.deliver(delivery, webhook) {
+        log::error!(
+          "cannot update the delivery {}: {}",
+          delivery.get_id(),
+          err
+        );
+      }
+    }
+    Ok(due.len())
+  }
+
+  /// Sends a delivery and records the result of the attempt.
+  ///
+  /// # Arguments
+  /// * `delivery` - The delivery.
+  /// * `webhook` - Its webhook.
+  ///
+  /// # Return
+  /// * A repository error if the result can't be recorded.
+  fn deliver(&self, delivery: &Delivery, webhook: &Webhook) -> RepoResult<()> {
+    let body = delivery.get_payload();
+    let timestamp = Utc::now().timestamp();
+    let sent = self
+      .agent
+      .post(&webhook.get_url())
+      .set("Content-Type", "application/json")
+      .set("X-Webhook-Id", &delivery.get_id().to_string())
+      .set("X-Webhook-Event", &delivery.get_event())
+      .set(TIMESTAMP_HEADER, &timestamp.to_string())
+      .set(
+        SIGNATURE_HEADER,
+        &sign(&webhook.get_secret(), timestamp, &body),
+      )
+      .send_string(&body);
+    let error = match sent {
+      Ok(response) if (200..300).contains(&response.status()) => {
+        self.webhook_repository.mark_delivered(delivery.get_id())?;
+        return Ok(());
+      },
+      Ok(response) => format!("the webhook answered {}", response.status()),
+      Err(err) => {
+        log::debug!("the delivery {} failed: {}", delivery.get_id(), err);
+        describe(&err)
+      },
+    };
+
+    let attempt = delivery.get_attempts() + 1;
+    if attempt >= MAX_ATTEMPTS {
+      log::warn!(
+        "the delivery {} to the user {} is dead: {}",
+        delivery.get_id(),
+        webhook.get_user_id(),
+        error
+      );
+      self
+        .webhook_repository
+        .mark_dead(delivery.get_id(), error)?;
+    } else {
+      self.webhook_repository.reschedule(
+        delivery.get_id(),
+        Utc::now().naive_utc() + backoff(attempt),
+        error,
+      )?;
+    }
+    Ok(())
+  }
+}
+
+/// Describes a failed request for the owner of the webhook, without the
+/// addresses or the messages of the network of the server.
+///
+/// # Arguments
+/// * `err` - The error of the request.
+///
+/// # Return
+/// * The description.
+fn describe(err: &ureq::Error) -> String {
+  match err {
+    ureq::Error::Status(status, _) => {
+      format!("the webhook answered {}", status)
+    },
+    ureq::Error::Transport(transport) => String::from(match transport.kind() {
+      ErrorKind::Dns => "the host can't be resolved to a public address",
+      ErrorKind::ConnectionFailed => "the connection failed",
+      ErrorKind::Io => "the request was interrupted or timed out",
+      _ => "the request failed",
+    }),
+  }
+}
+
+/// Get how long to wait after a failed attempt: 30 seconds after the first
+/// one, doubled after every other one.
+///
+/// # Arguments
+/// * `attempt` - The number of the failed attempt, from 1.
+///
+/// # Return
+/// * The wait before the next attempt.
+fn backoff(attempt: i32) -> chrono::Duration {
+  chrono::Duration::seconds(BASE_BACKOFF_SECS << (attempt - 1))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{
+    repository::webhook_repository::MockWebhookRepository, webhook::Builder,
+  };
+  use mockall::predicate::{always, eq};
+  use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver},
+  };
+
+  /// Builds a worker that reaches the loopback stand-ins.
+  fn worker_with(
+    mock_repo: MockWebhookRepository,
+  ) -> WebhookWorker<MockWebhookRepository> {
+    WebhookWorker {
+      webhook_repository: mock_repo,
+      agent: AgentBuilder::new().redirects(0).build(),
+    }
+  }
+
+  /// Starts a stand-in for a webhook that answers one request with the given
+  /// status.
+  ///
+  /// # Return
+  /// * The URL of the webhook.
+  /// * The headers and the body of the request it gets.
+  fn stand_in(status: u16) -> (String, Receiver<(Vec<String>, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut reader = BufReader::new(stream);
+      let mut headers = Vec::new();
+      loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+          break;
+        }
+        headers.push(line.trim_end().to_lowercase());
+      }
+      let length = headers
+        .iter()
+        .find_map(|header| header.strip_prefix("content-length: "))
+        .map_or(0, |length| length.parse().unwrap());
+      let mut body = vec![0; length];
+      reader.read_exact(&mut body).unwrap();
+      write!(
+        reader.get_mut(),
+        "HTTP/1.1 {} Status\r\nContent-Length: 0\r\n\r\n",
+        status
+      )
+      .unwrap();
+      sender
+        .send((headers, String::from_utf8(body).unwrap()))
+        .unwrap();
+    });
+    (url, receiver)
+  }
+
+  #[test]
+  fn backoff_doubles() {
+    assert_eq!(backoff(1), chrono::Duration::seconds(30));
+    assert_eq!(backoff(3), chrono::Duration::seconds(120));
+  }
+
+  #[test]
+  fn deliver_a_signed_request() {
+    let (url, received) = stand_in(200);
+    let webhook = Builder::new().with_id(2).with_url(&url);
+    let delivery = webhook.delivery(7, 0, "{\"event\":\"message.created\"}");
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo
+      .expect_mark_delivered()
+      .with(eq(7))
+      .times(1)
+      .returning(|_| Ok(1));
+    mock_repo.expect_reschedule().times(0);
+
+    let worker = worker_with(mock_repo);
+    worker.deliver(&delivery, &webhook.build()).unwrap();
+
+    let (headers, body) = received.recv().unwrap();
+    assert_eq!(body, "{\"event\":\"message.created\"}");
+    assert!(headers.contains(&String::from("x-webhook-id: 7")));
+    assert!(headers.contains(&String::from("x-webhook-event: message.created")));
+    let timestamp = headers
+      .iter()
+      .find_map(|header| header.strip_prefix("x-webhook-timestamp: "))
+      .unwrap()
+      .parse()
+      .unwrap();
+    assert!(headers.contains(&format!(
+      "x-webhook-signature: {}",
+      sign("secret", timestamp, &body)
+    )));
+  }
+
+  #[test]
+  fn reschedule_a_failed_delivery() {
+    let (url, _received) = stand_in(500);
+    let webhook = Builder::new().with_url(&url);
+    let delivery = webhook.delivery(7, 2, "{}");
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_mark_delivered().times(0);
+    mock_repo
+      .expect_reschedule()
+      .with(eq(7), always(), always())
+      .times(1)
+      .returning(|_, next_attempt, _| {
+        let wait = next_attempt - Utc::now().naive_utc();
+        assert!(wait > chrono::Duration::seconds(110));
+        Ok(1)
+      });
+
+    let worker = worker_with(mock_repo);
+    worker.deliver(&delivery, &webhook.build()).unwrap();
+  }
+
+  #[test]
+  fn do_not_follow_a_redirect() {
+    let (url, _received) = stand_in(302);
+    let webhook = Builder::new().with_url(&url);
+    let delivery = webhook.delivery(7, 0, "{}");
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_mark_delivered().times(0);
+    mock_repo
+      .expect_reschedule()
+      .with(
+        eq(7),
+        always(),
+        eq(String::from("the webhook answered 302")),
+      )
+      .times(1)
+      .returning(|_, _, _| Ok(1));
+
+    let worker = worker_with(mock_repo);
+    worker.deliver(&delivery, &webhook.build()).unwrap();
+  }
+
+  #[test]
+  fn do_not_reach_a_private_address() {
+    let webhook = Builder::new().with_url("http://127.0.0.1:8081/hook");
+    let delivery = webhook.delivery(7, 0, "{}");
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_mark_delivered().times(0);
+    mock_repo
+      .expect_reschedule()
+      .with(
+        eq(7),
+        always(),
+        eq(String::from(
+          "the host can't be resolved to a public address",
+        )),
+      )
+      .times(1)
+      .returning(|_, _, _| Ok(1));
+
+    let worker = WebhookWorker::new(mock_repo);
+    worker.deliver(&delivery, &webhook.build()).unwrap();
+  }
+
+  #[test]
+  fn dead_letter_after_the_last_attempt() {
+    let (url, _received) = stand_in(503);
+    let webhook = Builder::new().with_url(&url);
+    let delivery = webhook.delivery(7, MAX_ATTEMPTS - 1, "{}");
+    let mut mock_repo = MockWebhookRepository::new();
+    mock_repo.expect_reschedule().times(0);
+    mock_repo
+      .expect_mark_dead()
+      .with(eq(7), always())
+      .times(1)
+      .returning(|_, _| Ok(1));
+
+    let worker = worker_with(mock_repo);
+    worker.deliver(&delivery, &webhook.build()).unwrap();
+  }
+}
@@ -0,0 +1,3 @@
+pub mod event;
+pub mod hub;
+pub mod server;
@@ -0,0 +1,87 @@
+use serde::Serialize;
+
+use crate::{
+  application::session_handler::to_rfc3339, model::message::Message,
+};
+
+/// A change pushed to the connected users. It's sent as a JSON text frame
+/// tagged with its `type`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+  /// A new message, direct or of a group conversation.
+  Message {
+    id: i32,
+    from: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversation_id: Option<i32>,
+    message: String,
+    created_at: String,
+  },
+  /// The new content of an edited message.
+  Edited {
+    id: i32,
+    message: String,
+    updated_at: String,
+  },
+  /// A deleted message, the readers keep it as a tombstone.
+  Deleted { id: i32 },
+  /// A message read by one of its recipients.
+  Read { message_id: i32, user_id: i32 },
+  /// The messages of a direct conversation read up to the given one.
+  ConversationRead { user_id: i32, until: i32 },
+}
+
+impl Event {
+  pub fn message(msg: &Message) -> Event {
+    Event::Message {
+      id: msg.get_id(),
+      from: msg.get_from(),
+      to: msg.get_to(),
+      conversation_id: msg.get_conversation_id(),
+      message: msg.get_message(),
+      created_at: to_rfc3339(msg.get_created_at()),
+    }
+  }
+
+  pub fn edited(msg: &Message) -> Event {
+    Event::Edited {
+      id: msg.get_id(),
+      message: msg.get_message(),
+      updated_at: to_rfc3339(msg.get_updated_at()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::message::Builder;
+
+  #[test]
+  fn events_are_tagged_with_their_type() {
+    let msg = Builder::new()
+      .with_id(4)
+      .with_from(1)
+      .with_to(2)
+      .with_message("Hi")
+      .build();
+    assert_eq!(
+      serde_json::to_string(&Event::message(&msg)).unwrap(),
+      concat!(
+        "{\"type\":\"message\",\"id\":4,\"from\":1,\"to\":2,",
+        "\"message\":\"Hi\",\"created_at\":\"1970-01-01T00:00:00+00:00\"}",
+      )
+    );
+    assert_eq!(
+      serde_json::to_string(&Event::ConversationRead {
+        user_id: 2,
+        until: 4
+      })
+      .unwrap(),
+      "{\"type\":\"conversation_read\",\"user_id\":2,\"until\":4}"
+    );
+  }
+}
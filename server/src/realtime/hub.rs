@@ -0,0 +1,156 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    mpsc::{channel, Receiver, Sender, TryIter},
+    Mutex,
+  },
+  time::Duration,
+};
+
+use crate::realtime::event::Event;
+
+/// The events published for a user while its subscription is alive. Every
+/// connection of the user has its own subscription.
+pub struct Subscription {
+  receiver: Receiver<Event>,
+}
+
+impl Subscription {
+  /// The events received since the last call, without waiting for new ones.
+  pub fn pending(&self) -> TryIter<'_, Event> {
+    return self.receiver.try_iter();
+  }
+
+  /// Waits for the next event.
+  ///
+  /// # Arguments
+  /// * `timeout` - How long to wait.
+  ///
+  /// # Return
+  /// * The event, or None if the timeout expired first.
+  pub fn wait(&self, timeout: Duration) -> Option<Event> {
+    return self.receiver.recv_timeout(timeout).ok();
+  }
+}
+
+/// An in-process publish/subscribe hub that routes the events to the
+/// connections of their users. A dropped subscription is forgotten the next
+/// time an event is published for its user.
+#[derive(Default)]
+pub struct Hub {
+  subscribers: Mutex<HashMap<i32, Vec<Sender<Event>>>>,
+}
+
+impl Hub {
+  /// Subscribes a new connection of a user. Subscribe before reading the
+  /// messages the user missed: the ones sent meanwhile are then queued
+  /// instead of lost, a message could arrive twice and the clients discard
+  /// it by its id.
+  ///
+  /// # Arguments
+  /// * `uid` - The id of the user.
+  ///
+  /// # Return
+  /// * The subscription that receives the events of the user.
+  pub fn subscribe(&self, uid: i32) -> Subscription {
+    let (sender, receiver) = channel();
+    self
+      .subscribers
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+      .entry(uid)
+      .or_default()
+      .push(sender);
+    Subscription {
+      receiver,
+    }
+  }
+
+  /// Publishes an event to every connection of the given users. The users
+  /// without connections are ignored.
+  ///
+  /// # Arguments
+  /// * `uids` - The ids of the users.
+  /// * `event` - The event.
+  pub fn publish(&self, uids: &[i32], event: &Event) {
+    let mut subscribers = self
+      .subscribers
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner());
+    for uid in uids {
+      if let Some(senders) = subscribers.get_mut(uid) {
+        senders.retain(|sender| sender.send(event.clone()).is_ok());
+        if senders.is_empty() {
+          subscribers.remove(uid);
+        }
+      }
+    }
+  }
+
+  /// Counts the subscriptions of a user, the dropped ones included until the
+  /// next event of the user is published.
+  #[cfg(test)]
+  pub fn subscriptions(&self, uid: i32) -> usize {
+    self
+      .subscribers
+      .lock()
+      .unwrap()
+      .get(&uid)
+      .map_or(0, |senders| senders.len())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn publish_only_to_the_given_users() {
+    let hub = Hub::default();
+    let first = hub.subscribe(1);
+    let second = hub.subscribe(1);
+    let other = hub.subscribe(2);
+
+    hub.publish(
+      &[1, 3],
+      &Event::Deleted {
+        id: 4,
+      },
+    );
+
+    assert_eq!(
+      first.pending().collect::<Vec<Event>>(),
+      vec![Event::Deleted {
+        id: 4
+      }]
+    );
+    assert_eq!(second.pending().count(), 1);
+    assert_eq!(other.wait(Duration::from_millis(10)), None);
+  }
+
+  #[test]
+  fn dropped_subscriptions_are_forgotten() {
+    let hub = Hub::default();
+    let kept = hub.subscribe(1);
+    drop(hub.subscribe(1));
+    assert_eq!(hub.subscriptions(1), 2);
+
+    hub.publish(
+      &[1],
+      &Event::Deleted {
+        id: 4,
+      },
+    );
+    assert_eq!(hub.subscriptions(1), 1);
+    assert_eq!(kept.pending().count(), 1);
+
+    drop(kept);
+    hub.publish(
+      &[1],
+      &Event::Deleted {
+        id: 5,
+      },
+    );
+    assert_eq!(hub.subscriptions(1), 0);
+  }
+}
@@ -0,0 +1,450 @@
+use std::{
+  env,
+  io::{self, Write},
+  net::{SocketAddr, TcpListener, TcpStream},
+  sync::Arc,
+  thread,
+  time::{Duration, Instant},
+};
+
+use dotenv::dotenv;
+use tungstenite::{
+  handshake::server::{ErrorResponse, Request},
+  http::StatusCode,
+  Error,
+};
+
+use crate::{
+  application::polling::Polls,
+  auth::{
+    middleware::{AccessToken, SESSION_COOKIE},
+    token::{Session, BEARER},
+  },
+  realtime::{event::Event, hub::Hub},
+  Authenticator, MessageService,
+};
+
+mod sse;
+mod websocket;
+
+/// The address of the real-time server when `ws_address` isn't set.
+const DEFAULT_ADDRESS: &str = "0.0.0.0:8082";
+/// The quantity of open connections when `ws_max_connections` isn't set.
+const DEFAULT_MAX_CONNECTIONS: usize = 1024;
+/// How often the session of an open connection is checked again, so a
+/// logout, a revoked session or a suspension closes it.
+const REVALIDATE_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the server checks that the clients are still there.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a client can take to send its request.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The longest request the server reads to choose the protocol.
+const HEAD_LIMIT: usize = 8192;
+/// The quantity of messages replayed at once when a client resumes.
+const RESUME_PAGE: i64 = 100;
+
+/// The answer to the connections over the limit.
+const UNAVAILABLE: &[u8] = b"HTTP/1.1 503 Service Unavailable\r\n\
+  Content-Length: 0\r\n\
+  Connection: close\r\n\r\n";
+
+/// What the request of a client carries: the access token, in the Bearer
+/// form, from the `Authorization` or `x-access-token` headers or, for the
+/// browsers, the session cookie, and the id of the last message received
+/// before a reconnection. The token isn't taken from the query, it would end
+/// up in the logs of the proxies.
+#[derive(Debug, Default, PartialEq)]
+struct Handshake {
+  token: Option<String>,
+  last_id: Option<i32>,
+}
+
+impl Handshake {
+  fn from_request(request: &Request) -> Handshake {
+    let mut handshake = Handshake::default();
+    for (key, value) in request
+      .uri()
+      .query()
+      .unwrap_or_default()
+      .split('&')
+      .filter_map(|param| param.split_once('='))
+    {
+      if key == "last_id" {
+        handshake.last_id = value.parse().ok();
+      }
+    }
+    handshake.token = ["Authorization", "x-access-token"]
+      .iter()
+      .find_map(|name| request.headers().get(*name))
+      .and_then(|header| header.to_str().ok())
+      .map(String::from)
+      .or_else(|| {
+        request
+          .headers()
+          .get_all("Cookie")
+          .iter()
+          .filter_map(|header| header.to_str().ok())
+          .flat_map(|cookies| cookies.split(';'))
+          .filter_map(|cookie| cookie.trim().split_once('='))
+          .find(|(name, _)| *name == SESSION_COOKIE)
+          .map(|(_, value)| format!("{}{}", BEARER, value))
+      });
+    handshake
+  }
+}
+
+/// Pushes the events of the hub to the users connected in real time, by
+/// WebSocket or, for the clients behind proxies that block the upgrades, by
+/// Server-Sent Events. Rocket can neither upgrade its connections nor flush a
+/// streamed body, so the server listens on its own address and every
+/// connection runs in its own thread, as many as `with_max_connections`
+/// allows.
+pub struct RealtimeServer {
+  hub: Arc<Hub>,
+  authenticator: Arc<dyn Authenticator>,
+  message_service: Arc<dyn MessageService>,
+  revalidate_interval: Duration,
+  connections: Polls,
+}
+
+impl RealtimeServer {
+  pub fn new(
+    the_hub: Arc<Hub>,
+    the_authenticator: Arc<dyn Authenticator>,
+    the_message_service: Arc<dyn MessageService>,
+  ) -> Self {
+    RealtimeServer {
+      hub: the_hub,
+      authenticator: the_authenticator,
+      message_service: the_message_service,
+      revalidate_interval: REVALIDATE_INTERVAL,
+      connections: Polls::new(DEFAULT_MAX_CONNECTIONS),
+    }
+  }
+
+  /// Limits the quantity of open connections, the next ones are answered
+  /// with 503 Service Unavailable.
+  ///
+  /// # Arguments
+  /// * `max_connections` - The quantity of connections served at once.
+  pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+    self.connections = Polls::new(max_connections);
+    self
+  }
+
+  /// Starts listening in a new thread.
+  ///
+  /// # Arguments
+  /// * `address` - The address to listen on.
+  ///
+  /// # Return
+  /// * The address the server is listening on.
+  /// * An error if the address can't be bound.
+  pub fn start(self, address: &str) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    let server = Arc::new(self);
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        match stream {
+          Ok(stream) => {
+            let server = server.clone();
+            thread::spawn(move || server.accept(stream));
+          },
+          Err(err) => log::error!("cannot accept a connection: {}", err),
+        }
+      }
+    });
+    log::info!("real-time server listening on {}", local_address);
+    Ok(local_address)
+  }
+
+  /// Serves a new connection if the limit isn't reached, refuses it
+  /// otherwise.
+  ///
+  /// # Arguments
+  /// * `stream` - The connection of the client.
+  fn accept(&self, mut stream: TcpStream) {
+    let _permit = match self.connections.acquire() {
+      Some(permit) => permit,
+      None => {
+        log::warn!("too many real-time connections");
+        let _ = stream.write_all(UNAVAILABLE);
+        return;
+      },
+    };
+    if let Err(err) = self.serve(stream) {
+      log::debug!("real-time connection closed: {}", err);
+    }
+  }
+
+  /// Serves a connection with the protocol its request asks for: the event
+  /// stream for `GET /message/stream`, WebSocket otherwise.
+  ///
+  /// # Arguments
+  /// * `stream` - The connection of the client.
+  ///
+  /// # Return
+  /// * An error if the connection is broken.
+  fn serve(&self, stream: TcpStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let head = peek_head(&stream)?;
+    match parse_request(&String::from_utf8_lossy(&head)) {
+      Some(request) if request.uri().path() == sse::PATH => {
+        self.serve_events(stream, &request, head.len())
+      },
+      _ => self.serve_websocket(stream),
+    }
+  }
+
+  /// Opens the session of a handshake with its access token.
+  ///
+  /// # Arguments
+  /// * `handshake` - The handshake request.
+  ///
+  /// # Return
+  /// * The session of the owner of the token.
+  /// * The 401 Unauthorized response if the token is missing or invalid.
+  fn authenticate(
+    &self,
+    handshake: &Handshake,
+  ) -> Result<Session, ErrorResponse> {
+    handshake
+      .token
+      .as_ref()
+      .ok_or_else(|| String::from("missing access token"))
+      .and_then(|token| {
+        self
+          .authenticator
+          .open_session(&AccessToken::new(token))
+          .map_err(|err| err.to_string())
+      })
+      .map_err(|err| {
+        log::debug!("real-time connection rejected: {}", err);
+        let mut response =
+          ErrorResponse::new(Some(String::from("Access denied")));
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        response
+      })
+  }
+
+  /// Checks again the session of an open connection, once in a
+  /// `revalidate_interval`. The access token of the handshake isn't checked
+  /// again, it expires long before the connection ends.
+  ///
+  /// # Arguments
+  /// * `session` - The session of the connection.
+  /// * `last_check` - When the session was last checked, reset by the check.
+  ///
+  /// # Return
+  /// * false if the session was closed.
+  fn still_authorized(
+    &self,
+    session: &Session,
+    last_check: &mut Instant,
+  ) -> bool {
+    if last_check.elapsed() < self.revalidate_interval {
+      return true;
+    }
+    *last_check = Instant::now();
+    match self.authenticator.check_session(session) {
+      Ok(()) => true,
+      Err(err) => {
+        log::debug!(
+          "the access of the user {} was revoked: {}",
+          session.get_uid(),
+          err
+        );
+        false
+      },
+    }
+  }
+
+  /// Marks a pushed message as delivered if the user received it.
+  ///
+  /// # Arguments
+  /// * `uid` - The connected user.
+  /// * `event` - The pushed event.
+  fn delivered(&self, uid: i32, event: &Event) {
+    if let Event::Message {
+      id,
+      from,
+      ..
+    } = event
+    {
+      if *from != uid {
+        if let Err(err) = self.message_service.deliver(uid, vec![*id]) {
+          log::warn!("cannot deliver the message {}: {}", id, err);
+        }
+      }
+    }
+  }
+}
+
+/// Reads the head of a request, the request line and the headers, without
+/// consuming it, so the WebSocket handshake can still read it.
+///
+/// # Arguments
+/// * `stream` - The connection of the client.
+///
+/// # Return
+/// * The head, its blank line included, or what arrived of it if it's too long
+///   or the client stopped sending.
+/// * An error if nothing arrived in time.
+fn peek_head(stream: &TcpStream) -> io::Result<Vec<u8>> {
+  let mut buffer = vec![0; HEAD_LIMIT];
+  let started = Instant::now();
+  loop {
+    let read = stream.peek(&mut buffer)?;
+    if let Some(end) = buffer[..read]
+      .windows(4)
+      .position(|window| window == b"\r\n\r\n")
+    {
+      buffer.truncate(end + 4);
+      return Ok(buffer);
+    }
+    if read == 0 || read == HEAD_LIMIT || started.elapsed() > HANDSHAKE_TIMEOUT
+    {
+      buffer.truncate(read);
+      return Ok(buffer);
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+}
+
+/// Parses the head of a request.
+///
+/// # Arguments
+/// * `head` - The request line and the headers.
+///
+/// # Return
+/// * The request, or None if the head isn't valid.
+fn parse_request(head: &str) -> Option<Request> {
+  let mut lines = head.split("\r\n");
+  let mut builder = Request::builder().uri(lines.next()?.split(' ').nth(1)?);
+  for line in lines.take_while(|line| !line.is_empty()) {
+    let (name, value) = line.split_once(':')?;
+    builder = builder.header(name.trim(), value.trim());
+  }
+  builder.body(()).ok()
+}
+
+/// Get the address of the real-time server from the `ws_address` variable.
+///
+/// # Return
+/// * The address, `DEFAULT_ADDRESS` if it isn't set.
+pub fn realtime_address() -> String {
+  dotenv().ok();
+  env::var("ws_address").unwrap_or_else(|_| String::from(DEFAULT_ADDRESS))
+}
+
+/// Get the limit of open real-time connections from the
+/// `ws_max_connections` variable.
+///
+/// # Return
+/// * The limit, `DEFAULT_MAX_CONNECTIONS` if it isn't set or valid.
+pub fn realtime_max_connections() -> usize {
+  dotenv().ok();
+  env::var("ws_max_connections")
+    .ok()
+    .and_then(|max| max.parse().ok())
+    .unwrap_or(DEFAULT_MAX_CONNECTIONS)
+}
+
+/// Builds a server with mocks.
+#[cfg(test)]
+fn server_with(
+  hub: Arc<Hub>,
+  mock_auth: crate::auth::token::MockAuthenticator,
+  mock_ms: crate::model::message_service::MockMessageService,
+) -> RealtimeServer {
+  RealtimeServer::new(hub, Arc::new(mock_auth), Arc::new(mock_ms))
+}
+
+/// Starts a server with mocks on a free port.
+#[cfg(test)]
+fn start_with(
+  hub: Arc<Hub>,
+  mock_auth: crate::auth::token::MockAuthenticator,
+  mock_ms: crate::model::message_service::MockMessageService,
+) -> SocketAddr {
+  server_with(hub, mock_auth, mock_ms)
+    .start("127.0.0.1:0")
+    .expect("free port")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::token::MockAuthenticator, model::message_service::MockMessageService,
+  };
+  use std::io::Read;
+  use tungstenite::client::IntoClientRequest;
+
+  #[test]
+  fn handshake_takes_the_cookie_and_the_last_id() {
+    let mut request = "ws://localhost/?access_token=abc&last_id=7"
+      .into_client_request()
+      .unwrap();
+    request
+      .headers_mut()
+      .insert("Cookie", "theme=dark; session=def".parse().unwrap());
+    assert_eq!(
+      Handshake::from_request(&request),
+      Handshake {
+        token: Some(String::from("Bearer def")),
+        last_id: Some(7),
+      }
+    );
+  }
+
+  #[test]
+  fn handshake_ignores_the_token_in_the_query() {
+    let request = "ws://localhost/?access_token=abc"
+      .into_client_request()
+      .unwrap();
+    assert_eq!(Handshake::from_request(&request), Handshake::default());
+  }
+
+  #[test]
+  fn handshake_prefers_the_header() {
+    let request = parse_request(
+      "GET /message/stream HTTP/1.1\r\nCookie: session=abc\r\nx-access-token: \
+       Bearer def\r\n\r\n",
+    )
+    .unwrap();
+    assert_eq!(request.uri().path(), "/message/stream");
+    assert_eq!(
+      Handshake::from_request(&request),
+      Handshake {
+        token: Some(String::from("Bearer def")),
+        last_id: None,
+      }
+    );
+  }
+
+  #[test]
+  fn refuse_the_connections_over_the_limit() {
+    let address = server_with(
+      Arc::new(Hub::default()),
+      MockAuthenticator::new(),
+      MockMessageService::new(),
+    )
+    .with_max_connections(1)
+    .start("127.0.0.1:0")
+    .expect("free port");
+
+    let _idle = TcpStream::connect(address).unwrap();
+    let mut refused = TcpStream::connect(address).unwrap();
+    let mut answer = String::new();
+    refused.read_to_string(&mut answer).unwrap();
+    assert!(answer.starts_with("HTTP/1.1 503 Service Unavailable"));
+  }
+
+  #[test]
+  fn parse_an_invalid_request() {
+    assert!(parse_request("GET\r\n\r\n").is_none());
+    assert!(parse_request("GET / HTTP/1.1\r\nno header\r\n\r\n").is_none());
+  }
+}
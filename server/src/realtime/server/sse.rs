@@ -0,0 +1,318 @@
+use std::{
+  io::{self, Read, Write},
+  net::TcpStream,
+  time::Instant,
+};
+
+use serde::Serialize;
+use serde_json::json;
+use tungstenite::{handshake::server::Request, Error};
+
+use crate::realtime::{
+  event::Event,
+  server::{Handshake, RealtimeServer, HEARTBEAT_INTERVAL, RESUME_PAGE},
+};
+
+/// The path of the event stream.
+pub(super) const PATH: &str = "/message/stream";
+
+/// The head of the event stream. The stream ends when the connection does,
+/// and `X-Accel-Buffering` keeps nginx from buffering it.
+const STREAM_HEAD: &[u8] = b"HTTP/1.1 200 OK\r\n\
+  Content-Type: text/event-stream\r\n\
+  Cache-Control: no-cache\r\n\
+  X-Accel-Buffering: no\r\n\
+  Connection: close\r\n\r\n";
+
+impl RealtimeServer {
+  /// Streams the events of a user as Server-Sent Events until the client
+  /// leaves or its session is closed. The messages carry their id, so
+  /// a client reconnecting with the `Last-Event-ID` header, or the `last_id`
+  /// query param, gets the messages it missed.
+  ///
+  /// # Arguments
+  /// * `stream` - The connection of the client.
+  /// * `request` - The request of the client, only peeked.
+  /// * `head_len` - The length of the request.
+  ///
+  /// # Return
+  /// * An error if the connection is broken.
+  pub(super) fn serve_events(
+    &self,
+    mut stream: TcpStream,
+    request: &Request,
+    head_len: usize,
+  ) -> Result<(), Error> {
+    stream.read_exact(&mut vec![0; head_len])?;
+    let mut handshake = Handshake::from_request(request);
+    if let Some(last_id) = request
+      .headers()
+      .get("Last-Event-ID")
+      .and_then(|header| header.to_str().ok())
+      .and_then(|last_id| last_id.trim().parse().ok())
+    {
+      handshake.last_id = Some(last_id);
+    }
+    let session = match self.authenticate(&handshake) {
+      Ok(session) => session,
+      Err(response) => {
+        let body = response.body().clone().unwrap_or_default();
+        write!(
+          stream,
+          "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: \
+           {}\r\nConnection: close\r\n\r\n{}",
+          response.status(),
+          body.len(),
+          body
+        )?;
+        return Ok(());
+      },
+    };
+    stream.write_all(STREAM_HEAD)?;
+    let uid = session.get_uid();
+
+    let subscription = self.hub.subscribe(uid);
+    if let Some(last_id) = handshake.last_id {
+      self.replay(&mut stream, uid, last_id)?;
+    }
+
+    let mut last_check = Instant::now();
+    loop {
+      match subscription.wait(HEARTBEAT_INTERVAL.min(self.revalidate_interval))
+      {
+        Some(event) => {
+          stream.write_all(frame(event_id(&event), &event)?.as_bytes())?;
+          self.delivered(uid, &event);
+        },
+        // A comment keeps the proxies from closing an idle stream, and the
+        // write fails once the client is gone.
+        None => stream.write_all(b": ping\n\n")?,
+      }
+
+      // Ending the stream makes the client reconnect, and the new request
+      // is refused.
+      if !self.still_authorized(&session, &mut last_check) {
+        return Ok(());
+      }
+    }
+  }
+
+  /// Replays the messages the user received after the given one.
+  ///
+  /// # Arguments
+  /// * `stream` - The connection of the client.
+  /// * `uid` - The connected user.
+  /// * `last_id` - The id of the last message the client got.
+  fn replay(
+    &self,
+    stream: &mut TcpStream,
+    uid: i32,
+    last_id: i32,
+  ) -> Result<(), Error> {
+    let mut last_id = last_id;
+    loop {
+      let messages =
+        match self.message_service.missed(uid, last_id, Some(RESUME_PAGE)) {
+          Ok(messages) => messages,
+          Err(err) => {
+            log::error!("cannot replay the user {}: {}", uid, err);
+            let detail = format!("Cannot replay because {}", err);
+            let error = json!({"type": "error", "detail": detail});
+            stream.write_all(frame(None, &error)?.as_bytes())?;
+            return Ok(());
+          },
+        };
+      for msg in &messages {
+        let event = Event::message(msg);
+        stream.write_all(frame(Some(msg.get_id()), &event)?.as_bytes())?;
+        last_id = msg.get_id();
+      }
+      if (messages.len() as i64) < RESUME_PAGE {
+        return Ok(());
+      }
+    }
+  }
+}
+
+/// Get the id of an event in the stream, the id of the message for the new
+/// messages. The other events keep the last id of the client.
+fn event_id(event: &Event) -> Option<i32> {
+  match event {
+    Event::Message {
+      id, ..
+    } => Some(*id),
+    _ => None,
+  }
+}
+
+/// Formats a value as an event of the stream.
+///
+/// # Arguments
+/// * `id` - The id of the event, if any.
+/// * `value` - The data of the event, as JSON.
+///
+/// # Return
+/// * The event.
+/// * An error if the value can't be serialized.
+fn frame<T: Serialize>(id: Option<i32>, value: &T) -> io::Result<String> {
+  let data = serde_json::to_string(value)
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+  Ok(match id {
+    Some(id) => format!("id: {}\ndata: {}\n\n", id, data),
+    None => format!("data: {}\n\n", data),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::{
+      error::Error::RevokedTokenError,
+      token::{MockAuthenticator, Session},
+    },
+    model::{message::Builder, message_service::MockMessageService},
+    realtime::{
+      hub::Hub,
+      server::{server_with, start_with},
+    },
+  };
+  use mockall::{predicate::eq, Sequence};
+  use std::{
+    io::{BufRead, BufReader},
+    sync::Arc,
+    time::Duration,
+  };
+
+  fn read_line(reader: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+  }
+
+  #[test]
+  fn frame_an_event() {
+    assert_eq!(
+      frame(
+        None,
+        &Event::Deleted {
+          id: 4
+        }
+      )
+      .unwrap(),
+      "data: {\"type\":\"deleted\",\"id\":4}\n\n"
+    );
+  }
+
+  #[test]
+  fn stream_without_a_valid_token() {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+    let address = start_with(
+      Arc::new(Hub::default()),
+      mock_auth,
+      MockMessageService::new(),
+    );
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+      .write_all(b"GET /message/stream HTTP/1.1\r\n\r\n")
+      .unwrap();
+    let mut reader = BufReader::new(stream);
+    assert_eq!(read_line(&mut reader), "HTTP/1.1 401 Unauthorized");
+  }
+
+  #[test]
+  fn end_the_stream_once_the_token_is_revoked() {
+    let mut mock_auth = MockAuthenticator::new();
+    let mut seq = Sequence::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Ok(Session::new(2, 1)));
+    mock_auth
+      .expect_check_session()
+      .with(eq(Session::new(2, 1)))
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Err(RevokedTokenError));
+    let mut server = server_with(
+      Arc::new(Hub::default()),
+      mock_auth,
+      MockMessageService::new(),
+    );
+    server.revalidate_interval = Duration::from_millis(50);
+    let address = server.start("127.0.0.1:0").expect("free port");
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+      .set_read_timeout(Some(Duration::from_secs(5)))
+      .unwrap();
+    stream
+      .write_all(
+        b"GET /message/stream HTTP/1.1\r\n\
+          Authorization: Bearer abc\r\n\r\n",
+      )
+      .unwrap();
+    let mut reader = BufReader::new(stream);
+    assert_eq!(read_line(&mut reader), "HTTP/1.1 200 OK");
+    let mut rest = String::new();
+    reader
+      .read_to_string(&mut rest)
+      .expect("the stream must end");
+  }
+
+  #[test]
+  fn replay_and_stream_the_events() {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .returning(|_| Ok(Session::new(2, 1)));
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_missed()
+      .with(eq(2), eq(3), eq(Some(RESUME_PAGE)))
+      .times(1)
+      .returning(|_, _, _| {
+        Ok(vec![Builder::new()
+          .with_id(5)
+          .with_from(1)
+          .with_to(2)
+          .build()])
+      });
+    let hub = Arc::new(Hub::default());
+    let address = start_with(hub.clone(), mock_auth, mock_ms);
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+      .write_all(
+        b"GET /message/stream HTTP/1.1\r\n\
+          Authorization: Bearer abc\r\n\
+          Last-Event-ID: 3\r\n\r\n",
+      )
+      .unwrap();
+    let mut reader = BufReader::new(stream);
+    assert_eq!(read_line(&mut reader), "HTTP/1.1 200 OK");
+    while !read_line(&mut reader).is_empty() {}
+
+    assert_eq!(read_line(&mut reader), "id: 5");
+    assert!(read_line(&mut reader).starts_with("data: {\"type\":\"message\""));
+    assert_eq!(read_line(&mut reader), "");
+
+    hub.publish(
+      &[2],
+      &Event::Deleted {
+        id: 4,
+      },
+    );
+    assert_eq!(
+      read_line(&mut reader),
+      "data: {\"type\":\"deleted\",\"id\":4}"
+    );
+  }
+}
@@ -0,0 +1,309 @@
+use std::{
+  io,
+  net::TcpStream,
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tungstenite::{
+  accept_hdr,
+  handshake::server::{Request, Response},
+  protocol::{frame::coding::CloseCode, CloseFrame},
+  Error, Message, WebSocket,
+};
+
+use crate::realtime::{
+  event::Event,
+  server::{Handshake, RealtimeServer, HEARTBEAT_INTERVAL, RESUME_PAGE},
+};
+
+/// How long a client can stay silent, pongs included, before it's
+/// disconnected.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+/// How long a read waits for the client before forwarding the events of the
+/// hub.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The frames a client can send, JSON texts tagged with their `type`.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Command {
+  /// An application level ping, for the clients that can't send ping frames.
+  Ping,
+  /// Replays the messages received after the given one.
+  Resume { last_id: i32 },
+}
+
+impl RealtimeServer {
+  /// Serves a WebSocket until the client leaves or stops answering, or its
+  /// session is closed.
+  ///
+  /// # Arguments
+  /// * `stream` - The connection of the client.
+  ///
+  /// # Return
+  /// * An error if the connection is broken.
+  pub(super) fn serve_websocket(&self, stream: TcpStream) -> Result<(), Error> {
+    let mut handshake = Handshake::default();
+    let mut session = None;
+    let mut socket =
+      accept_hdr(stream, |request: &Request, response: Response| {
+        handshake = Handshake::from_request(request);
+        session = Some(self.authenticate(&handshake)?);
+        Ok(response)
+      })
+      .map_err(|err| {
+        Error::Io(io::Error::new(io::ErrorKind::Other, err.to_string()))
+      })?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    let session = session.expect("an accepted handshake opens a session");
+    let uid = session.get_uid();
+
+    let subscription = self.hub.subscribe(uid);
+    if let Some(last_id) = handshake.last_id {
+      self.resume(&mut socket, uid, last_id)?;
+    }
+
+    let mut last_seen = Instant::now();
+    let mut last_ping = Instant::now();
+    let mut last_check = Instant::now();
+    loop {
+      match socket.read_message() {
+        Ok(Message::Text(text)) => {
+          last_seen = Instant::now();
+          self.execute(&mut socket, uid, &text)?;
+        },
+        Ok(_) => last_seen = Instant::now(),
+        Err(Error::Io(err))
+          if matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+          ) => {},
+        Err(Error::ConnectionClosed) => return Ok(()),
+        Err(err) => return Err(err),
+      }
+
+      for event in subscription.pending() {
+        send(&mut socket, &event)?;
+        self.delivered(uid, &event);
+      }
+
+      if last_seen.elapsed() > CLIENT_TIMEOUT {
+        log::debug!("user {} stopped answering", uid);
+        return socket.close(None);
+      } else if last_ping.elapsed() >= HEARTBEAT_INTERVAL {
+        socket.write_message(Message::Ping(vec![]))?;
+        last_ping = Instant::now();
+      }
+
+      if !self.still_authorized(&session, &mut last_check) {
+        return socket.close(Some(CloseFrame {
+          code: CloseCode::Policy,
+          reason: "Access revoked".into(),
+        }));
+      }
+    }
+  }
+
+  /// Runs a command sent by the client.
+  ///
+  /// # Arguments
+  /// * `socket` - The connection of the client.
+  /// * `uid` - The connected user.
+  /// * `text` - The command.
+  fn execute(
+    &self,
+    socket: &mut WebSocket<TcpStream>,
+    uid: i32,
+    text: &str,
+  ) -> Result<(), Error> {
+    match serde_json::from_str::<Command>(text) {
+      Ok(Command::Ping) => send(socket, &json!({"type": "pong"})),
+      Ok(Command::Resume {
+        last_id,
+      }) => self.resume(socket, uid, last_id),
+      Err(err) => {
+        send(socket, &json!({"type": "error", "detail": err.to_string()}))
+      },
+    }
+  }
+
+  /// Replays the messages the user received after the given one.
+  ///
+  /// # Arguments
+  /// * `socket` - The connection of the client.
+  /// * `uid` - The connected user.
+  /// * `last_id` - The id of the last message the client got.
+  fn resume(
+    &self,
+    socket: &mut WebSocket<TcpStream>,
+    uid: i32,
+    last_id: i32,
+  ) -> Result<(), Error> {
+    let mut last_id = last_id;
+    loop {
+      let messages =
+        match self.message_service.missed(uid, last_id, Some(RESUME_PAGE)) {
+          Ok(messages) => messages,
+          Err(err) => {
+            log::error!("cannot resume the user {}: {}", uid, err);
+            let detail = format!("Cannot resume because {}", err);
+            return send(socket, &json!({"type": "error", "detail": detail}));
+          },
+        };
+      for msg in &messages {
+        send(socket, &Event::message(msg))?;
+        last_id = msg.get_id();
+      }
+      if (messages.len() as i64) < RESUME_PAGE {
+        return Ok(());
+      }
+    }
+  }
+}
+
+/// Sends a value as a JSON text frame.
+fn send<T: Serialize>(
+  socket: &mut WebSocket<TcpStream>,
+  value: &T,
+) -> Result<(), Error> {
+  let text = serde_json::to_string(value).map_err(|err| {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, err))
+  })?;
+  socket.write_message(Message::Text(text))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    auth::{
+      error::Error::RevokedTokenError,
+      token::{MockAuthenticator, Session},
+    },
+    model::message_service::MockMessageService,
+    realtime::{
+      hub::Hub,
+      server::{server_with, start_with},
+    },
+  };
+  use mockall::{predicate::eq, Sequence};
+  use std::{net::SocketAddr, sync::Arc};
+  use tungstenite::{client::IntoClientRequest, connect, http::StatusCode};
+
+  /// Builds the handshake of a browser, with the session cookie.
+  fn handshake(address: SocketAddr, query: &str) -> Request {
+    let mut request = format!("ws://{}/{}", address, query)
+      .into_client_request()
+      .unwrap();
+    request
+      .headers_mut()
+      .insert("Cookie", "session=abc".parse().unwrap());
+    request
+  }
+
+  #[test]
+  fn command_parsing() {
+    assert_eq!(
+      serde_json::from_str::<Command>(r#"{"type": "resume", "last_id": 3}"#)
+        .unwrap(),
+      Command::Resume {
+        last_id: 3
+      }
+    );
+    assert!(serde_json::from_str::<Command>(r#"{"type": "jump"}"#).is_err());
+  }
+
+  #[test]
+  fn connect_without_a_valid_token() {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .returning(|_| Err(RevokedTokenError));
+    let address = start_with(
+      Arc::new(Hub::default()),
+      mock_auth,
+      MockMessageService::new(),
+    );
+
+    match connect(handshake(address, "")) {
+      Err(Error::Http(response)) => {
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED)
+      },
+      _ => panic!("the handshake must be rejected"),
+    }
+  }
+
+  #[test]
+  fn close_the_socket_once_the_token_is_revoked() {
+    let mut mock_auth = MockAuthenticator::new();
+    let mut seq = Sequence::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Ok(Session::new(2, 1)));
+    mock_auth
+      .expect_check_session()
+      .with(eq(Session::new(2, 1)))
+      .times(1)
+      .in_sequence(&mut seq)
+      .returning(|_| Err(RevokedTokenError));
+    let mut server = server_with(
+      Arc::new(Hub::default()),
+      mock_auth,
+      MockMessageService::new(),
+    );
+    server.revalidate_interval = Duration::from_millis(50);
+    let address = server.start("127.0.0.1:0").expect("free port");
+
+    let (mut socket, _) =
+      connect(handshake(address, "")).expect("handshake accepted");
+    match socket.read_message() {
+      Ok(Message::Close(Some(frame))) => {
+        assert_eq!(frame.code, CloseCode::Policy)
+      },
+      other => panic!("the socket must be closed, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn resume_and_push_the_events() {
+    let mut mock_auth = MockAuthenticator::new();
+    mock_auth
+      .expect_open_session()
+      .times(1)
+      .returning(|_| Ok(Session::new(2, 1)));
+    let mut mock_ms = MockMessageService::new();
+    mock_ms
+      .expect_missed()
+      .with(eq(2), eq(3), eq(Some(RESUME_PAGE)))
+      .times(1)
+      .returning(|_, _, _| Ok(vec![]));
+    let hub = Arc::new(Hub::default());
+    let address = start_with(hub.clone(), mock_auth, mock_ms);
+
+    let (mut socket, _) =
+      connect(handshake(address, "?last_id=3")).expect("handshake accepted");
+    socket
+      .write_message(Message::Text(String::from(r#"{"type": "ping"}"#)))
+      .unwrap();
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text(String::from("{\"type\":\"pong\"}"))
+    );
+
+    hub.publish(
+      &[2],
+      &Event::Deleted {
+        id: 4,
+      },
+    );
+    assert_eq!(
+      socket.read_message().unwrap(),
+      Message::Text(String::from("{\"type\":\"deleted\",\"id\":4}"))
+    );
+  }
+}
@@ -1,8 +1,72 @@
+table! {
+    attachments (id) {
+        id -> Integer,
+        user_id -> Integer,
+        message_id -> Nullable<Integer>,
+        hash -> Text,
+        filename -> Text,
+        mime_type -> Text,
+        size -> BigInt,
+        created_at -> Timestamp,
+        width -> Nullable<Integer>,
+        height -> Nullable<Integer>,
+        thumbnail_state -> Nullable<Text>,
+        thumbnail_attempts -> Integer,
+        thumbnail_retry_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    conversation_members (id) {
+        id -> Integer,
+        conversation_id -> Integer,
+        user_id -> Integer,
+        role -> Text,
+        joined_at -> Timestamp,
+    }
+}
+
+table! {
+    conversations (id) {
+        id -> Integer,
+        name -> Text,
+        created_by -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     logins (id) {
         id -> Integer,
+        user_id -> Integer,
         username -> Text,
         token -> Text,
+        jti -> Text,
+        family -> Text,
+        device -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        ip -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_seen_at -> Timestamp,
+    }
+}
+
+table! {
+    message_receipts (id) {
+        id -> Integer,
+        message_id -> Integer,
+        user_id -> Integer,
+        delivered_at -> Nullable<Timestamp>,
+        read_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    message_revisions (id) {
+        id -> Integer,
+        message_id -> Integer,
+        message -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -10,8 +74,36 @@ table! {
     messages (id) {
         id -> Integer,
         from -> Integer,
-        to -> Integer,
+        to -> Nullable<Integer>,
         message -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+        conversation_id -> Nullable<Integer>,
+    }
+}
+
+table! {
+    refresh_tokens (id) {
+        id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        family -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        revoked -> Bool,
+    }
+}
+
+table! {
+    thumbnails (id) {
+        id -> Integer,
+        attachment_id -> Integer,
+        size -> Text,
+        hash -> Text,
+        mime_type -> Text,
+        width -> Integer,
+        height -> Integer,
     }
 }
 
@@ -20,7 +112,57 @@ table! {
         id -> Integer,
         username -> Text,
         hashed_password -> Text,
+        role -> Text,
+        suspended -> Bool,
     }
 }
 
-allow_tables_to_appear_in_same_query!(logins, messages, users,);
+table! {
+    webhook_outbox (id) {
+        id -> Integer,
+        webhook_id -> Integer,
+        event -> Text,
+        payload -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        delivered_at -> Nullable<Timestamp>,
+        dead_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    webhooks (id) {
+        id -> Integer,
+        user_id -> Integer,
+        url -> Text,
+        secret -> Text,
+        events -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+joinable!(attachments -> messages (message_id));
+joinable!(attachments -> users (user_id));
+joinable!(conversation_members -> conversations (conversation_id));
+joinable!(message_receipts -> messages (message_id));
+joinable!(message_revisions -> messages (message_id));
+joinable!(thumbnails -> attachments (attachment_id));
+joinable!(webhook_outbox -> webhooks (webhook_id));
+joinable!(webhooks -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(
+  attachments,
+  conversation_members,
+  conversations,
+  logins,
+  message_receipts,
+  message_revisions,
+  messages,
+  refresh_tokens,
+  thumbnails,
+  users,
+  webhook_outbox,
+  webhooks,
+);
@@ -0,0 +1,127 @@
+use crate::storage::{local::LocalBlobStore, s3::S3BlobStore};
+
+use dotenv::dotenv;
+#[cfg(test)]
+use mockall::automock;
+use std::{env, io};
+use thiserror::Error;
+
+pub type StorageResult<T> = Result<T, Error>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+  #[error("blob not found")]
+  NotFound,
+  #[error("invalid blob key {0}")]
+  InvalidKey(String),
+  #[error("storage error: {0}")]
+  Io(String),
+}
+
+impl From<io::Error> for Error {
+  fn from(err: io::Error) -> Self {
+    match err.kind() {
+      io::ErrorKind::NotFound => Error::NotFound,
+      _ => Error::Io(err.to_string()),
+    }
+  }
+}
+
+/// Stores the content of the files by key. The content of a key never
+/// changes until it's deleted, so a key already stored can be put again, in
+/// case it was deleted meanwhile.
+#[cfg_attr(test, automock)]
+pub trait BlobStore: Send + Sync {
+  /// Stores a content under a key.
+  ///
+  /// # Arguments
+  /// * `key` - The key, lowercase letters, digits and dashes.
+  /// * `content` - The content.
+  ///
+  /// # Return
+  /// * A storage error.
+  fn put(&self, key: &str, content: &[u8]) -> StorageResult<()>;
+
+  /// Get the content of a key.
+  ///
+  /// # Arguments
+  /// * `key` - The key.
+  ///
+  /// # Return
+  /// * The content.
+  /// * NotFound if nothing is stored under the key.
+  /// * A storage error.
+  fn get(&self, key: &str) -> StorageResult<Vec<u8>>;
+
+  /// Checks if a content is stored under a key.
+  ///
+  /// # Arguments
+  /// * `key` - The key.
+  ///
+  /// # Return
+  /// * True if there is a content.
+  /// * A storage error.
+  fn exists(&self, key: &str) -> StorageResult<bool>;
+
+  /// Deletes the content of a key, a key that isn't stored is ignored.
+  ///
+  /// # Arguments
+  /// * `key` - The key.
+  ///
+  /// # Return
+  /// * A storage error.
+  fn delete(&self, key: &str) -> StorageResult<()>;
+}
+
+/// Checks that a key is safe to be a file name or a part of a URL: at least
+/// 3 lowercase letters, digits or dashes, like the SHA-256 in hex.
+///
+/// # Arguments
+/// * `key` - The key.
+///
+/// # Return
+/// * InvalidKey if the key has other chars.
+pub fn check_key(key: &str) -> StorageResult<()> {
+  let valid = key.len() >= 3
+    && key
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+  if !valid {
+    return Err(Error::InvalidKey(key.to_string()));
+  }
+  Ok(())
+}
+
+/// Initialize the blob store of the attachments. The kind is taken from the
+/// `blob_store` variable: `local` keeps the files in the `blob_dir`
+/// directory, `./blobs` by default, and `s3` in a bucket of an S3 compatible
+/// service. The local one is used when it's not set.
+///
+/// # Return
+/// * The blob store.
+pub fn setup_blob_store() -> Box<dyn BlobStore> {
+  dotenv().ok();
+
+  match env::var("blob_store").as_deref() {
+    Ok("s3") => Box::new(S3BlobStore::from_env()),
+    Ok("local") | Err(_) => Box::new(LocalBlobStore::new(
+      env::var("blob_dir").unwrap_or_else(|_| String::from("./blobs")),
+    )),
+    Ok(other) => panic!("Unknown blob_store {}", other),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_plain_keys_are_valid() {
+    assert!(check_key("ba7816bf8f01cfea414140de5dae2223").is_ok());
+    assert!(check_key("thumb-ba7816bf").is_ok());
+    assert!(check_key("../etc/passwd").is_err());
+    assert!(check_key("a/b").is_err());
+    assert!(check_key("AB12").is_err());
+    assert!(check_key("ab").is_err());
+  }
+}
@@ -0,0 +1,97 @@
+use crate::storage::blob_store::{check_key, BlobStore, StorageResult};
+
+use rand::{rngs::OsRng, RngCore};
+use std::{fs, io, path::PathBuf};
+
+/// Keeps the blobs as files of a directory. They are spread in
+/// subdirectories by the first 2 chars of the key, so no directory gets too
+/// big.
+pub struct LocalBlobStore {
+  root: PathBuf,
+}
+
+impl LocalBlobStore {
+  pub fn new(the_root: impl Into<PathBuf>) -> Self {
+    LocalBlobStore {
+      root: the_root.into(),
+    }
+  }
+
+  /// Get the path of the file of a key.
+  ///
+  /// # Arguments
+  /// * `key` - The key.
+  ///
+  /// # Return
+  /// * The path, inside the root directory.
+  /// * InvalidKey if the key could point outside of it.
+  fn path_of(&self, key: &str) -> StorageResult<PathBuf> {
+    check_key(key)?;
+    Ok(self.root.join(&key[..2]).join(key))
+  }
+}
+
+impl BlobStore for LocalBlobStore {
+  fn put(&self, key: &str, content: &[u8]) -> StorageResult<()> {
+    let path = self.path_of(key)?;
+    if path.exists() {
+      return Ok(());
+    }
+    let dir = path.parent().expect("the path is inside the root");
+    fs::create_dir_all(dir)?;
+    // Written aside and renamed, so a reader never gets a partial file
+    let temp = dir.join(format!("{}.{:x}.tmp", key, OsRng.next_u64()));
+    fs::write(&temp, content)?;
+    if let Err(err) = fs::rename(&temp, &path) {
+      let _ = fs::remove_file(&temp);
+      return Err(err.into());
+    }
+    Ok(())
+  }
+
+  fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+    let content = fs::read(self.path_of(key)?)?;
+    Ok(content)
+  }
+
+  fn exists(&self, key: &str) -> StorageResult<bool> {
+    Ok(self.path_of(key)?.is_file())
+  }
+
+  fn delete(&self, key: &str) -> StorageResult<()> {
+    match fs::remove_file(self.path_of(key)?) {
+      Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+      _ => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::storage::blob_store::Error;
+  use std::{env, process};
+
+  #[test]
+  fn put_and_get_a_blob() {
+    let root = env::temp_dir().join(format!("blobs-{}", process::id()));
+    let store = LocalBlobStore::new(&root);
+
+    assert!(!store.exists("abc123").unwrap());
+    assert!(matches!(store.get("abc123"), Err(Error::NotFound)));
+    store.put("abc123", b"content").unwrap();
+    store.put("abc123", b"content").unwrap();
+    assert!(store.exists("abc123").unwrap());
+    assert_eq!(store.get("abc123").unwrap(), b"content");
+    assert!(root.join("ab").join("abc123").is_file());
+    store.delete("abc123").unwrap();
+    store.delete("abc123").unwrap();
+    assert!(!store.exists("abc123").unwrap());
+    assert!(matches!(
+      store.put("../abc", b"content"),
+      Err(Error::InvalidKey(_))
+    ));
+
+    fs::remove_dir_all(root).unwrap();
+  }
+}
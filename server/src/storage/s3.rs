@@ -0,0 +1,372 @@
+use crate::{
+  crypto::{hex, hmac_sha256},
+  storage::blob_store::{check_key, BlobStore, Error, StorageResult},
+};
+
+use chrono::Utc;
+use dotenv::dotenv;
+use sha2::{Digest, Sha256};
+use std::{env, io::Read, time::Duration};
+use ureq::{Agent, AgentBuilder, Request};
+
+/// How long the service can take to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The headers covered by the signature of the requests.
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+/// Keeps the blobs as objects of a bucket of an S3 compatible service, like
+/// AWS S3 or MinIO. The objects are addressed with the path style,
+/// `<endpoint>/<bucket>/<key>`, that every service supports, and the requests
+/// are signed with the AWS signature version 4.
+pub struct S3BlobStore {
+  endpoint: String,
+  host: String,
+  bucket: String,
+  region: String,
+  access_key: String,
+  secret_key: String,
+  agent: Agent,
+}
+
+impl S3BlobStore {
+  /// Creates the store of a bucket.
+  ///
+  /// # Arguments
+  /// * `the_endpoint` - The URL of the service, like `http://localhost:9000`.
+  /// * `the_bucket` - The name of the bucket, it must exist.
+  /// * `the_region` - The region of the bucket.
+  /// * `the_access_key` - The id of the access key.
+  /// * `the_secret_key` - The secret of the access key.
+  pub fn new(
+    the_endpoint: &str,
+    the_bucket: &str,
+    the_region: &str,
+    the_access_key: &str,
+    the_secret_key: &str,
+  ) -> Self {
+    let endpoint = the_endpoint.trim_end_matches('/').to_string();
+    let host = endpoint
+      .split_once("://")
+      .map_or(endpoint.as_str(), |(_, rest)| rest)
+      .split('/')
+      .next()
+      .unwrap_or_default()
+      .to_string();
+    S3BlobStore {
+      endpoint,
+      host,
+      bucket: the_bucket.to_string(),
+      region: the_region.to_string(),
+      access_key: the_access_key.to_string(),
+      secret_key: the_secret_key.to_string(),
+      agent: AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+    }
+  }
+
+  /// Initialize the store with the `s3_endpoint`, `s3_bucket`,
+  /// `s3_access_key` and `s3_secret_key` variables, and the `s3_region` one,
+  /// `us-east-1` when it's not set.
+  ///
+  /// # Return
+  /// * The store.
+  pub fn from_env() -> Self {
+    dotenv().ok();
+
+    let var = |name: &str| {
+      env::var(name).unwrap_or_else(|_| panic!("{} must be set", name))
+    };
+    S3BlobStore::new(
+      &var("s3_endpoint"),
+      &var("s3_bucket"),
+      &env::var("s3_region").unwrap_or_else(|_| String::from("us-east-1")),
+      &var("s3_access_key"),
+      &var("s3_secret_key"),
+    )
+  }
+
+  /// Get the path of the object of a key.
+  ///
+  /// # Arguments
+  /// * `key` - The key.
+  ///
+  /// # Return
+  /// * The path, that doesn't need to be encoded.
+  /// * InvalidKey if the key isn't valid.
+  fn path_of(&self, key: &str) -> StorageResult<String> {
+    check_key(key)?;
+    Ok(format!("/{}/{}", self.bucket, key))
+  }
+
+  /// Calculates the `Authorization` header of a request without a query.
+  ///
+  /// # Arguments
+  /// * `method` - The method of the request.
+  /// * `path` - The path of the object.
+  /// * `amz_date` - The date of the request, as `20220925T090000Z`.
+  /// * `payload_hash` - The SHA-256 of the body, in hex.
+  ///
+  /// # Return
+  /// * The value of the header.
+  fn authorization(
+    &self,
+    method: &str,
+    path: &str,
+    amz_date: &str,
+    payload_hash: &str,
+  ) -> String {
+    let date = amz_date.get(..8).unwrap_or_default();
+    let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+    let canonical_request = format!(
+      "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+      method,
+      path,
+      self.host,
+      payload_hash,
+      amz_date,
+      SIGNED_HEADERS,
+      payload_hash
+    );
+    let string_to_sign = format!(
+      "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+      amz_date,
+      scope,
+      hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = signing_key(&self.secret_key, date, &self.region, "s3");
+    format!(
+      "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+      self.access_key,
+      scope,
+      SIGNED_HEADERS,
+      hex(&hmac_sha256(&key, string_to_sign.as_bytes()))
+    )
+  }
+
+  /// Creates a signed request on the object of a key.
+  ///
+  /// # Arguments
+  /// * `method` - The method of the request.
+  /// * `key` - The key.
+  /// * `content` - The body that is sent with the request.
+  ///
+  /// # Return
+  /// * The request, ready to be sent.
+  /// * InvalidKey if the key isn't valid.
+  fn request(
+    &self,
+    method: &str,
+    key: &str,
+    content: &[u8],
+  ) -> StorageResult<Request> {
+    let path = self.path_of(key)?;
+    let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let payload_hash = hex(&Sha256::digest(content));
+    let authorization =
+      self.authorization(method, &path, &amz_date, &payload_hash);
+    Ok(
+      self
+        .agent
+        .request(method, &format!("{}{}", self.endpoint, path))
+        .set("Host", &self.host)
+        .set("x-amz-content-sha256", &payload_hash)
+        .set("x-amz-date", &amz_date)
+        .set("Authorization", &authorization),
+    )
+  }
+}
+
+impl BlobStore for S3BlobStore {
+  fn put(&self, key: &str, content: &[u8]) -> StorageResult<()> {
+    self
+      .request("PUT", key, content)?
+      .send_bytes(content)
+      .map_err(storage_error)?;
+    Ok(())
+  }
+
+  fn get(&self, key: &str) -> StorageResult<Vec<u8>> {
+    let response = self
+      .request("GET", key, &[])?
+      .call()
+      .map_err(storage_error)?;
+    let mut content = Vec::new();
+    response.into_reader().read_to_end(&mut content)?;
+    Ok(content)
+  }
+
+  fn exists(&self, key: &str) -> StorageResult<bool> {
+    match self.request("HEAD", key, &[])?.call() {
+      Ok(_) => Ok(true),
+      Err(ureq::Error::Status(404, _)) => Ok(false),
+      Err(err) => Err(storage_error(err)),
+    }
+  }
+
+  fn delete(&self, key: &str) -> StorageResult<()> {
+    match self.request("DELETE", key, &[])?.call() {
+      Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+      Err(err) => Err(storage_error(err)),
+    }
+  }
+}
+
+/// Converts the error of a request to the service.
+fn storage_error(err: ureq::Error) -> Error {
+  match err {
+    ureq::Error::Status(404, _) => Error::NotFound,
+    ureq::Error::Status(status, _) => {
+      Error::Io(format!("the S3 service answered {}", status))
+    },
+    ureq::Error::Transport(transport) => Error::Io(transport.to_string()),
+  }
+}
+
+/// Derives the key that signs the requests of a day to a service.
+///
+/// # Arguments
+/// * `secret` - The secret of the access key.
+/// * `date` - The day, as `20220925`.
+/// * `region` - The region of the service.
+/// * `service` - The name of the service.
+///
+/// # Return
+/// * The signing key.
+fn signing_key(
+  secret: &str,
+  date: &str,
+  region: &str,
+  service: &str,
+) -> Vec<u8> {
+  let date_key =
+    hmac_sha256(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+  let region_key = hmac_sha256(&date_key, region.as_bytes());
+  let service_key = hmac_sha256(&region_key, service.as_bytes());
+  hmac_sha256(&service_key, b"aws4_request")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+  };
+
+  /// Starts a stand-in of an S3 service, like a local MinIO. It keeps in
+  /// memory the objects of the requests signed with the `access` key and the
+  /// `secret` secret.
+  ///
+  /// # Return
+  /// * The endpoint of the service.
+  fn stand_in() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let checker =
+      S3BlobStore::new(&endpoint, "bucket", "us-east-1", "access", "secret");
+    thread::spawn(move || {
+      let mut objects = HashMap::new();
+      for stream in listener.incoming() {
+        let _ = answer(&checker, &mut objects, stream.unwrap());
+      }
+    });
+    endpoint
+  }
+
+  /// Answers a request to the stand-in, the connection is closed after it.
+  fn answer(
+    checker: &S3BlobStore,
+    objects: &mut HashMap<String, Vec<u8>>,
+    mut stream: TcpStream,
+  ) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut headers = HashMap::new();
+    loop {
+      let mut line = String::new();
+      reader.read_line(&mut line)?;
+      match line.trim_end().split_once(':') {
+        Some((name, value)) => {
+          headers.insert(name.to_lowercase(), value.trim().to_string());
+        },
+        None => break,
+      }
+    }
+    let header = |name: &str| headers.get(name).cloned().unwrap_or_default();
+    let mut body = vec![0; header("content-length").parse().unwrap_or(0)];
+    reader.read_exact(&mut body)?;
+
+    let payload_hash = header("x-amz-content-sha256");
+    let signed = header("host") == checker.host
+      && payload_hash == hex(&Sha256::digest(&body))
+      && header("authorization")
+        == checker.authorization(
+          &method,
+          &path,
+          &header("x-amz-date"),
+          &payload_hash,
+        );
+    let (status, content) = match (signed, method.as_str()) {
+      (false, _) => ("403 Forbidden", vec![]),
+      (true, "PUT") => {
+        objects.insert(path, body);
+        ("200 OK", vec![])
+      },
+      (true, "GET") | (true, "HEAD") => match objects.get(&path) {
+        Some(object) if method == "GET" => ("200 OK", object.clone()),
+        Some(_) => ("200 OK", vec![]),
+        None => ("404 Not Found", vec![]),
+      },
+      (true, "DELETE") => {
+        objects.remove(&path);
+        ("204 No Content", vec![])
+      },
+      _ => ("405 Method Not Allowed", vec![]),
+    };
+    write!(
+      stream,
+      "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+      status,
+      content.len()
+    )?;
+    stream.write_all(&content)
+  }
+
+  #[test]
+  fn signing_key_of_the_aws_example() {
+    let key = signing_key(
+      "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+      "20120215",
+      "us-east-1",
+      "iam",
+    );
+    assert_eq!(
+      hex(&key),
+      "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+    );
+  }
+
+  #[test]
+  fn put_and_get_an_object() {
+    let endpoint = stand_in();
+    let store =
+      S3BlobStore::new(&endpoint, "bucket", "us-east-1", "access", "secret");
+
+    assert!(!store.exists("abc123").unwrap());
+    assert!(matches!(store.get("abc123"), Err(Error::NotFound)));
+    store.put("abc123", b"content").unwrap();
+    assert!(store.exists("abc123").unwrap());
+    assert_eq!(store.get("abc123").unwrap(), b"content");
+    store.delete("abc123").unwrap();
+    assert!(!store.exists("abc123").unwrap());
+
+    let forged =
+      S3BlobStore::new(&endpoint, "bucket", "us-east-1", "access", "other");
+    assert!(matches!(forged.get("abc123"), Err(Error::Io(_))));
+  }
+}